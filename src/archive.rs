@@ -0,0 +1,159 @@
+//! Extracting ZIP and tar archives, such as malware samples and software bundles.
+
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use flate2::read::GzDecoder;
+use zip::{ZipArchive, result::ZipError};
+
+use crate::{
+    container::has_container_extension, error::Result, package::sanitize, windows::FileFilter,
+};
+
+/// Signature of ZIP files, at the start of the first local file header.
+const ZIP_SIGNATURE: &[u8; 4] = b"PK\x03\x04";
+/// Signature of POSIX tar files, at offset 257.
+const TAR_SIGNATURE: &[u8; 5] = b"ustar";
+const TAR_SIGNATURE_OFFSET: u64 = 257;
+/// Signature of gzip files.
+pub(crate) const GZIP_SIGNATURE: &[u8; 2] = b"\x1f\x8b";
+/// Password conventionally used for ZIP files of malware samples, tried for encrypted
+/// entries.
+const SAMPLE_PASSWORD: &[u8] = b"infected";
+
+/// The archive formats that are extracted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    /// A gzip-compressed tar file.
+    TarGz,
+}
+
+impl ArchiveKind {
+    /// Detects the format of the file by its signature, falling back to its extension
+    /// for tar files without the POSIX signature.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let mut file = File::open(path).ok()?;
+        let mut signature = [0; 4];
+        if file.read_exact(&mut signature).is_ok() {
+            if signature == *ZIP_SIGNATURE {
+                return Some(Self::Zip);
+            }
+            if signature[..2] == *GZIP_SIGNATURE && is_tar_name(path) {
+                return Some(Self::TarGz);
+            }
+        }
+        let mut tar = [0; 5];
+        if file.seek(SeekFrom::Start(TAR_SIGNATURE_OFFSET)).is_ok()
+            && file.read_exact(&mut tar).is_ok()
+            && tar == *TAR_SIGNATURE
+        {
+            return Some(Self::Tar);
+        }
+        is_tar_name(path).then_some(Self::Tar)
+    }
+}
+
+/// Returns whether the file is a ZIP or tar archive.
+pub fn is_archive(path: &Path) -> bool {
+    ArchiveKind::detect(path).is_some()
+}
+
+/// Extracts the files that `filter` accepts from the archive at `path` into `dest`,
+/// plus the archives and other containers nested in it, and returns their paths.
+///
+/// Encrypted ZIP entries are decrypted with the password `infected` used for malware
+/// samples, and skipped if that does not work.
+pub fn extract_archive(path: &Path, dest: &Path, filter: &FileFilter) -> Result<Vec<PathBuf>> {
+    match ArchiveKind::detect(path) {
+        Some(ArchiveKind::Zip) => extract_zip(path, dest, filter),
+        Some(ArchiveKind::Tar) => extract_tar(File::open(path)?, dest, filter),
+        Some(ArchiveKind::TarGz) => extract_tar(GzDecoder::new(File::open(path)?), dest, filter),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Extracts the entries of a ZIP file that [`entry_target`] picks.
+fn extract_zip(path: &Path, dest: &Path, filter: &FileFilter) -> Result<Vec<PathBuf>> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut extracted = Vec::new();
+    for index in 0..archive.len() {
+        let encrypted = matches!(
+            archive.by_index(index),
+            Err(ZipError::UnsupportedArchive(message)) if message == ZipError::PASSWORD_REQUIRED
+        );
+        let mut entry = if encrypted {
+            archive.by_index_decrypt(index, SAMPLE_PASSWORD)
+        } else {
+            archive.by_index(index)
+        };
+        let entry = match &mut entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::warn!("Skipping entry {} of {}: {}", index, path.display(), e);
+                continue;
+            }
+        };
+        if entry.is_dir() {
+            continue;
+        }
+        let Some(target) = entry_target(entry.name(), dest, filter) else {
+            continue;
+        };
+        io::copy(entry, &mut File::create(&target)?)?;
+        extracted.push(target);
+    }
+    Ok(extracted)
+}
+
+/// Extracts the regular files of a tar stream that [`entry_target`] picks.
+fn extract_tar(reader: impl Read, dest: &Path, filter: &FileFilter) -> Result<Vec<PathBuf>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut extracted = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let Some(target) = entry_target(&name, dest, filter) else {
+            continue;
+        };
+        io::copy(&mut entry, &mut File::create(&target)?)?;
+        extracted.push(target);
+    }
+    Ok(extracted)
+}
+
+/// Returns where to extract the entry `name` below `dest`, creating its directory, or
+/// `None` for entries that are neither accepted by `filter` nor nested archives, and
+/// for unsafe paths.
+fn entry_target(name: &str, dest: &Path, filter: &FileFilter) -> Option<PathBuf> {
+    let Some(relative) = sanitize(name) else {
+        tracing::warn!("Skipping {}: unsafe path", name);
+        return None;
+    };
+    let target = dest.join(relative);
+    if !filter.accepts_name(&target) && !has_container_extension(&target) {
+        return None;
+    }
+    if let Some(parent) = target.parent()
+        && let Err(e) = fs::create_dir_all(parent)
+    {
+        tracing::warn!("Skipping {}: {}", name, e);
+        return None;
+    }
+    Some(target)
+}
+
+pub(crate) fn is_tar_name(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    name.ends_with(".tar") || name.ends_with(".tar.gz") || name.ends_with(".tgz")
+}
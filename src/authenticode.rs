@@ -0,0 +1,800 @@
+//! The Authenticode signers of PE files, read from the certificate table of the file
+//! or, for the system files Windows signs in bulk, from the security catalogs under
+//! `System32/CatRoot`.
+//!
+//! Only as much of DER and PKCS #7 is parsed as is needed to name the signer; the
+//! signatures are not checked, so this tells signed binaries from third-party ones but
+//! is no defense against forged certificates.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    bytes::{read_u16, read_u32},
+    pe::PeImage,
+};
+
+/// Index of the security directory, which holds the certificate table, among the data
+/// directories of the optional header.
+const SECURITY_DIRECTORY: usize = 4;
+/// `WIN_CERT_TYPE_PKCS_SIGNED_DATA`, a certificate table entry holding a PKCS #7
+/// `SignedData` structure.
+const WIN_CERT_TYPE_PKCS_SIGNED_DATA: u16 = 2;
+/// Length of the `WIN_CERTIFICATE` header before the certificate data.
+const WIN_CERTIFICATE_HEADER_LEN: usize = 8;
+/// Extension of security catalog files.
+const CATALOG_EXTENSION: &str = "cat";
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_UTF8_STRING: u8 = 0x0c;
+const TAG_PRINTABLE_STRING: u8 = 0x13;
+const TAG_TELETEX_STRING: u8 = 0x14;
+const TAG_IA5_STRING: u8 = 0x16;
+const TAG_BMP_STRING: u8 = 0x1e;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+/// `[0]`, constructed and context-specific.
+const TAG_CONTEXT_0: u8 = 0xa0;
+/// Bit set in the tags of constructed types, whose contents are more elements.
+const CONSTRUCTED: u8 = 0x20;
+
+/// `commonName` (2.5.4.3), DER-encoded.
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+/// `organizationName` (2.5.4.10), DER-encoded.
+const OID_ORGANIZATION: &[u8] = &[0x55, 0x04, 0x0a];
+
+/// The layout of the parts of a PE file that Authenticode digests leave out.
+struct SignedLayout {
+    /// Offset of the `CheckSum` field of the optional header.
+    checksum: usize,
+    /// Offset of the security directory entry.
+    security_entry: usize,
+    /// The certificate table, if the file has one.
+    table: Option<(usize, usize)>,
+}
+
+impl SignedLayout {
+    fn of(data: &[u8]) -> Option<Self> {
+        let pe = read_u32(data, 0x3c)? as usize;
+        if data.get(pe..pe + 4)? != b"PE\0\0" {
+            return None;
+        }
+        let optional = pe + 24;
+        let directories = match read_u16(data, optional)? {
+            0x10b => optional + 96,
+            0x20b => optional + 112,
+            _ => return None,
+        };
+        let directory_count = read_u32(data, directories - 4)? as usize;
+        if directory_count <= SECURITY_DIRECTORY {
+            return None;
+        }
+        let security_entry = directories + SECURITY_DIRECTORY * 8;
+        // Unlike the other directories, the security directory holds a file offset.
+        let offset = read_u32(data, security_entry)? as usize;
+        let size = read_u32(data, security_entry + 4)? as usize;
+        let table = (offset != 0 && size != 0 && offset.checked_add(size)? <= data.len())
+            .then_some((offset, size));
+        Some(Self {
+            checksum: optional + 64,
+            security_entry,
+            table,
+        })
+    }
+}
+
+/// Returns the signer of the Authenticode signature embedded in the PE file `data`, as
+/// the common name and organization of its certificate, e.g. `CN=Microsoft Windows,
+/// O=Microsoft Corporation`, or `None` if the file is not signed this way.
+pub fn embedded_signer(data: &[u8]) -> Option<String> {
+    let (offset, size) = SignedLayout::of(data)?.table?;
+    let mut table = &data[offset..offset + size];
+    while table.len() >= WIN_CERTIFICATE_HEADER_LEN {
+        let length = read_u32(table, 0)? as usize;
+        let kind = read_u16(table, 6)?;
+        if length < WIN_CERTIFICATE_HEADER_LEN || length > table.len() {
+            return None;
+        }
+        if kind == WIN_CERT_TYPE_PKCS_SIGNED_DATA
+            && let Some(signer) = signed_data_signer(&table[WIN_CERTIFICATE_HEADER_LEN..length])
+        {
+            return Some(signer);
+        }
+        // Entries are aligned to 8 bytes.
+        table = table.get(length.next_multiple_of(8)..)?;
+    }
+    None
+}
+
+/// Returns the SHA-1 and SHA-256 Authenticode digests of the PE file `data`, under
+/// which security catalogs list the files they sign.
+///
+/// Like the Authenticode hash, these cover the whole file except the checksum, the
+/// security directory entry and the certificate table.
+pub fn image_digests(data: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let layout = SignedLayout::of(data)?;
+    let end = match layout.table {
+        Some((offset, _)) => offset,
+        None => data.len(),
+    };
+    if layout.security_entry + 8 > end {
+        return None;
+    }
+    let parts = [
+        &data[..layout.checksum],
+        &data[layout.checksum + 4..layout.security_entry],
+        &data[layout.security_entry + 8..end],
+    ];
+    let mut sha1 = Sha1::new();
+    let mut sha256 = Sha256::new();
+    for part in parts {
+        sha1.update(part);
+        sha256.update(part);
+    }
+    Some((sha1.finalize().to_vec(), sha256.finalize().to_vec()))
+}
+
+/// The files listed in security catalogs, by their Authenticode digests, with the
+/// signers of the catalogs.
+///
+/// Most files that ship with Windows carry no signature of their own; they are signed
+/// by a catalog under `System32/CatRoot` instead.
+#[derive(Debug, Default)]
+pub struct CatalogIndex {
+    signers: HashMap<Vec<u8>, Arc<str>>,
+}
+
+impl CatalogIndex {
+    /// Reads every `.cat` file in and below `dirs`. Catalogs that cannot be read are
+    /// skipped with a warning.
+    pub fn load(dirs: &[PathBuf]) -> Self {
+        let mut index = Self::default();
+        let mut pending = dirs.to_vec();
+        while let Some(dir) = pending.pop() {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    tracing::warn!("Failed to read catalogs in {}: {}", dir.display(), e);
+                    continue;
+                }
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else if path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case(CATALOG_EXTENSION))
+                {
+                    match fs::read(&path) {
+                        Ok(data) if index.add_catalog(&data) => {}
+                        Ok(_) => tracing::debug!("No signed files in {}", path.display()),
+                        Err(e) => tracing::warn!("Failed to read {}: {}", path.display(), e),
+                    }
+                }
+            }
+        }
+        tracing::info!(
+            "Loaded {} catalog entries from {} directories",
+            index.len(),
+            dirs.len()
+        );
+        index
+    }
+
+    /// Adds the files listed in the catalog `data`. Returns `false` if the catalog is
+    /// unsigned or lists no files.
+    pub fn add_catalog(&mut self, data: &[u8]) -> bool {
+        let Some(signer) = signed_data_signer(data) else {
+            return false;
+        };
+        let Some(subjects) = trusted_subjects(data) else {
+            return false;
+        };
+        let signer: Arc<str> = signer.into();
+        let mut added = false;
+        for subject in subjects {
+            let mut digests = Vec::new();
+            subject_digests(subject, &mut digests);
+            for digest in digests {
+                self.signers.insert(digest, signer.clone());
+                added = true;
+            }
+        }
+        added
+    }
+
+    /// Returns the signer of the catalog listing the PE file `data`.
+    pub fn signer(&self, data: &[u8]) -> Option<&str> {
+        if self.signers.is_empty() {
+            return None;
+        }
+        let (sha1, sha256) = image_digests(data)?;
+        self.signers
+            .get(&sha256)
+            .or_else(|| self.signers.get(&sha1))
+            .map(|signer| &**signer)
+    }
+
+    /// Returns the number of file digests in the index.
+    pub fn len(&self) -> usize {
+        self.signers.len()
+    }
+
+    /// Returns whether no catalog listed any files.
+    pub fn is_empty(&self) -> bool {
+        self.signers.is_empty()
+    }
+}
+
+/// Returns the `System32/CatRoot` directories of the Windows installations `paths` are
+/// in, or are the root of, for looking up catalog-signed files.
+pub fn find_catalog_dirs(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for path in paths {
+        for ancestor in path.ancestors() {
+            for candidate in [
+                ancestor.join("System32").join("CatRoot"),
+                ancestor.join("Windows").join("System32").join("CatRoot"),
+            ] {
+                if candidate.is_dir() && !dirs.contains(&candidate) {
+                    dirs.push(candidate);
+                }
+            }
+        }
+    }
+    dirs
+}
+
+/// Scans only binaries whose Authenticode signer contains one of a set of names, e.g.
+/// `Microsoft`, so third-party software installed next to Windows does not turn into
+/// symbol server requests that cannot succeed.
+#[derive(Debug)]
+pub struct SignerFilter {
+    names: Vec<String>,
+    catalogs: CatalogIndex,
+}
+
+impl SignerFilter {
+    /// Accepts binaries whose signer contains one of `names`, ignoring case, looking up
+    /// files without an embedded signature in `catalogs`.
+    pub fn new(names: &[String], catalogs: CatalogIndex) -> Self {
+        Self {
+            names: names.iter().map(|name| name.to_lowercase()).collect(),
+            catalogs,
+        }
+    }
+
+    /// Returns the signer of the PE file at `path`, from its embedded signature or from
+    /// the catalogs.
+    pub fn signer(&self, path: &Path) -> Option<String> {
+        let image = PeImage::open(path).ok()?;
+        embedded_signer(image.as_slice()).or_else(|| {
+            if self.catalogs.is_empty() {
+                return None;
+            }
+            // Catalogs list digests of the whole file.
+            let data = fs::read(path).ok()?;
+            self.catalogs.signer(&data).map(str::to_string)
+        })
+    }
+
+    /// Returns whether the file at `path` is signed by one of the names.
+    pub fn accepts(&self, path: &Path) -> bool {
+        match self.signer(path) {
+            Some(signer) => {
+                let lower = signer.to_lowercase();
+                let accepted = self.names.iter().any(|name| lower.contains(name.as_str()));
+                if !accepted {
+                    tracing::debug!("Skipping {} signed by {}", path.display(), signer);
+                }
+                accepted
+            }
+            None => {
+                tracing::debug!("Skipping unsigned {}", path.display());
+                false
+            }
+        }
+    }
+}
+
+/// A DER element.
+#[derive(Clone, Copy)]
+struct Der<'a> {
+    tag: u8,
+    /// The encoding of the whole element, tag and length included.
+    raw: &'a [u8],
+    contents: &'a [u8],
+}
+
+/// Reads the DER element at the start of `data`, returning it and the rest of `data`.
+/// Multi-byte tags and indefinite lengths, which DER does not use, are rejected.
+fn read_der(data: &[u8]) -> Option<(Der<'_>, &[u8])> {
+    let tag = *data.first()?;
+    if tag & 0x1f == 0x1f {
+        return None;
+    }
+    let first = *data.get(1)?;
+    let (len, header) = if first < 0x80 {
+        (first as usize, 2)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let len = data
+            .get(2..2 + count)?
+            .iter()
+            .fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        (len, 2 + count)
+    };
+    let end = header.checked_add(len)?;
+    let der = Der {
+        tag,
+        raw: data.get(..end)?,
+        contents: &data[header..end],
+    };
+    Some((der, &data[end..]))
+}
+
+/// Returns the elements in the contents of a constructed element, stopping at the
+/// first that cannot be read.
+fn children(contents: &[u8]) -> Vec<Der<'_>> {
+    let mut elements = Vec::new();
+    let mut rest = contents;
+    while let Some((element, next)) = read_der(rest) {
+        elements.push(element);
+        rest = next;
+    }
+    elements
+}
+
+/// Returns the elements of a PKCS #7 `SignedData` structure wrapped in a
+/// `ContentInfo`: version, digest algorithms, content, certificates and so on.
+fn signed_data(data: &[u8]) -> Option<Vec<Der<'_>>> {
+    let (content_info, _) = read_der(data)?;
+    let explicit = children(content_info.contents)
+        .into_iter()
+        .find(|element| element.tag == TAG_CONTEXT_0)?;
+    let (signed, _) = read_der(explicit.contents)?;
+    (signed.tag == TAG_SEQUENCE).then(|| children(signed.contents))
+}
+
+/// Returns the subject of the certificate that made the first signature of a PKCS #7
+/// `SignedData` structure, found by the issuer and serial number the signature names.
+fn signed_data_signer(data: &[u8]) -> Option<String> {
+    let elements = signed_data(data)?;
+    let certificates = elements
+        .iter()
+        .find(|element| element.tag == TAG_CONTEXT_0)?;
+    let signer_infos = elements
+        .iter()
+        .rev()
+        .find(|element| element.tag == TAG_SET)?;
+    let (signer_info, _) = read_der(signer_infos.contents)?;
+    let signer_info = children(signer_info.contents);
+    let id = signer_info.get(1)?;
+    if id.tag != TAG_SEQUENCE {
+        // Signers named by their subject key identifier are not looked up.
+        return None;
+    }
+    let id = children(id.contents);
+    let (issuer, serial) = (id.first()?, id.get(1)?);
+    children(certificates.contents)
+        .into_iter()
+        .find_map(|certificate| {
+            let (tbs, _) = read_der(certificate.contents)?;
+            let mut fields = children(tbs.contents);
+            if fields.first()?.tag == TAG_CONTEXT_0 {
+                fields.remove(0);
+            }
+            // serialNumber, signature, issuer, validity, subject
+            let serial_number = fields.first()?;
+            if serial_number.tag != TAG_INTEGER
+                || serial_number.contents != serial.contents
+                || fields.get(2)?.raw != issuer.raw
+            {
+                return None;
+            }
+            name_string(fields.get(4)?)
+        })
+}
+
+/// Formats the common name and organization of an X.501 name, e.g.
+/// `CN=Microsoft Windows, O=Microsoft Corporation`.
+fn name_string(name: &Der<'_>) -> Option<String> {
+    let mut parts = Vec::new();
+    for rdn in children(name.contents) {
+        for attribute in children(rdn.contents) {
+            let fields = children(attribute.contents);
+            let (Some(oid), Some(value)) = (fields.first(), fields.get(1)) else {
+                continue;
+            };
+            if oid.tag != TAG_OID {
+                continue;
+            }
+            let label = if oid.contents == OID_COMMON_NAME {
+                "CN"
+            } else if oid.contents == OID_ORGANIZATION {
+                "O"
+            } else {
+                continue;
+            };
+            if let Some(value) = der_string(value) {
+                parts.push(format!("{}={}", label, value));
+            }
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
+/// Decodes the string types used in certificate names.
+fn der_string(value: &Der<'_>) -> Option<String> {
+    match value.tag {
+        TAG_UTF8_STRING | TAG_PRINTABLE_STRING | TAG_IA5_STRING => {
+            Some(String::from_utf8_lossy(value.contents).into_owned())
+        }
+        // Teletex strings are in practice Latin-1.
+        TAG_TELETEX_STRING => Some(value.contents.iter().map(|&b| b as char).collect()),
+        TAG_BMP_STRING => {
+            let units: Vec<u16> = value
+                .contents
+                .chunks_exact(2)
+                .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                .collect();
+            Some(String::from_utf16_lossy(&units))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the `TrustedSubject` entries of the certificate trust list a security
+/// catalog signs: sequences starting with the subject identifier, an octet string.
+fn trusted_subjects(data: &[u8]) -> Option<Vec<Der<'_>>> {
+    let elements = signed_data(data)?;
+    let content = elements.get(2)?;
+    let explicit = children(content.contents)
+        .into_iter()
+        .find(|element| element.tag == TAG_CONTEXT_0)?;
+    let (list, _) = read_der(explicit.contents)?;
+    children(list.contents).into_iter().find_map(|element| {
+        let subjects = children(element.contents);
+        let is_subject_list = element.tag == TAG_SEQUENCE
+            && !subjects.is_empty()
+            && subjects.iter().all(|subject| {
+                subject.tag == TAG_SEQUENCE
+                    && read_der(subject.contents).is_some_and(|(id, _)| id.tag == TAG_OCTET_STRING)
+            });
+        is_subject_list.then_some(subjects)
+    })
+}
+
+/// Collects the digests a catalog lists a file under: the subject identifier, which is
+/// the digest as UTF-16 hex, and the SHA-1 and SHA-256 digests in its attributes.
+fn subject_digests(subject: Der<'_>, digests: &mut Vec<Vec<u8>>) {
+    for element in children(subject.contents) {
+        if element.tag == TAG_OCTET_STRING {
+            if matches!(element.contents.len(), 20 | 32) {
+                digests.push(element.contents.to_vec());
+            } else if let Some(digest) = utf16_hex(element.contents) {
+                digests.push(digest);
+            }
+        } else if element.tag & CONSTRUCTED != 0 {
+            subject_digests(element, digests);
+        }
+    }
+}
+
+/// Decodes a digest written as UTF-16LE hex digits, optionally NUL-terminated.
+fn utf16_hex(data: &[u8]) -> Option<Vec<u8>> {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect();
+    let text = String::from_utf16(&units).ok()?;
+    if !matches!(text.len(), 40 | 64) {
+        return None;
+    }
+    hex::decode(text).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNED_DATA_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x07, 0x02];
+    const SHA256_OID: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+    /// Offsets of the checksum and the security directory entry in [`pe`] files.
+    const CHECKSUM: usize = 0x58 + 64;
+    const SECURITY_ENTRY: usize = 0x58 + 112 + SECURITY_DIRECTORY * 8;
+
+    fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut data = vec![tag];
+        match contents.len() {
+            len @ 0..0x80 => data.push(len as u8),
+            len @ 0x80..0x100 => data.extend([0x81, len as u8]),
+            len => data.extend([0x82, (len >> 8) as u8, len as u8]),
+        }
+        data.extend(contents);
+        data
+    }
+
+    fn seq(elements: &[Vec<u8>]) -> Vec<u8> {
+        der(TAG_SEQUENCE, &elements.concat())
+    }
+
+    fn name(common_name: &str, organization: &str) -> Vec<u8> {
+        seq(&[
+            der(
+                TAG_SET,
+                &seq(&[
+                    der(TAG_OID, OID_COMMON_NAME),
+                    der(TAG_UTF8_STRING, common_name.as_bytes()),
+                ]),
+            ),
+            der(
+                TAG_SET,
+                &seq(&[
+                    der(TAG_OID, OID_ORGANIZATION),
+                    der(TAG_PRINTABLE_STRING, organization.as_bytes()),
+                ]),
+            ),
+        ])
+    }
+
+    fn certificate(serial: &[u8], issuer: &[u8], subject: &[u8]) -> Vec<u8> {
+        seq(&[
+            seq(&[
+                der(TAG_CONTEXT_0, &der(TAG_INTEGER, &[2])),
+                der(TAG_INTEGER, serial),
+                seq(&[der(TAG_OID, SHA256_OID)]),
+                issuer.to_vec(),
+                seq(&[]),
+                subject.to_vec(),
+                seq(&[]),
+            ]),
+            seq(&[der(TAG_OID, SHA256_OID)]),
+            der(0x03, &[0]),
+        ])
+    }
+
+    /// Builds a PKCS #7 `SignedData` structure around `content`, signed by the holder
+    /// of the certificate with serial number `signer` among an intermediate and a leaf
+    /// certificate.
+    fn signed(content: Vec<u8>, signer: &[u8]) -> Vec<u8> {
+        let root = name("Root Authority", "Microsoft Corporation");
+        let certificates = [
+            certificate(
+                &[1],
+                &root,
+                &name("Code Signing PCA", "Microsoft Corporation"),
+            ),
+            certificate(
+                &[7, 7],
+                &root,
+                &name("Microsoft Windows", "Microsoft Corporation"),
+            ),
+        ];
+        let signer_info = seq(&[
+            der(TAG_INTEGER, &[1]),
+            seq(&[root, der(TAG_INTEGER, signer)]),
+            seq(&[der(TAG_OID, SHA256_OID)]),
+        ]);
+        seq(&[
+            der(TAG_OID, SIGNED_DATA_OID),
+            der(
+                TAG_CONTEXT_0,
+                &seq(&[
+                    der(TAG_INTEGER, &[1]),
+                    der(TAG_SET, &seq(&[der(TAG_OID, SHA256_OID)])),
+                    content,
+                    der(TAG_CONTEXT_0, &certificates.concat()),
+                    der(TAG_SET, &signer_info),
+                ]),
+            ),
+        ])
+    }
+
+    fn spc_content() -> Vec<u8> {
+        seq(&[
+            der(TAG_OID, &[0x2b, 0x06, 0x01]),
+            der(TAG_CONTEXT_0, &seq(&[])),
+        ])
+    }
+
+    /// Builds a catalog listing the files with the given digests, the first by the
+    /// UTF-16 hex subject identifier and the others in attributes.
+    fn catalog(digests: &[&[u8]], signer: &[u8]) -> Vec<u8> {
+        let id: Vec<u8> = hex::encode_upper(digests[0])
+            .encode_utf16()
+            .chain([0])
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let attributes: Vec<Vec<u8>> = digests[1..]
+            .iter()
+            .map(|digest| {
+                seq(&[
+                    der(TAG_OID, &[0x2b, 0x06, 0x02]),
+                    der(TAG_SET, &der(TAG_OCTET_STRING, digest)),
+                ])
+            })
+            .collect();
+        let subjects = seq(&[seq(&[
+            der(TAG_OCTET_STRING, &id),
+            der(TAG_SET, &attributes.concat()),
+        ])]);
+        let list = seq(&[
+            seq(&[der(TAG_OID, &[0x2b, 0x06, 0x03])]),
+            der(TAG_OCTET_STRING, &[1; 16]),
+            der(0x17, b"250101000000Z"),
+            seq(&[der(TAG_OID, &[0x2b, 0x06, 0x04])]),
+            subjects,
+        ]);
+        let content = seq(&[der(TAG_OID, &[0x2b, 0x06, 0x05]), der(TAG_CONTEXT_0, &list)]);
+        signed(content, signer)
+    }
+
+    /// Builds a PE32+ file of 0x200 bytes followed by a certificate table holding
+    /// `signature`, if given.
+    fn pe(signature: Option<&[u8]>) -> Vec<u8> {
+        let mut data: Vec<u8> = (0..0x200).map(|index| (index % 251) as u8).collect();
+        data[..0x40].fill(0);
+        data[..2].copy_from_slice(b"MZ");
+        data[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        data[0x40..0x44].copy_from_slice(b"PE\0\0");
+        data[0x58..0x5a].copy_from_slice(&0x20bu16.to_le_bytes());
+        data[0x58 + 108..0x58 + 112].copy_from_slice(&16u32.to_le_bytes());
+        data[SECURITY_ENTRY..SECURITY_ENTRY + 8].fill(0);
+        if let Some(signature) = signature {
+            let mut entry = ((WIN_CERTIFICATE_HEADER_LEN + signature.len()) as u32)
+                .to_le_bytes()
+                .to_vec();
+            entry.extend(0x200u16.to_le_bytes());
+            entry.extend(WIN_CERT_TYPE_PKCS_SIGNED_DATA.to_le_bytes());
+            entry.extend(signature);
+            entry.resize(entry.len().next_multiple_of(8), 0);
+            data[SECURITY_ENTRY..SECURITY_ENTRY + 4].copy_from_slice(&0x200u32.to_le_bytes());
+            data[SECURITY_ENTRY + 4..SECURITY_ENTRY + 8]
+                .copy_from_slice(&(entry.len() as u32).to_le_bytes());
+            data.extend(entry);
+        }
+        data
+    }
+
+    #[test]
+    fn reads_embedded_signers() {
+        let data = pe(Some(&signed(spc_content(), &[7, 7])));
+        assert_eq!(
+            embedded_signer(&data).as_deref(),
+            Some("CN=Microsoft Windows, O=Microsoft Corporation")
+        );
+        let data = pe(Some(&signed(spc_content(), &[1])));
+        assert_eq!(
+            embedded_signer(&data).as_deref(),
+            Some("CN=Code Signing PCA, O=Microsoft Corporation")
+        );
+    }
+
+    #[test]
+    fn rejects_unsigned_and_corrupt_signatures() {
+        assert_eq!(embedded_signer(&pe(None)), None);
+        assert_eq!(
+            embedded_signer(&pe(Some(&signed(spc_content(), &[9])))),
+            None
+        );
+        let signature = signed(spc_content(), &[7, 7]);
+        assert_eq!(
+            embedded_signer(&pe(Some(&signature[..signature.len() / 2]))),
+            None
+        );
+        let mut data = pe(Some(&signature));
+        data.truncate(data.len() - 8);
+        assert_eq!(embedded_signer(&data), None);
+        let mut data = pe(Some(&signature));
+        data[0x200..0x204].copy_from_slice(&4u32.to_le_bytes());
+        assert_eq!(embedded_signer(&data), None);
+        let mut data = pe(Some(&signature));
+        data[0x200..0x204].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(embedded_signer(&data), None);
+        assert_eq!(embedded_signer(&data[..0x50]), None);
+        assert_eq!(embedded_signer(b"MZ"), None);
+    }
+
+    #[test]
+    fn image_digests_skip_the_signature() {
+        let unsigned = pe(None);
+        let digests = image_digests(&unsigned).unwrap();
+        assert_eq!(digests.0.len(), 20);
+        assert_eq!(digests.1.len(), 32);
+        let mut data = unsigned.clone();
+        data[CHECKSUM] ^= 0xff;
+        assert_eq!(image_digests(&data).unwrap(), digests);
+        assert_eq!(
+            image_digests(&pe(Some(&signed(spc_content(), &[7, 7])))).unwrap(),
+            digests
+        );
+        let mut data = unsigned.clone();
+        data[0x150] ^= 0xff;
+        assert_ne!(image_digests(&data).unwrap(), digests);
+        assert_eq!(image_digests(&unsigned[..SECURITY_ENTRY + 4]), None);
+    }
+
+    #[test]
+    fn looks_up_files_in_catalogs() {
+        let file = pe(None);
+        let other = pe(Some(&signed(spc_content(), &[1])));
+        let mut other = other[..0x200].to_vec();
+        other[0x100] ^= 0xff;
+        let (sha1, _) = image_digests(&file).unwrap();
+        let (_, sha256) = image_digests(&other).unwrap();
+        let mut index = CatalogIndex::default();
+        assert!(index.add_catalog(&catalog(&[&sha1, &sha256], &[7, 7])));
+        assert_eq!(index.len(), 2);
+        let signer = Some("CN=Microsoft Windows, O=Microsoft Corporation");
+        assert_eq!(index.signer(&file), signer);
+        assert_eq!(index.signer(&other), signer);
+        let mut unknown = file.clone();
+        unknown[0x180] ^= 0xff;
+        assert_eq!(index.signer(&unknown), None);
+    }
+
+    #[test]
+    fn rejects_unsigned_and_corrupt_catalogs() {
+        let digest = [0xab; 32];
+        let mut index = CatalogIndex::default();
+        assert!(!index.add_catalog(&catalog(&[&digest], &[9])));
+        let data = catalog(&[&digest], &[7, 7]);
+        assert!(!index.add_catalog(&data[..data.len() / 2]));
+        assert!(!index.add_catalog(&[]));
+        assert!(index.is_empty());
+        assert_eq!(index.signer(&pe(None)), None);
+    }
+
+    #[test]
+    fn read_der_rejects_malformed_elements() {
+        let long = der(TAG_OCTET_STRING, &[1; 300]);
+        let (element, rest) = read_der(&long).unwrap();
+        assert_eq!(
+            (element.tag, element.contents.len(), rest.len()),
+            (TAG_OCTET_STRING, 300, 0)
+        );
+        assert!(read_der(&long[..100]).is_none());
+        assert!(read_der(&[TAG_SEQUENCE, 0x80, 0, 0]).is_none());
+        assert!(read_der(&[0x1f, 0x01, 0]).is_none());
+        assert!(read_der(&[TAG_SEQUENCE, 0x85, 1, 1, 1, 1, 1]).is_none());
+        assert!(read_der(&[TAG_SEQUENCE, 0x84, 0xff, 0xff, 0xff, 0xff]).is_none());
+        assert!(read_der(&[TAG_SEQUENCE]).is_none());
+    }
+
+    #[test]
+    fn decodes_certificate_strings() {
+        let bmp: Vec<u8> = "Micr\u{f8}soft"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        let decode = |tag, contents: &[u8]| {
+            let data = der(tag, contents);
+            der_string(&read_der(&data).unwrap().0)
+        };
+        assert_eq!(
+            decode(TAG_BMP_STRING, &bmp).as_deref(),
+            Some("Micr\u{f8}soft")
+        );
+        assert_eq!(
+            decode(TAG_TELETEX_STRING, b"Micr\xf8soft").as_deref(),
+            Some("Micr\u{f8}soft")
+        );
+        assert_eq!(decode(TAG_INTEGER, b"1"), None);
+        assert_eq!(utf16_hex(&[0x41, 0]), None);
+    }
+}
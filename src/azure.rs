@@ -0,0 +1,222 @@
+//! A symbol store in an Azure Blob Storage container.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use azure_storage::{ConnectionString, StorageCredentials};
+use azure_storage_blobs::{
+    blob::{BlobBlockType, BlockList},
+    prelude::{BlobClient, BlockId, ClientBuilder, ContainerClient},
+};
+use tokio::io::AsyncReadExt;
+
+use crate::{
+    error::{Error, Result},
+    push::PushTarget,
+    store::{LocalStore, SymbolStore},
+    windows::PdbMeta,
+};
+
+/// Size of the blocks large PDBs are uploaded in.
+const BLOCK_SIZE: usize = 8 * 1024 * 1024;
+
+/// A symbol store in an Azure Blob Storage container, using the flat symstore layout
+/// `<prefix>/<name>/<GUID><age>/<name>` for blob names.
+///
+/// Credentials are taken from `AZURE_STORAGE_CONNECTION_STRING`,
+/// `AZURE_STORAGE_SAS_TOKEN` or `AZURE_STORAGE_KEY`, in that order, and otherwise from
+/// the Azure identity environment (service principal variables or a managed identity).
+/// Downloads are staged in a local store before they are uploaded.
+pub struct AzureStore {
+    container: ContainerClient,
+    account: String,
+    container_name: String,
+    prefix: String,
+    staging: LocalStore,
+}
+
+impl AzureStore {
+    /// Connects to the container given as `az://account/container/prefix`, staging
+    /// downloads in `staging`.
+    pub fn new(url: &str, staging: LocalStore) -> Result<Self> {
+        let invalid = |message: &str| Error::RemoteStore {
+            location: url.to_string(),
+            message: message.to_string(),
+        };
+        let (account, container_name, prefix) =
+            parse_url(url).ok_or_else(|| invalid("expected az://account/container/prefix"))?;
+        let credentials = credentials(&account).map_err(|e| invalid(&e.to_string()))?;
+        tracing::info!("Using Azure Blob symbol store: {}", url);
+        Ok(Self {
+            container: ClientBuilder::new(&account, credentials).container_client(&container_name),
+            account,
+            container_name,
+            prefix,
+            staging,
+        })
+    }
+
+    /// Returns the blob name of the PDB.
+    pub fn blob_name(&self, pdb: &PdbMeta) -> String {
+        self.prefixed(&format!("{}/{}/{}", pdb.name, pdb.key(), pdb.name))
+    }
+
+    /// Returns the blob name of `relative`, a path below the prefix.
+    fn prefixed(&self, relative: &str) -> String {
+        if self.prefix.is_empty() {
+            relative.to_string()
+        } else {
+            format!("{}/{}", self.prefix, relative)
+        }
+    }
+
+    /// Returns the size of the PDB's blob, or `None` if it does not exist.
+    pub async fn exists(&self, pdb: &PdbMeta) -> Result<Option<u64>> {
+        let blob = self.blob(pdb);
+        if !blob.exists().await.map_err(|e| self.error(pdb, e))? {
+            return Ok(None);
+        }
+        let properties = blob
+            .get_properties()
+            .await
+            .map_err(|e| self.error(pdb, e))?;
+        Ok(Some(properties.blob.properties.content_length))
+    }
+
+    /// Downloads the PDB's blob, or returns `None` if it does not exist.
+    pub async fn get(&self, pdb: &PdbMeta) -> Result<Option<Vec<u8>>> {
+        let blob = self.blob(pdb);
+        if !blob.exists().await.map_err(|e| self.error(pdb, e))? {
+            return Ok(None);
+        }
+        let content = blob.get_content().await.map_err(|e| self.error(pdb, e))?;
+        Ok(Some(content))
+    }
+
+    /// Uploads the file at `path` as the PDB's blob, in blocks so that large PDBs are
+    /// never held in memory as a whole.
+    pub async fn put(&self, pdb: &PdbMeta, path: &Path) -> Result<()> {
+        self.put_blob(&self.blob_name(pdb), path).await
+    }
+
+    /// Uploads the file at `path` as the blob `name`, in blocks.
+    async fn put_blob(&self, name: &str, path: &Path) -> Result<()> {
+        let blob = self.container.blob_client(name);
+        let error = |e: azure_core::Error| Error::RemoteStore {
+            location: format!("az://{}/{}/{}", self.account, self.container_name, name),
+            message: e.to_string(),
+        };
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .map_err(Error::store(path))?;
+        let mut blocks = Vec::new();
+        loop {
+            let mut chunk = Vec::with_capacity(BLOCK_SIZE);
+            (&mut file)
+                .take(BLOCK_SIZE as u64)
+                .read_to_end(&mut chunk)
+                .await
+                .map_err(Error::store(path))?;
+            if chunk.is_empty() && !blocks.is_empty() {
+                break;
+            }
+            let id = BlockId::new(format!("{:08}", blocks.len()));
+            let last = chunk.len() < BLOCK_SIZE;
+            blob.put_block(id.clone(), chunk).await.map_err(error)?;
+            blocks.push(BlobBlockType::new_uncommitted(id));
+            if last {
+                break;
+            }
+        }
+        blob.put_block_list(BlockList { blocks })
+            .await
+            .map_err(error)?;
+        Ok(())
+    }
+
+    fn blob(&self, pdb: &PdbMeta) -> BlobClient {
+        self.container.blob_client(self.blob_name(pdb))
+    }
+
+    fn error(&self, pdb: &PdbMeta, error: azure_core::Error) -> Error {
+        Error::RemoteStore {
+            location: self.location(pdb).display().to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SymbolStore for AzureStore {
+    fn location(&self, pdb: &PdbMeta) -> PathBuf {
+        PathBuf::from(format!(
+            "az://{}/{}/{}",
+            self.account,
+            self.container_name,
+            self.blob_name(pdb)
+        ))
+    }
+
+    async fn stored_size(&self, pdb: &PdbMeta) -> Result<Option<u64>> {
+        self.exists(pdb).await
+    }
+
+    async fn prepare(&self, pdb: &PdbMeta) -> Result<PathBuf> {
+        self.staging.prepare(pdb).await
+    }
+
+    /// Uploads the staged download and removes it from the staging store.
+    async fn persist(&self, pdb: &PdbMeta) -> Result<PathBuf> {
+        let part = self.staging.part_path(pdb);
+        self.put(pdb, &part).await?;
+        tokio::fs::remove_file(&part)
+            .await
+            .map_err(Error::store(&part))?;
+        Ok(self.location(pdb))
+    }
+}
+
+#[async_trait::async_trait]
+impl PushTarget for AzureStore {
+    fn describe(&self) -> String {
+        format!(
+            "az://{}/{}/{}",
+            self.account, self.container_name, self.prefix
+        )
+    }
+
+    async fn upload(&self, relative: &str, path: &Path) -> Result<()> {
+        self.put_blob(&self.prefixed(relative), path).await
+    }
+}
+
+/// Reads the storage credentials for `account` from the environment.
+fn credentials(account: &str) -> azure_core::Result<StorageCredentials> {
+    if let Ok(connection_string) = std::env::var("AZURE_STORAGE_CONNECTION_STRING") {
+        return ConnectionString::new(&connection_string)?.storage_credentials();
+    }
+    if let Ok(sas_token) = std::env::var("AZURE_STORAGE_SAS_TOKEN") {
+        return StorageCredentials::sas_token(sas_token);
+    }
+    if let Ok(key) = std::env::var("AZURE_STORAGE_KEY") {
+        return Ok(StorageCredentials::access_key(account.to_string(), key));
+    }
+    let identity = azure_identity::create_default_credential()?;
+    Ok(StorageCredentials::token_credential(Arc::clone(&identity)))
+}
+
+/// Splits `az://account/container/prefix` into its parts, the prefix without slashes.
+fn parse_url(url: &str) -> Option<(String, String, String)> {
+    let rest = url.strip_prefix("az://")?;
+    let mut parts = rest.splitn(3, '/');
+    let account = parts.next().filter(|account| !account.is_empty())?;
+    let container = parts.next().filter(|container| !container.is_empty())?;
+    let prefix = parts.next().unwrap_or("").trim_matches('/');
+    Some((
+        account.to_string(),
+        container.to_string(),
+        prefix.to_string(),
+    ))
+}
@@ -0,0 +1,234 @@
+//! Breakpad text symbols (`.sym` files) converted from PDBs, for crash reporting
+//! backends such as Socorro and Sentry.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use msvc_demangler::DemangleFlags;
+use pdb::{FallibleIterator, MachineType, PDB, SymbolData};
+
+use crate::{
+    compress::ExpandedFile,
+    error::{Error, Result},
+    windows::PdbMeta,
+};
+
+/// A module's symbols in the form Breakpad writes them.
+struct Module {
+    /// Breakpad CPU name, e.g. `x86_64`.
+    arch: &'static str,
+    /// Debug ID: the GUID and the age in hex.
+    id: String,
+    files: Vec<String>,
+    functions: Vec<Function>,
+    /// Addresses and names of public symbols not covered by a function.
+    publics: Vec<(u32, String)>,
+}
+
+struct Function {
+    rva: u32,
+    len: u32,
+    name: String,
+    lines: Vec<Line>,
+}
+
+struct Line {
+    rva: u32,
+    len: u32,
+    line: u32,
+    /// Index into [`Module::files`].
+    file: usize,
+}
+
+/// Converts the PDB at `store_path`, or its compressed variant, into Breakpad symbols
+/// and writes them to `name/ID/name.sym` in `symbols`, where Breakpad tools and symbol
+/// servers look for them. Returns the path of the `.sym` file.
+///
+/// The file has `MODULE`, `FILE`, `FUNC` with line records, and `PUBLIC` records;
+/// stack unwinding (`STACK WIN`) records are not written, so unwinders fall back to
+/// scanning the stack for frames without frame pointers.
+pub fn write_symbol_file(symbols: &Path, pdb: &PdbMeta, store_path: &Path) -> Result<PathBuf> {
+    let file = ExpandedFile::open(store_path)?;
+    let module = read_module(file.path())?;
+    let stem = pdb
+        .name
+        .rsplit_once('.')
+        .map_or(pdb.name.as_str(), |(stem, _)| stem);
+    let dest = symbols
+        .join(&pdb.name)
+        .join(&module.id)
+        .join(format!("{}.sym", stem));
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let part = dest.with_extension("sym.part");
+    let mut out = BufWriter::new(File::create(&part)?);
+    write_module(&mut out, &module, &pdb.name)?;
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&part, &dest)?;
+    Ok(dest)
+}
+
+/// Reads the functions with their line numbers and the public symbols of a PDB.
+fn read_module(path: &Path) -> Result<Module> {
+    let invalid = |e: pdb::Error| Error::Pdb {
+        path: path.to_path_buf(),
+        reason: e.to_string(),
+    };
+    let mut pdb = PDB::open(File::open(path)?).map_err(invalid)?;
+    let info = pdb.pdb_information().map_err(invalid)?;
+    let dbi = pdb.debug_information().map_err(invalid)?;
+    let age = dbi.age().unwrap_or(info.age);
+    let arch = match dbi.machine_type().map_err(invalid)? {
+        MachineType::X86 => "x86",
+        MachineType::Amd64 => "x86_64",
+        MachineType::Arm64 => "arm64",
+        MachineType::Arm | MachineType::ArmNT | MachineType::Thumb => "arm",
+        _ => "unknown",
+    };
+    let address_map = pdb.address_map().map_err(invalid)?;
+    // Public PDBs are stripped of the string table that line information refers to.
+    let strings = pdb.string_table().ok();
+
+    let mut files = Vec::new();
+    let mut file_ids = HashMap::new();
+    let mut functions = Vec::new();
+    let mut modules = dbi.modules().map_err(invalid)?;
+    while let Some(module) = modules.next().map_err(invalid)? {
+        let Some(module_info) = pdb.module_info(&module).map_err(invalid)? else {
+            continue;
+        };
+        let program = module_info.line_program().ok();
+        let mut symbols = module_info.symbols().map_err(invalid)?;
+        while let Some(symbol) = symbols.next().map_err(invalid)? {
+            let Ok(SymbolData::Procedure(procedure)) = symbol.parse() else {
+                continue;
+            };
+            let Some(rva) = procedure.offset.to_rva(&address_map) else {
+                continue;
+            };
+            let mut function = Function {
+                rva: rva.0,
+                len: procedure.len,
+                name: procedure.name.to_string().into_owned(),
+                lines: Vec::new(),
+            };
+            if let (Some(program), Some(strings)) = (&program, &strings) {
+                let mut lines = program.lines_for_symbol(procedure.offset);
+                while let Some(line) = lines.next().map_err(invalid)? {
+                    let Some(line_rva) = line.offset.to_rva(&address_map) else {
+                        continue;
+                    };
+                    let file = program.get_file_info(line.file_index).map_err(invalid)?;
+                    let name = file.name.to_string_lossy(strings).map_err(invalid)?;
+                    let file = *file_ids.entry(name.to_string()).or_insert_with(|| {
+                        files.push(name.to_string());
+                        files.len() - 1
+                    });
+                    function.lines.push(Line {
+                        rva: line_rva.0,
+                        len: line.length.unwrap_or(0),
+                        line: line.line_start,
+                        file,
+                    });
+                }
+            }
+            fill_line_lengths(&mut function);
+            functions.push(function);
+        }
+    }
+    functions.sort_by_key(|function| function.rva);
+    functions.dedup_by_key(|function| function.rva);
+
+    let mut publics = Vec::new();
+    let globals = pdb.global_symbols().map_err(invalid)?;
+    let mut symbols = globals.iter();
+    while let Some(symbol) = symbols.next().map_err(invalid)? {
+        let Ok(SymbolData::Public(public)) = symbol.parse() else {
+            continue;
+        };
+        if !(public.function || public.code) {
+            continue;
+        }
+        if let Some(rva) = public.offset.to_rva(&address_map) {
+            publics.push((rva.0, undecorate(&public.name.to_string(), arch)));
+        }
+    }
+    publics.sort();
+    publics.dedup_by_key(|(rva, _)| *rva);
+    publics.retain(|(rva, _)| {
+        functions
+            .binary_search_by_key(rva, |function| function.rva)
+            .is_err()
+    });
+
+    Ok(Module {
+        arch,
+        id: format!("{:X}{:X}", info.guid.simple(), age),
+        files,
+        functions,
+        publics,
+    })
+}
+
+/// Sorts the line records of a function and gives the ones without a length the
+/// distance to the next record, or to the end of the function.
+fn fill_line_lengths(function: &mut Function) {
+    function.lines.sort_by_key(|line| line.rva);
+    let end = function.rva.saturating_add(function.len);
+    let starts: Vec<u32> = function.lines.iter().map(|line| line.rva).collect();
+    for (index, line) in function.lines.iter_mut().enumerate() {
+        if line.len == 0 {
+            let next = starts.get(index + 1).copied().unwrap_or(end);
+            line.len = next.saturating_sub(line.rva);
+        }
+    }
+}
+
+/// Returns the readable name of a public symbol: C++ names are demangled, and the
+/// `_name@8` decorations of 32-bit x86 C functions are stripped.
+fn undecorate(name: &str, arch: &str) -> String {
+    if name.starts_with('?') {
+        return msvc_demangler::demangle(name, DemangleFlags::llvm())
+            .unwrap_or_else(|_| name.to_string());
+    }
+    if arch != "x86" {
+        return name.to_string();
+    }
+    let name = name.strip_prefix(['_', '@']).unwrap_or(name);
+    match name.rsplit_once('@') {
+        Some((base, size)) if !size.is_empty() && size.bytes().all(|b| b.is_ascii_digit()) => {
+            base.to_string()
+        }
+        _ => name.to_string(),
+    }
+}
+
+fn write_module(out: &mut impl Write, module: &Module, name: &str) -> io::Result<()> {
+    writeln!(out, "MODULE windows {} {} {}", module.arch, module.id, name)?;
+    for (id, file) in module.files.iter().enumerate() {
+        writeln!(out, "FILE {} {}", id, file)?;
+    }
+    for function in &module.functions {
+        writeln!(
+            out,
+            "FUNC {:x} {:x} 0 {}",
+            function.rva, function.len, function.name
+        )?;
+        for line in &function.lines {
+            writeln!(
+                out,
+                "{:x} {:x} {} {}",
+                line.rva, line.len, line.line, line.file
+            )?;
+        }
+    }
+    for (rva, name) in &module.publics {
+        writeln!(out, "PUBLIC {:x} 0 {}", rva, name)?;
+    }
+    Ok(())
+}
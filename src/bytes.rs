@@ -0,0 +1,64 @@
+//! Bounds-checked reads of integers from byte buffers, shared by the binary and
+//! image parsers. Each returns `None` if the value does not fit in the buffer.
+
+use std::io;
+
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    read(data, offset).map(u16::from_le_bytes)
+}
+
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    read(data, offset).map(u32::from_le_bytes)
+}
+
+pub(crate) fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    read(data, offset).map(u64::from_le_bytes)
+}
+
+pub(crate) fn read_be_u16(data: &[u8], offset: usize) -> Option<u16> {
+    read(data, offset).map(u16::from_be_bytes)
+}
+
+pub(crate) fn read_be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    read(data, offset).map(u32::from_be_bytes)
+}
+
+pub(crate) fn read_be_u64(data: &[u8], offset: usize) -> Option<u64> {
+    read(data, offset).map(u64::from_be_bytes)
+}
+
+/// Error for a structure too short for the fields read from it, e.g. `truncated("debug
+/// directory entry")`.
+pub(crate) fn truncated(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("truncated {}", what))
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> Option<[u8; N]> {
+    data.get(offset..offset.checked_add(N)?)?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_within_bounds() {
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+        assert_eq!(read_u16(&data, 7), Some(0x0908));
+        assert_eq!(read_u32(&data, 0), Some(0x04030201));
+        assert_eq!(read_u64(&data, 1), Some(0x0908070605040302));
+        assert_eq!(read_be_u16(&data, 7), Some(0x0809));
+        assert_eq!(read_be_u32(&data, 0), Some(0x01020304));
+        assert_eq!(read_be_u64(&data, 0), Some(0x0102030405060708));
+    }
+
+    #[test]
+    fn rejects_reads_past_the_end() {
+        let data = [0; 8];
+        assert_eq!(read_u16(&data, 7), None);
+        assert_eq!(read_u32(&data, 5), None);
+        assert_eq!(read_u64(&data, 1), None);
+        assert_eq!(read_be_u32(&data, usize::MAX), None);
+        assert_eq!(read_be_u64(&[], 0), None);
+    }
+}
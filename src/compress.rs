@@ -0,0 +1,144 @@
+//! Keeping the files of a local symbol store compressed on disk.
+
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::download::{compressed_name, expand_cab};
+
+/// zstd level for stored files: a good ratio at a speed that keeps up with downloads.
+const ZSTD_LEVEL: i32 = 9;
+
+/// How files are compressed in a store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StoreCompression {
+    /// Files are stored as they are.
+    #[default]
+    None,
+    /// Files are stored as MSZIP cabinets named like `ntdll.pd_`, as written by
+    /// `symstore /compress` and understood by debuggers and symbol servers.
+    Cab,
+    /// Files are stored zstd-compressed as `ntdll.pdb.zst`, which is smaller and
+    /// faster but only understood by symbolfetcher.
+    Zstd,
+}
+
+impl std::str::FromStr for StoreCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "cab" => Ok(Self::Cab),
+            "zstd" => Ok(Self::Zstd),
+            _ => Err(format!(
+                "unknown store compression {:?}, expected none, cab or zstd",
+                s
+            )),
+        }
+    }
+}
+
+impl StoreCompression {
+    /// Returns the name of the compressed variant of `file`, e.g. `ntdll.pd_` or
+    /// `ntdll.pdb.zst`, or `None` without compression.
+    pub fn compressed_name(self, file: &str) -> Option<String> {
+        match self {
+            Self::None => None,
+            Self::Cab => Some(compressed_name(file)),
+            Self::Zstd => Some(format!("{}.zst", file)),
+        }
+    }
+}
+
+/// Compresses the file at `path` to `dest` and returns the compressed size.
+pub fn compress_file(path: &Path, dest: &Path, compression: StoreCompression) -> io::Result<u64> {
+    match compression {
+        StoreCompression::None => std::fs::copy(path, dest),
+        StoreCompression::Cab => {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let mut builder = cab::CabinetBuilder::new();
+            builder
+                .add_folder(cab::CompressionType::MsZip)
+                .add_file(name);
+            let mut writer = builder.build(File::create(dest)?)?;
+            while let Some(mut entry) = writer.next_file()? {
+                io::copy(&mut File::open(path)?, &mut entry)?;
+            }
+            writer.finish()?;
+            Ok(std::fs::metadata(dest)?.len())
+        }
+        StoreCompression::Zstd => {
+            zstd::stream::copy_encode(File::open(path)?, File::create(dest)?, ZSTD_LEVEL)?;
+            Ok(std::fs::metadata(dest)?.len())
+        }
+    }
+}
+
+/// Expands the compressed file at `path` to `dest` and returns the expanded size.
+pub fn expand_file(path: &Path, compression: StoreCompression, dest: &Path) -> io::Result<u64> {
+    match compression {
+        StoreCompression::None => std::fs::copy(path, dest),
+        StoreCompression::Cab => expand_cab(path, dest),
+        StoreCompression::Zstd => {
+            let mut out = File::create(dest)?;
+            zstd::stream::copy_decode(File::open(path)?, &mut out)?;
+            Ok(out.metadata()?.len())
+        }
+    }
+}
+
+/// A file of the store made readable: the stored file itself, or its compressed
+/// variant expanded into a temporary file that is removed on drop.
+#[derive(Debug)]
+pub struct ExpandedFile {
+    path: PathBuf,
+    _expanded: Option<tempfile::NamedTempFile>,
+}
+
+impl ExpandedFile {
+    /// Opens the file at `store_path`, expanding its compressed variant in the same
+    /// folder if the store keeps it compressed.
+    pub fn open(store_path: &Path) -> io::Result<Self> {
+        if store_path.is_file() {
+            return Ok(Self {
+                path: store_path.to_path_buf(),
+                _expanded: None,
+            });
+        }
+        let folder = store_path.parent().unwrap_or(Path::new("."));
+        let name = store_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let (compressed, compression) = find_compressed(folder, &name)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        let expanded = tempfile::NamedTempFile::new()?;
+        expand_file(&compressed, compression, expanded.path())?;
+        Ok(Self {
+            path: expanded.path().to_path_buf(),
+            _expanded: Some(expanded),
+        })
+    }
+
+    /// Returns the path to read the file from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Finds a compressed copy of `file` in `folder`, in any of the supported formats.
+pub fn find_compressed(folder: &Path, file: &str) -> Option<(PathBuf, StoreCompression)> {
+    [StoreCompression::Cab, StoreCompression::Zstd]
+        .into_iter()
+        .filter_map(|compression| {
+            let path = folder.join(compression.compressed_name(file)?);
+            path.is_file().then_some((path, compression))
+        })
+        .next()
+}
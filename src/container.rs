@@ -0,0 +1,281 @@
+//! Walking nested containers, such as an ISO holding a WIM, down to the binaries
+//! inside.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use tracing::info;
+
+use crate::{
+    archive::{self, ArchiveKind},
+    disk::DiskImage,
+    error::Result,
+    ewf::is_ewf,
+    iso::Iso,
+    oci::{self, ContainerImage},
+    package,
+    vhd::is_virtual_disk,
+    wim::{ImageSelector, Wim},
+    windows::{FileFilter, PdbMeta, Windows, scan_with},
+};
+
+/// Extensions of files that are extracted from containers even if the filter does not
+/// accept them, to descend into them.
+const CONTAINER_EXTENSIONS: &[&str] = &[
+    "iso", "wim", "esd", "cab", "msu", "zip", "tar", "tgz", "vhd", "vhdx", "e01",
+];
+/// Deepest nesting of containers walked.
+const MAX_NESTING: usize = 8;
+
+/// The kinds of containers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerKind {
+    /// A Windows installation ISO.
+    Iso,
+    /// A WIM or ESD file.
+    Wim,
+    /// An MSU or CAB update package.
+    Package,
+    /// A ZIP or tar archive.
+    Archive,
+    /// A disk, virtual disk or NTFS volume image.
+    Disk,
+    /// A Windows container image, as a `docker save` tarball or OCI image layout.
+    Image,
+}
+
+/// How containers are walked.
+#[derive(Debug, Clone)]
+pub struct ContainerOptions {
+    /// The image to extract from ISO and WIM files.
+    pub image: ImageSelector,
+    /// How deep to scan the Windows installations found, see
+    /// [`Windows::with_max_depth`].
+    pub max_depth: Option<usize>,
+    /// The files to extract and scan.
+    pub filter: FileFilter,
+}
+
+/// What scanning a container with [`scan`] yields.
+#[derive(Debug, Default)]
+pub struct Scanned {
+    /// The PDB keys found, with paths relative to the container.
+    pub pdbs: Vec<PdbMeta>,
+    /// Number of binaries scanned, including those without a PDB reference.
+    pub files: usize,
+}
+
+/// What unpacking a container yields.
+#[derive(Debug, Default)]
+pub struct Unpacked {
+    /// Extracted files: binaries, or nested containers to descend into.
+    pub files: Vec<PathBuf>,
+    /// Extracted Windows directories, scanned like a Windows installation.
+    pub installations: Vec<PathBuf>,
+}
+
+/// A file holding other files, unpacked one level at a time by [`fetch_pdbs`].
+pub trait Container {
+    /// Extracts the files that the filter accepts, and any nested containers, into
+    /// `dest`.
+    fn unpack(&mut self, dest: &Path, options: &ContainerOptions) -> Result<Unpacked>;
+}
+
+impl Container for Iso {
+    fn unpack(&mut self, dest: &Path, _options: &ContainerOptions) -> Result<Unpacked> {
+        Ok(Unpacked {
+            files: vec![self.extract_install_image(dest)?],
+            installations: Vec::new(),
+        })
+    }
+}
+
+impl Container for Wim {
+    fn unpack(&mut self, dest: &Path, options: &ContainerOptions) -> Result<Unpacked> {
+        Ok(Unpacked {
+            files: Vec::new(),
+            installations: vec![self.extract_system_directories(
+                &options.image,
+                dest,
+                options.max_depth,
+                &options.filter,
+            )?],
+        })
+    }
+}
+
+impl Container for DiskImage {
+    fn unpack(&mut self, dest: &Path, options: &ContainerOptions) -> Result<Unpacked> {
+        Ok(Unpacked {
+            files: Vec::new(),
+            installations: vec![self.extract_system_directories(dest, &options.filter)?],
+        })
+    }
+}
+
+/// An MSU or CAB update package.
+struct Package(PathBuf);
+
+impl Container for Package {
+    fn unpack(&mut self, dest: &Path, options: &ContainerOptions) -> Result<Unpacked> {
+        Ok(Unpacked {
+            files: package::extract_package(&self.0, dest, &options.filter)?,
+            installations: Vec::new(),
+        })
+    }
+}
+
+/// A ZIP or tar archive.
+struct Archive(PathBuf);
+
+impl Container for Archive {
+    fn unpack(&mut self, dest: &Path, options: &ContainerOptions) -> Result<Unpacked> {
+        Ok(Unpacked {
+            files: archive::extract_archive(&self.0, dest, &options.filter)?,
+            installations: Vec::new(),
+        })
+    }
+}
+
+impl ContainerKind {
+    /// Detects the kind of container by the file's extension or signature, or returns
+    /// `None` if it is not one. Directories are only containers if they hold an OCI
+    /// image layout.
+    pub fn detect(path: &Path) -> Option<Self> {
+        if path.is_dir() && oci::is_image(path) {
+            return Some(Self::Image);
+        }
+        if !path.is_file() {
+            return None;
+        }
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase)
+            .unwrap_or_default();
+        match extension.as_str() {
+            "iso" => Some(Self::Iso),
+            "wim" | "esd" => Some(Self::Wim),
+            "dd" | "img" | "vhd" | "vhdx" | "e01" | "ex01" => Some(Self::Disk),
+            _ if package::is_package(path) => Some(Self::Package),
+            _ if oci::is_image(path) => Some(Self::Image),
+            _ if ArchiveKind::detect(path).is_some() => Some(Self::Archive),
+            _ if is_virtual_disk(path) || is_ewf(path) => Some(Self::Disk),
+            _ => None,
+        }
+    }
+
+    /// Opens the file at `path` as this kind of container.
+    pub fn open(self, path: &Path) -> Result<Box<dyn Container>> {
+        Ok(match self {
+            Self::Iso => Box::new(Iso::new(path.to_path_buf())?),
+            Self::Wim => Box::new(Wim::open(path)?),
+            Self::Package => Box::new(Package(path.to_path_buf())),
+            Self::Archive => Box::new(Archive(path.to_path_buf())),
+            Self::Disk => Box::new(DiskImage::open(path)?),
+            Self::Image => Box::new(ContainerImage::new(path.to_path_buf())),
+        })
+    }
+}
+
+/// Unpacks the container at `path` of the given kind, descends into all containers
+/// nested in it, and scans the binaries found for PDB references, adding their image
+/// keys if the filter asks for them.
+///
+/// Each nested container is unpacked into a directory next to it and deleted once
+/// walked, so an ISO only needs scratch space for its `install.wim` and the files
+/// extracted from it. The temporary directory is removed afterwards, and the paths of
+/// the returned PDBs are relative to the container.
+pub fn fetch_pdbs(
+    path: &Path,
+    kind: ContainerKind,
+    options: &ContainerOptions,
+) -> Result<Vec<PdbMeta>> {
+    scan(path, kind, options).map(|scanned| scanned.pdbs)
+}
+
+/// Like [`fetch_pdbs`], but also returns how many binaries were scanned.
+pub fn scan(path: &Path, kind: ContainerKind, options: &ContainerOptions) -> Result<Scanned> {
+    let scratch = tempfile::tempdir()?;
+    let root = scratch.path();
+    let mut files = Vec::new();
+    walk(path, kind, root, options, 0, &mut files)?;
+    info!("Found {} files in {}", files.len(), path.display());
+    let mut pdbs = scan_with(&files, &options.filter);
+    for pdb in &mut pdbs {
+        if let Ok(relative) = pdb.path.strip_prefix(root) {
+            pdb.path = relative.to_path_buf();
+        }
+    }
+    Ok(Scanned {
+        pdbs,
+        files: files.len(),
+    })
+}
+
+/// Returns whether the name looks like a container worth extracting from another
+/// container even if the filter does not accept it.
+pub(crate) fn has_container_extension(path: &Path) -> bool {
+    archive::is_tar_name(path)
+        || path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                CONTAINER_EXTENSIONS
+                    .iter()
+                    .any(|container| container.eq_ignore_ascii_case(ext))
+            })
+}
+
+/// Unpacks the container at `path` into `dest`, collecting the files the filter accepts
+/// and descending into nested containers, each unpacked into a `.d` directory next to
+/// it.
+fn walk(
+    path: &Path,
+    kind: ContainerKind,
+    dest: &Path,
+    options: &ContainerOptions,
+    depth: usize,
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    tracing::debug!("Unpacking {:?} {}", kind, path.display());
+    fs::create_dir_all(dest)?;
+    let unpacked = kind.open(path)?.unpack(dest, options)?;
+    for installation in unpacked.installations {
+        files.extend(
+            Windows::new(installation)
+                .with_max_depth(options.max_depth)
+                .with_filter(options.filter.clone())
+                .list_files()?,
+        );
+    }
+    for file in unpacked.files {
+        let Some(nested) = ContainerKind::detect(&file) else {
+            if options.filter.accepts_name(&file) {
+                files.push(file);
+            }
+            continue;
+        };
+        if depth == MAX_NESTING {
+            tracing::warn!("Not unpacking {}, nested too deep", file.display());
+            continue;
+        }
+        let mut nested_dest = file.clone().into_os_string();
+        nested_dest.push(".d");
+        let result = walk(
+            &file,
+            nested,
+            Path::new(&nested_dest),
+            options,
+            depth + 1,
+            files,
+        );
+        fs::remove_file(&file)?;
+        if let Err(e) = result {
+            tracing::warn!("Skipping {}: {}", file.display(), e);
+        }
+    }
+    Ok(())
+}
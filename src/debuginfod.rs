@@ -0,0 +1,189 @@
+//! Downloading the debug info and executables of ELF files from debuginfod servers
+//! into the `.build-id` layout GDB and other Linux debuggers look in.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    download::fetch_url,
+    elf::ElfFile,
+    error::{Error, Result},
+    http::HttpClient,
+};
+
+/// Environment variable listing the debuginfod servers, separated by spaces.
+pub const DEBUGINFOD_URLS: &str = "DEBUGINFOD_URLS";
+
+/// The files debuginfod servers provide for a build ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Artifact {
+    /// The separate debug info file.
+    Debuginfo,
+    /// The binary itself.
+    Executable,
+}
+
+impl Artifact {
+    /// Returns the name of the artifact in debuginfod URLs.
+    pub fn name(self) -> &'static str {
+        match self {
+            Artifact::Debuginfo => "debuginfo",
+            Artifact::Executable => "executable",
+        }
+    }
+}
+
+/// Outcome counts of fetching from debuginfod servers.
+#[derive(Debug, Default)]
+pub struct DebuginfodSummary {
+    pub fetched: usize,
+    pub existing: usize,
+    /// Files none of the servers have.
+    pub missing: usize,
+    pub failed: usize,
+}
+
+/// Returns the servers listed in `DEBUGINFOD_URLS`, if set.
+pub fn servers_from_env() -> Vec<String> {
+    std::env::var(DEBUGINFOD_URLS)
+        .map(|urls| urls.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Returns the URL of `artifact` for `build_id` on `server`.
+pub fn url(server: &str, build_id: &str, artifact: Artifact) -> String {
+    format!(
+        "{}/buildid/{}/{}",
+        server.trim_end_matches('/'),
+        build_id,
+        artifact.name()
+    )
+}
+
+/// Returns where `artifact` for `build_id` is kept below `root`: the debug info at
+/// `.build-id/ab/cdef....debug` and the executable at `.build-id/ab/cdef...`, split
+/// after the first two hex digits of the build ID.
+pub fn build_id_path(root: &Path, build_id: &str, artifact: Artifact) -> PathBuf {
+    let (dir, rest) = build_id.split_at(2.min(build_id.len()));
+    let name = match artifact {
+        Artifact::Debuginfo => format!("{}.debug", rest),
+        Artifact::Executable => rest.to_string(),
+    };
+    root.join(".build-id").join(dir).join(name)
+}
+
+/// Downloads the debug info of the ELF files, and their executables if `executables`
+/// is set, from `servers`, tried in order, into the `.build-id` layout below `root`.
+/// Runs up to `jobs` requests at once; files already present are not downloaded again.
+pub async fn fetch(
+    client: &HttpClient,
+    servers: &[String],
+    files: &[ElfFile],
+    root: &Path,
+    executables: bool,
+    jobs: usize,
+) -> DebuginfodSummary {
+    let artifacts: &[Artifact] = if executables {
+        &[Artifact::Debuginfo, Artifact::Executable]
+    } else {
+        &[Artifact::Debuginfo]
+    };
+    let servers = Arc::new(servers.to_vec());
+    let mut summary = DebuginfodSummary::default();
+    let mut seen = HashSet::new();
+    let permits = Arc::new(Semaphore::new(jobs.max(1)));
+    let mut tasks = JoinSet::new();
+    for file in files {
+        if !seen.insert(file.build_id.clone()) {
+            continue;
+        }
+        for &artifact in artifacts {
+            let dest = build_id_path(root, &file.build_id, artifact);
+            if dest.is_file() {
+                summary.existing += 1;
+                continue;
+            }
+            let client = client.clone();
+            let servers = servers.clone();
+            let permits = permits.clone();
+            let file = file.clone();
+            tasks.spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                let result =
+                    fetch_artifact(&client, &servers, &file.build_id, artifact, &dest).await;
+                match &result {
+                    Ok(()) => tracing::info!(
+                        "Fetched {} of {} to {}",
+                        artifact.name(),
+                        file.path.display(),
+                        dest.display()
+                    ),
+                    Err(Error::NotFound) => tracing::info!(
+                        "No debuginfod server has the {} of {}",
+                        artifact.name(),
+                        file.path.display()
+                    ),
+                    Err(e) => tracing::warn!(
+                        "Failed to fetch the {} of {}: {}",
+                        artifact.name(),
+                        file.path.display(),
+                        e
+                    ),
+                }
+                result
+            });
+        }
+    }
+    while let Some(result) = tasks.join_next().await {
+        match result {
+            Ok(Ok(())) => summary.fetched += 1,
+            Ok(Err(Error::NotFound)) => summary.missing += 1,
+            Ok(Err(_)) => summary.failed += 1,
+            Err(e) => {
+                tracing::error!("debuginfod download task failed: {}", e);
+                summary.failed += 1;
+            }
+        }
+    }
+    summary
+}
+
+/// Downloads one artifact from the first server that has it, through a `.part` file
+/// that interrupted downloads resume from. Fails with [`Error::NotFound`] if no server
+/// has it.
+async fn fetch_artifact(
+    client: &HttpClient,
+    servers: &[String],
+    build_id: &str,
+    artifact: Artifact,
+    dest: &Path,
+) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut part = dest.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+    let mut failure = Error::NotFound;
+    for server in servers {
+        match fetch_url(client, &url(server, build_id, artifact), &part).await {
+            Ok(_) => {
+                tokio::fs::rename(&part, dest).await?;
+                return Ok(());
+            }
+            Err(e) => {
+                // A partial file from another server would corrupt the next download.
+                tokio::fs::remove_file(&part).await.ok();
+                if !matches!(e, Error::NotFound) {
+                    failure = e;
+                }
+            }
+        }
+    }
+    Err(failure)
+}
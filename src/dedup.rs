@@ -0,0 +1,126 @@
+//! Replacing identical files in a local symbol store with links to one copy.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io,
+    path::Path,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::{Error, Result},
+    store::{LocalStore, StoredFile},
+};
+
+/// How duplicates are replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DedupMode {
+    /// Hard links: all paths share one file, which must not be modified in place.
+    #[default]
+    Hardlink,
+    /// Reflinks (copy-on-write clones) on filesystems that support them, such as
+    /// Btrfs, XFS, APFS and ReFS: the paths stay independent files.
+    Reflink,
+}
+
+/// The outcome of a dedup run.
+#[derive(Debug, Default)]
+pub struct DedupSummary {
+    /// Sets of identical files found.
+    pub groups: usize,
+    /// Files replaced by a link to another copy.
+    pub linked: usize,
+    /// Bytes freed by the replaced files.
+    pub saved: u64,
+}
+
+/// Finds stored files with identical contents and, unless `dry_run` is set, replaces
+/// all but the first of each set with a link to it.
+///
+/// Only files of equal size are hashed. Files that are already hard links to the same
+/// copy are not counted again. Each file is replaced by linking to a temporary name
+/// and renaming it over the file, so readers never see it missing.
+pub fn dedup_store(store: &LocalStore, mode: DedupMode, dry_run: bool) -> Result<DedupSummary> {
+    let mut by_size: HashMap<u64, Vec<StoredFile>> = HashMap::new();
+    for file in store.stored_files()? {
+        if file.size > 0 {
+            by_size.entry(file.size).or_default().push(file);
+        }
+    }
+    let mut summary = DedupSummary::default();
+    for files in by_size.into_values().filter(|files| files.len() > 1) {
+        let mut by_hash: HashMap<[u8; 32], Vec<StoredFile>> = HashMap::new();
+        for file in files {
+            match hash_file(&file.path) {
+                Ok(hash) => by_hash.entry(hash).or_default().push(file),
+                Err(e) => tracing::warn!("Failed to hash {}: {}", file.path.display(), e),
+            }
+        }
+        for mut group in by_hash.into_values().filter(|group| group.len() > 1) {
+            group.sort_by(|a, b| a.path.cmp(&b.path));
+            summary.groups += 1;
+            let (original, duplicates) = group.split_first().expect("group is not empty");
+            for duplicate in duplicates {
+                if is_same_file(&original.path, &duplicate.path) {
+                    continue;
+                }
+                tracing::debug!(
+                    "{} is identical to {}",
+                    duplicate.path.display(),
+                    original.path.display()
+                );
+                if !dry_run {
+                    link(&original.path, &duplicate.path, mode)?;
+                }
+                summary.linked += 1;
+                summary.saved += duplicate.size;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Replaces `duplicate` with a link to `original`.
+fn link(original: &Path, duplicate: &Path, mode: DedupMode) -> Result<()> {
+    let temp = duplicate.with_extension("dedup");
+    let _ = fs::remove_file(&temp);
+    let linked = match mode {
+        DedupMode::Hardlink => fs::hard_link(original, &temp),
+        DedupMode::Reflink => reflink_copy::reflink(original, &temp),
+    };
+    linked
+        .and_then(|()| fs::rename(&temp, duplicate))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temp);
+            Error::Store {
+                path: duplicate.to_path_buf(),
+                source: e,
+            }
+        })
+}
+
+/// Returns the SHA-256 of the file at `path`.
+fn hash_file(path: &Path) -> io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+/// Returns whether both paths are hard links to the same file.
+#[cfg(unix)]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Returns whether both paths are hard links to the same file; not detected on this
+/// platform, so linking again is harmless but counted.
+#[cfg(not(unix))]
+fn is_same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
@@ -0,0 +1,500 @@
+//! Reading Windows installations from disk and NTFS volume images without mounting
+//! them.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use ::ntfs::{Ntfs, NtfsFile, indexes::NtfsFileNameIndex, structured_values::NtfsFileNamespace};
+use tracing::info;
+
+use crate::{
+    bytes::{read_u32, read_u64},
+    error::{Error, Result},
+    ewf::{EwfImage, is_ewf},
+    vhd::{VirtualDisk, is_virtual_disk},
+    windows::{FileFilter, PdbMeta, Windows},
+};
+
+/// Directories extracted from a volume before scanning, as path components below the
+/// root.
+const SYSTEM_DIRECTORIES: &[&[&str]] = &[&["Windows", "System32"], &["Windows", "SysWOW64"]];
+/// Size of the sectors partition tables are addressed in.
+const SECTOR_SIZE: u64 = 512;
+/// OEM ID in the boot sector of NTFS volumes.
+const NTFS_OEM_ID: &[u8; 8] = b"NTFS    ";
+/// MBR partition type of a protective MBR in front of a GPT.
+const MBR_TYPE_GPT: u8 = 0xee;
+/// Deepest directory level extracted, in case of directory cycles in a corrupt volume.
+const MAX_DEPTH: usize = 32;
+
+/// Anything a disk image can be read from.
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// A byte range of another reader, such as a partition of a disk.
+pub struct Slice<R> {
+    inner: R,
+    start: u64,
+    len: u64,
+    position: u64,
+}
+
+/// A raw disk image with an MBR or GPT partition table, or an image of a single NTFS
+/// volume.
+pub struct DiskImage {
+    path: PathBuf,
+    reader: Box<dyn ReadSeek>,
+}
+
+impl<R: Seek> Slice<R> {
+    /// Returns a reader of the `len` bytes of `inner` starting at `start`.
+    pub fn new(inner: R, start: u64, len: u64) -> Self {
+        Self {
+            inner,
+            start,
+            len,
+            position: 0,
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for Slice<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.position);
+        let len = (buf.len() as u64).min(remaining) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+        let start = self.start.checked_add(self.position).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "slice beyond the end of the reader",
+            )
+        })?;
+        self.inner.seek(SeekFrom::Start(start))?;
+        let read = self.inner.read(&mut buf[..len])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for Slice<R> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+        Ok(self.position)
+    }
+}
+
+impl DiskImage {
+    /// Opens the disk or volume image at `path`: a VHD or VHDX file or an E01 image,
+    /// recognized by their signatures, or a raw image.
+    pub fn open(path: &Path) -> Result<Self> {
+        info!("Opening disk image: {}", path.display());
+        let reader: Box<dyn ReadSeek> = if is_virtual_disk(path) {
+            Box::new(VirtualDisk::open(path)?)
+        } else if is_ewf(path) {
+            Box::new(EwfImage::open(path)?)
+        } else {
+            Box::new(File::open(path)?)
+        };
+        Ok(Self::from_reader(path, reader))
+    }
+
+    /// Reads a disk image from `reader`, e.g. a decoded virtual disk; `path` is only
+    /// used in messages.
+    pub fn from_reader(path: &Path, reader: Box<dyn ReadSeek>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            reader,
+        }
+    }
+
+    /// Returns the byte ranges of the NTFS volumes on the disk, as `(start, len)`.
+    ///
+    /// A volume image is a single volume. Disks are searched through the primary
+    /// partitions of an MBR, or through a GPT assuming 512-byte sectors; logical
+    /// partitions in extended partitions are not.
+    pub fn ntfs_volumes(&mut self) -> Result<Vec<(u64, u64)>> {
+        let len = self.reader.seek(SeekFrom::End(0))?;
+        if self.is_ntfs_at(0)? {
+            return Ok(vec![(0, len)]);
+        }
+        let mbr = self.read_at(0, SECTOR_SIZE as usize)?;
+        if mbr[510..512] != [0x55, 0xaa] {
+            return Ok(Vec::new());
+        }
+        let mut partitions = Vec::new();
+        for entry in mbr[0x1be..0x1fe].chunks_exact(16) {
+            let (Some(first), Some(sectors)) = (read_u32(entry, 8), read_u32(entry, 12)) else {
+                continue;
+            };
+            let start = u64::from(first) * SECTOR_SIZE;
+            let sectors = u64::from(sectors);
+            match entry[4] {
+                0 => {}
+                MBR_TYPE_GPT => partitions.extend(self.gpt_partitions()?),
+                _ => partitions.push((start, sectors * SECTOR_SIZE)),
+            }
+        }
+        let mut volumes = Vec::new();
+        for (start, len) in partitions {
+            if self.is_ntfs_at(start)? {
+                volumes.push((start, len));
+            }
+        }
+        Ok(volumes)
+    }
+
+    /// Extracts the system directories of the Windows installation on the first NTFS
+    /// volume that has one and scans them for PDB references.
+    ///
+    /// The temporary directory is removed afterwards, and the paths of the returned
+    /// PDBs are relative to the volume root.
+    pub fn fetch_pdbs(
+        &mut self,
+        max_depth: Option<usize>,
+        filter: &FileFilter,
+    ) -> Result<Vec<PdbMeta>> {
+        let scratch = tempfile::tempdir()?;
+        let root = scratch.path();
+        let windows = self.extract_system_directories(root, filter)?;
+        let mut pdbs = Windows::new(windows)
+            .with_max_depth(max_depth)
+            .with_filter(filter.clone())
+            .fetch_all_pdbs()?;
+        for pdb in &mut pdbs {
+            if let Ok(relative) = pdb.path.strip_prefix(root) {
+                pdb.path = relative.to_path_buf();
+            }
+        }
+        Ok(pdbs)
+    }
+
+    /// Extracts the files in the system directories of the Windows installation on the
+    /// first NTFS volume that has one into `root`, and returns the path of the
+    /// `Windows` directory below it.
+    ///
+    /// Only files whose names `filter` accepts are extracted. Files the NTFS reader
+    /// cannot read, such as NTFS-compressed files and files compressed by CompactOS,
+    /// are skipped with a warning.
+    pub fn extract_system_directories(
+        &mut self,
+        root: &Path,
+        filter: &FileFilter,
+    ) -> Result<PathBuf> {
+        let volumes = self.ntfs_volumes()?;
+        if volumes.is_empty() {
+            return Err(Error::MissingInImage("NTFS volume".to_string()));
+        }
+        for (start, len) in volumes {
+            let mut volume = BufReader::new(Slice::new(&mut *self.reader, start, len));
+            let ntfs = Ntfs::new(&mut volume).and_then(|mut ntfs| {
+                ntfs.read_upcase_table(&mut volume)?;
+                Ok(ntfs)
+            });
+            let ntfs = match ntfs {
+                Ok(ntfs) => ntfs,
+                Err(e) => {
+                    tracing::warn!("Skipping NTFS volume at offset {}: {}", start, e);
+                    continue;
+                }
+            };
+            let mut found = false;
+            for components in SYSTEM_DIRECTORIES {
+                let Some(directory) = find_directory(&ntfs, &mut volume, components)? else {
+                    continue;
+                };
+                found = true;
+                let dest = components
+                    .iter()
+                    .fold(root.to_path_buf(), |path, c| path.join(c));
+                info!(
+                    "Extracting {} from the NTFS volume at offset {} of {}",
+                    components.join("\\"),
+                    start,
+                    self.path.display()
+                );
+                extract_directory(&ntfs, &mut volume, &directory, &dest, filter, 0)?;
+            }
+            if found {
+                return Ok(root.join("Windows"));
+            }
+            tracing::debug!(
+                "No Windows installation on the NTFS volume at offset {}",
+                start
+            );
+        }
+        Err(Error::MissingInImage("Windows\\System32".to_string()))
+    }
+
+    /// Reads the partitions of the GPT following a protective MBR.
+    fn gpt_partitions(&mut self) -> Result<Vec<(u64, u64)>> {
+        let header = self.read_at(SECTOR_SIZE, 92)?;
+        if &header[..8] != b"EFI PART" {
+            return Ok(Vec::new());
+        }
+        let (Some(entries), Some(count), Some(entry_len)) = (
+            read_u64(&header, 0x48),
+            read_u32(&header, 0x50),
+            read_u32(&header, 0x54),
+        ) else {
+            return Ok(Vec::new());
+        };
+        let entry_len = entry_len as usize;
+        let Some((entries, table_len)) = entries
+            .checked_mul(SECTOR_SIZE)
+            .zip((count as usize).checked_mul(entry_len))
+        else {
+            return Ok(Vec::new());
+        };
+        if entry_len < 48 || table_len > 1024 * 1024 {
+            return Ok(Vec::new());
+        }
+        let table = self.read_at(entries, table_len)?;
+        Ok(table
+            .chunks_exact(entry_len)
+            .filter(|entry| entry[..16].iter().any(|&b| b != 0))
+            .filter_map(|entry| {
+                let first = read_u64(entry, 32)?;
+                let last = read_u64(entry, 40)?;
+                let sectors = last.saturating_sub(first).checked_add(1)?;
+                Some((
+                    first.checked_mul(SECTOR_SIZE)?,
+                    sectors.checked_mul(SECTOR_SIZE)?,
+                ))
+            })
+            .collect())
+    }
+
+    /// Returns whether an NTFS boot sector starts at `offset`.
+    fn is_ntfs_at(&mut self, offset: u64) -> Result<bool> {
+        match self.read_at(offset, 11) {
+            Ok(boot) => Ok(&boot[3..11] == NTFS_OEM_ID),
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_at(&mut self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let mut data = vec![0; len];
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.reader.read_exact(&mut data)?;
+        Ok(data)
+    }
+}
+
+/// Looks up the directory at `components` below the root, case-insensitively.
+fn find_directory<'n, T: Read + Seek>(
+    ntfs: &'n Ntfs,
+    volume: &mut T,
+    components: &[&str],
+) -> Result<Option<NtfsFile<'n>>> {
+    let mut directory = ntfs.root_directory(volume)?;
+    for component in components {
+        let index = directory.directory_index(volume)?;
+        let mut finder = index.finder();
+        let Some(entry) = NtfsFileNameIndex::find(&mut finder, ntfs, volume, component) else {
+            return Ok(None);
+        };
+        let file = entry?.to_file(ntfs, volume)?;
+        if !file.is_directory() {
+            return Ok(None);
+        }
+        directory = file;
+    }
+    Ok(Some(directory))
+}
+
+/// Copies the files of `directory` that `filter` accepts to `dest`, recursively.
+fn extract_directory<T: Read + Seek>(
+    ntfs: &Ntfs,
+    volume: &mut T,
+    directory: &NtfsFile<'_>,
+    dest: &Path,
+    filter: &FileFilter,
+    depth: usize,
+) -> Result<()> {
+    if depth > MAX_DEPTH {
+        tracing::warn!("Not descending into {}, too deep", dest.display());
+        return Ok(());
+    }
+    let index = directory.directory_index(volume)?;
+    let mut entries = index.entries();
+    let mut children = Vec::new();
+    while let Some(entry) = entries.next(volume) {
+        let entry = entry?;
+        let Some(name) = entry.key().transpose()? else {
+            continue;
+        };
+        // Files with a short name are listed under both names.
+        if name.namespace() == NtfsFileNamespace::Dos {
+            continue;
+        }
+        children.push((
+            name.name().to_string_lossy(),
+            name.is_directory(),
+            entry.file_reference(),
+        ));
+    }
+    for (name, is_directory, reference) in children {
+        let path = dest.join(&name);
+        let file = reference.to_file(ntfs, volume)?;
+        if is_directory {
+            extract_directory(ntfs, volume, &file, &path, filter, depth + 1)?;
+        } else if filter.accepts_name(&path)
+            && let Err(e) = extract_file(volume, &file, &path)
+        {
+            tracing::warn!("Skipping {}: {}", path.display(), e);
+            let _ = fs::remove_file(&path);
+        }
+    }
+    Ok(())
+}
+
+/// Copies the unnamed data stream of `file` to `dest`.
+fn extract_file<T: Read + Seek>(volume: &mut T, file: &NtfsFile<'_>, dest: &Path) -> Result<()> {
+    let Some(data) = file.data(volume, "") else {
+        return Ok(());
+    };
+    let attribute = data?.to_attribute()?;
+    let value = attribute.value(volume)?;
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut out = File::create(dest)?;
+    io::copy(&mut value.attach(volume), &mut out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::put;
+    use std::io::Cursor;
+
+    fn disk(data: Vec<u8>) -> DiskImage {
+        DiskImage::from_reader(Path::new("disk.img"), Box::new(Cursor::new(data)))
+    }
+
+    fn mbr_entry(data: &mut [u8], index: u64, kind: u8, first: u32, sectors: u32) {
+        let entry = 0x1be + index * 16;
+        data[entry as usize + 4] = kind;
+        put(data, entry + 8, &first.to_le_bytes());
+        put(data, entry + 12, &sectors.to_le_bytes());
+    }
+
+    /// Builds a disk of `sectors` sectors with a boot sector signature, and NTFS boot
+    /// sectors at the given sectors.
+    fn mbr_disk(sectors: u64, ntfs: &[u64]) -> Vec<u8> {
+        let mut data = vec![0; (sectors * SECTOR_SIZE) as usize];
+        put(&mut data, 510, &[0x55, 0xaa]);
+        for sector in ntfs {
+            put(&mut data, sector * SECTOR_SIZE + 3, NTFS_OEM_ID);
+        }
+        data
+    }
+
+    /// Builds a disk with a GPT of four 128-byte entries at sector 2, whose first entry
+    /// spans sectors `first` to `last`.
+    fn gpt_disk(first: u64, last: u64) -> Vec<u8> {
+        let mut data = mbr_disk(8, &[6]);
+        mbr_entry(&mut data, 0, MBR_TYPE_GPT, 1, 7);
+        put(&mut data, SECTOR_SIZE, b"EFI PART");
+        put(&mut data, SECTOR_SIZE + 0x48, &2u64.to_le_bytes());
+        put(&mut data, SECTOR_SIZE + 0x50, &4u32.to_le_bytes());
+        put(&mut data, SECTOR_SIZE + 0x54, &128u32.to_le_bytes());
+        let entry = 2 * SECTOR_SIZE;
+        put(&mut data, entry, &[0xa2; 16]);
+        put(&mut data, entry + 32, &first.to_le_bytes());
+        put(&mut data, entry + 40, &last.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn slice_reads_and_seeks_within_its_range() {
+        let mut slice = Slice::new(Cursor::new((0..100).collect::<Vec<u8>>()), 10, 20);
+        let mut data = Vec::new();
+        slice.read_to_end(&mut data).unwrap();
+        assert_eq!(data, (10..30).collect::<Vec<u8>>());
+        assert_eq!(slice.seek(SeekFrom::End(-5)).unwrap(), 15);
+        let mut data = Vec::new();
+        slice.read_to_end(&mut data).unwrap();
+        assert_eq!(data, (25..30).collect::<Vec<u8>>());
+        assert!(slice.seek(SeekFrom::Current(-100)).is_err());
+        slice.seek(SeekFrom::Start(1000)).unwrap();
+        assert_eq!(slice.read(&mut [0; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn slice_rejects_ranges_past_the_end_of_the_address_space() {
+        let mut slice = Slice::new(Cursor::new(vec![0; 16]), u64::MAX - 1, 16);
+        slice.seek(SeekFrom::Start(4)).unwrap();
+        assert!(slice.read(&mut [0; 4]).is_err());
+    }
+
+    #[test]
+    fn finds_volume_images() {
+        let mut image = disk(mbr_disk(4, &[0]));
+        assert_eq!(image.ntfs_volumes().unwrap(), [(0, 4 * SECTOR_SIZE)]);
+    }
+
+    #[test]
+    fn finds_ntfs_partitions_in_an_mbr() {
+        let mut data = mbr_disk(8, &[2]);
+        mbr_entry(&mut data, 0, 0x07, 2, 2);
+        mbr_entry(&mut data, 1, 0x07, 4, 4);
+        mbr_entry(&mut data, 2, 0x07, 100, 4);
+        let mut image = disk(data);
+        assert_eq!(
+            image.ntfs_volumes().unwrap(),
+            [(2 * SECTOR_SIZE, 2 * SECTOR_SIZE)]
+        );
+    }
+
+    #[test]
+    fn finds_ntfs_partitions_in_a_gpt() {
+        let mut image = disk(gpt_disk(6, 7));
+        assert_eq!(
+            image.ntfs_volumes().unwrap(),
+            [(6 * SECTOR_SIZE, 2 * SECTOR_SIZE)]
+        );
+    }
+
+    #[test]
+    fn skips_corrupt_partition_tables() {
+        let mut image = disk(gpt_disk(u64::MAX / SECTOR_SIZE, u64::MAX));
+        assert!(image.ntfs_volumes().unwrap().is_empty());
+        let mut data = gpt_disk(6, 7);
+        put(&mut data, SECTOR_SIZE + 0x50, &u32::MAX.to_le_bytes());
+        assert!(disk(data).ntfs_volumes().unwrap().is_empty());
+        let mut data = gpt_disk(6, 7);
+        put(&mut data, SECTOR_SIZE + 0x48, &u64::MAX.to_le_bytes());
+        assert!(disk(data).ntfs_volumes().unwrap().is_empty());
+        let mut image = disk(mbr_disk(4, &[]));
+        let root = tempfile::tempdir().unwrap();
+        assert!(
+            image
+                .extract_system_directories(root.path(), &FileFilter::default())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_disks() {
+        let mut image = disk(vec![0; 100]);
+        assert!(image.ntfs_volumes().is_err());
+        let mut data = gpt_disk(6, 7);
+        data.truncate(2 * SECTOR_SIZE as usize + 100);
+        assert!(disk(data).ntfs_volumes().is_err());
+    }
+}
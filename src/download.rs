@@ -0,0 +1,921 @@
+//! Downloading PDBs from symbol servers into a [`SymbolStore`].
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{self, Arc},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncSeekExt, AsyncWriteExt},
+    sync::{Mutex, Semaphore},
+    task::JoinSet,
+};
+use tokio_stream::{Stream, StreamExt};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    error::{Error, Result},
+    hash::{FileHashes, HashOptions, hash_file, hash_stored},
+    health::{SourceHealth, SourceStats},
+    http::HttpClient,
+    missing::MissingCache,
+    quota::StoreQuota,
+    source::{EmbeddedSource, HttpSource, SymbolSource, fetch_from, fetch_tracked, source_for},
+    store::{SymbolStore, verify_file},
+    windows::{PdbMeta, SymbolKind},
+};
+
+/// A PDB fetched from a symbol server.
+#[derive(Debug)]
+pub struct Download {
+    /// The URL the PDB was fetched from.
+    pub url: String,
+    /// Size of the (expanded) PDB in bytes.
+    pub size: u64,
+}
+
+/// Final state of a PDB after a download run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadStatus {
+    /// Downloaded and written to the store in this run.
+    Downloaded,
+    /// Already present in the store.
+    Existing,
+    /// Not available on any of the configured servers.
+    Missing,
+    /// Downloading or storing failed.
+    Failed,
+}
+
+/// The result of processing a single PDB in a download run.
+#[derive(Debug)]
+pub struct DownloadRecord {
+    /// The PDB that was processed.
+    pub pdb: PdbMeta,
+    /// What happened to the PDB.
+    pub status: DownloadStatus,
+    /// The URL the PDB was downloaded from, if it was downloaded in this run.
+    pub url: Option<String>,
+    /// Where the PDB lives (or would live) in the store.
+    pub store_path: PathBuf,
+    /// Size of the PDB in the store, if present.
+    pub size: Option<u64>,
+    /// Why the PDB failed, for [`DownloadStatus::Failed`].
+    pub error: Option<String>,
+    /// Hashes of the PDB, if it was downloaded or is in a local store.
+    pub pdb_hashes: Option<FileHashes>,
+}
+
+/// Outcome counts of a download run.
+#[derive(Debug, Default)]
+pub struct DownloadSummary {
+    pub downloaded: usize,
+    pub existing: usize,
+    pub missing: usize,
+    pub failed: usize,
+    /// Total size of the PDBs downloaded in this run.
+    pub bytes: u64,
+}
+
+/// Downloads PDBs concurrently over a shared HTTP client.
+pub struct DownloadManager {
+    store: Arc<dyn SymbolStore>,
+    sources: Arc<Vec<Arc<dyn SymbolSource>>>,
+    /// Sources tried after [`DownloadManager::sources`] for portable PDBs only.
+    portable_sources: Arc<Vec<Arc<dyn SymbolSource>>>,
+    jobs: usize,
+    missing: Arc<MissingCache>,
+    health: Arc<SourceHealth>,
+    quota: Option<Arc<StoreQuota>>,
+    hashes: Option<HashOptions>,
+    cancel: CancellationToken,
+    /// PDBs not started before the run was cancelled.
+    pending: Arc<sync::Mutex<Vec<PdbMeta>>>,
+}
+
+/// The Microsoft public symbol server, used when no other server is configured.
+pub const DEFAULT_SYMBOL_SERVER: &str = "https://msdl.microsoft.com/download/symbols";
+
+/// The NuGet symbol server, which has the portable PDBs of .NET packages.
+pub const NUGET_SYMBOL_SERVER: &str = "https://symbols.nuget.org/download/symbols";
+
+impl PdbMeta {
+    /// Downloads the PDB file to `dest`, trying each symbol server in order until one
+    /// has it.
+    ///
+    /// The response is streamed to disk, so memory use does not depend on the PDB size.
+    pub async fn download(
+        &self,
+        client: &HttpClient,
+        servers: &[String],
+        dest: &Path,
+    ) -> Result<Download> {
+        let sources: Vec<Arc<dyn SymbolSource>> = servers
+            .iter()
+            .map(|server| Arc::new(HttpSource::new(client.clone(), server)) as _)
+            .collect();
+        fetch_from(&sources, self, dest).await
+    }
+
+    /// Streams the PDB file from `server`, at [`PdbMeta::url`], into `writer` and
+    /// returns the number of bytes written, e.g. to keep it in memory or hand it to
+    /// another store.
+    ///
+    /// Unlike [`PdbMeta::download`], only the uncompressed file is requested and
+    /// failures are not retried, since data already written cannot be taken back. A
+    /// 404 is reported as [`Error::NotFound`], so callers can try the next server.
+    pub async fn download_to<W: Write>(
+        &self,
+        client: &HttpClient,
+        server: &str,
+        writer: &mut W,
+    ) -> Result<u64> {
+        let url = self.url(server);
+        let mut response = client.get(&url).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(Error::NotFound);
+        }
+        if !response.status().is_success() {
+            return Err(Error::HttpStatus {
+                url,
+                status: response.status(),
+            });
+        }
+        let mut size = 0;
+        while let Some(chunk) = response.chunk().await? {
+            writer.write_all(&chunk)?;
+            size += chunk.len() as u64;
+            client.throttle(chunk.len() as u64).await;
+        }
+        writer.flush()?;
+        Ok(size)
+    }
+
+    /// Returns the download URL of the PDB on the given symbol server.
+    pub fn url(&self, server: &str) -> String {
+        self.url_for(server, &self.name)
+    }
+
+    pub(crate) fn url_for(&self, server: &str, file_name: &str) -> String {
+        format!(
+            "{}/{}/{}/{}",
+            server.trim_end_matches('/'),
+            self.name,
+            self.key(),
+            file_name
+        )
+    }
+}
+
+/// Fetches a URL into the file at `dest` via a retrying http request and returns the
+/// size of the file.
+///
+/// Server errors (5xx), rate limiting (429) and transport failures are retried as set
+/// by the client's [`RetryPolicy`](crate::RetryPolicy), waiting as long as a
+/// `Retry-After` header asks for if there is one, up to
+/// [`HttpClient::max_retry_after`]. A 404 is reported as
+/// [`Error::NotFound`] and any other status fails immediately.
+/// Data already in `dest`, whether from an earlier attempt or an interrupted run, is
+/// kept and the download resumed with a `Range` request; servers that do not support
+/// ranges send the whole file again. `dest` is only created once the server answers
+/// with the file, so nothing is left behind for files the server does not have.
+pub(crate) async fn fetch_url(client: &HttpClient, url: &str, dest: &Path) -> Result<u64> {
+    tracing::info!("Generated download URL: {}", url);
+
+    let policy = client.retry_policy();
+    let mut attempts = 0;
+
+    loop {
+        attempts += 1;
+        let offset = match tokio::fs::metadata(dest).await {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(Error::store(dest)(e)),
+        };
+        let mut request = client.get(url);
+        if offset > 0 {
+            tracing::info!("Resuming download of {} at byte {}", url, offset);
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        }
+        let mut retry_after = None;
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                let start = resume_offset(&response, offset);
+                match stream_to_file(client, response, dest, start).await {
+                    Ok(size) => {
+                        tracing::info!("Successfully fetched {} bytes from URL", size - start);
+                        return Ok(size);
+                    }
+                    Err(e @ Error::Network(_)) => e,
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(response) if response.status() == reqwest::StatusCode::NOT_FOUND => {
+                tracing::info!("Symbol not available on server: {}", url);
+                return Err(Error::NotFound);
+            }
+            Ok(response) if response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE => {
+                // The partial file does not match the file on the server; start over.
+                tokio::fs::remove_file(dest)
+                    .await
+                    .map_err(Error::store(dest))?;
+                Error::HttpStatus {
+                    url: url.to_string(),
+                    status: response.status(),
+                }
+            }
+            Ok(response) => {
+                let status = response.status();
+                let error = Error::HttpStatus {
+                    url: url.to_string(),
+                    status,
+                };
+                if !status.is_server_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    return Err(error);
+                }
+                if status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    || status == reqwest::StatusCode::SERVICE_UNAVAILABLE
+                {
+                    retry_after = parse_retry_after(&response);
+                }
+                error
+            }
+            Err(e) => Error::Network(e),
+        };
+        if attempts > policy.retries {
+            tracing::error!("Failed to fetch data from URL after {} attempts", attempts);
+            return Err(error);
+        }
+        let delay = match retry_after {
+            Some(delay) => delay.min(client.max_retry_after()),
+            None => policy.delay(attempts),
+        };
+        tracing::warn!(
+            "Attempt {} failed to fetch data: {}. Retrying in {:?}...",
+            attempts,
+            error,
+            delay
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Returns how long the server asks to wait before retrying, from a `Retry-After`
+/// header given either in seconds or as an HTTP date.
+fn parse_retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?;
+    retry_after_delay(value, chrono::Utc::now())
+}
+
+/// Parses a `Retry-After` value at time `now`. Dates in the past mean no wait, and
+/// numbers of seconds too large to represent mean waiting as long as possible.
+fn retry_after_delay(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+        return Some(
+            value
+                .parse::<u64>()
+                .map_or(Duration::MAX, Duration::from_secs),
+        );
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - now)
+        .to_std()
+        .ok()
+        .or(Some(Duration::ZERO))
+}
+
+/// Returns the offset at which the body of `response` starts: `offset` for a partial
+/// response continuing the requested range, otherwise 0.
+fn resume_offset(response: &reqwest::Response, offset: u64) -> u64 {
+    if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        return 0;
+    }
+    let continues = response
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)
+        .and_then(|range| range.to_str().ok())
+        .and_then(|range| range.strip_prefix("bytes "))
+        .and_then(|range| range.split('-').next())
+        .and_then(|start| start.parse::<u64>().ok())
+        == Some(offset);
+    if continues { offset } else { 0 }
+}
+
+/// Removes the staging file of a PDB none of the sources have, along with its key and
+/// name folders if [`SymbolStore::prepare`] left them empty.
+async fn discard_part(part: &Path) {
+    let _ = tokio::fs::remove_file(part).await;
+    // Removing a folder fails while it still holds other files.
+    let mut folder = part.parent();
+    for _ in 0..2 {
+        let Some(dir) = folder else {
+            break;
+        };
+        if tokio::fs::remove_dir(dir).await.is_err() {
+            break;
+        }
+        folder = dir.parent();
+    }
+}
+
+/// Writes the response body chunk by chunk to `dest` starting at `offset`, discarding
+/// anything after it, and returns the resulting file size.
+///
+/// Reading is throttled to the client's bandwidth limit, if any.
+async fn stream_to_file(
+    client: &HttpClient,
+    mut response: reqwest::Response,
+    dest: &Path,
+    offset: u64,
+) -> Result<u64> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dest)
+        .await
+        .map_err(Error::store(dest))?;
+    file.set_len(offset).await.map_err(Error::store(dest))?;
+    file.seek(SeekFrom::Start(offset))
+        .await
+        .map_err(Error::store(dest))?;
+    let mut size = offset;
+    let result = loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                file.write_all(&chunk).await.map_err(Error::store(dest))?;
+                size += chunk.len() as u64;
+                client.throttle(chunk.len() as u64).await;
+            }
+            Ok(None) => break Ok(size),
+            Err(e) => break Err(Error::Network(e)),
+        }
+    };
+    // Flush even on failure so the next attempt resumes after everything received.
+    file.flush().await.map_err(Error::store(dest))?;
+    result
+}
+
+impl DownloadManager {
+    /// Creates a manager downloading into `store` from `servers`, tried in order,
+    /// with at most `jobs` concurrent requests sent with `client`, built from
+    /// [`HttpOptions`](crate::HttpOptions).
+    ///
+    /// Servers are HTTP symbol server URLs or local symbol store directories, see
+    /// [`source_for`]. Portable PDBs embedded in the scanned binaries are extracted
+    /// before any server is asked, see [`EmbeddedSource`].
+    pub fn new(
+        store: Arc<dyn SymbolStore>,
+        client: HttpClient,
+        servers: Vec<String>,
+        jobs: usize,
+    ) -> Self {
+        let sources = std::iter::once(Arc::new(EmbeddedSource) as Arc<dyn SymbolSource>)
+            .chain(servers.iter().map(|server| source_for(&client, server)))
+            .collect();
+        Self {
+            store,
+            sources: Arc::new(sources),
+            portable_sources: Arc::default(),
+            jobs: jobs.max(1),
+            missing: Arc::default(),
+            health: Arc::default(),
+            quota: None,
+            hashes: None,
+            cancel: CancellationToken::new(),
+            pending: Arc::default(),
+        }
+    }
+
+    /// Replaces the sources PDBs are fetched from, tried in order.
+    ///
+    /// Use this to add custom [`SymbolSource`] implementations, e.g. an internal
+    /// artifact store in front of the default [`HttpSource`].
+    pub fn with_sources(mut self, sources: Vec<Arc<dyn SymbolSource>>) -> Self {
+        self.sources = Arc::new(sources);
+        self
+    }
+
+    /// Tries `sources` after the other sources for portable PDBs only, e.g. the NuGet
+    /// symbol server, which has no Windows PDBs.
+    pub fn with_portable_pdb_sources(mut self, sources: Vec<Arc<dyn SymbolSource>>) -> Self {
+        self.portable_sources = Arc::new(sources);
+        self
+    }
+
+    /// Skips PDBs recorded in `missing` and records the ones no server has in it.
+    ///
+    /// The cache is saved at the end of every [`DownloadManager::run`].
+    pub fn with_missing_cache(mut self, missing: MissingCache) -> Self {
+        self.missing = Arc::new(missing);
+        self
+    }
+
+    /// Keeps the store within `quota`: once it is full, PDBs fail instead of being
+    /// downloaded, unless the quota prunes the store to make room.
+    pub fn with_quota(mut self, quota: StoreQuota) -> Self {
+        self.quota = Some(Arc::new(quota));
+        self
+    }
+
+    /// Records the hashes of every PDB in the download records. PDBs are hashed as
+    /// they are downloaded, before they are handed to the store, and ones already
+    /// present only if the store is local. Binaries are hashed while they are scanned,
+    /// see [`FileFilter::with_hashes`](crate::windows::FileFilter::with_hashes).
+    pub fn with_hashes(mut self, options: HashOptions) -> Self {
+        self.hashes = Some(options);
+        self
+    }
+
+    /// Stops starting downloads once `cancel` is cancelled, e.g. on Ctrl-C. Downloads
+    /// already running are finished, the others are kept for
+    /// [`DownloadManager::take_pending`].
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Returns the request counts and timings of the sources asked so far.
+    pub fn source_stats(&self) -> Vec<SourceStats> {
+        self.health.stats()
+    }
+
+    /// Returns the PDBs that were not started because the run was cancelled, so they
+    /// can be saved and fetched by a later run.
+    pub fn take_pending(&self) -> Vec<PdbMeta> {
+        std::mem::take(&mut self.pending.lock().unwrap())
+    }
+
+    /// Downloads all given PDBs into the store, running up to `jobs` requests at once.
+    ///
+    /// Binaries referencing the same PDB are handled by a single task, so each PDB is
+    /// downloaded at most once and never written by two tasks at the same time.
+    pub async fn run(&self, pdbs: Vec<PdbMeta>) -> Vec<DownloadRecord> {
+        let mut groups: Vec<Vec<PdbMeta>> = Vec::new();
+        let mut group_of: HashMap<PathBuf, usize> = HashMap::new();
+        for pdb in pdbs {
+            let index = *group_of
+                .entry(self.store.location(&pdb))
+                .or_insert_with(|| {
+                    groups.push(Vec::new());
+                    groups.len() - 1
+                });
+            groups[index].push(pdb);
+        }
+        tracing::info!(
+            "Downloading {} PDBs with {} workers",
+            groups.len(),
+            self.jobs
+        );
+        let permits = Arc::new(Semaphore::new(self.jobs));
+        let mut tasks = JoinSet::new();
+        for group in groups {
+            let store = self.store.clone();
+            let sources = self.sources.clone();
+            let portable_sources = self.portable_sources.clone();
+            let permits = permits.clone();
+            let missing = self.missing.clone();
+            let health = self.health.clone();
+            let quota = self.quota.clone();
+            let hashes = self.hashes;
+            let cancel = self.cancel.clone();
+            let pending = self.pending.clone();
+            tasks.spawn(async move {
+                let _permit = tokio::select! {
+                    biased;
+                    () = cancel.cancelled() => {
+                        pending.lock().unwrap().extend(group);
+                        return Vec::new();
+                    }
+                    permit = permits.acquire_owned() => permit,
+                };
+                let mut records = Vec::with_capacity(group.len());
+                for pdb in group {
+                    records.push(
+                        store_pdb(
+                            store.as_ref(),
+                            &sources,
+                            &portable_sources,
+                            &missing,
+                            &health,
+                            quota.as_deref(),
+                            hashes,
+                            pdb,
+                        )
+                        .await,
+                    );
+                }
+                records
+            });
+        }
+        let mut records = Vec::new();
+        while let Some(result) = tasks.join_next().await {
+            match result {
+                Ok(group) => records.extend(group),
+                Err(e) => tracing::error!("Download task failed: {}", e),
+            }
+        }
+        self.finish();
+        records
+    }
+
+    /// Downloads PDBs into the store as a scan yields them, e.g. from
+    /// [`Windows::pdb_stream`](crate::Windows::pdb_stream), so parsing binaries, network
+    /// requests and disk writes overlap instead of running one after the other.
+    ///
+    /// The stream is only polled while fewer than `jobs` downloads are running, so a
+    /// scan feeding a bounded channel is held back instead of running ahead. PDBs with
+    /// the same store location are handled one after the other, as in
+    /// [`DownloadManager::run`]. Once cancelled, the rest of the stream is kept for
+    /// [`DownloadManager::take_pending`], so the scan feeding it should stop as well.
+    pub async fn run_stream<S>(&self, mut pdbs: S) -> Vec<DownloadRecord>
+    where
+        S: Stream<Item = PdbMeta> + Unpin,
+    {
+        tracing::info!(
+            "Downloading PDBs as they are scanned with {} workers",
+            self.jobs
+        );
+        let permits = Arc::new(Semaphore::new(self.jobs));
+        let mut locations: HashMap<PathBuf, Arc<Mutex<()>>> = HashMap::new();
+        let mut tasks = JoinSet::new();
+        let mut records = Vec::new();
+        let mut collect = |result: std::result::Result<DownloadRecord, _>| match result {
+            Ok(record) => records.push(record),
+            Err(e) => tracing::error!("Download task failed: {}", e),
+        };
+        while let Some(pdb) = pdbs.next().await {
+            let permit = tokio::select! {
+                biased;
+                () = self.cancel.cancelled() => None,
+                permit = permits.clone().acquire_owned() => permit.ok(),
+            };
+            let Some(permit) = permit else {
+                self.pending.lock().unwrap().push(pdb);
+                continue;
+            };
+            let location = locations
+                .entry(self.store.location(&pdb))
+                .or_default()
+                .clone();
+            let store = self.store.clone();
+            let sources = self.sources.clone();
+            let portable_sources = self.portable_sources.clone();
+            let missing = self.missing.clone();
+            let health = self.health.clone();
+            let quota = self.quota.clone();
+            let hashes = self.hashes;
+            tasks.spawn(async move {
+                let _permit = permit;
+                let _location = location.lock().await;
+                store_pdb(
+                    store.as_ref(),
+                    &sources,
+                    &portable_sources,
+                    &missing,
+                    &health,
+                    quota.as_deref(),
+                    hashes,
+                    pdb,
+                )
+                .await
+            });
+            while let Some(result) = tasks.try_join_next() {
+                collect(result);
+            }
+        }
+        while let Some(result) = tasks.join_next().await {
+            collect(result);
+        }
+        self.finish();
+        records
+    }
+
+    /// Records the store transaction and saves the missing PDB cache after a run.
+    fn finish(&self) {
+        if let Err(e) = self.store.commit_transaction() {
+            tracing::error!("Failed to record store transaction: {}", e);
+        }
+        if let Err(e) = self.missing.save() {
+            tracing::error!("Failed to save the missing PDB cache: {}", e);
+        }
+    }
+}
+
+impl DownloadSummary {
+    /// Counts the records of a download run by status.
+    pub fn from_records(records: &[DownloadRecord]) -> Self {
+        let mut summary = Self::default();
+        for record in records {
+            match record.status {
+                DownloadStatus::Downloaded => {
+                    summary.downloaded += 1;
+                    summary.bytes += record.size.unwrap_or(0);
+                }
+                DownloadStatus::Existing => summary.existing += 1,
+                DownloadStatus::Missing => summary.missing += 1,
+                DownloadStatus::Failed => summary.failed += 1,
+            }
+        }
+        summary
+    }
+}
+
+/// Downloads a single PDB and writes it to the store unless it is already present.
+async fn store_pdb(
+    store: &dyn SymbolStore,
+    sources: &[Arc<dyn SymbolSource>],
+    portable_sources: &[Arc<dyn SymbolSource>],
+    missing: &MissingCache,
+    health: &SourceHealth,
+    quota: Option<&StoreQuota>,
+    hashes: Option<HashOptions>,
+    pdb: PdbMeta,
+) -> DownloadRecord {
+    tracing::debug!("PDB: {:?}", pdb);
+    let store_path = store.location(&pdb);
+    let mut record = DownloadRecord {
+        status: DownloadStatus::Failed,
+        url: None,
+        size: None,
+        error: None,
+        pdb_hashes: None,
+        store_path,
+        pdb,
+    };
+    match store.stored_size(&record.pdb).await {
+        Ok(Some(size)) => {
+            tracing::warn!(
+                event = "already_present",
+                name = %record.pdb.name,
+                key = %record.pdb.key(),
+                "PDB already exists: {:?}",
+                record.store_path
+            );
+            record.status = DownloadStatus::Existing;
+            record.size = Some(size);
+            if let Some(options) = hashes {
+                record.pdb_hashes = hash_stored(&record.store_path, options).await.ok();
+            }
+            return record;
+        }
+        Ok(None) => {}
+        Err(e) => {
+            tracing::error!("Failed to look up PDB {} in store: {}", record.pdb.name, e);
+            record.error = Some(e.to_string());
+            return record;
+        }
+    }
+    if missing.contains(&record.pdb) {
+        tracing::debug!("Skipping PDB known to be missing: {}", record.pdb.name);
+        record.status = DownloadStatus::Missing;
+        return record;
+    }
+    let lock = match store.lock(&record.pdb).await {
+        Ok(lock) => lock,
+        Err(e) => {
+            tracing::error!("Failed to lock PDB {} in store: {}", record.pdb.name, e);
+            record.error = Some(e.to_string());
+            return record;
+        }
+    };
+    // Another run sharing the store may have stored the PDB while this one waited.
+    if lock.is_some()
+        && let Ok(Some(size)) = store.stored_size(&record.pdb).await
+    {
+        record.status = DownloadStatus::Existing;
+        record.size = Some(size);
+        if let Some(options) = hashes {
+            record.pdb_hashes = hash_stored(&record.store_path, options).await.ok();
+        }
+        return record;
+    }
+    if let Some(quota) = quota
+        && let Err(e) = quota.check().await
+    {
+        tracing::debug!("Not downloading PDB {}: {}", record.pdb.name, e);
+        record.error = Some(e.to_string());
+        return record;
+    }
+    tracing::info!(
+        event = "download_started",
+        name = %record.pdb.name,
+        key = %record.pdb.key(),
+        "Downloading PDB {}",
+        record.pdb.name
+    );
+    let part = match store.prepare(&record.pdb).await {
+        Ok(part) => part,
+        Err(e) => {
+            tracing::error!("Failed to prepare store for PDB {}: {}", record.pdb.name, e);
+            record.error = Some(e.to_string());
+            return record;
+        }
+    };
+    let fetched = match fetch_tracked(sources, health, &record.pdb, &part).await {
+        Err(Error::NotFound)
+            if record.pdb.kind == SymbolKind::PortablePdb && !portable_sources.is_empty() =>
+        {
+            fetch_tracked(portable_sources, health, &record.pdb, &part).await
+        }
+        fetched => fetched,
+    };
+    let download = match fetched {
+        Ok(download) => download,
+        Err(Error::NotFound) => {
+            tracing::warn!(
+                event = "download_missing",
+                name = %record.pdb.name,
+                key = %record.pdb.key(),
+                "PDB not available: {:?}",
+                record.pdb
+            );
+            missing.insert(&record.pdb);
+            discard_part(&part).await;
+            record.status = DownloadStatus::Missing;
+            return record;
+        }
+        Err(e) => {
+            tracing::error!(
+                event = "download_failed",
+                name = %record.pdb.name,
+                key = %record.pdb.key(),
+                error = %e,
+                "Failed to download PDB {:?}: {}",
+                record.pdb,
+                e
+            );
+            record.error = Some(e.to_string());
+            return record;
+        }
+    };
+    if let Err(reason) = verify_file(&record.pdb, &part) {
+        tracing::error!(
+            event = "download_failed",
+            name = %record.pdb.name,
+            key = %record.pdb.key(),
+            error = %reason,
+            "Rejected PDB {} from {}: {}",
+            record.pdb.name,
+            download.url,
+            reason
+        );
+        let _ = tokio::fs::remove_file(&part).await;
+        record.url = Some(download.url);
+        record.error = Some(reason.to_string());
+        return record;
+    }
+    if let Some(options) = hashes {
+        record.pdb_hashes = hash_file(&part, options)
+            .await
+            .inspect_err(|e| tracing::warn!("Failed to hash PDB {}: {}", record.pdb.name, e))
+            .ok();
+    }
+    tracing::info!(
+        event = "download_finished",
+        name = %record.pdb.name,
+        key = %record.pdb.key(),
+        url = %download.url,
+        size = download.size,
+        "Downloaded PDB {} from {}",
+        record.pdb.name,
+        download.url
+    );
+    match store.persist(&record.pdb).await {
+        Ok(_) => {
+            tracing::info!(
+                event = "store_write",
+                name = %record.pdb.name,
+                key = %record.pdb.key(),
+                path = %record.store_path.display(),
+                "Stored PDB {} at {}",
+                record.pdb.name,
+                record.store_path.display()
+            );
+            missing.remove(&record.pdb);
+            if let Some(quota) = quota {
+                quota.add(download.size).await;
+            }
+            record.status = DownloadStatus::Downloaded;
+            record.url = Some(download.url);
+            record.size = Some(download.size);
+        }
+        Err(e) => {
+            tracing::error!(
+                event = "download_failed",
+                name = %record.pdb.name,
+                key = %record.pdb.key(),
+                error = %e,
+                "Failed to write PDB {} to store: {}",
+                record.pdb.name,
+                e
+            );
+            record.error = Some(e.to_string());
+        }
+    }
+    record
+}
+
+/// Returns the CAB-compressed file name, e.g. `ntdll.pd_` for `ntdll.pdb`.
+pub(crate) fn compressed_name(name: &str) -> String {
+    let mut compressed = name.to_string();
+    compressed.pop();
+    compressed.push('_');
+    compressed
+}
+
+/// Expands the CAB-compressed file `name` at `cab` to `dest` on a blocking thread and
+/// returns the expanded size.
+pub(crate) async fn expand_compressed(name: &str, cab: &Path, dest: &Path) -> Result<u64> {
+    tracing::info!("Expanding compressed PDB: {}", name);
+    let (cab, out) = (cab.to_path_buf(), dest.to_path_buf());
+    tokio::task::spawn_blocking(move || expand_cab(&cab, &out))
+        .await
+        .map_err(std::io::Error::other)
+        .and_then(|result| result)
+        .map_err(|source| Error::Decompress {
+            name: name.to_string(),
+            source,
+        })
+}
+
+/// Extracts the single file contained in the CAB archive at `cab` to `dest` and
+/// returns its size.
+pub(crate) fn expand_cab(cab: &Path, dest: &Path) -> std::io::Result<u64> {
+    let mut cabinet = cab::Cabinet::new(fs::File::open(cab)?)?;
+    let name = cabinet
+        .folder_entries()
+        .flat_map(|folder| folder.file_entries())
+        .map(|file| file.name().to_string())
+        .next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "empty cabinet"))?;
+    let mut reader = cabinet.read_file(&name)?;
+    std::io::copy(&mut reader, &mut fs::File::create(dest)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&chrono::Utc)
+    }
+
+    #[test]
+    fn retry_after_accepts_delta_seconds() {
+        assert_eq!(
+            retry_after_delay("120", now()),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(retry_after_delay(" 0 ", now()), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn retry_after_accepts_http_dates() {
+        assert_eq!(
+            retry_after_delay("Wed, 21 Oct 2015 07:30:00 GMT", now()),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            retry_after_delay("Wed, 21 Oct 2015 07:00:00 GMT", now()),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn retry_after_saturates_very_large_values() {
+        assert_eq!(
+            retry_after_delay(&u64::MAX.to_string(), now()),
+            Some(Duration::from_secs(u64::MAX))
+        );
+        assert_eq!(
+            retry_after_delay("99999999999999999999999", now()),
+            Some(Duration::MAX)
+        );
+    }
+
+    #[test]
+    fn retry_after_ignores_garbage() {
+        for value in [
+            "",
+            "soon",
+            "-5",
+            "1.5",
+            "+10",
+            "Wed, 32 Oct 2015 07:30:00 GMT",
+        ] {
+            assert_eq!(retry_after_delay(value, now()), None, "{value}");
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! GNU build IDs of ELF files, which key debug info on debuginfod servers and in the
+//! `.build-id` directories of Linux systems.
+
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    bytes::{read_be_u16, read_be_u32, read_be_u64, read_u16, read_u32, read_u64},
+    error::{Error, Result},
+};
+
+/// Signature of ELF files.
+const ELF_MAGIC: &[u8; 4] = b"\x7fELF";
+/// Size of the ELF header of 64-bit files; 32-bit headers are shorter.
+const HEADER_LEN: usize = 64;
+/// `PT_NOTE`
+const PT_NOTE: u32 = 4;
+/// `SHT_NOTE`
+const SHT_NOTE: u32 = 7;
+/// `NT_GNU_BUILD_ID`
+const NT_GNU_BUILD_ID: u32 = 3;
+/// Upper bound for the size of a note segment read from a file, which keeps corrupt
+/// files from triggering huge allocations.
+const MAX_NOTES_LEN: u64 = 64 * 1024;
+/// Upper bound for the number of program or section headers read from a file.
+const MAX_HEADERS: u16 = 4096;
+
+/// An ELF file and its GNU build ID.
+#[derive(Debug, Clone)]
+pub struct ElfFile {
+    pub path: PathBuf,
+    /// The build ID as lowercase hex digits.
+    pub build_id: String,
+}
+
+/// Layout of the ELF header and its tables, which depends on the class and byte order
+/// of the file.
+struct Layout {
+    is_64: bool,
+    big_endian: bool,
+}
+
+impl Layout {
+    fn u16(&self, data: &[u8], offset: usize) -> Option<u16> {
+        if self.big_endian {
+            read_be_u16(data, offset)
+        } else {
+            read_u16(data, offset)
+        }
+    }
+
+    fn u32(&self, data: &[u8], offset: usize) -> Option<u32> {
+        if self.big_endian {
+            read_be_u32(data, offset)
+        } else {
+            read_u32(data, offset)
+        }
+    }
+
+    /// Reads an address-sized field: 8 bytes in 64-bit files, 4 in 32-bit ones.
+    fn word(&self, data: &[u8], offset: usize) -> Option<u64> {
+        if !self.is_64 {
+            self.u32(data, offset).map(u64::from)
+        } else if self.big_endian {
+            read_be_u64(data, offset)
+        } else {
+            read_u64(data, offset)
+        }
+    }
+
+    /// Reads the offset, entry size and entry count of a header table from the fields
+    /// starting at `offset` and `entry_len` of the ELF header.
+    fn table(&self, header: &[u8], offset: usize, entry_len: usize) -> Option<(u64, u16, u16)> {
+        Some((
+            self.word(header, offset)?,
+            self.u16(header, entry_len)?,
+            self.u16(header, entry_len + 2)?,
+        ))
+    }
+
+    /// Reads the type, file offset and size of a program header, or of a section
+    /// header if `is_section`.
+    fn note_location(&self, entry: &[u8], is_section: bool) -> Option<(u32, u64, u64)> {
+        // Segments keep the type first and sections after the name; the offsets and
+        // sizes follow in the order of the class.
+        let (kind, offset, size) = match (is_section, self.is_64) {
+            (false, true) => (0, 0x08, 0x20),
+            (false, false) => (0, 0x04, 0x10),
+            (true, true) => (0x04, 0x18, 0x20),
+            (true, false) => (0x04, 0x10, 0x14),
+        };
+        Some((
+            self.u32(entry, kind)?,
+            self.word(entry, offset)?,
+            self.word(entry, size)?,
+        ))
+    }
+}
+
+/// Returns whether the file starts with the ELF signature, whatever its name.
+pub fn is_elf(path: &Path) -> bool {
+    let mut signature = [0; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .is_ok_and(|()| signature == *ELF_MAGIC)
+}
+
+/// Reads the GNU build ID of the ELF file at `path`, or returns `None` if it was
+/// linked without one.
+///
+/// The note segments of the program headers are searched first, then the note
+/// sections, which separate debug files keep even though they have no loadable
+/// segments. Only the headers and notes are read.
+pub fn build_id(path: &Path) -> Result<Option<String>> {
+    let invalid = |reason: &str| Error::Elf {
+        path: path.to_path_buf(),
+        reason: reason.to_string(),
+    };
+    let mut file = File::open(path)?;
+    let mut header = [0; HEADER_LEN];
+    let len = read_up_to(&mut file, &mut header)?;
+    if len < 0x34 || header[..4] != *ELF_MAGIC {
+        return Err(invalid("not an ELF file"));
+    }
+    let layout = Layout {
+        is_64: match header[4] {
+            1 => false,
+            2 => true,
+            _ => return Err(invalid("unknown ELF class")),
+        },
+        big_endian: match header[5] {
+            1 => false,
+            2 => true,
+            _ => return Err(invalid("unknown byte order")),
+        },
+    };
+    if layout.is_64 && len < HEADER_LEN {
+        return Err(invalid("truncated ELF header"));
+    }
+    // Offsets of e_phoff, e_shoff, e_phentsize, e_phnum, e_shentsize and e_shnum.
+    let (phoff, shoff, phentsize) = if layout.is_64 {
+        (0x20, 0x28, 0x36)
+    } else {
+        (0x1c, 0x20, 0x2a)
+    };
+    let (Some(segments), Some(sections)) = (
+        layout.table(&header, phoff, phentsize),
+        layout.table(&header, shoff, phentsize + 4),
+    ) else {
+        return Err(invalid("truncated ELF header"));
+    };
+    let tables = [(segments, PT_NOTE), (sections, SHT_NOTE)];
+    // The fields `Layout::note_location` reads end within the first 0x28 bytes of
+    // 64-bit entries and 0x18 bytes of 32-bit ones.
+    let min_entry_len = if layout.is_64 { 0x28 } else { 0x18 };
+    for (index, ((offset, entry_len, count), note_type)) in tables.into_iter().enumerate() {
+        let is_sections = index == 1;
+        if offset == 0 || count == 0 {
+            continue;
+        }
+        if count > MAX_HEADERS || usize::from(entry_len) < min_entry_len {
+            return Err(invalid("implausible header table"));
+        }
+        let mut table = vec![0; usize::from(entry_len) * usize::from(count)];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut table)
+            .map_err(|_| invalid("header table outside of the file"))?;
+        for entry in table.chunks_exact(entry_len.into()) {
+            let Some((kind, notes_offset, notes_len)) = layout.note_location(entry, is_sections)
+            else {
+                continue;
+            };
+            if kind != note_type || notes_len > MAX_NOTES_LEN {
+                continue;
+            }
+            let mut notes = vec![0; notes_len as usize];
+            if file
+                .seek(SeekFrom::Start(notes_offset))
+                .and_then(|_| file.read_exact(&mut notes))
+                .is_err()
+            {
+                continue;
+            }
+            if let Some(id) = find_build_id(&layout, &notes) {
+                return Ok(Some(id));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Searches the notes of a note segment or section for the GNU build ID.
+fn find_build_id(layout: &Layout, notes: &[u8]) -> Option<String> {
+    let align = |len: usize| len.div_ceil(4) * 4;
+    let mut rest = notes;
+    while rest.len() >= 12 {
+        let name_len = layout.u32(rest, 0)? as usize;
+        let desc_len = layout.u32(rest, 4)? as usize;
+        let kind = layout.u32(rest, 8)?;
+        let name_start = 12;
+        let desc_start = name_start.checked_add(align(name_len))?;
+        let end = desc_start.checked_add(align(desc_len))?;
+        let name = rest.get(name_start..name_start + name_len)?;
+        let desc = rest.get(desc_start..desc_start + desc_len)?;
+        if kind == NT_GNU_BUILD_ID && name == b"GNU\0" && !desc.is_empty() {
+            return Some(hex::encode(desc));
+        }
+        rest = rest.get(end..)?;
+    }
+    None
+}
+
+/// Reads into `buf` until it is full or the file ends, returning the bytes read.
+fn read_up_to(file: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match file.read(&mut buf[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(len)
+}
+
+/// Finds the ELF files with a build ID in `paths`, descending into directories up to
+/// `max_depth` levels, or without limit if `None`. ELF files are recognized by their
+/// signature, whatever their name; symbolic links are not followed, so each file of a
+/// Linux userland is read once.
+pub fn scan_paths(paths: &[PathBuf], max_depth: Option<usize>) -> Vec<ElfFile> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            tracing::info!("Looking for ELF files in: {}", path.display());
+            collect_elf_files(path, max_depth, &mut files);
+        } else {
+            read_elf_file(path, &mut files);
+        }
+    }
+    files
+}
+
+fn collect_elf_files(dir: &Path, depth: Option<usize>, files: &mut Vec<ElfFile>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!("Skipping {}: {}", dir.display(), e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            match depth {
+                Some(0) => {}
+                Some(depth) => collect_elf_files(&path, Some(depth - 1), files),
+                None => collect_elf_files(&path, None, files),
+            }
+        } else if file_type.is_file() && is_elf(&path) {
+            read_elf_file(&path, files);
+        }
+    }
+}
+
+fn read_elf_file(path: &Path, files: &mut Vec<ElfFile>) {
+    match build_id(path) {
+        Ok(Some(build_id)) => files.push(ElfFile {
+            path: path.to_path_buf(),
+            build_id,
+        }),
+        Ok(None) => tracing::debug!("No build ID in {}", path.display()),
+        Err(e) => tracing::warn!("Skipping {}: {}", path.display(), e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{put, write};
+
+    const LE: Layout = Layout {
+        is_64: true,
+        big_endian: false,
+    };
+    const BE: Layout = Layout {
+        is_64: false,
+        big_endian: true,
+    };
+
+    /// Builds a note with the given name, type and description, padded to 4 bytes.
+    fn note(big_endian: bool, name: &[u8], kind: u32, desc: &[u8]) -> Vec<u8> {
+        let word = |value: u32| {
+            if big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            }
+        };
+        let mut note = Vec::new();
+        note.extend(word(name.len() as u32));
+        note.extend(word(desc.len() as u32));
+        note.extend(word(kind));
+        note.extend(name);
+        note.resize(note.len().div_ceil(4) * 4, 0);
+        note.extend(desc);
+        note.resize(note.len().div_ceil(4) * 4, 0);
+        note
+    }
+
+    /// Builds a little-endian 64-bit ELF file whose one program header is a note
+    /// segment holding `notes`.
+    fn elf64(notes: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; 120];
+        put(&mut data, 0, ELF_MAGIC);
+        put(&mut data, 4, &[2, 1, 1]);
+        put(&mut data, 0x20, &64u64.to_le_bytes());
+        put(&mut data, 0x36, &56u16.to_le_bytes());
+        put(&mut data, 0x38, &1u16.to_le_bytes());
+        put(&mut data, 64, &PT_NOTE.to_le_bytes());
+        put(&mut data, 64 + 0x08, &120u64.to_le_bytes());
+        put(&mut data, 64 + 0x20, &(notes.len() as u64).to_le_bytes());
+        data.extend(notes);
+        data
+    }
+
+    /// Builds a big-endian 32-bit ELF file whose one section header is a note section
+    /// holding `notes`, as in separate debug files.
+    fn elf32(notes: &[u8]) -> Vec<u8> {
+        let mut data = vec![0; 92];
+        put(&mut data, 0, ELF_MAGIC);
+        put(&mut data, 4, &[1, 2, 1]);
+        put(&mut data, 0x20, &52u32.to_be_bytes());
+        put(&mut data, 0x2e, &40u16.to_be_bytes());
+        put(&mut data, 0x30, &1u16.to_be_bytes());
+        put(&mut data, 52 + 0x04, &SHT_NOTE.to_be_bytes());
+        put(&mut data, 52 + 0x10, &92u32.to_be_bytes());
+        put(&mut data, 52 + 0x14, &(notes.len() as u32).to_be_bytes());
+        data.extend(notes);
+        data
+    }
+
+    #[test]
+    fn reads_build_ids_from_note_segments() {
+        let id: Vec<u8> = (1..=20).collect();
+        let file = write(&elf64(&note(false, b"GNU\0", NT_GNU_BUILD_ID, &id)));
+        assert!(is_elf(file.path()));
+        assert_eq!(build_id(file.path()).unwrap(), Some(hex::encode(&id)));
+    }
+
+    #[test]
+    fn reads_build_ids_from_note_sections() {
+        let mut notes = note(true, b"stapsdt\0", NT_GNU_BUILD_ID, b"probe");
+        notes.extend(note(true, b"GNU\0", 1, b"abi"));
+        notes.extend(note(
+            true,
+            b"GNU\0",
+            NT_GNU_BUILD_ID,
+            &[0xde, 0xad, 0xbe, 0xef],
+        ));
+        let file = write(&elf32(&notes));
+        assert_eq!(build_id(file.path()).unwrap().as_deref(), Some("deadbeef"));
+    }
+
+    #[test]
+    fn find_build_id_stops_at_corrupt_notes() {
+        let notes = note(false, b"GNU\0", NT_GNU_BUILD_ID, &[1; 20]);
+        assert_eq!(find_build_id(&LE, &notes), Some("01".repeat(20)));
+        assert_eq!(find_build_id(&LE, &notes[..30]), None);
+        assert_eq!(find_build_id(&LE, &notes[..8]), None);
+        assert_eq!(find_build_id(&BE, &notes), None);
+        let mut huge = notes.clone();
+        put(&mut huge, 0, &u32::MAX.to_le_bytes());
+        assert_eq!(find_build_id(&LE, &huge), None);
+        let empty = note(false, b"GNU\0", NT_GNU_BUILD_ID, &[]);
+        assert_eq!(find_build_id(&LE, &empty), None);
+    }
+
+    #[test]
+    fn returns_none_without_a_build_id() {
+        let file = write(&elf64(&note(false, b"GNU\0", 1, b"abi")));
+        assert_eq!(build_id(file.path()).unwrap(), None);
+        let mut data = elf64(&note(false, b"GNU\0", NT_GNU_BUILD_ID, &[1; 20]));
+        put(&mut data, 64 + 0x08, &u64::MAX.to_le_bytes());
+        assert_eq!(build_id(write(&data).path()).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let data = elf64(&note(false, b"GNU\0", NT_GNU_BUILD_ID, &[1; 20]));
+        assert!(build_id(write(&data[..50]).path()).is_err());
+        assert!(build_id(write(&data[..100]).path()).is_err());
+        assert!(build_id(write(&data[..2]).path()).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_files() {
+        let data = elf64(&note(false, b"GNU\0", NT_GNU_BUILD_ID, &[1; 20]));
+        let corrupt = |offset: usize, bytes: &[u8]| {
+            let mut data = data.clone();
+            put(&mut data, offset, bytes);
+            build_id(write(&data).path())
+        };
+        assert!(corrupt(0, b"\x7fXLF").is_err());
+        assert!(corrupt(4, &[3]).is_err());
+        assert!(corrupt(5, &[0]).is_err());
+        assert!(corrupt(0x38, &u16::MAX.to_le_bytes()).is_err());
+        assert!(corrupt(0x36, &8u16.to_le_bytes()).is_err());
+        assert!(corrupt(0x20, &u64::MAX.to_le_bytes()).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! The crate-wide error type.
+
+use std::path::PathBuf;
+
+/// Errors returned by symbolfetcher.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A filesystem operation failed.
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The file is not a valid PE image.
+    #[error("failed to parse PE file {path}: {source}")]
+    Pe {
+        path: PathBuf,
+        #[source]
+        source: exe::Error,
+    },
+    /// The PE image has no debug directory, or the Mach-O binary no UUID.
+    #[error("no debug directory in {0}")]
+    NoDebugInfo(PathBuf),
+    /// The debug directory does not contain a usable CodeView record.
+    #[error("invalid CodeView record in {path}: {reason}")]
+    InvalidCodeView { path: PathBuf, reason: String },
+    /// None of the symbol servers have the requested file.
+    #[error("symbol not found on server")]
+    NotFound,
+    /// A symbol server answered with an unexpected HTTP status.
+    #[error("server returned {status} for {url}")]
+    HttpStatus {
+        url: String,
+        status: reqwest::StatusCode,
+    },
+    /// A symbol server could not be reached or the transfer broke off.
+    #[error("network error: {0}")]
+    Network(#[from] reqwest::Error),
+    /// A compressed `.pd_` file could not be expanded.
+    #[error("failed to expand {name}: {source}")]
+    Decompress {
+        name: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// Reading or writing the symbol store failed.
+    #[error("store error at {path}: {source}")]
+    Store {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// An ISO image could not be read.
+    #[error("ISO error: {0}")]
+    Iso(#[from] cdfs::ISOError),
+    /// wimlib failed to open or extract a WIM file.
+    #[error("wimlib error {code}: {message}")]
+    Wim { code: i32, message: String },
+    /// The requested image index does not exist in the WIM file.
+    #[error("image {image} does not exist, the WIM file has {count} images")]
+    InvalidImage { image: u32, count: u32 },
+    /// No image with the requested name exists in the WIM file.
+    #[error("no image named {0:?} in the WIM file")]
+    ImageNotFound(String),
+    /// A VHD or VHDX file could not be read.
+    #[error("invalid virtual disk {path}: {reason}")]
+    VirtualDisk { path: PathBuf, reason: String },
+    /// An EnCase image could not be read.
+    #[error("invalid E01 image {path}: {reason}")]
+    Ewf { path: PathBuf, reason: String },
+    /// An NTFS volume in a disk image could not be read.
+    #[error("NTFS error: {0}")]
+    Ntfs(#[from] ntfs::NtfsError),
+    /// A file expected inside an installation image is missing.
+    #[error("{0} not found in image")]
+    MissingInImage(String),
+    /// A manifest could not be serialized.
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    /// A store or symbol server needs a backend this build was compiled without.
+    #[error("{location} needs symbolfetcher built with the `{feature}` feature")]
+    FeatureDisabled {
+        location: String,
+        feature: &'static str,
+    },
+    /// A remote symbol store rejected a request.
+    #[error("store error at {location}: {message}")]
+    RemoteStore { location: String, message: String },
+    /// A PDB could not be parsed.
+    #[error("failed to read PDB {path}: {reason}")]
+    Pdb { path: PathBuf, reason: String },
+    /// A crash dump could not be read.
+    #[error("invalid crash dump {path}: {reason}")]
+    Dump { path: PathBuf, reason: String },
+    /// An ELF file could not be parsed.
+    #[error("invalid ELF file {path}: {reason}")]
+    Elf { path: PathBuf, reason: String },
+    /// A Mach-O file could not be parsed.
+    #[error("invalid Mach-O file {path}: {reason}")]
+    MachO { path: PathBuf, reason: String },
+    /// A container image could not be read or pulled.
+    #[error("invalid container image {location}: {reason}")]
+    ContainerImage { location: String, reason: String },
+    /// A ZIP archive could not be read.
+    #[error("ZIP error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    /// A glob pattern for selecting files is invalid.
+    #[error("invalid glob pattern: {0}")]
+    Glob(#[from] globset::Error),
+    /// The store does not have room for the files about to be downloaded.
+    #[error("not enough space in {path}: {needed} bytes needed, {available} available")]
+    InsufficientSpace {
+        path: PathBuf,
+        needed: u64,
+        available: u64,
+    },
+    /// The store reached the size set with [`StoreQuota`](crate::quota::StoreQuota).
+    #[error("store quota of {max_size} bytes reached")]
+    QuotaExceeded { max_size: u64 },
+    /// A file could not be signed, or its signature does not match.
+    #[error("signature error for {path}: {reason}")]
+    Signature { path: PathBuf, reason: String },
+    /// The state database could not be read or updated.
+    #[error("state database error: {0}")]
+    State(#[from] rusqlite::Error),
+}
+
+/// A [`Result`](std::result::Result) using the crate-wide [`Error`].
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Wraps an I/O error that occurred while accessing `path` in the store.
+    pub(crate) fn store(path: impl Into<PathBuf>) -> impl FnOnce(std::io::Error) -> Self {
+        let path = path.into();
+        move |source| Self::Store { path, source }
+    }
+}
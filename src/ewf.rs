@@ -0,0 +1,504 @@
+//! Reading EnCase (E01) forensic images as raw disks.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use flate2::read::ZlibDecoder;
+
+use crate::{
+    bytes::{read_u32, read_u64},
+    error::{Error, Result},
+};
+
+/// Signature of EWF segment files, as written by EnCase up to version 6 and libewf.
+const EWF_SIGNATURE: &[u8; 8] = b"EVF\x09\x0d\x0a\xff\x00";
+/// Signature of EWF2 segment files (Ex01), written by EnCase 7 and later.
+const EWF2_SIGNATURE: &[u8; 8] = b"EVF2\x0d\x0a\x81\x00";
+/// Size of the file header of a segment file.
+const FILE_HEADER_LEN: u64 = 13;
+/// Size of a section descriptor.
+const SECTION_LEN: u64 = 76;
+/// Size of the header of a table section, before its entries.
+const TABLE_HEADER_LEN: usize = 24;
+/// Flag of table entries pointing at zlib-compressed chunks.
+const CHUNK_COMPRESSED: u32 = 1 << 31;
+/// Size of the Adler-32 checksum following uncompressed chunks.
+const CHUNK_CHECKSUM_LEN: u64 = 4;
+/// Upper bound for the size of a chunk, in case of a corrupt volume section.
+const MAX_CHUNK_LEN: u64 = 64 * 1024 * 1024;
+/// Upper bound for the entries of a table section, which EnCase limits to 16375.
+const MAX_TABLE_ENTRIES: usize = (MAX_CHUNK_LEN / 4) as usize;
+
+/// An E01 image, possibly split into segment files `.E01`, `.E02`, ..., read as the
+/// disk it holds.
+pub struct EwfImage {
+    segments: Vec<File>,
+    chunks: Vec<Chunk>,
+    chunk_size: u64,
+    size: u64,
+    position: u64,
+    /// The last chunk read, as its index and data.
+    cached: Option<(usize, Vec<u8>)>,
+}
+
+/// Where a chunk of the image is stored.
+#[derive(Debug, Clone, Copy)]
+struct Chunk {
+    segment: usize,
+    offset: u64,
+    size: u64,
+    compressed: bool,
+}
+
+/// Returns whether the file is an EWF or EWF2 segment file, by its signature.
+pub fn is_ewf(path: &Path) -> bool {
+    let mut signature = [0; 8];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .is_ok_and(|()| signature == *EWF_SIGNATURE || signature == *EWF2_SIGNATURE)
+}
+
+impl EwfImage {
+    /// Opens the image whose first segment file is at `path`, picking up the following
+    /// segment files next to it.
+    ///
+    /// Ex01 images in the EWF2 format are not supported.
+    pub fn open(path: &Path) -> Result<Self> {
+        let invalid = |reason: String| Error::Ewf {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let mut image = Self {
+            segments: Vec::new(),
+            chunks: Vec::new(),
+            chunk_size: 0,
+            size: 0,
+            position: 0,
+            cached: None,
+        };
+        for segment_path in segment_paths(path) {
+            let mut file = File::open(&segment_path)?;
+            let mut signature = [0; 8];
+            file.read_exact(&mut signature)?;
+            if signature == *EWF2_SIGNATURE {
+                return Err(invalid("Ex01 (EWF2) images are not supported".to_string()));
+            }
+            if signature != *EWF_SIGNATURE {
+                return Err(invalid(format!(
+                    "{} is not an EWF segment file",
+                    segment_path.display()
+                )));
+            }
+            let done = image
+                .read_sections(&mut file)
+                .map_err(|e| invalid(format!("{}: {}", segment_path.display(), e)))?;
+            image.segments.push(file);
+            if done {
+                break;
+            }
+        }
+        if image.chunk_size == 0 {
+            return Err(invalid("missing volume section".to_string()));
+        }
+        tracing::debug!(
+            "E01 image {} holds {} bytes in {} chunks and {} segments",
+            path.display(),
+            image.size,
+            image.chunks.len(),
+            image.segments.len()
+        );
+        Ok(image)
+    }
+
+    /// Reads the sections of the next segment file, returning whether it is the last.
+    fn read_sections(&mut self, file: &mut File) -> io::Result<bool> {
+        let segment = self.segments.len();
+        let mut offset = FILE_HEADER_LEN;
+        let mut sectors_end = 0;
+        loop {
+            let descriptor = read_at(file, offset, SECTION_LEN as usize)?;
+            let kind = section_type(&descriptor);
+            let (Some(next), Some(size)) = (read_u64(&descriptor, 16), read_u64(&descriptor, 24))
+            else {
+                return Err(invalid_data("truncated section descriptor"));
+            };
+            let data = offset
+                .checked_add(SECTION_LEN)
+                .ok_or_else(|| invalid_data("section beyond the end of the file"))?;
+            match kind.as_str() {
+                "volume" | "disk" => {
+                    let volume = read_at(file, data, 24)?;
+                    let (Some(sectors_per_chunk), Some(bytes_per_sector), Some(sectors)) = (
+                        read_u32(&volume, 8),
+                        read_u32(&volume, 12),
+                        read_u64(&volume, 16),
+                    ) else {
+                        return Err(invalid_data("truncated volume section"));
+                    };
+                    let bytes_per_sector = u64::from(bytes_per_sector);
+                    self.chunk_size = u64::from(sectors_per_chunk) * bytes_per_sector;
+                    self.size = sectors
+                        .checked_mul(bytes_per_sector)
+                        .ok_or_else(|| invalid_data("invalid sector count"))?;
+                    if self.chunk_size == 0 || self.chunk_size > MAX_CHUNK_LEN {
+                        return Err(invalid_data("invalid chunk size"));
+                    }
+                }
+                "sectors" => {
+                    sectors_end = offset
+                        .checked_add(size)
+                        .ok_or_else(|| invalid_data("invalid sectors section size"))?;
+                }
+                "table" => self.read_table(file, segment, data, size, sectors_end)?,
+                "next" => return Ok(false),
+                "done" => return Ok(true),
+                _ => {}
+            }
+            if next <= offset {
+                return Err(invalid_data("section chain does not advance"));
+            }
+            offset = next;
+        }
+    }
+
+    /// Reads the chunk offsets of a table section at `data`, `size` bytes long with its
+    /// descriptor; the last chunk ends where the sectors section does, at `sectors_end`.
+    fn read_table(
+        &mut self,
+        file: &mut File,
+        segment: usize,
+        data: u64,
+        size: u64,
+        sectors_end: u64,
+    ) -> io::Result<()> {
+        let header = read_at(file, data, TABLE_HEADER_LEN)?;
+        let (Some(count), Some(base)) = (read_u32(&header, 0), read_u64(&header, 8)) else {
+            return Err(invalid_data("truncated table header"));
+        };
+        let count = count as usize;
+        let room = size.saturating_sub(SECTION_LEN + TABLE_HEADER_LEN as u64) / 4;
+        if count > MAX_TABLE_ENTRIES || count as u64 > room {
+            return Err(invalid_data("table larger than its section"));
+        }
+        let entries = read_at(file, data + TABLE_HEADER_LEN as u64, count * 4)?;
+        let entries: Vec<u32> = entries
+            .chunks_exact(4)
+            .filter_map(|entry| read_u32(entry, 0))
+            .collect();
+        for (index, &entry) in entries.iter().enumerate() {
+            let offset = base
+                .checked_add(u64::from(entry & !CHUNK_COMPRESSED))
+                .ok_or_else(|| invalid_data("chunk beyond the end of the file"))?;
+            let end = match entries.get(index + 1) {
+                Some(&next) => base.saturating_add(u64::from(next & !CHUNK_COMPRESSED)),
+                None => sectors_end,
+            };
+            let compressed = entry & CHUNK_COMPRESSED != 0;
+            let size = if compressed {
+                end.saturating_sub(offset)
+            } else {
+                match end.checked_sub(offset.saturating_add(CHUNK_CHECKSUM_LEN)) {
+                    Some(len) if len > 0 => len.min(self.chunk_size),
+                    _ => self.chunk_size,
+                }
+            };
+            if size == 0 || size > MAX_CHUNK_LEN {
+                return Err(invalid_data("invalid chunk in table"));
+            }
+            self.chunks.push(Chunk {
+                segment,
+                offset,
+                size,
+                compressed,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the data of chunk `index`, reading and expanding it if needed.
+    fn chunk(&mut self, index: usize) -> io::Result<&[u8]> {
+        if self
+            .cached
+            .as_ref()
+            .is_none_or(|(cached, _)| *cached != index)
+        {
+            let chunk = *self
+                .chunks
+                .get(index)
+                .ok_or_else(|| invalid_data("chunk beyond the table"))?;
+            let raw = read_at(
+                &mut self.segments[chunk.segment],
+                chunk.offset,
+                chunk.size as usize,
+            )?;
+            let data = if chunk.compressed {
+                let mut data = Vec::with_capacity(self.chunk_size as usize);
+                ZlibDecoder::new(raw.as_slice())
+                    .take(self.chunk_size)
+                    .read_to_end(&mut data)?;
+                data
+            } else {
+                raw
+            };
+            self.cached = Some((index, data));
+        }
+        Ok(&self.cached.as_ref().expect("chunk was cached").1)
+    }
+}
+
+impl Read for EwfImage {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.size.saturating_sub(self.position);
+        if buf.is_empty() || remaining == 0 {
+            return Ok(0);
+        }
+        let index = (self.position / self.chunk_size) as usize;
+        let in_chunk = (self.position % self.chunk_size) as usize;
+        let chunk = self.chunk(index)?;
+        let available = chunk.len().saturating_sub(in_chunk);
+        let len = buf.len().min(available).min(remaining as usize);
+        if len == 0 {
+            return Err(invalid_data("chunk shorter than expected"));
+        }
+        buf[..len].copy_from_slice(&chunk[in_chunk..in_chunk + len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for EwfImage {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+        Ok(self.position)
+    }
+}
+
+/// Returns the paths of the segment files of the image starting at `first`, in order:
+/// `.E01` to `.E99`, then `.EAA` to `.EZZ`, `.FAA` and so on, for as long as they
+/// exist.
+fn segment_paths(first: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![first.to_path_buf()];
+    let Some(extension) = first.extension().and_then(|ext| ext.to_str()) else {
+        return paths;
+    };
+    let Some(letter) = extension.chars().next().filter(char::is_ascii_alphabetic) else {
+        return paths;
+    };
+    let lowercase = letter.is_ascii_lowercase();
+    for number in 2.. {
+        let mut extension = segment_extension(letter.to_ascii_uppercase(), number);
+        if lowercase {
+            extension = extension.to_ascii_lowercase();
+        }
+        let path = first.with_extension(extension);
+        if !path.is_file() {
+            break;
+        }
+        paths.push(path);
+    }
+    paths
+}
+
+/// Returns the extension of segment `number`, counting from 1, for images whose first
+/// extension starts with `letter`.
+fn segment_extension(letter: char, number: u32) -> String {
+    if number < 100 {
+        return format!("{}{:02}", letter, number);
+    }
+    let index = number - 100;
+    let letters = [
+        u32::from(letter) + index / (26 * 26),
+        u32::from(b'A') + index / 26 % 26,
+        u32::from(b'A') + index % 26,
+    ];
+    letters.into_iter().filter_map(char::from_u32).collect()
+}
+
+/// Returns the type of a section descriptor, without the NUL padding.
+fn section_type(descriptor: &[u8]) -> String {
+    let kind = &descriptor[..16];
+    let end = kind.iter().position(|&b| b == 0).unwrap_or(kind.len());
+    String::from_utf8_lossy(&kind[..end]).into_owned()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_at(file: &mut File, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+    let mut data = vec![0; len];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::put;
+    use std::{fs, io::Write};
+    use tempfile::TempDir;
+
+    const SECTOR: usize = 512;
+
+    fn pattern(len: usize, seed: u8) -> Vec<u8> {
+        (0..len).map(|index| (index % 251) as u8 ^ seed).collect()
+    }
+
+    /// Appends a section descriptor of `kind` whose data is `data`, pointing at the
+    /// section following it.
+    fn section(image: &mut Vec<u8>, kind: &str, data: &[u8]) {
+        let offset = image.len();
+        let size = SECTION_LEN as usize + data.len();
+        let mut descriptor = vec![0; SECTION_LEN as usize];
+        put(&mut descriptor, 0, kind.as_bytes());
+        put(&mut descriptor, 16, &((offset + size) as u64).to_le_bytes());
+        put(&mut descriptor, 24, &(size as u64).to_le_bytes());
+        image.extend(descriptor);
+        image.extend(data);
+    }
+
+    /// Builds a single segment image of two 512-byte chunks, the first stored as is
+    /// and the second compressed.
+    fn ewf() -> Vec<u8> {
+        let mut image = EWF_SIGNATURE.to_vec();
+        image.extend([1, 1, 0, 0, 0]);
+        let mut volume = vec![0; 24];
+        put(&mut volume, 8, &1u32.to_le_bytes());
+        put(&mut volume, 12, &(SECTOR as u32).to_le_bytes());
+        put(&mut volume, 16, &2u64.to_le_bytes());
+        section(&mut image, "volume", &volume);
+        let first = image.len() + SECTION_LEN as usize;
+        let mut sectors = pattern(SECTOR, 0);
+        sectors.extend([0; CHUNK_CHECKSUM_LEN as usize]);
+        let second = first + sectors.len();
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&pattern(SECTOR, 0x5a)).unwrap();
+        sectors.extend(encoder.finish().unwrap());
+        section(&mut image, "sectors", &sectors);
+        let mut table = vec![0; TABLE_HEADER_LEN];
+        put(&mut table, 0, &2u32.to_le_bytes());
+        table.extend((first as u32).to_le_bytes());
+        table.extend((second as u32 | CHUNK_COMPRESSED).to_le_bytes());
+        section(&mut image, "table", &table);
+        section(&mut image, "done", &[]);
+        image
+    }
+
+    fn write(data: &[u8]) -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("image.E01");
+        fs::write(&path, data).unwrap();
+        (dir, path)
+    }
+
+    /// Returns the offset of the descriptor of the section of `kind` in `image`.
+    fn find_section(image: &[u8], kind: &str) -> usize {
+        let mut offset = FILE_HEADER_LEN as usize;
+        while section_type(&image[offset..]) != kind {
+            offset = read_u64(image, offset + 16).unwrap() as usize;
+        }
+        offset
+    }
+
+    #[test]
+    fn reads_images() {
+        let (_dir, path) = write(&ewf());
+        assert!(is_ewf(&path));
+        let mut image = EwfImage::open(&path).unwrap();
+        let mut data = Vec::new();
+        image.read_to_end(&mut data).unwrap();
+        assert_eq!(data[..SECTOR], pattern(SECTOR, 0));
+        assert_eq!(data[SECTOR..], pattern(SECTOR, 0x5a));
+        image.seek(SeekFrom::Start(SECTOR as u64 - 2)).unwrap();
+        let mut buf = [0; 4];
+        image.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[..2], pattern(SECTOR, 0)[SECTOR - 2..]);
+        assert_eq!(buf[2..], pattern(2, 0x5a));
+    }
+
+    #[test]
+    fn names_segment_files() {
+        assert_eq!(segment_extension('E', 2), "E02");
+        assert_eq!(segment_extension('E', 99), "E99");
+        assert_eq!(segment_extension('E', 100), "EAA");
+        assert_eq!(segment_extension('E', 101), "EAB");
+        assert_eq!(segment_extension('E', 100 + 26 * 26), "FAA");
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["image.e01", "image.e02", "image.e04"] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        assert_eq!(
+            segment_paths(&dir.path().join("image.e01")),
+            [dir.path().join("image.e01"), dir.path().join("image.e02")]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        let image = ewf();
+        let done = find_section(&image, "done");
+        assert!(EwfImage::open(&write(&image[..done]).1).is_err());
+        assert!(EwfImage::open(&write(&image[..100]).1).is_err());
+        assert!(EwfImage::open(&write(&image[..4]).1).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_images() {
+        let mut image = ewf();
+        put(&mut image, 0, EWF2_SIGNATURE);
+        assert!(EwfImage::open(&write(&image).1).is_err());
+        let mut image = ewf();
+        let volume = find_section(&image, "volume");
+        put(&mut image, volume + 16, &(volume as u64).to_le_bytes());
+        assert!(EwfImage::open(&write(&image).1).is_err());
+        let mut image = ewf();
+        put(&mut image, volume, b"unknown\0");
+        assert!(EwfImage::open(&write(&image).1).is_err());
+        let mut image = ewf();
+        put(
+            &mut image,
+            volume + SECTION_LEN as usize + 8,
+            &0u32.to_le_bytes(),
+        );
+        assert!(EwfImage::open(&write(&image).1).is_err());
+        let mut image = ewf();
+        put(
+            &mut image,
+            volume + SECTION_LEN as usize + 16,
+            &u64::MAX.to_le_bytes(),
+        );
+        assert!(EwfImage::open(&write(&image).1).is_err());
+        let mut image = ewf();
+        let table = find_section(&image, "table");
+        put(
+            &mut image,
+            table + SECTION_LEN as usize,
+            &u32::MAX.to_le_bytes(),
+        );
+        assert!(EwfImage::open(&write(&image).1).is_err());
+    }
+
+    #[test]
+    fn fails_to_read_corrupt_chunks() {
+        let mut image = ewf();
+        let sectors = find_section(&image, "sectors");
+        let second = sectors + SECTION_LEN as usize + SECTOR + CHUNK_CHECKSUM_LEN as usize;
+        put(&mut image, second, &[0xff; 8]);
+        let (_dir, path) = write(&image);
+        let mut image = EwfImage::open(&path).unwrap();
+        let mut data = Vec::new();
+        assert!(image.read_to_end(&mut data).is_err());
+        assert_eq!(data, pattern(SECTOR, 0));
+    }
+}
@@ -0,0 +1,73 @@
+//! Content hashes of scanned binaries and downloaded PDBs, recorded in manifests and
+//! the state database for chain of custody and later verification.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::compress::ExpandedFile;
+
+/// Which hashes to compute besides SHA-256.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HashOptions {
+    /// Also compute MD5, e.g. for VirusTotal lookups.
+    pub md5: bool,
+}
+
+/// The hashes of a file's contents as lowercase hex.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct FileHashes {
+    pub sha256: String,
+    /// Only computed with [`HashOptions::md5`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+}
+
+impl FileHashes {
+    /// Hashes the file at `path`, reading it once for all hashes.
+    pub fn of(path: &Path, options: HashOptions) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut sha256 = Sha256::new();
+        let mut md5 = options.md5.then(Md5::new);
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            sha256.update(&buffer[..read]);
+            if let Some(md5) = &mut md5 {
+                md5.update(&buffer[..read]);
+            }
+        }
+        Ok(Self {
+            sha256: hex::encode(sha256.finalize()),
+            md5: md5.map(|md5| hex::encode(md5.finalize())),
+        })
+    }
+}
+
+/// Hashes the file at `path` on a blocking thread.
+pub async fn hash_file(path: &Path, options: HashOptions) -> io::Result<FileHashes> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || FileHashes::of(&path, options))
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Hashes the expanded contents of a file in a local store, which may be stored
+/// compressed, on a blocking thread.
+pub async fn hash_stored(store_path: &Path, options: HashOptions) -> io::Result<FileHashes> {
+    let store_path = store_path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let expanded = ExpandedFile::open(&store_path)?;
+        FileHashes::of(expanded.path(), options)
+    })
+    .await
+    .map_err(io::Error::other)?
+}
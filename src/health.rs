@@ -0,0 +1,149 @@
+//! Per-source statistics of download runs, and backing off from sources that keep
+//! timing out or refusing connections.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    download::Download,
+    error::{Error, Result},
+    source::SymbolSource,
+};
+
+/// Failures to reach a source in a row after which it is tried after the others.
+const FAILURES_BEFORE_BACKOFF: u32 = 3;
+/// How long a source that kept failing is tried after the others.
+const BACKOFF: Duration = Duration::from_secs(60);
+
+/// Request counts and timings of one source.
+#[derive(Debug, Clone, Default)]
+pub struct SourceStats {
+    /// The source, as given by [`SymbolSource::describe`].
+    pub source: String,
+    /// Requests sent to the source.
+    pub requests: u64,
+    /// Requests that returned the file.
+    pub hits: u64,
+    /// Requests for files the source does not have.
+    pub misses: u64,
+    /// Requests that failed otherwise, including timeouts.
+    pub errors: u64,
+    /// Requests that timed out or could not connect.
+    pub timeouts: u64,
+    /// Time spent on all requests, including retries and the transfer of the files.
+    pub total_time: Duration,
+}
+
+impl SourceStats {
+    /// Returns the share of requests that returned the file, from 0 to 1.
+    pub fn hit_rate(&self) -> f64 {
+        if self.requests == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / self.requests as f64
+    }
+
+    /// Returns the average time of a request.
+    pub fn mean_time(&self) -> Duration {
+        match u32::try_from(self.requests) {
+            Ok(0) => Duration::ZERO,
+            Ok(requests) => self.total_time / requests,
+            Err(_) => self.total_time.div_f64(self.requests as f64),
+        }
+    }
+}
+
+/// Tracks how the sources of a download run are doing, shared by all its tasks.
+///
+/// Sources that failed to respond [`FAILURES_BEFORE_BACKOFF`] times in a row are
+/// tried after the healthy ones for a while, so a server that is down does not hold
+/// up every download with its timeouts. They are still tried, so no PDB is missed.
+#[derive(Debug, Default)]
+pub struct SourceHealth {
+    sources: Mutex<HashMap<String, Entry>>,
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    stats: SourceStats,
+    failures_in_row: u32,
+    backoff_until: Option<Instant>,
+}
+
+impl SourceHealth {
+    /// Returns `sources` with the ones currently backed off from moved to the end,
+    /// otherwise in their configured order.
+    pub fn order<'a>(
+        &self,
+        sources: &'a [Arc<dyn SymbolSource>],
+    ) -> Vec<&'a Arc<dyn SymbolSource>> {
+        let now = Instant::now();
+        let entries = self.sources.lock().unwrap_or_else(PoisonError::into_inner);
+        let (healthy, backed_off): (Vec<_>, Vec<_>) = sources.iter().partition(|source| {
+            entries
+                .get(&source.describe())
+                .and_then(|entry| entry.backoff_until)
+                .is_none_or(|until| until <= now)
+        });
+        healthy.into_iter().chain(backed_off).collect()
+    }
+
+    /// Records the outcome of a request to `source` that took `elapsed`.
+    pub fn record(&self, source: &str, outcome: &Result<Download>, elapsed: Duration) {
+        let mut entries = self.sources.lock().unwrap_or_else(PoisonError::into_inner);
+        let entry = entries.entry(source.to_string()).or_insert_with(|| Entry {
+            stats: SourceStats {
+                source: source.to_string(),
+                ..SourceStats::default()
+            },
+            ..Entry::default()
+        });
+        entry.stats.requests += 1;
+        entry.stats.total_time += elapsed;
+        match outcome {
+            Ok(_) => entry.stats.hits += 1,
+            Err(Error::NotFound) => entry.stats.misses += 1,
+            Err(e) => {
+                entry.stats.errors += 1;
+                if is_unreachable(e) {
+                    entry.stats.timeouts += 1;
+                    entry.failures_in_row += 1;
+                    if entry.failures_in_row >= FAILURES_BEFORE_BACKOFF {
+                        if entry.backoff_until.is_none() {
+                            tracing::warn!(
+                                "{} keeps timing out, trying it after the other sources",
+                                source
+                            );
+                        }
+                        entry.backoff_until = Some(Instant::now() + BACKOFF);
+                    }
+                    return;
+                }
+            }
+        }
+        // The source answered, so it is reachable again.
+        entry.failures_in_row = 0;
+        entry.backoff_until = None;
+    }
+
+    /// Returns the statistics of the sources that were asked, by source name.
+    pub fn stats(&self) -> Vec<SourceStats> {
+        let entries = self.sources.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut stats: Vec<SourceStats> =
+            entries.values().map(|entry| entry.stats.clone()).collect();
+        stats.sort_by(|a, b| a.source.cmp(&b.source));
+        stats
+    }
+}
+
+/// Returns whether the request failed because the source did not respond, rather than
+/// with an answer.
+fn is_unreachable(error: &Error) -> bool {
+    match error {
+        Error::Network(e) => e.is_timeout() || e.is_connect(),
+        _ => false,
+    }
+}
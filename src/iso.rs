@@ -1,14 +1,93 @@
-use cdfs::ISO9660;
-use std::fs::File;
+//! Reading Windows installation media (ISO images).
+
+use cdfs::{DirectoryEntry, ISO9660};
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
 use tracing::info;
 
+use crate::{
+    error::{Error, Result},
+    preflight::check_space,
+    wim::{ImageSelector, Wim, WimImage},
+    windows::{FileFilter, PdbMeta},
+};
+
+/// Locations of the Windows image inside installation media, in order of preference.
+/// Media Creation Tool downloads ship a solid-compressed `install.esd` instead of a WIM.
+const INSTALL_IMAGES: &[&str] = &["sources/install.wim", "sources/install.esd"];
+
+/// A Windows installation ISO.
 pub struct Iso(pub ISO9660<File>);
 impl Iso {
-    pub fn new(_path: std::path::PathBuf) -> Result<Self, std::io::Error> {
-        info!("Opening ISO file: {}", _path.display());
-        let reader = File::open(_path)?;
-        let iso = ISO9660::new(reader).expect("Failed to read ISO file");
+    /// Opens the ISO image at `path`.
+    pub fn new(path: PathBuf) -> Result<Self> {
+        info!("Opening ISO file: {}", path.display());
+        let reader = File::open(path)?;
+        let iso = ISO9660::new(reader)?;
 
         Ok(Self(iso))
     }
+
+    /// Copies the file at `path` inside the ISO to `dest`, after checking that the
+    /// volume of `dest` has room for it.
+    pub fn extract_file(&self, path: &str, dest: &Path) -> Result<()> {
+        match self.0.open(path)? {
+            Some(DirectoryEntry::File(file)) => {
+                if let Some(dir) = dest.parent() {
+                    check_space(dir, u64::from(file.size()))?;
+                }
+                info!("Extracting {} from ISO to {}", path, dest.display());
+                io::copy(&mut file.read(), &mut File::create(dest)?)?;
+                Ok(())
+            }
+            _ => Err(Error::MissingInImage(path.to_string())),
+        }
+    }
+
+    /// Lists the Windows images contained in the ISO's `install.wim` or `install.esd`.
+    pub fn images(&self) -> Result<Vec<WimImage>> {
+        let scratch = tempfile::tempdir()?;
+        self.open_install_wim(scratch.path())?.images()
+    }
+
+    /// Extracts the system directories of a Windows image on the ISO and scans them
+    /// for PDB references, see [`Wim::fetch_pdbs`].
+    pub fn fetch_pdbs(
+        &self,
+        image: &ImageSelector,
+        max_depth: Option<usize>,
+        filter: &FileFilter,
+    ) -> Result<Vec<PdbMeta>> {
+        let scratch = tempfile::tempdir()?;
+        self.open_install_wim(scratch.path())?
+            .fetch_pdbs(image, max_depth, filter)
+    }
+
+    /// Copies `install.wim` (or `install.esd`) into `scratch` and opens it.
+    ///
+    /// wimlib needs a seekable file, so the image, often 4 to 6 GB, is copied out of
+    /// the ISO whole; the scratch directory must have room for it next to the files
+    /// extracted from it.
+    fn open_install_wim(&self, scratch: &Path) -> Result<Wim> {
+        Wim::open(&self.extract_install_image(scratch)?)
+    }
+
+    /// Copies `install.wim` (or `install.esd`) into `dest` and returns its path.
+    pub fn extract_install_image(&self, dest: &Path) -> Result<PathBuf> {
+        for image in INSTALL_IMAGES {
+            let Some(name) = image.rsplit('/').next() else {
+                continue;
+            };
+            let wim_path = dest.join(name);
+            match self.extract_file(image, &wim_path) {
+                Ok(()) => return Ok(wim_path),
+                Err(Error::MissingInImage(_)) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(Error::MissingInImage(INSTALL_IMAGES.join(" or ")))
+    }
 }
@@ -0,0 +1,597 @@
+//! Reading the drivers loaded in 64-bit Windows kernel and complete memory dumps.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::{
+    bytes::{read_u16, read_u32, read_u64, truncated},
+    error::{Error, Result},
+    minidump::{DumpModule, module_keys},
+    windows::{Architecture, PdbMeta},
+};
+
+/// Signature and `ValidDump` of 64-bit kernel dumps, `PAGEDU64`.
+const DUMP64_SIGNATURE: &[u8; 8] = b"PAGEDU64";
+/// Size of `DUMP_HEADER64`; the memory follows it.
+const HEADER_LEN: usize = 0x2000;
+/// Offsets of fields in `DUMP_HEADER64`.
+const DIRECTORY_TABLE_BASE_OFFSET: usize = 0x10;
+const LOADED_MODULE_LIST_OFFSET: usize = 0x20;
+const MACHINE_OFFSET: usize = 0x30;
+const PHYSICAL_MEMORY_BLOCK_OFFSET: usize = 0x88;
+const DUMP_TYPE_OFFSET: usize = 0xf98;
+/// `DUMP_TYPE` of complete memory dumps, which store the physical memory runs in order.
+const DUMP_TYPE_FULL: u32 = 1;
+/// `DUMP_TYPE`s of kernel and automatic memory dumps, which store the present pages
+/// after a bitmap.
+const DUMP_TYPES_BITMAP: [u32; 2] = [2, 5];
+/// Offset of the page bitmap in the `SDMP`/`FDMP` header following `DUMP_HEADER64`.
+const BITMAP_OFFSET: usize = 0x38;
+/// `IMAGE_FILE_MACHINE_AMD64`, the only machine whose page tables are walked.
+const MACHINE_AMD64: u32 = 0x8664;
+/// Upper bound on the number of loaded modules, in case the list is corrupt or
+/// circular.
+const MAX_MODULES: usize = 4096;
+/// Upper bound for the size of a single record read from the dump.
+const MAX_RECORD_LEN: usize = 64 * 1024;
+const PAGE_SIZE: u64 = 0x1000;
+/// Bits 12 to 51 of a page table entry hold the physical address.
+const PTE_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+/// Offsets of fields in `KLDR_DATA_TABLE_ENTRY` on x64.
+const LDR_DLL_BASE_OFFSET: u64 = 0x30;
+const LDR_SIZE_OF_IMAGE_OFFSET: u64 = 0x40;
+const LDR_FULL_DLL_NAME_OFFSET: u64 = 0x48;
+/// `IMAGE_DEBUG_TYPE_CODEVIEW`
+const DEBUG_TYPE_CODEVIEW: u32 = 2;
+
+/// Where the pages of physical memory are stored in the dump file.
+enum PhysicalMemory {
+    /// Runs of consecutive pages, as `(first page, page count, file offset)`.
+    Runs(Vec<(u64, u64, u64)>),
+    /// A bitmap of present pages, stored one after another from `first_page`, with the
+    /// number of set bits before each 64-bit word of the bitmap.
+    Bitmap {
+        first_page: u64,
+        words: Vec<u64>,
+        ranks: Vec<u64>,
+    },
+}
+
+/// A 64-bit kernel or complete memory dump, opened for reading virtual memory.
+struct KernelDump {
+    file: File,
+    directory_table_base: u64,
+    memory: PhysicalMemory,
+}
+
+/// Returns whether the file starts with the signature of a 64-bit kernel dump,
+/// whatever its extension.
+pub fn is_kernel_dump(path: &Path) -> bool {
+    let mut signature = [0; 8];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .is_ok_and(|()| signature == *DUMP64_SIGNATURE)
+}
+
+/// Reads the architecture and the drivers loaded in a 64-bit kernel, automatic or
+/// complete memory dump of an x64 machine.
+///
+/// The drivers are found by walking `PsLoadedModuleList`, whose address the dump
+/// header carries as a copy of the `KDDEBUGGER_DATA64` field, through the page tables
+/// saved in the dump. Drivers whose headers or CodeView records were paged out are
+/// listed without a CodeView record.
+pub fn read_modules(path: &Path) -> Result<(Architecture, Vec<DumpModule>)> {
+    let invalid = |reason: String| Error::Dump {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let mut file = File::open(path)?;
+    let mut header = vec![0; HEADER_LEN];
+    file.read_exact(&mut header)?;
+    if header[..8] != *DUMP64_SIGNATURE {
+        return Err(invalid("missing PAGEDU64 signature".to_string()));
+    }
+    let truncated_header = || invalid("truncated header".to_string());
+    let machine = read_u32(&header, MACHINE_OFFSET).ok_or_else(truncated_header)?;
+    let arch = Architecture::from_machine(machine as u16);
+    if machine != MACHINE_AMD64 {
+        return Err(invalid(format!(
+            "kernel dumps of {:?} machines are not supported",
+            arch
+        )));
+    }
+    let memory = match read_u32(&header, DUMP_TYPE_OFFSET).ok_or_else(truncated_header)? {
+        DUMP_TYPE_FULL => PhysicalMemory::runs(&header),
+        dump_type if DUMP_TYPES_BITMAP.contains(&dump_type) => {
+            PhysicalMemory::bitmap(&mut file).map_err(|e| invalid(e.to_string()))?
+        }
+        dump_type => return Err(invalid(format!("unsupported dump type {}", dump_type))),
+    };
+    let list_head = read_u64(&header, LOADED_MODULE_LIST_OFFSET).ok_or_else(truncated_header)?;
+    let directory_table_base =
+        read_u64(&header, DIRECTORY_TABLE_BASE_OFFSET).ok_or_else(truncated_header)?;
+    let mut dump = KernelDump {
+        file,
+        directory_table_base: directory_table_base & PTE_ADDRESS_MASK,
+        memory,
+    };
+    let modules = dump
+        .loaded_modules(list_head)
+        .map_err(|e| invalid(format!("failed to walk the loaded module list: {}", e)))?;
+    tracing::debug!("Found {} drivers in {}", modules.len(), path.display());
+    Ok((arch, modules))
+}
+
+/// Reads the keys of the PDBs of all drivers loaded in the kernel dump at `path`, plus
+/// the image keys of the drivers themselves if `images` is set.
+///
+/// See [`read_modules`] for the dumps that can be read.
+pub fn dump_pdbs(path: &Path, images: bool) -> Result<Vec<PdbMeta>> {
+    let (arch, modules) = read_modules(path)?;
+    Ok(module_keys(path, arch, modules, images))
+}
+
+impl PhysicalMemory {
+    /// Reads the `PHYSICAL_MEMORY_DESCRIPTOR64` of a complete memory dump.
+    fn runs(header: &[u8]) -> Self {
+        let count = read_u32(header, PHYSICAL_MEMORY_BLOCK_OFFSET).unwrap_or(0) as usize;
+        let mut offset = HEADER_LEN as u64;
+        let runs = (0..count)
+            .map_while(|index| {
+                let run = PHYSICAL_MEMORY_BLOCK_OFFSET + 16 + index * 16;
+                Some((read_u64(header, run)?, read_u64(header, run + 8)?))
+            })
+            .map(|(base, pages)| {
+                let run = (base, pages, offset);
+                offset = offset.saturating_add(pages.saturating_mul(PAGE_SIZE));
+                run
+            })
+            .collect();
+        Self::Runs(runs)
+    }
+
+    /// Reads the page bitmap of a kernel or automatic memory dump.
+    fn bitmap(file: &mut File) -> io::Result<Self> {
+        let mut header = [0; BITMAP_OFFSET];
+        file.seek(SeekFrom::Start(HEADER_LEN as u64))?;
+        file.read_exact(&mut header)?;
+        if !matches!(&header[..8], b"SDMPDUMP" | b"FDMPDUMP") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "missing page bitmap header",
+            ));
+        }
+        let first_page = read_u64(&header, 0x20).ok_or_else(|| truncated("bitmap header"))?;
+        let pages = read_u64(&header, 0x30).ok_or_else(|| truncated("bitmap header"))?;
+        let len = pages.div_ceil(8);
+        if len > file.metadata()?.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "page bitmap exceeds the file",
+            ));
+        }
+        let mut bitmap = vec![0; len as usize];
+        file.read_exact(&mut bitmap)?;
+        let words: Vec<u64> = bitmap
+            .chunks(8)
+            .map(|chunk| {
+                let mut word = [0; 8];
+                word[..chunk.len()].copy_from_slice(chunk);
+                u64::from_le_bytes(word)
+            })
+            .collect();
+        let mut rank = 0;
+        let ranks = words
+            .iter()
+            .map(|word| {
+                let before = rank;
+                rank += u64::from(word.count_ones());
+                before
+            })
+            .collect();
+        Ok(Self::Bitmap {
+            first_page,
+            words,
+            ranks,
+        })
+    }
+
+    /// Returns the file offset of physical page `page`, or `None` if it is not in the
+    /// dump.
+    fn page_offset(&self, page: u64) -> Option<u64> {
+        match self {
+            Self::Runs(runs) => runs
+                .iter()
+                .find(|(base, pages, _)| (*base..base.saturating_add(*pages)).contains(&page))
+                .and_then(|(base, _, offset)| offset.checked_add((page - base) * PAGE_SIZE)),
+            Self::Bitmap {
+                first_page,
+                words,
+                ranks,
+            } => {
+                let word = *words.get(usize::try_from(page / 64).ok()?)?;
+                let bit = page % 64;
+                if word & (1 << bit) == 0 {
+                    return None;
+                }
+                let index =
+                    ranks[(page / 64) as usize] + u64::from((word & ((1 << bit) - 1)).count_ones());
+                first_page.checked_add(index.checked_mul(PAGE_SIZE)?)
+            }
+        }
+    }
+}
+
+impl KernelDump {
+    /// Walks the `KLDR_DATA_TABLE_ENTRY` list starting at the list head at `head`.
+    fn loaded_modules(&mut self, head: u64) -> io::Result<Vec<DumpModule>> {
+        let mut modules = Vec::new();
+        let mut entry = self.read_u64(head)?;
+        while entry != head && entry != 0 {
+            if modules.len() == MAX_MODULES {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "too many entries, the list may be corrupt",
+                ));
+            }
+            // Virtual addresses wrap, so corrupt pointers fail to translate instead of
+            // overflowing.
+            let base = self.read_u64(entry.wrapping_add(LDR_DLL_BASE_OFFSET))?;
+            let size = self.read_u32(entry.wrapping_add(LDR_SIZE_OF_IMAGE_OFFSET))?;
+            let name = self.read_unicode_string(entry.wrapping_add(LDR_FULL_DLL_NAME_OFFSET))?;
+            let (timestamp, codeview) = match self.image_debug_info(base) {
+                Ok(info) => info,
+                Err(e) => {
+                    tracing::debug!("Failed to read the headers of {}: {}", name, e);
+                    (0, Vec::new())
+                }
+            };
+            modules.push(DumpModule {
+                name,
+                base,
+                size,
+                timestamp,
+                codeview,
+                misc: Vec::new(),
+            });
+            entry = self.read_u64(entry)?;
+        }
+        Ok(modules)
+    }
+
+    /// Reads the `TimeDateStamp` and the first CodeView record of the image loaded at
+    /// `base`.
+    fn image_debug_info(&mut self, base: u64) -> io::Result<(u32, Vec<u8>)> {
+        let nt_headers = base.wrapping_add(u64::from(self.read_u32(base.wrapping_add(0x3c))?));
+        let timestamp = self.read_u32(nt_headers.wrapping_add(8))?;
+        // The data directories follow the optional header fields, which are 16 bytes
+        // longer in PE32+ images.
+        let optional_header = nt_headers.wrapping_add(24);
+        let directories = match self.read(optional_header, 2)?.as_slice() {
+            [0x0b, 0x02] => optional_header.wrapping_add(112),
+            _ => optional_header.wrapping_add(96),
+        };
+        let debug = self.read(directories.wrapping_add(6 * 8), 8)?;
+        let (Some(rva), Some(size)) = (read_u32(&debug, 0), read_u32(&debug, 4)) else {
+            return Err(truncated("data directory"));
+        };
+        let directory = base.wrapping_add(u64::from(rva));
+        for index in 0..u64::from(size) / 28 {
+            let entry = self.read(directory.wrapping_add(index * 28), 28)?;
+            let (Some(kind), Some(size), Some(rva)) = (
+                read_u32(&entry, 12),
+                read_u32(&entry, 16),
+                read_u32(&entry, 20),
+            ) else {
+                return Err(truncated("debug directory entry"));
+            };
+            if kind == DEBUG_TYPE_CODEVIEW {
+                let data = self.read(base.wrapping_add(u64::from(rva)), size as usize)?;
+                return Ok((timestamp, data));
+            }
+        }
+        Ok((timestamp, Vec::new()))
+    }
+
+    /// Reads a `UNICODE_STRING` at `address`.
+    fn read_unicode_string(&mut self, address: u64) -> io::Result<String> {
+        let header = self.read(address, 16)?;
+        let (Some(length), Some(buffer)) = (read_u16(&header, 0), read_u64(&header, 8)) else {
+            return Err(truncated("UNICODE_STRING"));
+        };
+        let data = self.read(buffer, length.into())?;
+        let units: Vec<u16> = data
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .collect();
+        Ok(String::from_utf16_lossy(&units))
+    }
+
+    fn read_u32(&mut self, address: u64) -> io::Result<u32> {
+        read_u32(&self.read(address, 4)?, 0).ok_or_else(|| truncated("value"))
+    }
+
+    fn read_u64(&mut self, address: u64) -> io::Result<u64> {
+        read_u64(&self.read(address, 8)?, 0).ok_or_else(|| truncated("value"))
+    }
+
+    /// Reads `len` bytes of virtual memory at `address`, page by page.
+    fn read(&mut self, address: u64, len: usize) -> io::Result<Vec<u8>> {
+        if len > MAX_RECORD_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("record of {} bytes at {:#x} is too large", len, address),
+            ));
+        }
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            let virtual_address = address.wrapping_add(data.len() as u64);
+            let in_page = (PAGE_SIZE - virtual_address % PAGE_SIZE) as usize;
+            let chunk = in_page.min(len - data.len());
+            let physical = self.translate(virtual_address)?;
+            let mut buffer = vec![0; chunk];
+            self.read_physical(physical, &mut buffer)?;
+            data.extend_from_slice(&buffer);
+        }
+        Ok(data)
+    }
+
+    /// Translates a virtual address through the four levels of x64 page tables,
+    /// including 1 GB and 2 MB large pages.
+    fn translate(&mut self, address: u64) -> io::Result<u64> {
+        let mut table = self.directory_table_base;
+        for level in (0..4).rev() {
+            let index = (address >> (12 + 9 * level)) & 0x1ff;
+            let mut entry = [0; 8];
+            self.read_physical(table + index * 8, &mut entry)?;
+            let entry = u64::from_le_bytes(entry);
+            if entry & 1 == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{:#x} is not mapped", address),
+                ));
+            }
+            let large_page = (level == 1 || level == 2) && entry & 0x80 != 0;
+            if level == 0 || large_page {
+                let offset_mask = (1u64 << (12 + 9 * level)) - 1;
+                return Ok((entry & PTE_ADDRESS_MASK & !offset_mask) | (address & offset_mask));
+            }
+            table = entry & PTE_ADDRESS_MASK;
+        }
+        unreachable!("the last level always returns")
+    }
+
+    /// Reads physical memory at `address`, which must not cross a page boundary.
+    fn read_physical(&mut self, address: u64, buffer: &mut [u8]) -> io::Result<()> {
+        let offset = self
+            .memory
+            .page_offset(address / PAGE_SIZE)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "physical page {:#x} is not in the dump",
+                        address / PAGE_SIZE
+                    ),
+                )
+            })?;
+        self.file
+            .seek(SeekFrom::Start(offset.saturating_add(address % PAGE_SIZE)))?;
+        self.file.read_exact(buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{put, write};
+
+    /// Physical pages of the fixture: the four levels of page tables, then the pages
+    /// mapped at virtual addresses 0, 0x1000 and 0x2000.
+    const PAGES: u64 = 8;
+    const LIST_HEAD: u64 = 0x10;
+    const ENTRY: u64 = 0x100;
+    const IMAGE_BASE: u64 = 0x1000;
+
+    /// Writes `bytes` at virtual address `address` of the fixture.
+    fn put_virtual(data: &mut [u8], address: u64, bytes: &[u8]) {
+        put(data, HEADER_LEN as u64 + 5 * PAGE_SIZE + address, bytes);
+    }
+
+    /// Builds a complete memory dump of an x64 machine with one run of physical
+    /// memory, which has `ntoskrnl.exe` loaded at 0x1000.
+    fn kernel_dump() -> Vec<u8> {
+        let mut data = vec![0; HEADER_LEN + (PAGES * PAGE_SIZE) as usize];
+        put(&mut data, 0, DUMP64_SIGNATURE);
+        put(
+            &mut data,
+            DIRECTORY_TABLE_BASE_OFFSET as u64,
+            &PAGE_SIZE.to_le_bytes(),
+        );
+        put(
+            &mut data,
+            LOADED_MODULE_LIST_OFFSET as u64,
+            &LIST_HEAD.to_le_bytes(),
+        );
+        put(
+            &mut data,
+            MACHINE_OFFSET as u64,
+            &MACHINE_AMD64.to_le_bytes(),
+        );
+        put(
+            &mut data,
+            PHYSICAL_MEMORY_BLOCK_OFFSET as u64,
+            &1u32.to_le_bytes(),
+        );
+        put(
+            &mut data,
+            PHYSICAL_MEMORY_BLOCK_OFFSET as u64 + 16,
+            &0u64.to_le_bytes(),
+        );
+        put(
+            &mut data,
+            PHYSICAL_MEMORY_BLOCK_OFFSET as u64 + 24,
+            &PAGES.to_le_bytes(),
+        );
+        put(
+            &mut data,
+            DUMP_TYPE_OFFSET as u64,
+            &DUMP_TYPE_FULL.to_le_bytes(),
+        );
+        // Each table's first entry points at the next, and the last level maps three
+        // pages.
+        for level in 1..4 {
+            let entry = ((level + 1) * PAGE_SIZE) | 1;
+            put(
+                &mut data,
+                HEADER_LEN as u64 + level * PAGE_SIZE,
+                &entry.to_le_bytes(),
+            );
+        }
+        for page in 0..3 {
+            let entry = ((5 + page) * PAGE_SIZE) | 1;
+            put(
+                &mut data,
+                HEADER_LEN as u64 + 4 * PAGE_SIZE + page * 8,
+                &entry.to_le_bytes(),
+            );
+        }
+        // The loaded module list with a single entry.
+        let name: Vec<u8> = "\\SystemRoot\\system32\\ntoskrnl.exe"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        put_virtual(&mut data, LIST_HEAD, &ENTRY.to_le_bytes());
+        put_virtual(&mut data, ENTRY, &LIST_HEAD.to_le_bytes());
+        put_virtual(
+            &mut data,
+            ENTRY + LDR_DLL_BASE_OFFSET,
+            &IMAGE_BASE.to_le_bytes(),
+        );
+        put_virtual(
+            &mut data,
+            ENTRY + LDR_SIZE_OF_IMAGE_OFFSET,
+            &0x3000u32.to_le_bytes(),
+        );
+        put_virtual(
+            &mut data,
+            ENTRY + LDR_FULL_DLL_NAME_OFFSET,
+            &(name.len() as u16).to_le_bytes(),
+        );
+        put_virtual(
+            &mut data,
+            ENTRY + LDR_FULL_DLL_NAME_OFFSET + 8,
+            &0x200u64.to_le_bytes(),
+        );
+        put_virtual(&mut data, 0x200, &name);
+        // The headers of the image and its debug directory.
+        let mut codeview = b"RSDS".to_vec();
+        codeview.extend([0x22; 16]);
+        codeview.extend(1u32.to_le_bytes());
+        codeview.extend(b"ntkrnlmp.pdb\0");
+        put_virtual(&mut data, IMAGE_BASE + 0x3c, &0x80u32.to_le_bytes());
+        put_virtual(&mut data, IMAGE_BASE + 0x88, &0x6000_0000u32.to_le_bytes());
+        put_virtual(&mut data, IMAGE_BASE + 0x98, &0x20bu16.to_le_bytes());
+        put_virtual(
+            &mut data,
+            IMAGE_BASE + 0x98 + 112 + 48,
+            &0x1000u32.to_le_bytes(),
+        );
+        put_virtual(
+            &mut data,
+            IMAGE_BASE + 0x98 + 116 + 48,
+            &28u32.to_le_bytes(),
+        );
+        put_virtual(&mut data, 0x2000 + 12, &DEBUG_TYPE_CODEVIEW.to_le_bytes());
+        put_virtual(
+            &mut data,
+            0x2000 + 16,
+            &(codeview.len() as u32).to_le_bytes(),
+        );
+        put_virtual(&mut data, 0x2000 + 20, &0x1100u32.to_le_bytes());
+        put_virtual(&mut data, 0x2100, &codeview);
+        data
+    }
+
+    #[test]
+    fn reads_the_drivers_of_a_complete_dump() {
+        let file = write(&kernel_dump());
+        assert!(is_kernel_dump(file.path()));
+        let (arch, modules) = read_modules(file.path()).unwrap();
+        assert_eq!(arch, Architecture::X64);
+        assert_eq!(modules.len(), 1);
+        assert_eq!(modules[0].name, "\\SystemRoot\\system32\\ntoskrnl.exe");
+        assert_eq!((modules[0].base, modules[0].size), (IMAGE_BASE, 0x3000));
+        assert_eq!(modules[0].timestamp, 0x6000_0000);
+        let pdbs = dump_pdbs(file.path(), true).unwrap();
+        assert_eq!(pdbs.len(), 2);
+        assert_eq!(pdbs[0].name, "ntkrnlmp.pdb");
+        assert_eq!(pdbs[0].key(), format!("{}1", "22".repeat(16)));
+        assert_eq!(pdbs[1].key(), "600000003000");
+    }
+
+    #[test]
+    fn rejects_truncated_dumps() {
+        let data = kernel_dump();
+        assert!(read_modules(write(&data[..HEADER_LEN + 0x1800]).path()).is_err());
+        let file = write(&data[..0x100]);
+        assert!(is_kernel_dump(file.path()));
+        assert!(read_modules(file.path()).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_dumps() {
+        let mut data = kernel_dump();
+        put(&mut data, 0, b"PAGEDUMP");
+        let file = write(&data);
+        assert!(!is_kernel_dump(file.path()));
+        assert!(read_modules(file.path()).is_err());
+        // A circular module list.
+        let mut data = kernel_dump();
+        put_virtual(&mut data, ENTRY, &ENTRY.to_le_bytes());
+        assert!(read_modules(write(&data).path()).is_err());
+        // A module list head pointing out of the address space.
+        let mut data = kernel_dump();
+        put(
+            &mut data,
+            LOADED_MODULE_LIST_OFFSET as u64,
+            &u64::MAX.to_le_bytes(),
+        );
+        assert!(read_modules(write(&data).path()).is_err());
+        // A page bitmap larger than the file.
+        let mut data = kernel_dump();
+        put(&mut data, DUMP_TYPE_OFFSET as u64, &2u32.to_le_bytes());
+        put(&mut data, HEADER_LEN as u64, b"SDMPDUMP");
+        put(&mut data, HEADER_LEN as u64 + 0x30, &u64::MAX.to_le_bytes());
+        assert!(read_modules(write(&data).path()).is_err());
+    }
+
+    #[test]
+    fn tolerates_corrupt_runs() {
+        let mut data = kernel_dump();
+        put(
+            &mut data,
+            PHYSICAL_MEMORY_BLOCK_OFFSET as u64,
+            &2u32.to_le_bytes(),
+        );
+        put(
+            &mut data,
+            PHYSICAL_MEMORY_BLOCK_OFFSET as u64 + 24,
+            &u64::MAX.to_le_bytes(),
+        );
+        put(
+            &mut data,
+            PHYSICAL_MEMORY_BLOCK_OFFSET as u64 + 32,
+            &u64::MAX.to_le_bytes(),
+        );
+        put(
+            &mut data,
+            PHYSICAL_MEMORY_BLOCK_OFFSET as u64 + 40,
+            &u64::MAX.to_le_bytes(),
+        );
+        assert_eq!(read_modules(write(&data).path()).unwrap().1.len(), 1);
+    }
+}
@@ -0,0 +1,96 @@
+//! Lists of symbol server keys produced by other tools, for downloading without the
+//! binaries.
+
+use std::{
+    io::{self, Read},
+    path::Path,
+};
+
+use crate::{error::Result, macho::DSYM_KEY_PREFIX, windows::PdbMeta};
+
+/// Reads a key list from the file at `path`, or from standard input for `-`.
+///
+/// See [`parse_key_list`] for the accepted formats.
+pub fn read_key_list(path: &Path) -> Result<Vec<PdbMeta>> {
+    let text = if path == Path::new("-") {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        text
+    } else {
+        std::fs::read_to_string(path)?
+    };
+    parse_key_list(&text, path)
+}
+
+/// Parses a key list, skipping entries that are not valid.
+///
+/// Text lists have one key per line as `ntdll.pdb 1EB9FACB04C73C5DE79D35E14B2FDC231`,
+/// `ntdll.pdb/1EB9FACB04C73C5DE79D35E14B2FDC231[/ntdll.pdb]` or the tab-separated
+/// output of `scan`; lines starting with `#` are comments. JSON lists are arrays of
+/// such strings or of objects with a `name` (or `pdb_name`) and either a `key` or a
+/// `guid` and `age`, so manifests written by `fetch --manifest` and the `failed.json`
+/// retry files of `fetch` can be read back. The `path` of an object, if present, is
+/// kept as the binary referencing the PDB.
+pub fn parse_key_list(text: &str, source: &Path) -> Result<Vec<PdbMeta>> {
+    let trimmed = text.trim_start();
+    if trimmed.starts_with('[') {
+        let entries: Vec<serde_json::Value> = serde_json::from_str(trimmed)?;
+        return Ok(entries
+            .iter()
+            .filter_map(|entry| {
+                let pdb = match entry {
+                    serde_json::Value::String(line) => parse_line(line, source),
+                    entry => parse_object(entry, source),
+                };
+                if pdb.is_none() {
+                    tracing::warn!("Skipping invalid key list entry: {}", entry);
+                }
+                pdb
+            })
+            .collect());
+    }
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let pdb = parse_line(line, source);
+            if pdb.is_none() {
+                tracing::warn!("Skipping invalid key list line: {}", line);
+            }
+            pdb
+        })
+        .collect())
+}
+
+/// Parses the name and key at the start of a line.
+fn parse_line(line: &str, source: &Path) -> Option<PdbMeta> {
+    let mut fields = line
+        .split(|c: char| c.is_whitespace() || c == '/')
+        .filter(|field| !field.is_empty());
+    PdbMeta::from_key(fields.next()?, fields.next()?, source)
+}
+
+/// Parses an object with a name and a key, or a GUID and age.
+fn parse_object(entry: &serde_json::Value, source: &Path) -> Option<PdbMeta> {
+    let source = entry
+        .get("path")
+        .and_then(|path| path.as_str())
+        .map_or(source, Path::new);
+    let name = entry
+        .get("name")
+        .or_else(|| entry.get("pdb_name"))?
+        .as_str()?;
+    if let Some(key) = entry.get("key").and_then(|key| key.as_str()) {
+        return PdbMeta::from_key(name, key, source);
+    }
+    let guid = entry.get("guid")?.as_str()?;
+    let age = entry.get("age")?.as_u64()?;
+    let key = match entry.get("kind").and_then(|kind| kind.as_str()) {
+        Some("image" | "dbg") => format!("{}{:x}", guid, age),
+        Some("portablepdb") => format!("{}ffffffff", guid),
+        Some("dsym") => format!("{}{}", DSYM_KEY_PREFIX, guid),
+        _ => format!("{}{:X}", guid, age),
+    };
+    PdbMeta::from_key(name, &key, source)
+}
@@ -0,0 +1,92 @@
+//! Discover PDB keys in Windows binaries and download the matching symbols from
+//! symbol servers into a local, symstore-compatible symbol store.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use symbolfetcher::{DEFAULT_SYMBOL_SERVER, DownloadManager, HttpOptions, LocalStore, Windows};
+//!
+//! # async fn run() -> symbolfetcher::Result<()> {
+//! let pdbs = Windows::new("/mnt/windows".into()).fetch_all_pdbs()?;
+//! let store = Arc::new(LocalStore::new("symbols".into()));
+//! let client = HttpOptions::default().client()?;
+//! let manager = DownloadManager::new(store, client, vec![DEFAULT_SYMBOL_SERVER.to_string()], 8);
+//! let records = manager.run(pdbs).await;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod archive;
+pub mod authenticode;
+#[cfg(feature = "azure")]
+pub mod azure;
+pub mod breakpad;
+mod bytes;
+pub mod compress;
+pub mod container;
+pub mod debuginfod;
+pub mod dedup;
+pub mod disk;
+pub mod download;
+pub mod elf;
+pub mod error;
+pub mod ewf;
+pub mod hash;
+pub mod health;
+pub mod http;
+pub mod iso;
+pub mod kdump;
+pub mod keylist;
+pub mod live;
+pub mod lock;
+pub mod macho;
+pub mod manifest;
+pub mod merge;
+pub mod minidump;
+pub mod missing;
+pub mod msf;
+pub mod oci;
+pub mod package;
+pub mod pe;
+pub mod preflight;
+pub mod prune;
+pub mod push;
+pub mod quota;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod serve;
+#[cfg(feature = "sign")]
+pub mod sign;
+pub mod smb;
+pub mod source;
+pub mod sourcelink;
+pub mod sources;
+pub mod srcsrv;
+pub mod state;
+pub mod stats;
+pub mod store;
+pub mod symbol_path;
+pub mod symchk;
+pub mod symsorter;
+#[cfg(test)]
+mod test_util;
+pub mod transfer;
+pub mod verify;
+pub mod vhd;
+pub mod volatility;
+pub mod wim;
+pub mod windows;
+
+pub use compress::StoreCompression;
+pub use download::{
+    DEFAULT_SYMBOL_SERVER, Download, DownloadManager, DownloadRecord, DownloadStatus,
+    DownloadSummary, NUGET_SYMBOL_SERVER,
+};
+pub use error::{Error, Result};
+pub use http::{Credentials, DEFAULT_USER_AGENT, HttpClient, HttpOptions, RetryPolicy};
+pub use missing::MissingCache;
+pub use serve::SymbolServer;
+pub use source::{EmbeddedSource, HttpSource, LocalSource, SymbolSource};
+pub use state::StateDb;
+pub use store::{LocalStore, StoreLayout, StoredFile, SymbolStore};
+pub use symbol_path::SymbolPath;
+pub use windows::{Architecture, PdbMeta, Windows};
@@ -0,0 +1,185 @@
+//! Finding the modules loaded on the running Windows system.
+
+use std::path::PathBuf;
+
+use crate::error::Result;
+
+/// Returns the paths of the executables and DLLs loaded in all running processes that
+/// can be opened, plus the loaded drivers, without duplicates.
+///
+/// Processes that cannot be inspected, such as protected processes or those of other
+/// users without administrator rights, are skipped.
+#[cfg(windows)]
+pub fn loaded_modules() -> Result<Vec<PathBuf>> {
+    let mut paths = std::collections::BTreeSet::new();
+    for pid in sys::process_ids()? {
+        match sys::process_modules(pid) {
+            Ok(modules) => paths.extend(modules),
+            Err(e) => tracing::debug!("Skipping process {}: {}", pid, e),
+        }
+    }
+    let processes = paths.len();
+    match sys::device_drivers() {
+        Ok(drivers) => paths.extend(drivers.iter().filter_map(|name| driver_path(name))),
+        Err(e) => tracing::warn!("Failed to list loaded drivers: {}", e),
+    }
+    tracing::info!(
+        "Found {} loaded modules and {} drivers",
+        processes,
+        paths.len() - processes
+    );
+    Ok(paths.into_iter().collect())
+}
+
+/// Returns the paths of the loaded modules; only supported on Windows.
+#[cfg(not(windows))]
+pub fn loaded_modules() -> Result<Vec<PathBuf>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "listing loaded modules is only supported on Windows",
+    )
+    .into())
+}
+
+/// Maps the NT path of a driver, e.g. `\SystemRoot\system32\drivers\tcpip.sys` or
+/// `\??\C:\Windows\system32\drivers\foo.sys`, to a Win32 path.
+#[cfg(windows)]
+fn driver_path(name: &str) -> Option<PathBuf> {
+    if let Some(rest) = strip_prefix_ignore_case(name, r"\SystemRoot\") {
+        let root = std::env::var_os("SystemRoot").unwrap_or_else(|| r"C:\Windows".into());
+        return Some(PathBuf::from(root).join(rest));
+    }
+    if let Some(rest) = name.strip_prefix(r"\??\") {
+        return Some(PathBuf::from(rest));
+    }
+    if name.starts_with(r"\Device\") {
+        tracing::debug!("Skipping driver with device path {}", name);
+        return None;
+    }
+    if name.starts_with('\\') && !name.starts_with(r"\\") {
+        let drive = std::env::var("SystemDrive").unwrap_or_else(|_| "C:".to_string());
+        return Some(PathBuf::from(format!("{}{}", drive, name)));
+    }
+    Some(PathBuf::from(name))
+}
+
+#[cfg(windows)]
+fn strip_prefix_ignore_case<'a>(name: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = name.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then_some(&name[prefix.len()..])
+}
+
+/// Calls into the toolhelp and PSAPI functions.
+#[cfg(windows)]
+mod sys {
+    use std::{ffi::c_void, io, mem, path::PathBuf, ptr};
+
+    use windows_sys::Win32::{
+        Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE},
+        System::{
+            Diagnostics::ToolHelp::{
+                CreateToolhelp32Snapshot, MODULEENTRY32W, Module32FirstW, Module32NextW,
+                PROCESSENTRY32W, Process32FirstW, Process32NextW, TH32CS_SNAPMODULE,
+                TH32CS_SNAPMODULE32, TH32CS_SNAPPROCESS,
+            },
+            ProcessStatus::{EnumDeviceDrivers, GetDeviceDriverFileNameW},
+        },
+    };
+
+    /// Longest path returned for a driver.
+    const MAX_DRIVER_PATH: usize = 1024;
+
+    /// A toolhelp snapshot, closed on drop.
+    struct Snapshot(HANDLE);
+
+    impl Snapshot {
+        fn new(flags: u32, pid: u32) -> io::Result<Self> {
+            // SAFETY: plain call without pointers.
+            let handle = unsafe { CreateToolhelp32Snapshot(flags, pid) };
+            if handle == INVALID_HANDLE_VALUE {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self(handle))
+        }
+    }
+
+    impl Drop for Snapshot {
+        fn drop(&mut self) {
+            // SAFETY: the handle is valid and closed only once.
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    /// Returns the IDs of all running processes.
+    pub fn process_ids() -> io::Result<Vec<u32>> {
+        let snapshot = Snapshot::new(TH32CS_SNAPPROCESS, 0)?;
+        // SAFETY: PROCESSENTRY32W is plain data; dwSize is set as the API requires.
+        let mut entry: PROCESSENTRY32W = unsafe { mem::zeroed() };
+        entry.dwSize = mem::size_of::<PROCESSENTRY32W>() as u32;
+        let mut pids = Vec::new();
+        // SAFETY: the snapshot is valid and the entry is initialized.
+        let mut found = unsafe { Process32FirstW(snapshot.0, &mut entry) } != 0;
+        while found {
+            if entry.th32ProcessID != 0 {
+                pids.push(entry.th32ProcessID);
+            }
+            // SAFETY: as above.
+            found = unsafe { Process32NextW(snapshot.0, &mut entry) } != 0;
+        }
+        Ok(pids)
+    }
+
+    /// Returns the paths of the modules loaded in the process, both 64-bit and 32-bit.
+    pub fn process_modules(pid: u32) -> io::Result<Vec<PathBuf>> {
+        let snapshot = Snapshot::new(TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32, pid)?;
+        // SAFETY: MODULEENTRY32W is plain data; dwSize is set as the API requires.
+        let mut entry: MODULEENTRY32W = unsafe { mem::zeroed() };
+        entry.dwSize = mem::size_of::<MODULEENTRY32W>() as u32;
+        let mut paths = Vec::new();
+        // SAFETY: the snapshot is valid and the entry is initialized.
+        let mut found = unsafe { Module32FirstW(snapshot.0, &mut entry) } != 0;
+        while found {
+            paths.push(PathBuf::from(from_wide(&entry.szExePath)));
+            // SAFETY: as above.
+            found = unsafe { Module32NextW(snapshot.0, &mut entry) } != 0;
+        }
+        Ok(paths)
+    }
+
+    /// Returns the NT paths of the loaded drivers.
+    pub fn device_drivers() -> io::Result<Vec<String>> {
+        let mut bases: Vec<*mut c_void> = vec![ptr::null_mut(); 1024];
+        loop {
+            let size = (bases.len() * mem::size_of::<*mut c_void>()) as u32;
+            let mut needed = 0;
+            // SAFETY: the buffer holds `size` bytes.
+            if unsafe { EnumDeviceDrivers(bases.as_mut_ptr(), size, &mut needed) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let count = needed as usize / mem::size_of::<*mut c_void>();
+            if needed <= size {
+                bases.truncate(count);
+                break;
+            }
+            bases.resize(count, ptr::null_mut());
+        }
+        let mut names = Vec::with_capacity(bases.len());
+        let mut buffer = vec![0u16; MAX_DRIVER_PATH];
+        for base in bases {
+            // SAFETY: the buffer holds MAX_DRIVER_PATH characters.
+            let len = unsafe {
+                GetDeviceDriverFileNameW(base, buffer.as_mut_ptr(), MAX_DRIVER_PATH as u32)
+            };
+            if len > 0 {
+                names.push(from_wide(&buffer[..len as usize]));
+            }
+        }
+        Ok(names)
+    }
+
+    fn from_wide(wide: &[u16]) -> String {
+        let end = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+        String::from_utf16_lossy(&wide[..end])
+    }
+}
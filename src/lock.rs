@@ -0,0 +1,49 @@
+//! Advisory file locks that let concurrent runs share a store.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::error::{Error, Result};
+
+/// An exclusive lock on a lock file, released when dropped or when the process exits.
+///
+/// The locks are advisory: they only keep out other symbolfetcher runs, not other
+/// programs writing to the store.
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Locks the file at `path`, creating it if needed, and waits on a blocking
+    /// thread while another run holds the lock.
+    pub async fn acquire(path: PathBuf) -> Result<Self> {
+        tokio::task::spawn_blocking(move || {
+            Self::acquire_blocking(&path).map_err(Error::store(&path))
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+
+    /// Locks the file at `path` like [`FileLock::acquire`], blocking the calling
+    /// thread while another run holds the lock.
+    pub fn acquire_blocking(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                tracing::info!("Waiting for another run to release {}", path.display());
+                file.lock()?;
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        Ok(Self { _file: file })
+    }
+}
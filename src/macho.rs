@@ -0,0 +1,352 @@
+//! UUIDs of Mach-O binaries, which key their dSYM debug info on symbol servers.
+//!
+//! Symbol servers following the SSQP conventions store the DWARF file of a dSYM
+//! bundle as `_.dwarf/mach-uuid-sym-<uuid>/_.dwarf`, with the `LC_UUID` of the binary
+//! as lowercase hex digits.
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::{
+    bytes::{read_be_u32, read_be_u64, read_u32},
+    error::{Error, Result},
+    windows::{Architecture, PdbMeta, SymbolKind},
+};
+
+/// `MH_MAGIC`, the signature of 32-bit Mach-O files in their byte order.
+const MH_MAGIC: u32 = 0xfeed_face;
+/// `MH_MAGIC_64`
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+/// `FAT_MAGIC`, the big-endian signature of universal binaries.
+const FAT_MAGIC: u32 = 0xcafe_babe;
+/// `FAT_MAGIC_64`
+const FAT_MAGIC_64: u32 = 0xcafe_babf;
+/// `LC_UUID`
+const LC_UUID: u32 = 0x1b;
+/// Upper bound for the number of slices of a universal binary. Java class files share
+/// the signature, with their version in place of the count, which is above this.
+const MAX_FAT_ARCHS: u32 = 32;
+/// Upper bound for the size of the load commands read from a file, which keeps
+/// corrupt files from triggering huge allocations.
+const MAX_LOAD_COMMANDS_LEN: u32 = 16 * 1024 * 1024;
+/// File name of the DWARF file of a dSYM bundle on symbol servers.
+pub const DSYM_FILE_NAME: &str = "_.dwarf";
+/// Prefix of the symbol server keys of dSYM DWARF files.
+pub const DSYM_KEY_PREFIX: &str = "mach-uuid-sym-";
+
+/// The UUID of one architecture of a Mach-O binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachUuid {
+    pub arch: Architecture,
+    /// The UUID as 32 uppercase hex digits.
+    pub uuid: String,
+}
+
+/// Returns whether the file starts with the signature of a Mach-O file or universal
+/// binary, whatever its name.
+pub fn is_macho(path: &Path) -> bool {
+    let mut header = [0; 8];
+    if File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_err()
+    {
+        return false;
+    }
+    let (Some(magic), Some(count)) = (read_be_u32(&header, 0), read_be_u32(&header, 4)) else {
+        return false;
+    };
+    match magic {
+        FAT_MAGIC | FAT_MAGIC_64 => (1..=MAX_FAT_ARCHS).contains(&count),
+        _ => {
+            [MH_MAGIC, MH_MAGIC_64].contains(&magic)
+                || [MH_MAGIC, MH_MAGIC_64].contains(&magic.swap_bytes())
+        }
+    }
+}
+
+/// Reads the `LC_UUID` of every architecture of the Mach-O file or universal binary
+/// at `path`, skipping slices without one. Only the headers and load commands are
+/// read.
+pub fn read_uuids(path: &Path) -> Result<Vec<MachUuid>> {
+    let invalid = |reason: &str| Error::MachO {
+        path: path.to_path_buf(),
+        reason: reason.to_string(),
+    };
+    let mut file = File::open(path)?;
+    let mut header = [0; 8];
+    file.read_exact(&mut header)
+        .map_err(|_| invalid("not a Mach-O file"))?;
+    let (Some(magic), Some(count)) = (read_be_u32(&header, 0), read_be_u32(&header, 4)) else {
+        return Err(invalid("not a Mach-O file"));
+    };
+    let slices = match magic {
+        FAT_MAGIC | FAT_MAGIC_64 => {
+            if count > MAX_FAT_ARCHS {
+                return Err(invalid("not a universal binary"));
+            }
+            // fat_arch: cputype, cpusubtype, offset, size and align, with 64-bit
+            // offsets and sizes plus a reserved field in fat_arch_64.
+            let entry_len = if magic == FAT_MAGIC { 20 } else { 32 };
+            let mut table = vec![0; entry_len * count as usize];
+            file.read_exact(&mut table)
+                .map_err(|_| invalid("truncated universal header"))?;
+            table
+                .chunks_exact(entry_len)
+                .filter_map(|entry| {
+                    if magic == FAT_MAGIC {
+                        read_be_u32(entry, 8).map(u64::from)
+                    } else {
+                        read_be_u64(entry, 8)
+                    }
+                })
+                .collect()
+        }
+        _ => vec![0],
+    };
+    let mut uuids = Vec::new();
+    for offset in slices {
+        if let Some(uuid) = read_slice_uuid(&mut file, offset).map_err(invalid)? {
+            uuids.push(uuid);
+        }
+    }
+    Ok(uuids)
+}
+
+/// Reads the UUID of the Mach-O image starting at `offset` in `file`.
+fn read_slice_uuid(
+    file: &mut File,
+    offset: u64,
+) -> std::result::Result<Option<MachUuid>, &'static str> {
+    // mach_header: magic, cputype, cpusubtype, filetype, ncmds, sizeofcmds and flags,
+    // followed by a reserved field in mach_header_64.
+    let mut header = [0; 32];
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.read_exact(&mut header))
+        .map_err(|_| "truncated Mach-O header")?;
+    let le_magic = read_u32(&header, 0).ok_or("truncated Mach-O header")?;
+    let (little_endian, header_len) = match le_magic {
+        MH_MAGIC => (true, 28),
+        MH_MAGIC_64 => (true, 32),
+        _ => match le_magic.swap_bytes() {
+            MH_MAGIC => (false, 28),
+            MH_MAGIC_64 => (false, 32),
+            _ => return Err("not a Mach-O file"),
+        },
+    };
+    let field = |data: &[u8], at: usize| {
+        if little_endian {
+            read_u32(data, at)
+        } else {
+            read_be_u32(data, at)
+        }
+    };
+    let (Some(cputype), Some(count), Some(len)) =
+        (field(&header, 4), field(&header, 16), field(&header, 20))
+    else {
+        return Err("truncated Mach-O header");
+    };
+    let arch = match cputype {
+        7 => Architecture::X86,
+        0x0100_0007 => Architecture::X64,
+        12 => Architecture::Arm,
+        0x0100_000c | 0x0200_000c => Architecture::Arm64,
+        _ => Architecture::Unknown,
+    };
+    if len > MAX_LOAD_COMMANDS_LEN {
+        return Err("implausible load commands size");
+    }
+    let mut commands = vec![0; len as usize];
+    file.seek(SeekFrom::Start(offset + header_len))
+        .and_then(|_| file.read_exact(&mut commands))
+        .map_err(|_| "load commands outside of the file")?;
+    let mut rest = commands.as_slice();
+    for _ in 0..count {
+        let (Some(command), Some(size)) = (field(rest, 0), field(rest, 4)) else {
+            break;
+        };
+        let size = size as usize;
+        if size < 8 || size > rest.len() {
+            return Err("invalid load command size");
+        }
+        if command == LC_UUID && size >= 24 {
+            return Ok(Some(MachUuid {
+                arch,
+                uuid: hex::encode_upper(&rest[8..24]),
+            }));
+        }
+        rest = &rest[size..];
+    }
+    Ok(None)
+}
+
+/// Reads the keys of the dSYM DWARF files of every architecture of the Mach-O file
+/// at `path`.
+///
+/// Returns at least one key, or [`Error::NoDebugInfo`] for binaries without a UUID.
+pub fn dsym_keys(path: &Path) -> Result<Vec<PdbMeta>> {
+    let uuids = read_uuids(path)?;
+    if uuids.is_empty() {
+        return Err(Error::NoDebugInfo(path.to_path_buf()));
+    }
+    Ok(uuids
+        .into_iter()
+        .map(|uuid| PdbMeta {
+            path: path.to_path_buf(),
+            name: DSYM_FILE_NAME.to_string(),
+            guid: uuid.uuid,
+            age: 0,
+            kind: SymbolKind::Dsym,
+            arch: uuid.arch,
+            debug_entries: Vec::new(),
+            signer: None,
+            binary_hashes: None,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write;
+
+    const UUID: [u8; 16] = [
+        0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0xfe, 0xdc, 0xba, 0x98, 0x76, 0x54, 0x32,
+        0x10,
+    ];
+
+    /// Builds a Mach-O image of `cputype` whose load commands are a segment command
+    /// and, if given, an `LC_UUID`.
+    fn macho(is_64: bool, little_endian: bool, cputype: u32, uuid: Option<[u8; 16]>) -> Vec<u8> {
+        let word = |value: u32| {
+            if little_endian {
+                value.to_le_bytes()
+            } else {
+                value.to_be_bytes()
+            }
+        };
+        let mut commands = Vec::new();
+        commands.extend(word(0x19));
+        commands.extend(word(16));
+        commands.extend([0; 8]);
+        if let Some(uuid) = uuid {
+            commands.extend(word(LC_UUID));
+            commands.extend(word(24));
+            commands.extend(uuid);
+        }
+        let mut data = Vec::new();
+        data.extend(word(if is_64 { MH_MAGIC_64 } else { MH_MAGIC }));
+        data.extend(word(cputype));
+        data.extend(word(3));
+        data.extend(word(6));
+        data.extend(word(if uuid.is_some() { 2 } else { 1 }));
+        data.extend(word(commands.len() as u32));
+        data.extend(word(0));
+        if is_64 {
+            data.extend(word(0));
+        }
+        data.extend(commands);
+        data
+    }
+
+    /// Builds a universal binary of the given slices, each aligned to 4 KiB.
+    fn fat(slices: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = FAT_MAGIC.to_be_bytes().to_vec();
+        data.extend((slices.len() as u32).to_be_bytes());
+        for (index, slice) in slices.iter().enumerate() {
+            data.extend([0; 8]);
+            data.extend((4096 * (index as u32 + 1)).to_be_bytes());
+            data.extend((slice.len() as u32).to_be_bytes());
+            data.extend(12u32.to_be_bytes());
+        }
+        for slice in slices {
+            data.resize(data.len().div_ceil(4096) * 4096, 0);
+            data.extend(slice);
+        }
+        data
+    }
+
+    #[test]
+    fn reads_the_uuid_of_thin_binaries() {
+        let file = write(&macho(true, true, 0x0100_0007, Some(UUID)));
+        assert!(is_macho(file.path()));
+        let uuids = read_uuids(file.path()).unwrap();
+        assert_eq!(
+            uuids,
+            [MachUuid {
+                arch: Architecture::X64,
+                uuid: "0123456789ABCDEFFEDCBA9876543210".to_string(),
+            }]
+        );
+        let keys = dsym_keys(file.path()).unwrap();
+        assert_eq!(keys[0].name, DSYM_FILE_NAME);
+        assert_eq!(
+            keys[0].key(),
+            "mach-uuid-sym-0123456789abcdeffedcba9876543210"
+        );
+    }
+
+    #[test]
+    fn reads_the_uuids_of_universal_binaries() {
+        let mut other = UUID;
+        other.reverse();
+        let file = write(&fat(&[
+            macho(true, true, 0x0100_000c, Some(UUID)),
+            macho(false, false, 7, Some(other)),
+            macho(false, true, 12, None),
+        ]));
+        assert!(is_macho(file.path()));
+        let uuids = read_uuids(file.path()).unwrap();
+        assert_eq!(uuids.len(), 2);
+        assert_eq!(uuids[0].arch, Architecture::Arm64);
+        assert_eq!(uuids[1].arch, Architecture::X86);
+        assert_eq!(uuids[1].uuid, "1032547698BADCFEEFCDAB8967452301");
+    }
+
+    #[test]
+    fn rejects_binaries_without_a_uuid() {
+        let file = write(&macho(true, true, 0x0100_0007, None));
+        assert!(read_uuids(file.path()).unwrap().is_empty());
+        assert!(matches!(dsym_keys(file.path()), Err(Error::NoDebugInfo(_))));
+    }
+
+    #[test]
+    fn rejects_java_class_files() {
+        let mut class = FAT_MAGIC.to_be_bytes().to_vec();
+        class.extend([0, 0, 0, 52]);
+        class.extend([0; 64]);
+        let file = write(&class);
+        assert!(!is_macho(file.path()));
+        assert!(read_uuids(file.path()).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_binaries() {
+        let data = macho(true, true, 0x0100_0007, Some(UUID));
+        assert!(read_uuids(write(&data[..4]).path()).is_err());
+        assert!(read_uuids(write(&data[..20]).path()).is_err());
+        assert!(read_uuids(write(&data[..data.len() - 4]).path()).is_err());
+        let data = fat(&[data]);
+        assert!(read_uuids(write(&data[..20]).path()).is_err());
+        assert!(read_uuids(write(&data[..4096]).path()).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_load_commands() {
+        let data = macho(true, true, 0x0100_0007, Some(UUID));
+        let corrupt = |offset: usize, value: u32| {
+            let mut data = data.clone();
+            data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+            read_uuids(write(&data).path())
+        };
+        assert!(corrupt(0, 0x1234_5678).is_err());
+        assert!(corrupt(20, u32::MAX).is_err());
+        assert!(corrupt(32 + 4, 4).is_err());
+        assert!(corrupt(32 + 4, 1000).is_err());
+        let mut data = fat(&[data.clone()]);
+        data[16..20].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(read_uuids(write(&data).path()).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+//! JSON manifests describing the results of a run.
+
+use std::{fs, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    download::{DownloadRecord, DownloadStatus},
+    error::Result,
+    hash::FileHashes,
+    pe::DebugEntry,
+    windows::{Architecture, SymbolKind},
+};
+
+/// A machine-readable record of one scanned binary and its PDB.
+#[derive(Debug, Serialize)]
+pub struct ManifestEntry<'a> {
+    /// Path of the scanned binary.
+    pub path: &'a Path,
+    /// PDB file name referenced by the binary.
+    pub pdb_name: &'a str,
+    /// PDB signature GUID.
+    pub guid: &'a str,
+    /// PDB age.
+    pub age: u32,
+    /// Whether the entry is a PDB or the binary itself.
+    pub kind: SymbolKind,
+    /// Architecture of the binary.
+    pub arch: Architecture,
+    /// Entries of the binary's debug directory.
+    pub debug_entries: &'a [DebugEntry],
+    /// Authenticode signer of the binary, if it has an embedded signature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signer: Option<&'a str>,
+    /// URL the PDB was downloaded from in this run.
+    pub url: Option<&'a str>,
+    /// Location of the PDB in the store.
+    pub store_path: &'a Path,
+    /// Download result.
+    pub status: DownloadStatus,
+    /// Size of the stored PDB in bytes.
+    pub size: Option<u64>,
+    /// Hashes of the scanned binary, with `--hash`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binary_hashes: Option<&'a FileHashes>,
+    /// Hashes of the PDB, with `--hash`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pdb_hashes: Option<&'a FileHashes>,
+}
+
+impl<'a> From<&'a DownloadRecord> for ManifestEntry<'a> {
+    fn from(record: &'a DownloadRecord) -> Self {
+        Self {
+            path: &record.pdb.path,
+            pdb_name: &record.pdb.name,
+            guid: &record.pdb.guid,
+            age: record.pdb.age,
+            kind: record.pdb.kind,
+            arch: record.pdb.arch,
+            debug_entries: &record.pdb.debug_entries,
+            signer: record.pdb.signer.as_deref(),
+            url: record.url.as_deref(),
+            store_path: &record.store_path,
+            status: record.status,
+            size: record.size,
+            binary_hashes: record.pdb.binary_hashes.as_ref(),
+            pdb_hashes: record.pdb_hashes.as_ref(),
+        }
+    }
+}
+
+/// A PDB that failed to download, as written to the retry file by [`write_failed`].
+#[derive(Debug, Serialize)]
+pub struct FailedEntry<'a> {
+    /// PDB file name.
+    pub name: &'a str,
+    /// Symbol server key of the PDB.
+    pub key: String,
+    /// Path of the binary referencing the PDB.
+    pub path: &'a Path,
+    /// Why the download failed.
+    pub reason: &'a str,
+}
+
+/// Writes the PDBs of the records that failed, with the reasons, as a JSON array that
+/// [`read_key_list`](crate::keylist::read_key_list) reads back, so a later run can
+/// retry just these. Returns the number of PDBs written.
+pub fn write_failed(path: &Path, records: &[DownloadRecord]) -> Result<usize> {
+    let entries: Vec<FailedEntry> = records
+        .iter()
+        .filter(|record| record.status == DownloadStatus::Failed)
+        .map(|record| FailedEntry {
+            name: &record.pdb.name,
+            key: record.pdb.key(),
+            path: &record.pdb.path,
+            reason: record.error.as_deref().unwrap_or("unknown error"),
+        })
+        .collect();
+    let json = serde_json::to_string_pretty(&entries)?;
+    fs::write(path, json)?;
+    Ok(entries.len())
+}
+
+/// Writes the download records as a pretty-printed JSON array.
+pub fn write(path: &Path, records: &[DownloadRecord]) -> Result<()> {
+    let entries: Vec<ManifestEntry> = records.iter().map(ManifestEntry::from).collect();
+    let json = serde_json::to_string_pretty(&entries)?;
+    fs::write(path, json)?;
+    tracing::info!(
+        "Wrote manifest with {} entries to {}",
+        entries.len(),
+        path.display()
+    );
+    Ok(())
+}
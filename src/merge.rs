@@ -0,0 +1,113 @@
+//! Merging the files of another symbol store into a local store, e.g. stores collected
+//! on several air-gapped machines.
+
+use std::path::Path;
+
+use crate::{
+    compress::{ExpandedFile, find_compressed},
+    error::Result,
+    store::{LocalStore, StoredFile, verify_file},
+    windows::PdbMeta,
+};
+
+/// Outcome counts of [`merge`].
+#[derive(Debug, Default)]
+pub struct MergeSummary {
+    /// Files copied into the destination store.
+    pub copied: usize,
+    /// Files the destination store already had.
+    pub existing: usize,
+    /// Files skipped because they are damaged or do not match their key.
+    pub invalid: usize,
+    /// Files that could not be copied.
+    pub failed: usize,
+    /// Total size of the copied files.
+    pub bytes: u64,
+}
+
+/// Copies the files of `source` that `dest` does not have into `dest`, in either
+/// layout, keeping compressed files compressed.
+///
+/// PDBs are checked against their keys first, like `verify` does, and skipped if they
+/// are damaged. The copied files are recorded as a symstore transaction whose comment
+/// names the source store, so where they came from stays on record. With `dry_run`,
+/// the files are only logged and counted.
+pub async fn merge(source: &LocalStore, dest: &LocalStore, dry_run: bool) -> Result<MergeSummary> {
+    let comment = format!("merged from {}", source.get_root().display());
+    merge_with_comment(source, dest, dry_run, &comment).await
+}
+
+/// Merges like [`merge`], recording the transaction with `comment`.
+pub(crate) async fn merge_with_comment(
+    source: &LocalStore,
+    dest: &LocalStore,
+    dry_run: bool,
+    comment: &str,
+) -> Result<MergeSummary> {
+    let mut summary = MergeSummary::default();
+    for file in source.stored_files()? {
+        let Some(file_name) = file.path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if dest.find_file(&file.name, &file.key, &file.name).is_some()
+            || dest
+                .key_folder(&file.name, &file.key)
+                .join(file_name)
+                .is_file()
+        {
+            summary.existing += 1;
+            continue;
+        }
+        if let Err(reason) = check(source, &file) {
+            tracing::warn!("Skipping {}: {}", file.path.display(), reason);
+            summary.invalid += 1;
+            continue;
+        }
+        if dry_run {
+            tracing::info!("Would merge {}", file.path.display());
+            summary.copied += 1;
+            summary.bytes += file.size;
+            continue;
+        }
+        let _lock = dest.lock_file(&file.name, &file.key, file_name).await?;
+        match dest
+            .add_file(&file.name, &file.key, file_name, &file.path)
+            .await
+        {
+            Ok(path) => {
+                tracing::debug!("Merged {} into {}", file.path.display(), path.display());
+                summary.copied += 1;
+                summary.bytes += file.size;
+            }
+            Err(e) => {
+                tracing::warn!("Failed to merge {}: {}", file.path.display(), e);
+                summary.failed += 1;
+            }
+        }
+    }
+    if !dry_run {
+        dest.commit_transaction_with_comment(comment)?;
+    }
+    Ok(summary)
+}
+
+/// Checks a stored file against its key if it is the file the folder is named after,
+/// expanding it if it is compressed. Other files, such as `file.ptr`, are taken as
+/// they are.
+fn check(store: &LocalStore, file: &StoredFile) -> std::result::Result<(), String> {
+    let folder = store.key_folder(&file.name, &file.key);
+    let is_named_file = file
+        .path
+        .file_name()
+        .is_some_and(|name| name == file.name.as_str())
+        || find_compressed(&folder, &file.name).is_some_and(|(path, _)| path == file.path);
+    if !is_named_file {
+        return Ok(());
+    }
+    let Some(pdb) = PdbMeta::from_key(&file.name, &file.key, Path::new(&file.name)) else {
+        return Err(format!("invalid key {}", file.key));
+    };
+    let expanded = ExpandedFile::open(&folder.join(&file.name))
+        .map_err(|e| format!("failed to expand: {}", e))?;
+    verify_file(&pdb, expanded.path())
+}
@@ -0,0 +1,354 @@
+//! Reading the modules loaded in Windows minidumps.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::{
+    bytes::{read_u16, read_u32, read_u64, truncated},
+    error::{Error, Result},
+    pe::{MiscRecord, RsdsRecord},
+    windows::{Architecture, PdbMeta, SymbolKind, dbg_file_name, pdb_file_name},
+};
+
+/// Signature of minidumps, `MDMP`.
+const MINIDUMP_SIGNATURE: &[u8; 4] = b"MDMP";
+/// Size of `MINIDUMP_HEADER`.
+const HEADER_LEN: usize = 32;
+/// Size of a `MINIDUMP_DIRECTORY` entry: stream type, size and RVA.
+const DIRECTORY_ENTRY_LEN: usize = 12;
+/// Size of a `MINIDUMP_MODULE` entry.
+const MODULE_LEN: usize = 108;
+/// `ModuleListStream`
+const MODULE_LIST_STREAM: u32 = 4;
+/// `SystemInfoStream`
+const SYSTEM_INFO_STREAM: u32 = 7;
+/// Upper bound for the size of a single record read from a dump, which keeps corrupt
+/// dumps from triggering huge allocations.
+const MAX_RECORD_LEN: u32 = 64 * 1024;
+
+/// A module listed in a crash dump.
+#[derive(Debug, Clone)]
+pub struct DumpModule {
+    /// The path the module was loaded from on the crashed machine.
+    pub name: String,
+    /// The address the module was loaded at.
+    pub base: u64,
+    /// The `SizeOfImage` of the module.
+    pub size: u32,
+    /// The `TimeDateStamp` of the module's file header.
+    pub timestamp: u32,
+    /// The module's CodeView record, empty if the dump has none.
+    pub codeview: Vec<u8>,
+    /// The module's `IMAGE_DEBUG_MISC` record, empty if the dump has none.
+    pub misc: Vec<u8>,
+}
+
+/// Returns whether the file starts with the minidump signature, whatever its extension.
+pub fn is_minidump(path: &Path) -> bool {
+    let mut signature = [0; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .is_ok_and(|()| signature == *MINIDUMP_SIGNATURE)
+}
+
+/// Reads the processor architecture and the module list of a minidump.
+///
+/// Only the stream directory and the records it points to are read, so this is cheap
+/// even for full memory minidumps.
+pub fn read_modules(path: &Path) -> Result<(Architecture, Vec<DumpModule>)> {
+    let invalid = |reason: &str| Error::Dump {
+        path: path.to_path_buf(),
+        reason: reason.to_string(),
+    };
+    let mut file = File::open(path)?;
+    let header = read_at(&mut file, 0, HEADER_LEN as u32)?;
+    if header[..4] != *MINIDUMP_SIGNATURE {
+        return Err(invalid("missing MDMP signature"));
+    }
+    let (Some(streams), Some(directory)) = (read_u32(&header, 8), read_u32(&header, 12)) else {
+        return Err(invalid("truncated header"));
+    };
+
+    let mut arch = Architecture::Unknown;
+    let mut modules = None;
+    for index in 0..streams {
+        let entry = read_at(
+            &mut file,
+            u64::from(directory) + u64::from(index) * DIRECTORY_ENTRY_LEN as u64,
+            DIRECTORY_ENTRY_LEN as u32,
+        )?;
+        let (Some(kind), Some(size), Some(rva)) = (
+            read_u32(&entry, 0),
+            read_u32(&entry, 4),
+            read_u32(&entry, 8),
+        ) else {
+            return Err(invalid("truncated stream directory"));
+        };
+        match kind {
+            SYSTEM_INFO_STREAM if size >= 2 => {
+                let info = read_at(&mut file, rva.into(), 2)?;
+                arch = read_u16(&info, 0).map_or(Architecture::Unknown, architecture_of);
+            }
+            MODULE_LIST_STREAM if modules.is_none() => {
+                modules = Some(
+                    read_module_list(&mut file, rva, size)
+                        .map_err(|e| invalid(&format!("failed to read the module list: {}", e)))?,
+                );
+            }
+            _ => {}
+        }
+    }
+    let modules = modules.ok_or_else(|| invalid("no module list stream"))?;
+    tracing::debug!(
+        "Found {} modules in {} ({:?})",
+        modules.len(),
+        path.display(),
+        arch
+    );
+    Ok((arch, modules))
+}
+
+/// Reads the keys of the PDBs, and of the `.dbg` files, of all modules loaded in the
+/// minidump at `path`, plus the image keys of the modules themselves if `images` is
+/// set.
+///
+/// The keys record the dump as the binary they were read from. Modules whose CodeView
+/// record is missing or invalid are logged and skipped.
+pub fn dump_pdbs(path: &Path, images: bool) -> Result<Vec<PdbMeta>> {
+    let (arch, modules) = read_modules(path)?;
+    Ok(module_keys(path, arch, modules, images))
+}
+
+/// Reads the keys of the modules listed in the dump at `path`, see [`dump_pdbs`].
+pub(crate) fn module_keys(
+    path: &Path,
+    arch: Architecture,
+    modules: Vec<DumpModule>,
+    images: bool,
+) -> Vec<PdbMeta> {
+    let mut pdbs = Vec::new();
+    for module in modules {
+        let module_name = pdb_file_name(&module.name).to_string();
+        if !module.codeview.is_empty() {
+            match RsdsRecord::parse(&module.codeview) {
+                Ok(record) => {
+                    // Dumps do not carry the debug directory entry that tells portable
+                    // PDBs apart, so all CodeView records are Windows PDBs.
+                    match PdbMeta::from_record(path, &record, SymbolKind::Pdb, arch, Vec::new()) {
+                        Ok(pdb) => pdbs.push(pdb),
+                        Err(e) => tracing::warn!("Skipping module {}: {}", module.name, e),
+                    }
+                }
+                Err(reason) => tracing::warn!("Skipping module {}: {}", module.name, reason),
+            }
+        } else if let Ok(record) = MiscRecord::parse(&module.misc) {
+            pdbs.push(PdbMeta {
+                path: path.to_path_buf(),
+                name: dbg_file_name(&record.name),
+                guid: format!("{:08X}", module.timestamp),
+                age: module.size,
+                kind: SymbolKind::Dbg,
+                arch,
+                debug_entries: Vec::new(),
+                signer: None,
+                binary_hashes: None,
+            });
+        } else {
+            tracing::debug!("No debug record for module {}", module.name);
+        }
+        if images && module.timestamp != 0 && module.size != 0 {
+            pdbs.push(PdbMeta {
+                path: path.to_path_buf(),
+                name: module_name,
+                guid: format!("{:08X}", module.timestamp),
+                age: module.size,
+                kind: SymbolKind::Image,
+                arch,
+                debug_entries: Vec::new(),
+                signer: None,
+                binary_hashes: None,
+            });
+        }
+    }
+    pdbs
+}
+
+/// Reads the `MINIDUMP_MODULE_LIST` of `size` bytes at `rva`.
+fn read_module_list(file: &mut File, rva: u32, size: u32) -> io::Result<Vec<DumpModule>> {
+    let count =
+        read_u32(&read_at(file, rva.into(), 4)?, 0).ok_or_else(|| truncated("module list"))?;
+    if u64::from(count) * MODULE_LEN as u64 + 4 > u64::from(size) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "module count exceeds the stream",
+        ));
+    }
+    let mut modules = Vec::with_capacity(count as usize);
+    for index in 0..u64::from(count) {
+        let entry = read_at(
+            file,
+            u64::from(rva) + 4 + index * MODULE_LEN as u64,
+            MODULE_LEN as u32,
+        )?;
+        // BaseOfImage, SizeOfImage, CheckSum, TimeDateStamp and ModuleNameRva, then a
+        // VS_FIXEDFILEINFO and the locations of the CodeView and MISC records.
+        let (Some(base), Some(size), Some(timestamp), Some(name)) = (
+            read_u64(&entry, 0),
+            read_u32(&entry, 8),
+            read_u32(&entry, 16),
+            read_u32(&entry, 20),
+        ) else {
+            return Err(truncated("module entry"));
+        };
+        modules.push(DumpModule {
+            name: read_string(file, name)?,
+            base,
+            size,
+            timestamp,
+            codeview: read_location(file, &entry, 76)?,
+            misc: read_location(file, &entry, 84)?,
+        });
+    }
+    Ok(modules)
+}
+
+/// Reads the data the `MINIDUMP_LOCATION_DESCRIPTOR` at `offset` of `entry` points to.
+fn read_location(file: &mut File, entry: &[u8], offset: usize) -> io::Result<Vec<u8>> {
+    let (Some(size), Some(rva)) = (read_u32(entry, offset), read_u32(entry, offset + 4)) else {
+        return Err(truncated("location descriptor"));
+    };
+    if size == 0 || rva == 0 {
+        return Ok(Vec::new());
+    }
+    read_at(file, rva.into(), size)
+}
+
+/// Reads the `MINIDUMP_STRING` at `rva`: a length in bytes followed by UTF-16.
+fn read_string(file: &mut File, rva: u32) -> io::Result<String> {
+    let length = read_u32(&read_at(file, rva.into(), 4)?, 0).ok_or_else(|| truncated("string"))?;
+    let data = read_at(file, u64::from(rva) + 4, length)?;
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .collect();
+    Ok(String::from_utf16_lossy(&units))
+}
+
+/// Reads `len` bytes at `offset`.
+fn read_at(file: &mut File, offset: u64, len: u32) -> io::Result<Vec<u8>> {
+    if len > MAX_RECORD_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("record of {} bytes at offset {} is too large", len, offset),
+        ));
+    }
+    let mut data = vec![0; len as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Maps the `ProcessorArchitecture` of `MINIDUMP_SYSTEM_INFO` to an architecture.
+fn architecture_of(processor: u16) -> Architecture {
+    match processor {
+        0 => Architecture::X86,
+        5 => Architecture::Arm,
+        9 => Architecture::X64,
+        12 => Architecture::Arm64,
+        _ => Architecture::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{put, write};
+
+    /// Builds a minidump of an x64 machine with one module, `foo.dll`, whose CodeView
+    /// record names `foo.pdb`.
+    fn minidump() -> Vec<u8> {
+        let name: Vec<u8> = "C:\\Windows\\foo.dll"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let mut codeview = b"RSDS".to_vec();
+        codeview.extend([0x11; 16]);
+        codeview.extend(3u32.to_le_bytes());
+        codeview.extend(b"d:\\build\\foo.pdb\0");
+        let mut data = vec![0; 216];
+        put(&mut data, 0, MINIDUMP_SIGNATURE);
+        put(&mut data, 8, &2u32.to_le_bytes());
+        put(&mut data, 12, &32u32.to_le_bytes());
+        put(&mut data, 32, &SYSTEM_INFO_STREAM.to_le_bytes());
+        put(&mut data, 36, &8u32.to_le_bytes());
+        put(&mut data, 40, &56u32.to_le_bytes());
+        put(&mut data, 44, &MODULE_LIST_STREAM.to_le_bytes());
+        put(&mut data, 48, &(4 + MODULE_LEN as u32).to_le_bytes());
+        put(&mut data, 52, &64u32.to_le_bytes());
+        put(&mut data, 56, &9u16.to_le_bytes());
+        put(&mut data, 64, &1u32.to_le_bytes());
+        let module = 68;
+        put(&mut data, module, &0x7ff8_0000_0000u64.to_le_bytes());
+        put(&mut data, module + 8, &0x2000u32.to_le_bytes());
+        put(&mut data, module + 16, &0x5f00_0001u32.to_le_bytes());
+        put(&mut data, module + 20, &176u32.to_le_bytes());
+        put(
+            &mut data,
+            module + 76,
+            &(codeview.len() as u32).to_le_bytes(),
+        );
+        put(&mut data, module + 80, &216u32.to_le_bytes());
+        put(&mut data, 176, &(name.len() as u32).to_le_bytes());
+        put(&mut data, 180, &name);
+        data.extend(codeview);
+        data
+    }
+
+    #[test]
+    fn reads_the_modules_of_a_minidump() {
+        let file = write(&minidump());
+        assert!(is_minidump(file.path()));
+        let (arch, modules) = read_modules(file.path()).unwrap();
+        assert_eq!(arch, Architecture::X64);
+        assert_eq!(modules.len(), 1);
+        assert_eq!(modules[0].name, "C:\\Windows\\foo.dll");
+        assert_eq!(modules[0].base, 0x7ff8_0000_0000);
+        let pdbs = dump_pdbs(file.path(), true).unwrap();
+        assert_eq!(pdbs.len(), 2);
+        assert_eq!(pdbs[0].name, "foo.pdb");
+        assert_eq!(pdbs[0].key(), format!("{}3", "11".repeat(16)));
+        assert_eq!(
+            (pdbs[1].name.as_str(), pdbs[1].kind),
+            ("foo.dll", SymbolKind::Image)
+        );
+        assert_eq!(pdbs[1].key(), "5F0000012000");
+    }
+
+    #[test]
+    fn rejects_truncated_minidumps() {
+        let mut data = minidump();
+        data.truncate(150);
+        let file = write(&data);
+        assert!(is_minidump(file.path()));
+        assert!(read_modules(file.path()).is_err());
+        assert!(read_modules(write(&data[..20]).path()).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_minidumps() {
+        let mut data = minidump();
+        put(&mut data, 0, b"MDMX");
+        let file = write(&data);
+        assert!(!is_minidump(file.path()));
+        assert!(read_modules(file.path()).is_err());
+        let mut data = minidump();
+        put(&mut data, 64, &u32::MAX.to_le_bytes());
+        assert!(read_modules(write(&data).path()).is_err());
+        let mut data = minidump();
+        put(&mut data, 176, &u32::MAX.to_le_bytes());
+        assert!(read_modules(write(&data).path()).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+//! Remembering PDBs the symbol servers do not have.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{error::Result, windows::PdbMeta};
+
+/// File in the store root holding the negative cache.
+const MISSING_FILE: &str = "symbolfetcher-missing.json";
+
+/// How long a PDB is assumed to stay missing when no TTL is given.
+pub const DEFAULT_MISSING_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// A negative cache of PDB keys that were not found on any server.
+///
+/// Resource-only DLLs and MUI files never have symbols, so requesting them on every run
+/// only wastes time. Entries are keyed by name, GUID and age, store when the PDB was
+/// last found missing and are ignored once they are older than the TTL.
+#[derive(Debug)]
+pub struct MissingCache {
+    path: Option<PathBuf>,
+    ttl: Duration,
+    entries: Mutex<HashMap<String, u64>>,
+}
+
+impl Default for MissingCache {
+    /// Creates an empty cache that is kept in memory only.
+    fn default() -> Self {
+        Self {
+            path: None,
+            ttl: DEFAULT_MISSING_TTL,
+            entries: Mutex::default(),
+        }
+    }
+}
+
+impl MissingCache {
+    /// Loads the cache kept in the store at `root`, dropping entries older than `ttl`.
+    ///
+    /// A missing or unreadable cache file results in an empty cache.
+    pub fn load(root: &Path, ttl: Duration) -> Self {
+        let path = root.join(MISSING_FILE);
+        let mut entries: HashMap<String, u64> = fs::read(&path)
+            .ok()
+            .and_then(|json| match serde_json::from_slice(&json) {
+                Ok(entries) => Some(entries),
+                Err(e) => {
+                    tracing::warn!("Ignoring unreadable {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .unwrap_or_default();
+        let now = now();
+        entries.retain(|_, &mut seen| now.saturating_sub(seen) < ttl.as_secs());
+        tracing::info!("{} PDBs are known to be missing", entries.len());
+        Self {
+            path: Some(path),
+            ttl,
+            entries: Mutex::new(entries),
+        }
+    }
+
+    /// Forgets all entries, so every PDB is requested again.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Returns whether the PDB was found missing within the TTL.
+    pub fn contains(&self, pdb: &PdbMeta) -> bool {
+        self.lock()
+            .get(&key(pdb))
+            .is_some_and(|&seen| now().saturating_sub(seen) < self.ttl.as_secs())
+    }
+
+    /// Records that no server has the PDB.
+    pub fn insert(&self, pdb: &PdbMeta) {
+        self.lock().insert(key(pdb), now());
+    }
+
+    /// Forgets the PDB, e.g. because it was downloaded after all.
+    pub fn remove(&self, pdb: &PdbMeta) {
+        self.lock().remove(&key(pdb));
+    }
+
+    /// Writes the cache back to the store it was loaded from.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_vec_pretty(&*self.lock())?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, u64>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn key(pdb: &PdbMeta) -> String {
+    format!("{}/{}", pdb.name, pdb.key())
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
@@ -0,0 +1,61 @@
+//! Sanity checks for PDB files in the multi-stream file (MSF) format.
+
+use std::{fs::File, io::Read, path::Path};
+
+/// Signature of MSF 7.0 files as written by all current Microsoft toolchains.
+const MSF7_MAGIC: &[u8; 32] = b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0";
+/// Signature of the older MSF 2.0 format.
+const MSF2_MAGIC: &[u8] = b"Microsoft C/C++ program database 2.00\r\n\x1aJG\0\0";
+/// Signature of portable PDBs (ECMA-335 metadata).
+const PORTABLE_MAGIC: &[u8; 4] = b"BSJB";
+
+/// Checks that the file at `path` looks like a complete PDB.
+///
+/// Returns a description of the problem for empty files, files without a known PDB
+/// signature (e.g. HTML error pages), and MSF 7.0 files shorter than their header
+/// claims, as left behind by interrupted downloads.
+pub fn check_file(path: &Path) -> Result<(), String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let len = file.metadata().map_err(|e| e.to_string())?.len();
+    if len == 0 {
+        return Err("file is empty".to_string());
+    }
+    let mut header = [0u8; 56];
+    let read = file.read(&mut header).map_err(|e| e.to_string())?;
+    let header = &header[..read];
+
+    if header.starts_with(MSF7_MAGIC) && header.len() >= 44 {
+        let block_size = u32::from_le_bytes([header[32], header[33], header[34], header[35]]);
+        let num_blocks = u32::from_le_bytes([header[40], header[41], header[42], header[43]]);
+        let expected = u64::from(block_size) * u64::from(num_blocks);
+        if len < expected {
+            return Err(format!("truncated: {} of {} bytes", len, expected));
+        }
+        Ok(())
+    } else if header.starts_with(MSF2_MAGIC) || header.starts_with(PORTABLE_MAGIC) {
+        Ok(())
+    } else {
+        Err("not a PDB file".to_string())
+    }
+}
+
+/// Reads the symbol server key (GUID and age) of an MSF 7.0 PDB.
+///
+/// The GUID comes from the PDB information stream and the age from the DBI stream, as
+/// the age in the information stream is bumped on every incremental link. Returns
+/// `None` for files in other formats, which carry no such key.
+pub fn read_key(path: &Path) -> Result<Option<(String, u32)>, String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let mut magic = [0u8; 32];
+    if file.read_exact(&mut magic).is_err() || &magic != MSF7_MAGIC {
+        return Ok(None);
+    }
+    let mut pdb =
+        pdb::PDB::open(File::open(path).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+    let info = pdb.pdb_information().map_err(|e| e.to_string())?;
+    let age = match pdb.debug_information() {
+        Ok(dbi) => dbi.age().unwrap_or(info.age),
+        Err(_) => info.age,
+    };
+    Ok(Some((format!("{:X}", info.guid.simple()), age)))
+}
@@ -0,0 +1,639 @@
+//! Windows container images: `docker save` tarballs, OCI image layouts and images
+//! pulled from a registry, whose layers are applied to get the Windows directory.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use flate2::read::GzDecoder;
+use reqwest::{
+    StatusCode,
+    header::{ACCEPT, WWW_AUTHENTICATE},
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tracing::info;
+
+use crate::{
+    archive::{ArchiveKind, GZIP_SIGNATURE},
+    container::{Container, ContainerOptions, Unpacked},
+    error::{Error, Result},
+    http::HttpClient,
+    package::sanitize,
+    windows::FileFilter,
+};
+
+/// Registry of image references that do not name one, Docker Hub.
+const DEFAULT_REGISTRY: &str = "docker.io";
+/// Host serving the registry API of Docker Hub.
+const DOCKER_HUB_HOST: &str = "registry-1.docker.io";
+/// Media types of image indexes and manifests accepted from registries.
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+     application/vnd.docker.distribution.manifest.list.v2+json, \
+     application/vnd.oci.image.manifest.v1+json, \
+     application/vnd.docker.distribution.manifest.v2+json";
+/// Files marking an image tarball: `manifest.json` of `docker save` and `oci-layout`
+/// of OCI image layouts.
+const IMAGE_MARKERS: &[&str] = &["manifest.json", "oci-layout"];
+/// Directory of Windows layers holding the container's filesystem; the others hold
+/// registry hives and the utility VM.
+const LAYER_FILES: &str = "Files/";
+/// Prefix of whiteout entries, which delete a file of a lower layer.
+const WHITEOUT_PREFIX: &str = ".wh.";
+/// Whiteout entry that hides everything lower layers put in its directory.
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+/// Signature of zstd frames, for layers of media type `tar+zstd`.
+const ZSTD_SIGNATURE: &[u8; 4] = b"\x28\xb5\x2f\xfd";
+/// Deepest nesting of image indexes followed to an image manifest.
+const MAX_INDEX_DEPTH: usize = 4;
+
+/// A reference to an image in a registry, such as
+/// `mcr.microsoft.com/windows/servercore:ltsc2022`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageReference {
+    /// Host of the registry, e.g. `mcr.microsoft.com`.
+    pub registry: String,
+    /// Repository in the registry, e.g. `windows/servercore`.
+    pub repository: String,
+    /// Tag or `sha256:` digest of the image.
+    pub reference: String,
+}
+
+impl ImageReference {
+    /// Parses a reference the way `docker pull` takes it: images without a registry
+    /// are on Docker Hub, and images without a tag or digest are `latest`.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (name, reference) = match text.split_once('@') {
+            Some((name, digest)) => (name, digest),
+            None => match text.rsplit_once(':') {
+                Some((name, tag)) if !tag.contains('/') => (name, tag),
+                _ => (text, "latest"),
+            },
+        };
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, repository)) if host.contains(['.', ':']) || host == "localhost" => {
+                (host, repository.to_string())
+            }
+            _ if !name.contains('/') => (DEFAULT_REGISTRY, format!("library/{}", name)),
+            _ => (DEFAULT_REGISTRY, name.to_string()),
+        };
+        let valid_repository = repository.split('/').all(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-".contains(c))
+        });
+        let valid_reference = !reference.is_empty()
+            && reference
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "._-:".contains(c));
+        (valid_repository && valid_reference).then(|| Self {
+            registry: registry.to_string(),
+            repository,
+            reference: reference.to_string(),
+        })
+    }
+
+    /// Returns the URL of the repository in the registry API.
+    fn repository_url(&self) -> String {
+        let host = if self.registry == DEFAULT_REGISTRY {
+            DOCKER_HUB_HOST
+        } else {
+            &self.registry
+        };
+        format!("https://{}/v2/{}", host, self.repository)
+    }
+}
+
+impl fmt::Display for ImageReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let separator = if self.reference.contains(':') {
+            '@'
+        } else {
+            ':'
+        };
+        write!(
+            f,
+            "{}/{}{}{}",
+            self.registry, self.repository, separator, self.reference
+        )
+    }
+}
+
+/// The `manifest.json` entry of an image written by `docker save`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SavedImage {
+    /// Paths of the layer tarballs, lowest first.
+    layers: Vec<String>,
+}
+
+/// An OCI image index or image manifest, or their Docker equivalents.
+#[derive(Debug, Default, Deserialize)]
+struct Manifest {
+    /// The manifests of an index, one per platform.
+    #[serde(default)]
+    manifests: Vec<Descriptor>,
+    /// The layers of an image manifest, lowest first.
+    #[serde(default)]
+    layers: Vec<Descriptor>,
+}
+
+/// A reference to a blob in an index or manifest.
+#[derive(Debug, Deserialize)]
+struct Descriptor {
+    digest: String,
+    #[serde(default)]
+    platform: Option<Platform>,
+    /// Where foreign layers, such as older Windows base layers, are downloaded from.
+    #[serde(default)]
+    urls: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Platform {
+    os: String,
+    architecture: String,
+}
+
+/// A Windows container image saved as a `docker save` tarball or as an OCI image
+/// layout, a directory or a tarball.
+#[derive(Debug)]
+pub struct ContainerImage {
+    path: PathBuf,
+}
+
+impl ContainerImage {
+    /// Reads the image at `path`, a tarball or an OCI image layout directory.
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl Container for ContainerImage {
+    /// Applies the image's layers to `dest`, extracting the files below `Windows` that
+    /// the filter accepts, and returns that Windows directory as an installation.
+    ///
+    /// Tarballs are extracted into a temporary directory below `dest` first, which is
+    /// removed once the layers are applied.
+    fn unpack(&mut self, dest: &Path, options: &ContainerOptions) -> Result<Unpacked> {
+        let extracted;
+        let layout = if self.path.is_dir() {
+            self.path.as_path()
+        } else {
+            extracted = tempfile::tempdir_in(dest)?;
+            info!("Extracting the layers of {}", self.path.display());
+            tar::Archive::new(decompress(File::open(&self.path)?)?).unpack(extracted.path())?;
+            extracted.path()
+        };
+        let layers = layout_layers(layout)?;
+        for (index, layer) in layers.iter().enumerate() {
+            info!(
+                "Applying layer {}/{} of {}",
+                index + 1,
+                layers.len(),
+                self.path.display()
+            );
+            apply_layer(layer, dest, &options.filter)?;
+        }
+        let windows = dest.join("Windows");
+        if !windows.join("System32").is_dir() {
+            return Err(Error::MissingInImage("Windows\\System32".to_string()));
+        }
+        Ok(Unpacked {
+            files: Vec::new(),
+            installations: vec![windows],
+        })
+    }
+}
+
+/// Returns whether the directory is an OCI image layout or the file a tarball of an
+/// image, as written by `docker save`.
+///
+/// Only the tar headers are read, seeking past the entries, and compressed tarballs
+/// are not recognized.
+pub fn is_image(path: &Path) -> bool {
+    if path.is_dir() {
+        return path.join("oci-layout").is_file();
+    }
+    if ArchiveKind::detect(path) != Some(ArchiveKind::Tar) {
+        return false;
+    }
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    let mut archive = tar::Archive::new(file);
+    let Ok(mut entries) = archive.entries_with_seek() else {
+        return false;
+    };
+    entries.any(|entry| {
+        entry.is_ok_and(|entry| {
+            entry
+                .path()
+                .is_ok_and(|name| IMAGE_MARKERS.iter().any(|marker| name == Path::new(marker)))
+        })
+    })
+}
+
+/// Pulls the image from its registry into an OCI image layout at `dest`, which
+/// [`ContainerImage`] reads.
+///
+/// Of multi-platform images, the Windows amd64 image is pulled. Registries are
+/// accessed anonymously or with the credentials the client has for them, which is
+/// enough for public images such as the Windows base images on `mcr.microsoft.com`.
+/// Layers are checked against their digests.
+pub async fn pull_image(
+    client: &HttpClient,
+    reference: &ImageReference,
+    dest: &Path,
+) -> Result<()> {
+    let mut registry = Registry {
+        client,
+        reference,
+        token: None,
+    };
+    let repository = reference.repository_url();
+    let mut tag = reference.reference.clone();
+    let mut body = Vec::new();
+    let mut manifest = Manifest::default();
+    for _ in 0..MAX_INDEX_DEPTH {
+        let url = format!("{}/manifests/{}", repository, tag);
+        body = registry
+            .get(&url, MANIFEST_TYPES)
+            .await?
+            .bytes()
+            .await?
+            .to_vec();
+        manifest = serde_json::from_slice(&body)?;
+        if manifest.manifests.is_empty() {
+            break;
+        }
+        tag = select_manifest(&manifest.manifests)
+            .ok_or_else(|| invalid(reference, "the image index lists no images"))?
+            .digest
+            .clone();
+    }
+    if manifest.layers.is_empty() {
+        return Err(invalid(reference, "no image manifest with layers"));
+    }
+
+    fs::create_dir_all(dest.join("blobs").join("sha256"))?;
+    for (index, layer) in manifest.layers.iter().enumerate() {
+        info!(
+            "Downloading layer {}/{} of {} ({})",
+            index + 1,
+            manifest.layers.len(),
+            reference,
+            layer.digest
+        );
+        let path = blob_path(dest, &layer.digest)
+            .ok_or_else(|| invalid(reference, &format!("invalid digest {}", layer.digest)))?;
+        registry.download_layer(layer, &path).await?;
+    }
+    let digest = format!("sha256:{}", hex::encode(Sha256::digest(&body)));
+    fs::write(dest.join("blobs").join("sha256").join(&digest[7..]), &body)?;
+    let index = serde_json::json!({
+        "schemaVersion": 2,
+        "manifests": [{ "digest": digest, "size": body.len() }],
+    });
+    fs::write(dest.join("index.json"), serde_json::to_vec(&index)?)?;
+    fs::write(
+        dest.join("oci-layout"),
+        br#"{"imageLayoutVersion":"1.0.0"}"#,
+    )?;
+    Ok(())
+}
+
+/// A registry session, holding the token the registry handed out.
+struct Registry<'a> {
+    client: &'a HttpClient,
+    reference: &'a ImageReference,
+    token: Option<String>,
+}
+
+impl Registry<'_> {
+    /// Requests `url` from the registry, fetching a token and retrying if the registry
+    /// asks for one.
+    async fn get(&mut self, url: &str, accept: &str) -> Result<reqwest::Response> {
+        let response = self.send(url, accept).await?;
+        if response.status() != StatusCode::UNAUTHORIZED || self.token.is_some() {
+            return check_status(response);
+        }
+        let challenge = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        self.token = Some(self.authenticate(&challenge).await?);
+        check_status(self.send(url, accept).await?)
+    }
+
+    async fn send(&self, url: &str, accept: &str) -> Result<reqwest::Response> {
+        let mut request = self.client.get(url).header(ACCEPT, accept);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        Ok(request.send().await?)
+    }
+
+    /// Fetches a pull token from the realm of a `Bearer` challenge.
+    async fn authenticate(&self, challenge: &str) -> Result<String> {
+        let params = parse_challenge(challenge);
+        let realm = params.get("realm").ok_or_else(|| {
+            invalid(
+                self.reference,
+                "the registry asks for unsupported authentication",
+            )
+        })?;
+        let default_scope = format!("repository:{}:pull", self.reference.repository);
+        let query = [
+            ("service", params.get("service").map(String::as_str)),
+            (
+                "scope",
+                Some(
+                    params
+                        .get("scope")
+                        .map_or(default_scope.as_str(), String::as_str),
+                ),
+            ),
+        ];
+        let query: Vec<(&str, &str)> = query
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value?)))
+            .collect();
+        let response = check_status(self.client.get(realm).query(&query).send().await?)?;
+
+        #[derive(Deserialize)]
+        struct Token {
+            token: Option<String>,
+            access_token: Option<String>,
+        }
+        let token: Token = serde_json::from_slice(&response.bytes().await?)?;
+        token
+            .token
+            .or(token.access_token)
+            .ok_or_else(|| invalid(self.reference, "the registry returned no token"))
+    }
+
+    /// Downloads a layer to `dest`, from the URLs of foreign layers first, and checks
+    /// its digest.
+    async fn download_layer(&mut self, layer: &Descriptor, dest: &Path) -> Result<()> {
+        for url in &layer.urls {
+            // Foreign URLs are on other hosts, so they do not get the registry's token.
+            let result = match self.client.get(url).send().await {
+                Ok(response) => match check_status(response) {
+                    Ok(response) => self.save_blob(response, &layer.digest, dest).await,
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) => tracing::warn!("Failed to download {}: {}", url, e),
+            }
+        }
+        let url = format!("{}/blobs/{}", self.reference.repository_url(), layer.digest);
+        let response = self.get(&url, "*/*").await?;
+        self.save_blob(response, &layer.digest, dest).await
+    }
+
+    /// Writes the response body to `dest`, failing if its SHA-256 is not `digest`.
+    async fn save_blob(
+        &self,
+        mut response: reqwest::Response,
+        digest: &str,
+        dest: &Path,
+    ) -> Result<()> {
+        let mut file = tokio::fs::File::create(dest).await?;
+        let mut hasher = Sha256::new();
+        while let Some(chunk) = response.chunk().await? {
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+            self.client.throttle(chunk.len() as u64).await;
+        }
+        file.flush().await?;
+        let actual = format!("sha256:{}", hex::encode(hasher.finalize()));
+        if actual != digest {
+            return Err(invalid(
+                self.reference,
+                &format!("layer {} has digest {}", digest, actual),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the layer tarballs of the image in an extracted `docker save` tarball or
+/// OCI image layout, lowest first.
+fn layout_layers(layout: &Path) -> Result<Vec<PathBuf>> {
+    let location = layout.display().to_string();
+    let saved = layout.join("manifest.json");
+    if saved.is_file() {
+        let images: Vec<SavedImage> = serde_json::from_slice(&fs::read(saved)?)?;
+        if images.len() > 1 {
+            tracing::warn!("Only scanning the first of {} saved images", images.len());
+        }
+        let image = images
+            .into_iter()
+            .next()
+            .ok_or_else(|| invalid(&location, "manifest.json lists no images"))?;
+        return image
+            .layers
+            .iter()
+            .map(|layer| {
+                sanitize(layer)
+                    .map(|layer| layout.join(layer))
+                    .ok_or_else(|| invalid(&location, &format!("invalid layer path {}", layer)))
+            })
+            .collect();
+    }
+    let mut manifest: Manifest = serde_json::from_slice(&fs::read(layout.join("index.json"))?)?;
+    for _ in 0..MAX_INDEX_DEPTH {
+        if manifest.manifests.is_empty() {
+            break;
+        }
+        let digest = &select_manifest(&manifest.manifests)
+            .ok_or_else(|| invalid(&location, "the image index lists no images"))?
+            .digest;
+        let blob = blob_path(layout, digest)
+            .ok_or_else(|| invalid(&location, &format!("invalid digest {}", digest)))?;
+        manifest = serde_json::from_slice(&fs::read(blob)?)?;
+    }
+    manifest
+        .layers
+        .iter()
+        .map(|layer| {
+            blob_path(layout, &layer.digest)
+                .ok_or_else(|| invalid(&location, &format!("invalid digest {}", layer.digest)))
+        })
+        .collect()
+}
+
+/// Applies a layer tarball to `dest`: extracts the files below `Windows` that `filter`
+/// accepts and deletes the files its whiteouts hide.
+///
+/// Hard links are recreated, as Windows base layers link most of System32 to the
+/// component store, and fall back to copies.
+fn apply_layer(layer: &Path, dest: &Path, filter: &FileFilter) -> Result<()> {
+    let mut archive = tar::Archive::new(decompress(File::open(layer)?)?);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        // Registry hives, the utility VM and the files of Linux layers are not needed.
+        let Some(relative) = name.strip_prefix(LAYER_FILES).and_then(sanitize) else {
+            continue;
+        };
+        let target = dest.join(&relative);
+        let file_name = relative
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        if file_name == OPAQUE_WHITEOUT {
+            if let Some(directory) = target.parent() {
+                remove_path(directory)?;
+            }
+            continue;
+        }
+        if let Some(hidden) = file_name.strip_prefix(WHITEOUT_PREFIX) {
+            remove_path(&target.with_file_name(hidden))?;
+            continue;
+        }
+        let kind = entry.header().entry_type();
+        if !(kind.is_file() || kind.is_hard_link())
+            || !relative.starts_with("Windows")
+            || !filter.accepts_name(&target)
+        {
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if kind.is_file() {
+            io::copy(&mut entry, &mut File::create(&target)?)?;
+            continue;
+        }
+        let source = entry
+            .link_name()?
+            .and_then(|link| {
+                let link = link.to_string_lossy().into_owned();
+                link.strip_prefix(LAYER_FILES).and_then(sanitize)
+            })
+            .map(|link| dest.join(link));
+        match source {
+            Some(source) if source.is_file() => {
+                if fs::hard_link(&source, &target).is_err() {
+                    fs::copy(&source, &target)?;
+                }
+            }
+            // The link target was not extracted, e.g. because it is outside Windows.
+            _ => tracing::debug!("Skipping hard link {}", name),
+        }
+    }
+    Ok(())
+}
+
+/// Returns a reader of the tarball, decompressing gzip and zstd by their signature.
+fn decompress(mut file: File) -> Result<Box<dyn Read>> {
+    let mut signature = [0; 4];
+    let read = file.read(&mut signature)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(if read >= 2 && signature[..2] == *GZIP_SIGNATURE {
+        Box::new(GzDecoder::new(file))
+    } else if read == 4 && signature == *ZSTD_SIGNATURE {
+        Box::new(zstd::Decoder::new(file)?)
+    } else {
+        Box::new(file)
+    })
+}
+
+/// Picks the Windows amd64 image of an index, or the first Windows image, or the first
+/// image if the index does not say which platforms they are for.
+fn select_manifest(manifests: &[Descriptor]) -> Option<&Descriptor> {
+    let windows = |manifest: &&Descriptor| {
+        manifest
+            .platform
+            .as_ref()
+            .is_some_and(|platform| platform.os == "windows")
+    };
+    manifests
+        .iter()
+        .filter(windows)
+        .find(|manifest| {
+            manifest
+                .platform
+                .as_ref()
+                .is_some_and(|platform| platform.architecture == "amd64")
+        })
+        .or_else(|| manifests.iter().find(windows))
+        .or_else(|| manifests.first())
+}
+
+/// Returns the path of the blob with `digest` in an OCI image layout, or `None` for
+/// malformed digests.
+fn blob_path(layout: &Path, digest: &str) -> Option<PathBuf> {
+    let (algorithm, hash) = digest.split_once(':')?;
+    let valid = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric());
+    (valid(algorithm) && valid(hash)).then(|| layout.join("blobs").join(algorithm).join(hash))
+}
+
+/// Parses the parameters of a `WWW-Authenticate: Bearer realm="...",service="..."`
+/// challenge.
+fn parse_challenge(challenge: &str) -> HashMap<String, String> {
+    let Some(params) = challenge.strip_prefix("Bearer ") else {
+        return HashMap::new();
+    };
+    // Scopes may contain commas, so only split on commas outside of quotes.
+    let mut quoted = false;
+    params
+        .split(|c| {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            c == ',' && !quoted
+        })
+        .filter_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            Some((
+                key.trim().to_ascii_lowercase(),
+                value.trim().trim_matches('"').to_string(),
+            ))
+        })
+        .collect()
+}
+
+/// Deletes the file or directory at `path` if it exists.
+fn remove_path(path: &Path) -> io::Result<()> {
+    let result = if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    match result {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn check_status(response: reqwest::Response) -> Result<reqwest::Response> {
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        Err(Error::HttpStatus {
+            url: response.url().to_string(),
+            status: response.status(),
+        })
+    }
+}
+
+fn invalid(location: &impl fmt::Display, reason: &str) -> Error {
+    Error::ContainerImage {
+        location: location.to_string(),
+        reason: reason.to_string(),
+    }
+}
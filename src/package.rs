@@ -0,0 +1,80 @@
+//! Extracting Windows update packages (MSU and CAB files).
+
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{container::has_container_extension, error::Result, windows::FileFilter};
+
+/// Extensions of packages.
+const PACKAGE_EXTENSIONS: &[&str] = &["cab", "msu"];
+/// Signature of CAB files, which MSU files are too.
+const CAB_SIGNATURE: &[u8; 4] = b"MSCF";
+
+/// Returns whether the file is an MSU or CAB package, by its extension or signature.
+pub fn is_package(path: &Path) -> bool {
+    let has_extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            PACKAGE_EXTENSIONS
+                .iter()
+                .any(|package| package.eq_ignore_ascii_case(ext))
+        });
+    if has_extension {
+        return true;
+    }
+    let mut signature = [0; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut signature))
+        .is_ok_and(|()| signature == *CAB_SIGNATURE)
+}
+
+/// Extracts the files that `filter` accepts from the package at `path` into `dest`,
+/// plus the packages and other containers nested in it, and returns their paths.
+///
+/// Express updates, whose payload is a PSF file of deltas, yield no binaries.
+pub fn extract_package(path: &Path, dest: &Path, filter: &FileFilter) -> Result<Vec<PathBuf>> {
+    let mut cabinet = cab::Cabinet::new(File::open(path)?)?;
+    let names: Vec<String> = cabinet
+        .folder_entries()
+        .flat_map(|folder| folder.file_entries())
+        .map(|file| file.name().to_string())
+        .collect();
+    let mut files = Vec::new();
+    for name in names {
+        let Some(relative) = sanitize(&name) else {
+            tracing::warn!("Skipping {} in {}: unsafe path", name, path.display());
+            continue;
+        };
+        let target = dest.join(&relative);
+        if !filter.accepts_name(&target) && !has_container_extension(&target) {
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut cabinet.read_file(&name)?, &mut File::create(&target)?)?;
+        files.push(target);
+    }
+    Ok(files)
+}
+
+/// Turns a path stored in a package or archive, with `\` or `/` separators, into a
+/// relative path that cannot escape the extraction directory.
+pub(crate) fn sanitize(name: &str) -> Option<PathBuf> {
+    let path: PathBuf = name
+        .split(['\\', '/'])
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect();
+    if path.as_os_str().is_empty()
+        || path
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(path)
+}
@@ -0,0 +1,858 @@
+//! Reading the debug directory of PE images.
+
+use std::{
+    fmt, fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use exe::{Buffer, Castable, ImageDirectoryEntry, NTHeaders, PE, PETranslation, PtrPE, RVA};
+
+use crate::bytes::{read_u16, read_u32, read_u64};
+
+/// The parts of a PE file that scanning looks at: the headers, the certificate table,
+/// the load configuration and ARM64X relocations, and the debug directories with
+/// their data.
+///
+/// They are read at their file offsets into a zeroed buffer the size of the file, so
+/// the parsers see the usual layout while the rest of the file is never read. The
+/// whole buffer counts against the commit limit on Windows even where it is never
+/// touched, so files larger than [`MAX_IMAGE_LEN`] are rejected before allocating.
+pub struct PeImage {
+    data: Vec<u8>,
+}
+
+/// A step of [`PeImage::open`], returning the file regions to read next as offset
+/// and length.
+type RegionStep = fn(&PeImage) -> Vec<(usize, usize)>;
+
+impl PeImage {
+    /// Reads the parts of the PE file at `path` that scanning looks at, with one
+    /// bounded read per region. Files larger than [`MAX_IMAGE_LEN`] and files that
+    /// shrink while they are read fail with an error.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        let len = usize::try_from(len)
+            .ok()
+            .filter(|&len| len <= MAX_IMAGE_LEN)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("binary of {len} bytes is larger than {MAX_IMAGE_LEN} bytes"),
+                )
+            })?;
+        let mut image = Self { data: vec![0; len] };
+        image.fill(&mut file, 0, HEADER_PROBE_LEN)?;
+        if let Some(size_of_headers) = size_of_headers(&image.data) {
+            image.fill(&mut file, 0, size_of_headers)?;
+        }
+        // Each step locates its regions through the ones read before it.
+        let steps: [RegionStep; 5] = [
+            Self::directory_regions,
+            Self::relocation_header_regions,
+            Self::relocation_regions,
+            Self::arm64x_directory_regions,
+            Self::debug_data_regions,
+        ];
+        for step in steps {
+            for (offset, len) in step(&image) {
+                image.fill(&mut file, offset, len)?;
+            }
+        }
+        Ok(image)
+    }
+
+    /// Returns the image for parsing; it borrows the buffer, which must outlive it.
+    pub fn image(&self) -> PtrPE {
+        PtrPE::new_disk(self.data.as_ptr(), self.data.len())
+    }
+
+    /// Returns the contents of the file, zeroed outside of the regions read.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Reads up to `len` bytes of `file` at `offset` into place, within the file and
+    /// [`MAX_REGION_LEN`].
+    fn fill(&mut self, file: &mut fs::File, offset: usize, len: usize) -> io::Result<()> {
+        let end = offset
+            .saturating_add(len.min(MAX_REGION_LEN))
+            .min(self.data.len());
+        if offset >= end {
+            return Ok(());
+        }
+        file.seek(SeekFrom::Start(offset as u64))?;
+        file.read_exact(&mut self.data[offset..end])
+    }
+
+    /// The certificate table, the load configuration and the debug directory.
+    fn directory_regions(&self) -> Vec<(usize, usize)> {
+        let image = self.image();
+        let mut regions = Vec::new();
+        // Unlike the other directories, the security directory holds a file offset.
+        if let Ok(security) = image.get_data_directory(ImageDirectoryEntry::Security) {
+            regions.push((security.virtual_address.0 as usize, security.size as usize));
+        }
+        if let Ok(load_config) = image.get_data_directory(ImageDirectoryEntry::LoadConfig)
+            && let Ok(offset) = image.translate(PETranslation::Memory(load_config.virtual_address))
+        {
+            let len = (load_config.size as usize).max(LOAD_CONFIG_DVRT_OFFSET + 6);
+            regions.push((offset, len));
+        }
+        if let Ok(debug) = image.get_data_directory(ImageDirectoryEntry::Debug)
+            && let Ok(offset) = image.translate(PETranslation::Memory(debug.virtual_address))
+        {
+            regions.push((offset, debug.size as usize));
+        }
+        regions
+    }
+
+    /// The header of the dynamic relocations of ARM64X images, giving their size.
+    fn relocation_header_regions(&self) -> Vec<(usize, usize)> {
+        arm64x_relocation_table_offset(&self.image())
+            .map(|offset| (offset, 8))
+            .into_iter()
+            .collect()
+    }
+
+    /// The dynamic relocations of ARM64X images.
+    fn relocation_regions(&self) -> Vec<(usize, usize)> {
+        arm64x_relocation_table(&self.image()).into_iter().collect()
+    }
+
+    /// The debug directory of the x64 view of ARM64X images.
+    fn arm64x_directory_regions(&self) -> Vec<(usize, usize)> {
+        let image = self.image();
+        arm64x_debug_directory(&image)
+            .and_then(|(rva, size)| {
+                let offset = image.translate(PETranslation::Memory(rva)).ok()?;
+                Some((offset, size as usize))
+            })
+            .into_iter()
+            .collect()
+    }
+
+    /// The data of all debug directory entries.
+    fn debug_data_regions(&self) -> Vec<(usize, usize)> {
+        let image = self.image();
+        let entries = debug_entries(&image).unwrap_or_default();
+        entries
+            .into_iter()
+            .chain(arm64x_debug_entries(&image).into_iter().flatten())
+            .filter(|entry| entry.offset != 0)
+            .map(|entry| (entry.offset as usize, entry.size as usize))
+            .collect()
+    }
+}
+
+/// Returns the `SizeOfHeaders` of the optional header, at the same offset in PE32 and
+/// PE32+ images.
+fn size_of_headers(data: &[u8]) -> Option<usize> {
+    let pe = read_u32(data, 0x3c)? as usize;
+    if data.get(pe..pe.checked_add(4)?)? != b"PE\0\0" {
+        return None;
+    }
+    read_u32(data, pe.checked_add(24 + 60)?).map(|size| size as usize)
+}
+
+/// The type of a debug directory entry (`IMAGE_DEBUG_TYPE_*`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DebugType {
+    Coff,
+    CodeView,
+    Fpo,
+    Misc,
+    Exception,
+    Fixup,
+    OmapToSrc,
+    OmapFromSrc,
+    Borland,
+    Clsid,
+    VcFeature,
+    Pogo,
+    Iltcg,
+    Mpx,
+    Repro,
+    EmbeddedPortablePdb,
+    PdbChecksum,
+    ExDllCharacteristics,
+    Other(u32),
+}
+
+/// An entry of the debug directory of a PE image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct DebugEntry {
+    /// What the entry's data describes.
+    #[serde(rename = "type")]
+    pub kind: DebugType,
+    /// Size of the entry's data in bytes.
+    pub size: u32,
+    /// File offset of the entry's data, 0 if the data is not stored in the file.
+    pub offset: u32,
+    /// Major version of the entry's data format.
+    pub major_version: u16,
+    /// Minor version of the entry's data format.
+    pub minor_version: u16,
+}
+
+/// A CodeView record in the RSDS format used by all PDBs since Visual C++ 7.0.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RsdsRecord {
+    /// The PDB signature GUID as stored in the binary.
+    pub guid: [u8; 16],
+    /// The PDB age.
+    pub age: u32,
+    /// The PDB path as stored in the binary, without the terminating NUL.
+    pub name: Vec<u8>,
+}
+
+/// An `IMAGE_DEBUG_MISC` record naming the `.dbg` file the debug information of older
+/// binaries was split into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MiscRecord {
+    /// The name as stored in the binary, without the terminating NUL.
+    pub name: String,
+}
+
+/// Size of the fixed part of an RSDS record: signature, GUID and age.
+const RSDS_HEADER_LEN: usize = 24;
+/// Size of the fixed part of an `IMAGE_DEBUG_MISC` record: data type, length, the
+/// Unicode flag and three reserved bytes.
+const MISC_HEADER_LEN: usize = 12;
+/// `IMAGE_DEBUG_MISC_EXENAME`, the only MISC data type in use.
+const MISC_EXENAME: u32 = 1;
+/// Minor version (`PM`) of CodeView entries that reference a portable PDB.
+const PORTABLE_PDB_MINOR_VERSION: u16 = 0x504d;
+/// Size of the header of an embedded portable PDB: the `MPDB` signature and the
+/// uncompressed size.
+const EMBEDDED_PDB_HEADER_LEN: usize = 8;
+/// Bytes read from the start of a binary to find the size of its headers.
+const HEADER_PROBE_LEN: usize = 4096;
+/// Upper bound for a region read from a binary, in case of corrupt headers.
+const MAX_REGION_LEN: usize = 64 * 1024 * 1024;
+/// Largest binary scanned. The buffer of a [`PeImage`] is the size of the file, and the
+/// largest DLLs shipped, such as browser engines, are a few hundred megabytes.
+const MAX_IMAGE_LEN: usize = 512 * 1024 * 1024;
+
+/// `IMAGE_FILE_MACHINE_ARM64`, the machine type of ARM64X images.
+const MACHINE_ARM64: u16 = 0xaa64;
+/// `IMAGE_DYNAMIC_RELOCATION_ARM64X`
+const DYNAMIC_RELOCATION_ARM64X: u64 = 6;
+/// Offset of `DynamicValueRelocTableOffset` in `IMAGE_LOAD_CONFIG_DIRECTORY64`, followed
+/// by the 1-based `DynamicValueRelocTableSection`.
+const LOAD_CONFIG_DVRT_OFFSET: usize = 224;
+/// Offset of the debug data directory from the PE signature in a PE32+ image: the
+/// signature, the file header, the optional header fields and six data directories.
+const DEBUG_DIRECTORY_HEADER_OFFSET: u32 = 4 + 20 + 112 + 6 * 8;
+
+/// `IMAGE_DEBUG_DIRECTORY`
+#[allow(dead_code)]
+#[repr(C, packed)]
+struct DebugDirectoryRaw {
+    characteristics: u32,
+    time_date_stamp: u32,
+    major_version: u16,
+    minor_version: u16,
+    kind: u32,
+    size_of_data: u32,
+    address_of_raw_data: u32,
+    pointer_to_raw_data: u32,
+}
+
+unsafe impl Castable for DebugDirectoryRaw {}
+
+impl DebugType {
+    /// Maps an `IMAGE_DEBUG_TYPE_*` value to a debug type.
+    pub fn from_raw(value: u32) -> Self {
+        match value {
+            1 => Self::Coff,
+            2 => Self::CodeView,
+            3 => Self::Fpo,
+            4 => Self::Misc,
+            5 => Self::Exception,
+            6 => Self::Fixup,
+            7 => Self::OmapToSrc,
+            8 => Self::OmapFromSrc,
+            9 => Self::Borland,
+            11 => Self::Clsid,
+            12 => Self::VcFeature,
+            13 => Self::Pogo,
+            14 => Self::Iltcg,
+            15 => Self::Mpx,
+            16 => Self::Repro,
+            17 => Self::EmbeddedPortablePdb,
+            19 => Self::PdbChecksum,
+            20 => Self::ExDllCharacteristics,
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for DebugType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Coff => "coff",
+            Self::CodeView => "codeview",
+            Self::Fpo => "fpo",
+            Self::Misc => "misc",
+            Self::Exception => "exception",
+            Self::Fixup => "fixup",
+            Self::OmapToSrc => "omap_to_src",
+            Self::OmapFromSrc => "omap_from_src",
+            Self::Borland => "borland",
+            Self::Clsid => "clsid",
+            Self::VcFeature => "vc_feature",
+            Self::Pogo => "pogo",
+            Self::Iltcg => "iltcg",
+            Self::Mpx => "mpx",
+            Self::Repro => "repro",
+            Self::EmbeddedPortablePdb => "embedded_portable_pdb",
+            Self::PdbChecksum => "pdb_checksum",
+            Self::ExDllCharacteristics => "ex_dll_characteristics",
+            Self::Other(value) => return write!(f, "type{}", value),
+        };
+        f.write_str(name)
+    }
+}
+
+impl DebugEntry {
+    /// Returns whether this is a CodeView entry of a .NET assembly referencing a
+    /// portable PDB rather than a Windows PDB.
+    pub fn is_portable_pdb(&self) -> bool {
+        self.kind == DebugType::CodeView && self.minor_version == PORTABLE_PDB_MINOR_VERSION
+    }
+
+    /// Returns the entry's data in `image`, or `None` if it is not stored in the file
+    /// or lies outside of it.
+    pub fn data<'a>(&self, image: &'a [u8]) -> Option<&'a [u8]> {
+        if self.offset == 0 {
+            return None;
+        }
+        let start = self.offset as usize;
+        image.get(start..start.checked_add(self.size as usize)?)
+    }
+}
+
+impl RsdsRecord {
+    /// Parses the data of a CodeView debug entry.
+    ///
+    /// The name runs up to the first NUL or the end of the entry, so it is not limited
+    /// in length. Older NB10 records and anything else not starting with `RSDS` are
+    /// rejected.
+    pub fn parse(data: &[u8]) -> Result<Self, &'static str> {
+        let signature = data.get(..4).ok_or("CodeView record too short")?;
+        if signature == b"NB10" {
+            return Err("NB10 CodeView record (pre-RSDS PDB format)");
+        }
+        if signature != b"RSDS" {
+            return Err("unknown CodeView signature");
+        }
+        if data.len() < RSDS_HEADER_LEN {
+            return Err("RSDS record too short");
+        }
+        let mut guid = [0; 16];
+        guid.copy_from_slice(&data[4..20]);
+        let age = u32::from_le_bytes([data[20], data[21], data[22], data[23]]);
+        let name = &data[RSDS_HEADER_LEN..];
+        let name_end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        Ok(Self {
+            guid,
+            age,
+            name: name[..name_end].to_vec(),
+        })
+    }
+}
+
+impl MiscRecord {
+    /// Parses the data of a MISC debug entry.
+    ///
+    /// The name is stored as UTF-16 if the Unicode flag is set and as ANSI otherwise,
+    /// and runs up to the first NUL or the end of the record.
+    pub fn parse(data: &[u8]) -> Result<Self, &'static str> {
+        if data.len() < MISC_HEADER_LEN {
+            return Err("MISC record too short");
+        }
+        if read_u32(data, 0) != Some(MISC_EXENAME) {
+            return Err("MISC record does not name a file");
+        }
+        let length = read_u32(data, 4).map_or(data.len(), |length| length as usize);
+        let name = &data[MISC_HEADER_LEN..length.clamp(MISC_HEADER_LEN, data.len())];
+        let name = if data[8] != 0 {
+            let units: Vec<u16> = name
+                .chunks_exact(2)
+                .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                .take_while(|&unit| unit != 0)
+                .collect();
+            String::from_utf16_lossy(&units)
+        } else {
+            let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            String::from_utf8_lossy(&name[..end]).into_owned()
+        };
+        if name.is_empty() {
+            return Err("MISC record has an empty name");
+        }
+        Ok(Self { name })
+    }
+}
+
+/// Decompresses the data of an embedded portable PDB debug entry: an `MPDB` signature,
+/// the uncompressed size and the deflate-compressed PDB.
+pub fn expand_embedded_pdb(data: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+    if data.get(..4) != Some(b"MPDB".as_slice()) {
+        return Err(invalid("missing MPDB signature"));
+    }
+    let size = read_u32(data, 4).ok_or_else(|| invalid("embedded PDB header too short"))?;
+    let mut pdb = Vec::with_capacity(size as usize);
+    flate2::read::DeflateDecoder::new(&data[EMBEDDED_PDB_HEADER_LEN..]).read_to_end(&mut pdb)?;
+    if pdb.len() != size as usize {
+        return Err(invalid("embedded PDB size mismatch"));
+    }
+    Ok(pdb)
+}
+
+/// Returns whether the file starts with the MZ header of a PE image whose `e_lfanew`
+/// points to a `PE\0\0` signature, whatever its extension.
+pub fn is_pe_file(path: &Path) -> bool {
+    let mut header = [0; 64];
+    let Ok(mut file) = fs::File::open(path) else {
+        return false;
+    };
+    if file.read_exact(&mut header).is_err() || header[..2] != *b"MZ" {
+        return false;
+    }
+    let e_lfanew = u32::from_le_bytes([header[60], header[61], header[62], header[63]]);
+    let mut signature = [0; 4];
+    std::io::Seek::seek(&mut file, std::io::SeekFrom::Start(e_lfanew.into())).is_ok()
+        && file.read_exact(&mut signature).is_ok()
+        && signature == *b"PE\0\0"
+}
+
+/// Reads all entries of the image's debug directory, in file order.
+///
+/// Returns an empty list if the image has no debug directory.
+pub fn debug_entries<P: PE>(image: &P) -> Result<Vec<DebugEntry>, exe::Error> {
+    let Ok(directory) = image.get_data_directory(ImageDirectoryEntry::Debug) else {
+        return Ok(Vec::new());
+    };
+    read_debug_directory(image, directory.virtual_address, directory.size)
+}
+
+/// Reads the debug directory of the x64 view of an ARM64X image.
+///
+/// ARM64X images carry dynamic relocations that patch the headers when the image is
+/// loaded into an x64 process, which can point the debug data directory at a
+/// different debug directory. Returns `None` for other images, and for ARM64X images
+/// whose x64 view uses the native debug directory.
+pub fn arm64x_debug_entries<P: PE>(image: &P) -> Option<Vec<DebugEntry>> {
+    let (rva, size) = arm64x_debug_directory(image)?;
+    read_debug_directory(image, rva, size).ok()
+}
+
+/// Returns the file offset and length of the dynamic value relocation table of an
+/// ARM64X image.
+fn arm64x_relocation_table<P: PE>(image: &P) -> Option<(usize, usize)> {
+    let data = image.as_slice();
+    let table = arm64x_relocation_table_offset(image)?;
+    // IMAGE_DYNAMIC_RELOCATION_TABLE, version 1: a list of IMAGE_DYNAMIC_RELOCATION64
+    // headers, each followed by base relocation style blocks.
+    if read_u32(data, table)? != 1 {
+        return None;
+    }
+    Some((table, 8 + read_u32(data, table + 4)? as usize))
+}
+
+/// Returns the file offset of the dynamic value relocation table of an ARM64X image,
+/// as named by its load configuration.
+fn arm64x_relocation_table_offset<P: PE>(image: &P) -> Option<usize> {
+    match image.get_valid_nt_headers() {
+        Ok(NTHeaders::NTHeaders64(headers)) if headers.file_header.machine == MACHINE_ARM64 => {}
+        _ => return None,
+    }
+    let data = image.as_slice();
+    let load_config = image
+        .get_data_directory(ImageDirectoryEntry::LoadConfig)
+        .ok()?;
+    let load_config = image
+        .translate(PETranslation::Memory(load_config.virtual_address))
+        .ok()?;
+    if (read_u32(data, load_config)? as usize) < LOAD_CONFIG_DVRT_OFFSET + 6 {
+        return None;
+    }
+    let table_offset = read_u32(data, load_config + LOAD_CONFIG_DVRT_OFFSET)?;
+    let table_section = read_u16(data, load_config + LOAD_CONFIG_DVRT_OFFSET + 4)?;
+    let section = image
+        .get_section_table()
+        .ok()?
+        .get(usize::from(table_section).checked_sub(1)?)?;
+    image
+        .translate(PETranslation::Memory(RVA(section
+            .virtual_address
+            .0
+            .checked_add(table_offset)?)))
+        .ok()
+}
+
+/// Returns the RVA and size of the debug directory the ARM64X relocations point the
+/// x64 view of an image at, see [`arm64x_debug_entries`].
+fn arm64x_debug_directory<P: PE>(image: &P) -> Option<(RVA, u32)> {
+    let (table, len) = arm64x_relocation_table(image)?;
+    let data = image.as_slice();
+    let end = table.checked_add(len)?;
+    let directory_rva = read_u32(data, 0x3c)? + DEBUG_DIRECTORY_HEADER_OFFSET;
+    let mut directory = data
+        .get(directory_rva as usize..directory_rva as usize + 8)?
+        .to_vec();
+    let mut patched = false;
+    let mut position = table + 8;
+    while position + 12 <= end {
+        let symbol = read_u64(data, position)?;
+        let size = read_u32(data, position + 8)? as usize;
+        let blocks = position + 12;
+        position = blocks + size;
+        if symbol == DYNAMIC_RELOCATION_ARM64X {
+            patched |= apply_arm64x_fixups(data, blocks, position, directory_rva, &mut directory)?;
+        }
+    }
+    if !patched {
+        return None;
+    }
+    let rva = u32::from_le_bytes(directory[..4].try_into().ok()?);
+    let size = u32::from_le_bytes(directory[4..].try_into().ok()?);
+    Some((RVA(rva), size))
+}
+
+/// Applies the ARM64X fixups in the blocks between `start` and `end` that touch the
+/// bytes of `target` at `target_rva`, returning whether any did.
+///
+/// Each fixup is a 16-bit record of a 12-bit page offset, a 2-bit type (zero fill,
+/// value or delta) and a 2-bit size, followed by the value for value fixups or a
+/// 16-bit scaled delta for delta fixups.
+fn apply_arm64x_fixups(
+    data: &[u8],
+    start: usize,
+    end: usize,
+    target_rva: u32,
+    target: &mut [u8],
+) -> Option<bool> {
+    let target_range = u64::from(target_rva)..u64::from(target_rva) + target.len() as u64;
+    let mut patched = false;
+    let mut block = start;
+    while block + 8 <= end {
+        let page = read_u32(data, block)?;
+        let block_size = read_u32(data, block + 4)? as usize;
+        if block_size < 8 {
+            return None;
+        }
+        let block_end = block + block_size;
+        let mut position = block + 8;
+        while position + 2 <= block_end {
+            let record = read_u16(data, position)?;
+            position += 2;
+            if record == 0 {
+                // Padding at the end of a block.
+                continue;
+            }
+            let rva = u64::from(page) + u64::from(record & 0xfff);
+            let size = 1usize << (record >> 14);
+            let value = match (record >> 12) & 0x3 {
+                0 => vec![0; size],
+                1 => {
+                    let value = data.get(position..position + size)?.to_vec();
+                    position += size.max(2);
+                    value
+                }
+                2 => {
+                    // Deltas apply to pointers, not to the data directories.
+                    position += 2;
+                    continue;
+                }
+                _ => return None,
+            };
+            for (index, byte) in value.into_iter().enumerate() {
+                let byte_rva = rva + index as u64;
+                if target_range.contains(&byte_rva) {
+                    target[(byte_rva - target_range.start) as usize] = byte;
+                    patched = true;
+                }
+            }
+        }
+        block = block_end;
+    }
+    Some(patched)
+}
+
+/// Reads the debug directory of `size` bytes at `rva`.
+fn read_debug_directory<P: PE>(
+    image: &P,
+    rva: RVA,
+    size: u32,
+) -> Result<Vec<DebugEntry>, exe::Error> {
+    if rva.0 == 0 || size == 0 {
+        return Ok(Vec::new());
+    }
+    let start = image.translate(PETranslation::Memory(rva))?;
+    let entry_size = std::mem::size_of::<DebugDirectoryRaw>();
+    (0..size as usize / entry_size)
+        .map(|index| {
+            let raw = image.get_ref::<DebugDirectoryRaw>(start + index * entry_size)?;
+            Ok(DebugEntry {
+                kind: DebugType::from_raw(raw.kind),
+                size: raw.size_of_data,
+                offset: raw.pointer_to_raw_data,
+                major_version: raw.major_version,
+                minor_version: raw.minor_version,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{put, write};
+    use std::io::Write;
+
+    /// Size of an `IMAGE_DEBUG_DIRECTORY` entry.
+    const DEBUG_ENTRY_LEN: u32 = 28;
+
+    fn rsds(age: u32, name: &[u8]) -> Vec<u8> {
+        let mut data = b"RSDS".to_vec();
+        data.extend(0x10u8..0x20);
+        data.extend(age.to_le_bytes());
+        data.extend(name);
+        data
+    }
+
+    fn misc(unicode: bool, length: u32, name: &[u8]) -> Vec<u8> {
+        let mut data = MISC_EXENAME.to_le_bytes().to_vec();
+        data.extend(length.to_le_bytes());
+        data.extend([u8::from(unicode), 0, 0, 0]);
+        data.extend(name);
+        data
+    }
+
+    fn debug_entry(data: &mut [u8], offset: usize, size: u32, rva: u32, pointer: u32) {
+        put(data, offset + 12, &2u32.to_le_bytes());
+        put(data, offset + 16, &size.to_le_bytes());
+        put(data, offset + 20, &rva.to_le_bytes());
+        put(data, offset + 24, &pointer.to_le_bytes());
+    }
+
+    /// Builds a PE32+ image with one section at RVA 0x1000 and file offset 0x200. The
+    /// section holds the load configuration, the dynamic relocations at 0x1200, the
+    /// native debug directory at 0x1300 and the one the ARM64X relocations point the
+    /// x64 view at, at 0x1400. Their CodeView records have the ages 1 and 2.
+    fn arm64x_image(machine: u16) -> Vec<u8> {
+        let mut data = vec![0; 0x1200];
+        put(&mut data, 0, b"MZ");
+        put(&mut data, 0x3c, &0x40u32.to_le_bytes());
+        put(&mut data, 0x40, b"PE\0\0");
+        put(&mut data, 0x44, &machine.to_le_bytes());
+        put(&mut data, 0x46, &1u16.to_le_bytes());
+        put(&mut data, 0x54, &0xf0u16.to_le_bytes());
+        put(&mut data, 0x56, &0x22u16.to_le_bytes());
+        let optional = 0x58;
+        put(&mut data, optional, &0x20bu16.to_le_bytes());
+        put(&mut data, optional + 24, &0x1_4000_0000u64.to_le_bytes());
+        put(&mut data, optional + 32, &0x1000u32.to_le_bytes());
+        put(&mut data, optional + 36, &0x200u32.to_le_bytes());
+        put(&mut data, optional + 56, &0x2000u32.to_le_bytes());
+        put(&mut data, optional + 60, &0x200u32.to_le_bytes());
+        put(&mut data, optional + 108, &16u32.to_le_bytes());
+        // The debug and load configuration data directories.
+        put(&mut data, optional + 112 + 6 * 8, &0x1300u32.to_le_bytes());
+        put(
+            &mut data,
+            optional + 116 + 6 * 8,
+            &DEBUG_ENTRY_LEN.to_le_bytes(),
+        );
+        put(&mut data, optional + 112 + 10 * 8, &0x1000u32.to_le_bytes());
+        put(&mut data, optional + 116 + 10 * 8, &0x140u32.to_le_bytes());
+        let section = optional + 0xf0;
+        put(&mut data, section, b".rdata\0\0");
+        put(&mut data, section + 8, &0x1000u32.to_le_bytes());
+        put(&mut data, section + 12, &0x1000u32.to_le_bytes());
+        put(&mut data, section + 16, &0x1000u32.to_le_bytes());
+        put(&mut data, section + 20, &0x200u32.to_le_bytes());
+        put(&mut data, section + 36, &0x4000_0040u32.to_le_bytes());
+        // Load configuration naming the relocations at offset 0x200 of section 1.
+        put(&mut data, 0x200, &0x140u32.to_le_bytes());
+        put(
+            &mut data,
+            0x200 + LOAD_CONFIG_DVRT_OFFSET,
+            &0x200u32.to_le_bytes(),
+        );
+        put(
+            &mut data,
+            0x200 + LOAD_CONFIG_DVRT_OFFSET + 4,
+            &1u16.to_le_bytes(),
+        );
+        // A version 1 table with one ARM64X relocation of one block, which sets the
+        // RVA and size of the debug data directory with two 4-byte value fixups.
+        put(&mut data, 0x400, &1u32.to_le_bytes());
+        put(&mut data, 0x404, &32u32.to_le_bytes());
+        put(&mut data, 0x408, &DYNAMIC_RELOCATION_ARM64X.to_le_bytes());
+        put(&mut data, 0x410, &20u32.to_le_bytes());
+        put(&mut data, 0x414, &0u32.to_le_bytes());
+        put(&mut data, 0x418, &20u32.to_le_bytes());
+        put(&mut data, 0x41c, &0x90f8u16.to_le_bytes());
+        put(&mut data, 0x41e, &0x1400u32.to_le_bytes());
+        put(&mut data, 0x422, &0x90fcu16.to_le_bytes());
+        put(&mut data, 0x424, &DEBUG_ENTRY_LEN.to_le_bytes());
+        let native = rsds(1, b"native.pdb\0");
+        let x64 = rsds(2, b"x64.pdb\0");
+        debug_entry(&mut data, 0x500, native.len() as u32, 0x1600, 0x700);
+        debug_entry(&mut data, 0x600, x64.len() as u32, 0x1700, 0x800);
+        put(&mut data, 0x700, &native);
+        put(&mut data, 0x800, &x64);
+        data
+    }
+
+    fn pe(data: &[u8]) -> PtrPE {
+        PtrPE::new_disk(data.as_ptr(), data.len())
+    }
+
+    fn age(entry: &DebugEntry, image: &[u8]) -> u32 {
+        RsdsRecord::parse(entry.data(image).unwrap()).unwrap().age
+    }
+
+    #[test]
+    fn rsds_record_name_ends_at_nul() {
+        let record = RsdsRecord::parse(&rsds(3, b"d:\\a\\b.pdb\0junk")).unwrap();
+        assert_eq!(
+            record.guid,
+            <[u8; 16]>::try_from((0x10u8..0x20).collect::<Vec<_>>()).unwrap()
+        );
+        assert_eq!(record.age, 3);
+        assert_eq!(record.name, b"d:\\a\\b.pdb");
+    }
+
+    #[test]
+    fn rsds_record_name_runs_to_end_without_nul() {
+        let record = RsdsRecord::parse(&rsds(1, b"a/b.pdb")).unwrap();
+        assert_eq!(record.name, b"a/b.pdb");
+    }
+
+    #[test]
+    fn rsds_record_keeps_long_and_non_utf8_names() {
+        let mut name = vec![b'a'; 300];
+        name.extend(b"\xe9.pdb\0");
+        let record = RsdsRecord::parse(&rsds(1, &name)).unwrap();
+        assert_eq!(record.name.len(), 305);
+        assert_eq!(&record.name[300..], b"\xe9.pdb");
+    }
+
+    #[test]
+    fn rsds_record_rejects_other_formats() {
+        let mut nb10 = b"NB10".to_vec();
+        nb10.extend([0; 12]);
+        nb10.extend(b"a.pdb\0");
+        assert!(RsdsRecord::parse(&nb10).is_err());
+        assert!(RsdsRecord::parse(b"XXXX0123456789abcdef0123").is_err());
+        assert!(RsdsRecord::parse(b"RS").is_err());
+        assert!(RsdsRecord::parse(&rsds(1, b"")[..20]).is_err());
+        assert!(RsdsRecord::parse(&[]).is_err());
+    }
+
+    #[test]
+    fn misc_record_reads_ansi_names() {
+        let record = MiscRecord::parse(&misc(false, 20, b"old.dbg\0")).unwrap();
+        assert_eq!(record.name, "old.dbg");
+    }
+
+    #[test]
+    fn misc_record_reads_utf16_names() {
+        let name: Vec<u8> = "a.dbg\0"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let record = MiscRecord::parse(&misc(true, 24, &name)).unwrap();
+        assert_eq!(record.name, "a.dbg");
+    }
+
+    #[test]
+    fn misc_record_clamps_length_and_replaces_invalid_utf8() {
+        let record = MiscRecord::parse(&misc(false, 1000, b"\xff.dbg")).unwrap();
+        assert_eq!(record.name, "\u{fffd}.dbg");
+    }
+
+    #[test]
+    fn misc_record_rejects_corrupt_records() {
+        assert!(MiscRecord::parse(&misc(false, 20, b"")[..8]).is_err());
+        assert!(MiscRecord::parse(&misc(false, 20, b"\0\0\0\0")).is_err());
+        let mut other = misc(false, 20, b"old.dbg\0");
+        put(&mut other, 0, &2u32.to_le_bytes());
+        assert!(MiscRecord::parse(&other).is_err());
+    }
+
+    #[test]
+    fn expand_embedded_pdb_inflates_the_pdb() {
+        let pdb = b"BSJB portable PDB".repeat(10);
+        let mut encoder =
+            flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&pdb).unwrap();
+        let mut data = b"MPDB".to_vec();
+        data.extend((pdb.len() as u32).to_le_bytes());
+        data.extend(encoder.finish().unwrap());
+        assert_eq!(expand_embedded_pdb(&data).unwrap(), pdb);
+        put(&mut data, 4, &1u32.to_le_bytes());
+        assert!(expand_embedded_pdb(&data).is_err());
+    }
+
+    #[test]
+    fn expand_embedded_pdb_rejects_corrupt_data() {
+        assert!(expand_embedded_pdb(b"MPDB\x01").is_err());
+        assert!(expand_embedded_pdb(b"XPDB\x01\0\0\0\x03\0").is_err());
+        assert!(expand_embedded_pdb(b"MPDB\x10\0\0\0\xff\xff\xff").is_err());
+    }
+
+    #[test]
+    fn arm64x_debug_entries_reads_the_x64_view() {
+        let data = arm64x_image(MACHINE_ARM64);
+        let image = pe(&data);
+        let native = debug_entries(&image).unwrap();
+        assert_eq!(native.len(), 1);
+        assert_eq!(age(&native[0], &data), 1);
+        let x64 = arm64x_debug_entries(&image).unwrap();
+        assert_eq!(x64.len(), 1);
+        assert_eq!(x64[0].kind, DebugType::CodeView);
+        assert_eq!(age(&x64[0], &data), 2);
+    }
+
+    #[test]
+    fn arm64x_debug_entries_skips_other_machines() {
+        let data = arm64x_image(0x8664);
+        assert_eq!(arm64x_debug_entries(&pe(&data)), None);
+    }
+
+    #[test]
+    fn arm64x_debug_entries_rejects_truncated_relocations() {
+        let mut data = arm64x_image(MACHINE_ARM64);
+        data.truncate(0x420);
+        assert_eq!(arm64x_debug_entries(&pe(&data)), None);
+        let mut data = arm64x_image(MACHINE_ARM64);
+        put(&mut data, 0x418, &4u32.to_le_bytes());
+        assert_eq!(arm64x_debug_entries(&pe(&data)), None);
+    }
+
+    #[test]
+    fn pe_image_reads_the_regions_scanning_needs() {
+        let file = write(&arm64x_image(MACHINE_ARM64));
+        let pe_image = PeImage::open(file.path()).unwrap();
+        let data = pe_image.as_slice();
+        let image = pe_image.image();
+        assert_eq!(age(&debug_entries(&image).unwrap()[0], data), 1);
+        assert_eq!(age(&arm64x_debug_entries(&image).unwrap()[0], data), 2);
+    }
+
+    #[test]
+    fn pe_image_rejects_files_above_the_size_cap() {
+        let file = write(&arm64x_image(MACHINE_ARM64));
+        file.as_file().set_len(MAX_IMAGE_LEN as u64 + 1).unwrap();
+        let error = PeImage::open(file.path()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
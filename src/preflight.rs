@@ -0,0 +1,119 @@
+//! Estimating the size of a download run with HEAD requests before starting it, and
+//! checking that the store has room for it.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    error::{Error, Result},
+    http::HttpClient,
+    store::SymbolStore,
+    windows::PdbMeta,
+};
+
+/// Expected size of a download run, see [`estimate`].
+#[derive(Debug, Default)]
+pub struct Estimate {
+    /// Total size of the PDBs the servers reported a size for.
+    pub bytes: u64,
+    /// PDBs the servers reported a size for.
+    pub sized: usize,
+    /// PDBs no server reported a size for, e.g. because they are only available
+    /// compressed or not at all.
+    pub unknown: usize,
+    /// PDBs already in the store, which are not downloaded again.
+    pub existing: usize,
+}
+
+/// Asks the HTTP `servers`, tried in order, for the sizes of the PDBs that are not in
+/// `store` yet with HEAD requests, running up to `jobs` requests at once.
+pub async fn estimate(
+    client: &HttpClient,
+    store: &dyn SymbolStore,
+    servers: &[String],
+    pdbs: &[PdbMeta],
+    jobs: usize,
+) -> Estimate {
+    let servers: Arc<Vec<String>> = Arc::new(
+        servers
+            .iter()
+            .filter(|server| {
+                let lower = server.to_ascii_lowercase();
+                lower.starts_with("http://") || lower.starts_with("https://")
+            })
+            .cloned()
+            .collect(),
+    );
+    let mut estimate = Estimate::default();
+    let mut seen = HashSet::new();
+    let permits = Arc::new(Semaphore::new(jobs.max(1)));
+    let mut tasks = JoinSet::new();
+    for pdb in pdbs {
+        if !seen.insert(store.location(pdb)) {
+            continue;
+        }
+        if matches!(store.existing_size(pdb).await, Ok(Some(_))) {
+            estimate.existing += 1;
+            continue;
+        }
+        let client = client.clone();
+        let servers = servers.clone();
+        let permits = permits.clone();
+        let pdb = pdb.clone();
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            for server in servers.iter() {
+                let url = pdb.url(server);
+                match client.head(&url).send().await {
+                    Ok(response) if response.status().is_success() => {
+                        if let Some(len) = response.content_length() {
+                            return Some(len);
+                        }
+                    }
+                    Ok(response) => {
+                        tracing::debug!("HEAD {} returned {}", url, response.status())
+                    }
+                    Err(e) => tracing::debug!("HEAD {} failed: {}", url, e),
+                }
+            }
+            None
+        });
+    }
+    while let Some(result) = tasks.join_next().await {
+        match result {
+            Ok(Some(len)) => {
+                estimate.bytes += len;
+                estimate.sized += 1;
+            }
+            Ok(None) => estimate.unknown += 1,
+            Err(e) => {
+                tracing::error!("Preflight task failed: {}", e);
+                estimate.unknown += 1;
+            }
+        }
+    }
+    estimate
+}
+
+/// Checks that the volume holding `dir`, or the closest existing directory above it,
+/// has `needed` bytes free.
+pub fn check_space(dir: &Path, needed: u64) -> Result<()> {
+    let existing = dir
+        .ancestors()
+        .find(|path| path.is_dir())
+        .unwrap_or(Path::new("."));
+    let available = fs4::available_space(existing)?;
+    if available < needed {
+        return Err(Error::InsufficientSpace {
+            path: PathBuf::from(dir),
+            needed,
+            available,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,217 @@
+//! Garbage collection for local symbol stores.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    error::Result,
+    store::{LocalStore, StoredFile},
+};
+
+/// Which files [`select`] picks for deletion. A file is deleted if any of the
+/// criteria that are set applies to it.
+#[derive(Debug, Clone, Default)]
+pub struct PrunePolicy {
+    /// Delete files not read for this long.
+    pub older_than: Option<Duration>,
+    /// Delete the least recently read files until the store is at most this many
+    /// bytes.
+    pub max_size: Option<u64>,
+    /// Delete files whose name and key are not in the set, see [`manifest_references`].
+    pub referenced: Option<HashSet<(String, String)>>,
+}
+
+/// Picks the files to delete from `files` according to `policy`, least recently read
+/// first.
+///
+/// Access times depend on the filesystem: `noatime` mounts never update them, and
+/// `relatime` only about once a day.
+pub fn select(
+    mut files: Vec<StoredFile>,
+    policy: &PrunePolicy,
+    now: SystemTime,
+) -> Vec<StoredFile> {
+    files.sort_by_key(|file| file.accessed);
+    let cutoff = policy
+        .older_than
+        .and_then(|older_than| now.checked_sub(older_than));
+    let (mut victims, kept): (Vec<StoredFile>, Vec<StoredFile>) =
+        files.into_iter().partition(|file| {
+            cutoff.is_some_and(|cutoff| file.accessed < cutoff)
+                || policy.referenced.as_ref().is_some_and(|referenced| {
+                    !referenced.contains(&(file.name.clone(), file.key.clone()))
+                })
+        });
+    if let Some(max_size) = policy.max_size {
+        let mut total: u64 = kept.iter().map(|file| file.size).sum();
+        for file in kept {
+            if total <= max_size {
+                break;
+            }
+            total -= file.size;
+            victims.push(file);
+        }
+    }
+    victims
+}
+
+/// Reads the name and key of every stored file referenced by the given manifests, as
+/// written by `fetch --manifest`.
+pub fn manifest_references(manifests: &[PathBuf]) -> Result<HashSet<(String, String)>> {
+    let mut references = HashSet::new();
+    for manifest in manifests {
+        let entries: Vec<serde_json::Value> = serde_json::from_slice(&fs::read(manifest)?)?;
+        references.extend(
+            entries
+                .iter()
+                .filter_map(|entry| entry.get("store_path")?.as_str())
+                .filter_map(|path| name_and_key(Path::new(path))),
+        );
+        tracing::debug!("Read references from manifest {}", manifest.display());
+    }
+    Ok(references)
+}
+
+/// Extracts the name and key from the path of a stored file, `<name>/<key>/<file>`.
+fn name_and_key(path: &Path) -> Option<(String, String)> {
+    let key_dir = path.parent()?;
+    let name_dir = key_dir.parent()?;
+    Some((
+        name_dir.file_name()?.to_string_lossy().into_owned(),
+        key_dir.file_name()?.to_string_lossy().into_owned(),
+    ))
+}
+
+/// Deletes the selected files from the store and returns the number of bytes freed.
+///
+/// Files that cannot be deleted are logged and skipped.
+pub fn remove(store: &LocalStore, files: &[StoredFile]) -> u64 {
+    let mut freed = 0;
+    for file in files {
+        match store.remove(file) {
+            Ok(()) => {
+                tracing::debug!("Deleted {}", file.path.display());
+                freed += file.size;
+            }
+            Err(e) => tracing::warn!("Failed to delete {}: {}", file.path.display(), e),
+        }
+    }
+    freed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Returns a stored `<name>.pdb` of `size` bytes last read `days` days before `now`.
+    fn file(name: &str, size: u64, days: u32, now: SystemTime) -> StoredFile {
+        let name = format!("{}.pdb", name);
+        StoredFile {
+            path: PathBuf::from(&name).join("KEY").join(&name),
+            name,
+            key: "KEY".to_string(),
+            size,
+            accessed: now - DAY * days,
+        }
+    }
+
+    fn names(files: &[StoredFile]) -> Vec<&str> {
+        files.iter().map(|file| file.name.as_str()).collect()
+    }
+
+    fn files(now: SystemTime) -> Vec<StoredFile> {
+        vec![
+            file("new", 100, 1, now),
+            file("old", 200, 30, now),
+            file("middle", 300, 10, now),
+        ]
+    }
+
+    #[test]
+    fn selects_files_older_than_the_cutoff() {
+        let now = SystemTime::now();
+        let policy = PrunePolicy {
+            older_than: Some(DAY * 7),
+            ..Default::default()
+        };
+        assert_eq!(names(&select(files(now), &policy, now)), ["old", "middle"]);
+    }
+
+    #[test]
+    fn ages_beyond_the_epoch_select_nothing() {
+        let now = SystemTime::UNIX_EPOCH + DAY * 365;
+        let policy = PrunePolicy {
+            older_than: Some(Duration::MAX),
+            ..Default::default()
+        };
+        assert!(select(files(now), &policy, now).is_empty());
+    }
+
+    #[test]
+    fn selects_least_recently_read_files_until_the_store_fits() {
+        let now = SystemTime::now();
+        let policy = PrunePolicy {
+            max_size: Some(350),
+            ..Default::default()
+        };
+        assert_eq!(names(&select(files(now), &policy, now)), ["old", "middle"]);
+        let policy = PrunePolicy {
+            max_size: Some(600),
+            ..Default::default()
+        };
+        assert!(select(files(now), &policy, now).is_empty());
+    }
+
+    #[test]
+    fn size_limit_counts_only_files_kept_by_the_other_criteria() {
+        let now = SystemTime::now();
+        let policy = PrunePolicy {
+            older_than: Some(DAY * 20),
+            max_size: Some(300),
+            ..Default::default()
+        };
+        assert_eq!(names(&select(files(now), &policy, now)), ["old", "middle"]);
+    }
+
+    #[test]
+    fn selects_files_not_referenced_by_manifests() {
+        let now = SystemTime::now();
+        let policy = PrunePolicy {
+            referenced: Some(HashSet::from([(
+                "middle.pdb".to_string(),
+                "KEY".to_string(),
+            )])),
+            ..Default::default()
+        };
+        assert_eq!(names(&select(files(now), &policy, now)), ["old", "new"]);
+    }
+
+    #[test]
+    fn reads_references_from_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("manifest.json");
+        fs::write(
+            &manifest,
+            r#"[
+                {"store_path": "pdbs/ntdll.pdb/1EB9FACB04C73C5DE79D35E14B2FDC231/ntdll.pdb"},
+                {"store_path": "ntdll.pdb"},
+                {"name": "missing.pdb"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            manifest_references(&[manifest]).unwrap(),
+            HashSet::from([(
+                "ntdll.pdb".to_string(),
+                "1EB9FACB04C73C5DE79D35E14B2FDC231".to_string()
+            )])
+        );
+        assert!(manifest_references(&[dir.path().join("none.json")]).is_err());
+    }
+}
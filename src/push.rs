@@ -0,0 +1,196 @@
+//! Uploading the files of a local store to a remote target, incrementally, so a store
+//! filled offline or on a build machine can be published.
+
+use std::{path::Path, sync::Arc, time::UNIX_EPOCH};
+
+use tokio::{sync::Semaphore, task::JoinSet};
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    error::{Error, Result},
+    http::HttpClient,
+    state::StateDb,
+    store::LocalStore,
+};
+
+/// A remote location that [`push`] uploads store files to.
+#[async_trait::async_trait]
+pub trait PushTarget: Send + Sync {
+    /// Returns the target as given on the command line, e.g. `s3://bucket/prefix`. It
+    /// identifies the target in the state database.
+    fn describe(&self) -> String;
+
+    /// Uploads the file at `path` as `relative`, a path like `<name>/<key>/<file>`
+    /// below the target.
+    async fn upload(&self, relative: &str, path: &Path) -> Result<()>;
+}
+
+/// A symbol store behind an HTTP server that accepts PUT requests, such as Artifactory
+/// or a WebDAV share.
+///
+/// For WebDAV, the collections above each file are created with MKCOL first.
+pub struct HttpTarget {
+    client: HttpClient,
+    url: String,
+    base: String,
+    webdav: bool,
+}
+
+impl HttpTarget {
+    /// Uploads to `http(s)://host/path` with plain PUT requests, or to
+    /// `webdav(s)://host/path` over WebDAV.
+    pub fn new(url: &str, client: HttpClient) -> Result<Self> {
+        let (base, webdav) = if let Some(rest) = url.strip_prefix("webdav://") {
+            (format!("http://{}", rest), true)
+        } else if let Some(rest) = url.strip_prefix("webdavs://") {
+            (format!("https://{}", rest), true)
+        } else if url.starts_with("http://") || url.starts_with("https://") {
+            (url.to_string(), false)
+        } else {
+            return Err(Error::RemoteStore {
+                location: url.to_string(),
+                message: "expected an http(s):// or webdav(s):// URL".to_string(),
+            });
+        };
+        Ok(Self {
+            client,
+            url: url.to_string(),
+            base: base.trim_end_matches('/').to_string(),
+            webdav,
+        })
+    }
+
+    /// Creates the collection at `url`; collections that already exist are fine.
+    async fn create_collection(&self, url: &str) -> Result<()> {
+        let method = reqwest::Method::from_bytes(b"MKCOL").expect("valid method name");
+        let response = self.client.request(method, url).send().await?;
+        let status = response.status();
+        // 405 Method Not Allowed means the collection is already there.
+        if status.is_success() || status == reqwest::StatusCode::METHOD_NOT_ALLOWED {
+            return Ok(());
+        }
+        Err(Error::HttpStatus {
+            url: url.to_string(),
+            status,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl PushTarget for HttpTarget {
+    fn describe(&self) -> String {
+        self.url.clone()
+    }
+
+    async fn upload(&self, relative: &str, path: &Path) -> Result<()> {
+        if self.webdav
+            && let Some((folders, _)) = relative.rsplit_once('/')
+        {
+            let mut collection = self.base.clone();
+            for folder in folders.split('/') {
+                collection = format!("{}/{}", collection, folder);
+                self.create_collection(&collection).await?;
+            }
+        }
+        let url = format!("{}/{}", self.base, relative);
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(Error::store(path))?;
+        let len = file.metadata().await.map_err(Error::store(path))?.len();
+        let response = self
+            .client
+            .put(&url)
+            .header(reqwest::header::CONTENT_LENGTH, len)
+            .body(reqwest::Body::wrap_stream(ReaderStream::new(file)))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(Error::HttpStatus {
+                url,
+                status: response.status(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Outcome counts of [`push`].
+#[derive(Debug, Default)]
+pub struct PushSummary {
+    /// Files uploaded to the target.
+    pub uploaded: usize,
+    /// Files the target already got from an earlier push and that have not changed.
+    pub unchanged: usize,
+    /// Files that could not be uploaded.
+    pub failed: usize,
+    /// Total size of the uploaded files.
+    pub bytes: u64,
+}
+
+/// Uploads the files of `store` that were not pushed to `target` before, or changed
+/// since, running up to `jobs` uploads at once.
+///
+/// Pushed files are recorded in `state` with their size and modification time, so the
+/// next push only uploads what is new. Files are uploaded as `<name>/<key>/<file>`
+/// whatever the layout of the store, matching the flat layout of the remote stores.
+/// With `dry_run`, the files are only logged and counted.
+pub async fn push(
+    store: &LocalStore,
+    target: Arc<dyn PushTarget>,
+    state: &mut StateDb,
+    jobs: usize,
+    dry_run: bool,
+) -> Result<PushSummary> {
+    let name = target.describe();
+    let pushed = state.pushed_files(&name)?;
+    let mut summary = PushSummary::default();
+    let permits = Arc::new(Semaphore::new(jobs.max(1)));
+    let mut tasks = JoinSet::new();
+    for file in store.stored_files()? {
+        let Some(file_name) = file.path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let relative = format!("{}/{}/{}", file.name, file.key, file_name);
+        let mtime = std::fs::metadata(&file.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs());
+        if pushed.get(&relative) == Some(&(file.size, mtime)) {
+            summary.unchanged += 1;
+            continue;
+        }
+        if dry_run {
+            tracing::info!("Would push {}", relative);
+            summary.uploaded += 1;
+            summary.bytes += file.size;
+            continue;
+        }
+        let target = target.clone();
+        let permits = permits.clone();
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let result = target.upload(&relative, &file.path).await;
+            (relative, file.size, mtime, result)
+        });
+    }
+    while let Some(result) = tasks.join_next().await {
+        match result {
+            Ok((relative, size, mtime, Ok(()))) => {
+                tracing::debug!("Pushed {}", relative);
+                state.record_push(&name, &relative, size, mtime)?;
+                summary.uploaded += 1;
+                summary.bytes += size;
+            }
+            Ok((relative, _, _, Err(e))) => {
+                tracing::warn!("Failed to push {}: {}", relative, e);
+                summary.failed += 1;
+            }
+            Err(e) => {
+                tracing::error!("Push task failed: {}", e);
+                summary.failed += 1;
+            }
+        }
+    }
+    Ok(summary)
+}
@@ -0,0 +1,99 @@
+//! A size limit for local symbol stores, enforced while downloading.
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::SystemTime,
+};
+
+use tokio::sync::Mutex;
+
+use crate::{
+    error::{Error, Result},
+    prune::{self, PrunePolicy},
+    store::LocalStore,
+};
+
+/// Share of the quota a store is pruned down to once it is full, so pruning does not
+/// run again for every download.
+const PRUNE_TARGET_PERCENT: u64 = 90;
+
+/// Keeps a download run from growing a local store beyond `max_size` bytes.
+///
+/// Downloads are only started while the store is below the quota, so running ones can
+/// take it over by up to a PDB each. Once it is full, further downloads fail with
+/// [`Error::QuotaExceeded`], or with `prune` set the least recently read files are
+/// deleted to make room, as `prune --max-size` does.
+#[derive(Debug)]
+pub struct StoreQuota {
+    store: LocalStore,
+    max_size: u64,
+    prune: bool,
+    used: Mutex<u64>,
+    reported: AtomicBool,
+}
+
+impl StoreQuota {
+    /// Creates a quota for `store`, adding up the sizes of the files already in it.
+    pub fn new(store: LocalStore, max_size: u64, prune: bool) -> Result<Self> {
+        let used = store.stored_files()?.iter().map(|file| file.size).sum();
+        Ok(Self {
+            store,
+            max_size,
+            prune,
+            used: Mutex::new(used),
+            reported: AtomicBool::new(false),
+        })
+    }
+
+    /// Checks that another file may be downloaded, pruning the store first if it is
+    /// full and pruning is enabled.
+    pub(crate) async fn check(&self) -> Result<()> {
+        let mut used = self.used.lock().await;
+        if *used < self.max_size {
+            return Ok(());
+        }
+        if self.prune {
+            let store = self.store.clone();
+            let target = self.max_size / 100 * PRUNE_TARGET_PERCENT;
+            *used = tokio::task::spawn_blocking(move || prune_to(&store, target))
+                .await
+                .map_err(std::io::Error::other)??;
+            if *used < self.max_size {
+                return Ok(());
+            }
+        }
+        if !self.reported.swap(true, Ordering::Relaxed) {
+            tracing::warn!(
+                "The store reached its quota of {} bytes, not downloading any more PDBs",
+                self.max_size
+            );
+        }
+        Err(Error::QuotaExceeded {
+            max_size: self.max_size,
+        })
+    }
+
+    /// Counts `bytes` written to the store.
+    pub(crate) async fn add(&self, bytes: u64) {
+        *self.used.lock().await += bytes;
+    }
+}
+
+/// Deletes the least recently read files of the store until it is at most `target`
+/// bytes and returns its new size.
+fn prune_to(store: &LocalStore, target: u64) -> Result<u64> {
+    let files = store.stored_files()?;
+    let total: u64 = files.iter().map(|file| file.size).sum();
+    let policy = PrunePolicy {
+        max_size: Some(target),
+        ..PrunePolicy::default()
+    };
+    let victims = prune::select(files, &policy, SystemTime::now());
+    let freed = prune::remove(store, &victims);
+    tracing::info!(
+        "Pruned {} files, {} bytes, to stay within the store quota",
+        victims.len(),
+        freed
+    );
+    Ok(total - freed)
+}
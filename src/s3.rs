@@ -0,0 +1,180 @@
+//! A symbol store in an S3 (or S3-compatible) bucket.
+
+use std::path::{Path, PathBuf};
+
+use aws_sdk_s3::{
+    Client, config::BehaviorVersion, error::SdkError, operation::head_object::HeadObjectError,
+    primitives::ByteStream,
+};
+
+use crate::{
+    error::{Error, Result},
+    push::PushTarget,
+    store::{LocalStore, SymbolStore},
+    windows::PdbMeta,
+};
+
+/// A symbol store in an S3 bucket, using the flat symstore layout
+/// `<prefix>/<name>/<GUID><age>/<name>` for object keys.
+///
+/// Credentials and the region are read from the usual AWS environment variables and
+/// profiles. Set `AWS_ENDPOINT_URL` to use an S3-compatible service such as MinIO.
+/// Downloads are staged in a local store before they are uploaded.
+pub struct S3Store {
+    client: Client,
+    bucket: String,
+    prefix: String,
+    staging: LocalStore,
+}
+
+impl S3Store {
+    /// Connects to the bucket given as `s3://bucket/prefix`, staging downloads in
+    /// `staging`.
+    pub async fn new(url: &str, staging: LocalStore) -> Result<Self> {
+        let (bucket, prefix) = parse_url(url).ok_or_else(|| Error::RemoteStore {
+            location: url.to_string(),
+            message: "expected s3://bucket/prefix".to_string(),
+        })?;
+        let config = aws_config::load_defaults(BehaviorVersion::latest()).await;
+        let s3_config = aws_sdk_s3::config::Builder::from(&config)
+            .force_path_style(std::env::var_os("AWS_ENDPOINT_URL").is_some())
+            .build();
+        tracing::info!("Using S3 symbol store: {}", url);
+        Ok(Self {
+            client: Client::from_conf(s3_config),
+            bucket,
+            prefix,
+            staging,
+        })
+    }
+
+    /// Returns the object key of the PDB.
+    pub fn object_key(&self, pdb: &PdbMeta) -> String {
+        self.prefixed(&format!("{}/{}/{}", pdb.name, pdb.key(), pdb.name))
+    }
+
+    /// Returns the object key of `relative`, a path below the prefix.
+    fn prefixed(&self, relative: &str) -> String {
+        if self.prefix.is_empty() {
+            relative.to_string()
+        } else {
+            format!("{}/{}", self.prefix, relative)
+        }
+    }
+
+    /// Returns the size of the PDB's object, or `None` if it does not exist.
+    pub async fn exists(&self, pdb: &PdbMeta) -> Result<Option<u64>> {
+        let key = self.object_key(pdb);
+        match self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .send()
+            .await
+        {
+            Ok(head) => Ok(Some(head.content_length().unwrap_or(0).max(0) as u64)),
+            Err(SdkError::ServiceError(e)) if matches!(e.err(), HeadObjectError::NotFound(_)) => {
+                Ok(None)
+            }
+            Err(e) => Err(self.error(&key, e)),
+        }
+    }
+
+    /// Downloads the PDB's object, or returns `None` if it does not exist.
+    pub async fn get(&self, pdb: &PdbMeta) -> Result<Option<Vec<u8>>> {
+        let key = self.object_key(pdb);
+        let object = match self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .send()
+            .await
+        {
+            Ok(object) => object,
+            Err(SdkError::ServiceError(e)) if e.err().is_no_such_key() => return Ok(None),
+            Err(e) => return Err(self.error(&key, e)),
+        };
+        let data = object
+            .body
+            .collect()
+            .await
+            .map_err(|e| self.error(&key, e))?;
+        Ok(Some(data.to_vec()))
+    }
+
+    /// Uploads the file at `path` as the PDB's object.
+    pub async fn put(&self, pdb: &PdbMeta, path: &Path) -> Result<()> {
+        self.put_object(&self.object_key(pdb), path).await
+    }
+
+    /// Uploads the file at `path` as the object `key`.
+    async fn put_object(&self, key: &str, path: &Path) -> Result<()> {
+        let body = ByteStream::from_path(path)
+            .await
+            .map_err(|e| self.error(key, e))?;
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| self.error(key, e))?;
+        Ok(())
+    }
+
+    fn error(&self, key: &str, error: impl std::error::Error) -> Error {
+        Error::RemoteStore {
+            location: format!("s3://{}/{}", self.bucket, key),
+            message: aws_sdk_s3::error::DisplayErrorContext(error).to_string(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SymbolStore for S3Store {
+    fn location(&self, pdb: &PdbMeta) -> PathBuf {
+        PathBuf::from(format!("s3://{}/{}", self.bucket, self.object_key(pdb)))
+    }
+
+    async fn stored_size(&self, pdb: &PdbMeta) -> Result<Option<u64>> {
+        self.exists(pdb).await
+    }
+
+    async fn prepare(&self, pdb: &PdbMeta) -> Result<PathBuf> {
+        self.staging.prepare(pdb).await
+    }
+
+    /// Uploads the staged download and removes it from the staging store.
+    async fn persist(&self, pdb: &PdbMeta) -> Result<PathBuf> {
+        let part = self.staging.part_path(pdb);
+        self.put(pdb, &part).await?;
+        tokio::fs::remove_file(&part)
+            .await
+            .map_err(Error::store(&part))?;
+        Ok(self.location(pdb))
+    }
+}
+
+#[async_trait::async_trait]
+impl PushTarget for S3Store {
+    fn describe(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+
+    async fn upload(&self, relative: &str, path: &Path) -> Result<()> {
+        self.put_object(&self.prefixed(relative), path).await
+    }
+}
+
+/// Splits `s3://bucket/prefix` into the bucket and the prefix without slashes.
+fn parse_url(url: &str) -> Option<(String, String)> {
+    let rest = url.strip_prefix("s3://")?;
+    let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+    if bucket.is_empty() {
+        return None;
+    }
+    Some((bucket.to_string(), prefix.trim_matches('/').to_string()))
+}
@@ -0,0 +1,299 @@
+//! Serving a local symbol store over HTTP.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use axum::{
+    Router,
+    body::Body,
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    compress::{StoreCompression, expand_file},
+    download::fetch_url,
+    error::{Error, Result},
+    http::HttpClient,
+    store::{LocalStore, verify_file},
+    windows::PdbMeta,
+};
+
+/// An HTTP symbol server exposing a [`LocalStore`] with the `<name>/<key>/<file>`
+/// URL scheme used by symsrv, so debuggers can use it with `srv*<url>`.
+///
+/// Requests for files missing from the store are passed on to the upstream servers,
+/// if any, in order. In mirror mode the files are stored before they are returned, so
+/// the server acts as a caching symbol proxy.
+pub struct SymbolServer {
+    store: LocalStore,
+    upstreams: Vec<String>,
+    client: HttpClient,
+    mirror: bool,
+    in_flight: Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl SymbolServer {
+    /// Creates a server for `store` that forwards misses to `upstreams` with `client`,
+    /// built from [`HttpOptions`](crate::HttpOptions).
+    pub fn new(store: LocalStore, upstreams: Vec<String>, client: HttpClient) -> Self {
+        Self {
+            store,
+            upstreams,
+            client,
+            mirror: false,
+            in_flight: Mutex::default(),
+        }
+    }
+
+    /// Stores files fetched from the upstream servers instead of only passing them on.
+    pub fn with_mirror(mut self, mirror: bool) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    /// Returns the routes of the server, e.g. to nest them into another application.
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/{name}/{key}/{file}", get(get_file))
+            .with_state(Arc::new(self))
+    }
+
+    /// Listens on `addr` and serves requests until the process is stopped.
+    pub async fn run(self, addr: SocketAddr) -> Result<()> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        tracing::info!(
+            "Serving {} on http://{}",
+            self.store.get_root().display(),
+            addr
+        );
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+
+    /// Downloads a file missing from the store from the upstream servers into the store.
+    ///
+    /// Concurrent requests for the same file wait for a single download.
+    async fn mirror(&self, name: &str, key: &str, file: &str) -> Result<()> {
+        let path = self.store.key_folder(name, key).join(file);
+        let lock = self
+            .in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(path.clone())
+            .or_default()
+            .clone();
+        let result = {
+            let _guard = lock.lock().await;
+            self.mirror_once(name, key, file).await
+        };
+        let mut in_flight = self
+            .in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        // Requests still waiting hold clones too; the last one out removes the entry.
+        if Arc::strong_count(&lock) == 2 {
+            in_flight.remove(&path);
+        }
+        result
+    }
+
+    /// Downloads a file into a temporary file in the store root, and only moves it into
+    /// its folder once an upstream returned it and it matches its key, so requests for
+    /// files no upstream has leave nothing behind in the store.
+    ///
+    /// Each mirrored file is recorded as a transaction naming the upstream it came
+    /// from. Other runs sharing the store may download the same file at the same time;
+    /// the first to store it wins.
+    async fn mirror_once(&self, name: &str, key: &str, file: &str) -> Result<()> {
+        if self.store.find_file(name, key, file).is_some() {
+            return Ok(());
+        }
+        let root = self.store.get_root();
+        let download = tempfile::Builder::new()
+            .prefix(".mirror")
+            .tempfile_in(root)
+            .map_err(Error::store(root))?;
+        let mut failure = None;
+        for upstream in &self.upstreams {
+            let url = format!(
+                "{}/{}/{}/{}",
+                upstream.trim_end_matches('/'),
+                name,
+                key,
+                file
+            );
+            // `fetch_url` resumes from what is already in the file.
+            download
+                .as_file()
+                .set_len(0)
+                .map_err(Error::store(download.path()))?;
+            match fetch_url(&self.client, &url, download.path()).await {
+                Ok(_) => match check_mirrored(name, key, file, download.path()) {
+                    Ok(()) => {
+                        tracing::info!("Mirrored {}", url);
+                        return self.store_mirrored(name, key, file, download, &url).await;
+                    }
+                    Err(reason) => tracing::warn!("Rejected {} from {}: {}", file, url, reason),
+                },
+                Err(Error::NotFound) => {}
+                Err(e) => {
+                    tracing::warn!("Upstream request failed for {}: {}", url, e);
+                    failure = Some(e);
+                }
+            }
+        }
+        Err(failure.unwrap_or(Error::NotFound))
+    }
+
+    /// Moves a downloaded file into the store and records it in a transaction.
+    async fn store_mirrored(
+        &self,
+        name: &str,
+        key: &str,
+        file: &str,
+        download: tempfile::NamedTempFile,
+        url: &str,
+    ) -> Result<()> {
+        let _file_lock = self.store.lock_file(name, key, file).await?;
+        if self.store.find_file(name, key, file).is_some() {
+            return Ok(());
+        }
+        let part = self.store.prepare_file(name, key, file).await?;
+        download
+            .persist(&part)
+            .map_err(|e| Error::store(&part)(e.error))?;
+        self.store
+            .persist_file(name, key, file, std::path::Path::new(url))
+            .await?;
+        self.store
+            .commit_transaction_with_comment(&format!("mirrored from {}", url))
+    }
+
+    /// Passes a request for a file missing from the store on to the upstream servers.
+    async fn forward(&self, name: &str, key: &str, file: &str) -> Response {
+        for upstream in &self.upstreams {
+            let url = format!(
+                "{}/{}/{}/{}",
+                upstream.trim_end_matches('/'),
+                name,
+                key,
+                file
+            );
+            match self.client.get(&url).send().await {
+                Ok(response) if response.status().is_success() => {
+                    tracing::info!("Forwarding {}", url);
+                    let mut builder = Response::builder().status(StatusCode::OK);
+                    if let Some(length) = response.content_length() {
+                        builder = builder.header(header::CONTENT_LENGTH, length);
+                    }
+                    return builder
+                        .body(Body::from_stream(response.bytes_stream()))
+                        .unwrap_or_else(|_| StatusCode::BAD_GATEWAY.into_response());
+                }
+                Ok(response) => {
+                    tracing::debug!("Upstream returned {} for {}", response.status(), url)
+                }
+                Err(e) => tracing::warn!("Upstream request failed for {}: {}", url, e),
+            }
+        }
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+async fn get_file(
+    State(server): State<Arc<SymbolServer>>,
+    Path((name, key, file)): Path<(String, String, String)>,
+) -> Response {
+    if [&name, &key, &file]
+        .iter()
+        .any(|part| part.is_empty() || part.starts_with('.') || part.contains(['/', '\\']))
+    {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    if let Some(response) = serve_stored(&server.store, &name, &key, &file).await {
+        return response;
+    }
+    if !server.mirror {
+        return server.forward(&name, &key, &file).await;
+    }
+    match server.mirror(&name, &key, &file).await {
+        Ok(()) => serve_stored(&server.store, &name, &key, &file)
+            .await
+            .unwrap_or_else(|| StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+        Err(Error::NotFound) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            tracing::error!("Failed to mirror {}/{}/{}: {}", name, key, file, e);
+            StatusCode::BAD_GATEWAY.into_response()
+        }
+    }
+}
+
+/// Streams a file from the store, expanding it first if the store only has a
+/// compressed copy, or returns `None` if the store does not have it.
+async fn serve_stored(store: &LocalStore, name: &str, key: &str, file: &str) -> Option<Response> {
+    let (path, compression) = store.find_file(name, key, file)?;
+    if compression == StoreCompression::None {
+        return serve_file(&path).await;
+    }
+    tracing::info!("Serving {} expanded", path.display());
+    let expanded = tokio::task::spawn_blocking(move || {
+        let temp = tempfile::NamedTempFile::new()?;
+        expand_file(&path, compression, temp.path())?;
+        // The temporary file is deleted when it is dropped; the handle stays usable.
+        temp.reopen()
+    })
+    .await
+    .ok()?;
+    match expanded {
+        Ok(handle) => Some(stream_file(tokio::fs::File::from_std(handle)).await),
+        Err(e) => {
+            tracing::error!("Failed to expand {}/{}/{}: {}", name, key, file, e);
+            Some(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+/// Streams the file at `path`, or returns `None` if it cannot be opened.
+async fn serve_file(path: &std::path::Path) -> Option<Response> {
+    let handle = tokio::fs::File::open(path).await.ok()?;
+    tracing::info!("Serving {}", path.display());
+    Some(stream_file(handle).await)
+}
+
+/// Streams an open file as the response body.
+async fn stream_file(handle: tokio::fs::File) -> Response {
+    let mut builder = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/octet-stream");
+    if let Ok(metadata) = handle.metadata().await {
+        builder = builder.header(header::CONTENT_LENGTH, metadata.len());
+    }
+    builder
+        .body(Body::from_stream(ReaderStream::new(handle)))
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
+/// Checks a mirrored file against its key if it is the file the folder is named after,
+/// like `verify` does. Other files, such as compressed `.pd_` files, are taken as they
+/// are.
+fn check_mirrored(
+    name: &str,
+    key: &str,
+    file: &str,
+    path: &std::path::Path,
+) -> std::result::Result<(), String> {
+    if file != name {
+        return Ok(());
+    }
+    let pdb = PdbMeta::from_key(name, key, std::path::Path::new(file))
+        .ok_or_else(|| format!("invalid key {}", key))?;
+    verify_file(&pdb, path)
+}
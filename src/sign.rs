@@ -0,0 +1,129 @@
+//! Minisign (Ed25519) signatures of run manifests and store admin files, so consumers
+//! of a symbol bundle can check that it was not tampered with in transit.
+//!
+//! Signatures are written next to the signed files as `<file>.minisig` and can be
+//! checked with [`verify_file`] or `minisign -V`.
+
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use minisign::{PublicKey, PublicKeyBox, SecretKey, SecretKeyBox, SignatureBox};
+
+use crate::error::{Error, Result};
+pub use crate::store::SIGNATURE_EXTENSION;
+
+/// Signs files with a minisign secret key.
+pub struct Signer {
+    key: SecretKey,
+}
+
+impl Signer {
+    /// Loads the secret key file at `path`, as written by `minisign -G`, decrypting
+    /// it with `password`. Keys created without a password (`minisign -G -W`) need
+    /// none.
+    pub fn from_file(path: &Path, password: Option<String>) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let invalid = |e: minisign::PError| Error::Signature {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        };
+        let key_box = SecretKeyBox::from_string(&text).map_err(invalid)?;
+        let key = match password {
+            Some(password) => key_box.into_secret_key(Some(password)),
+            None => key_box.into_unencrypted_secret_key(),
+        }
+        .map_err(invalid)?;
+        Ok(Self { key })
+    }
+
+    /// Signs the file at `path`, writing the signature to `<path>.minisig`, and
+    /// returns the path of the signature. The trusted comment names the file, so a
+    /// signature cannot be passed off as one of another file.
+    pub fn sign_file(&self, path: &Path) -> Result<PathBuf> {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let trusted_comment = format!(
+            "file:{}\ttimestamp:{}",
+            name,
+            chrono::Utc::now().timestamp()
+        );
+        let signature = minisign::sign(
+            None,
+            &self.key,
+            File::open(path)?,
+            Some(&trusted_comment),
+            Some("signed by symbolfetcher"),
+        )
+        .map_err(|e| Error::Signature {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })?;
+        let signature_path = signature_path(path);
+        fs::write(&signature_path, signature.into_string())?;
+        tracing::debug!("Signed {}", path.display());
+        Ok(signature_path)
+    }
+}
+
+/// Reads a public key given as a file written by `minisign -G` or as the base64 line
+/// of one, e.g. `RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3`.
+pub fn read_public_key(key: &str) -> Result<PublicKey> {
+    let path = Path::new(key);
+    let invalid = |e: minisign::PError| Error::Signature {
+        path: path.to_path_buf(),
+        reason: e.to_string(),
+    };
+    if path.is_file() {
+        return PublicKeyBox::from_string(&fs::read_to_string(path)?)
+            .and_then(PublicKeyBox::into_public_key)
+            .map_err(invalid);
+    }
+    PublicKey::from_base64(key.trim()).map_err(invalid)
+}
+
+/// Checks the file at `path` against its signature in `<path>.minisig`, and that the
+/// trusted comment of the signature names the file, so the signature of another file
+/// signed with the same key is not accepted.
+pub fn verify_file(key: &PublicKey, path: &Path) -> Result<()> {
+    let signature_path = signature_path(path);
+    let invalid = |e: minisign::PError| Error::Signature {
+        path: path.to_path_buf(),
+        reason: e.to_string(),
+    };
+    let signature =
+        SignatureBox::from_string(&fs::read_to_string(&signature_path).map_err(|e| {
+            Error::Signature {
+                path: path.to_path_buf(),
+                reason: format!("no signature at {}: {}", signature_path.display(), e),
+            }
+        })?)
+        .map_err(invalid)?;
+    minisign::verify(key, &signature, File::open(path)?, true, false, false).map_err(invalid)?;
+    let trusted_comment = signature.trusted_comment().map_err(invalid)?;
+    let signed_name = trusted_comment
+        .split('\t')
+        .find_map(|field| field.strip_prefix("file:"));
+    let name = path.file_name().map(|name| name.to_string_lossy());
+    if signed_name.is_none() || signed_name != name.as_deref() {
+        return Err(Error::Signature {
+            path: path.to_path_buf(),
+            reason: format!(
+                "signature is for {}, not this file",
+                signed_name.unwrap_or("an unnamed file")
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Returns the path of the signature of the file at `path`.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(SIGNATURE_EXTENSION);
+    PathBuf::from(name)
+}
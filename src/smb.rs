@@ -0,0 +1,380 @@
+//! SMB shares given as UNC paths, `\\server\symbols`, the classic home of Windows
+//! symbol stores.
+//!
+//! Windows opens UNC paths natively, so there they are used like any other directory.
+//! Elsewhere shares are reached with libsmbclient: [`SmbSource`] reads symbols from
+//! one and [`SmbStore`] writes them to one. Credentials are taken from
+//! `SYMBOLFETCHER_SMB_USER`, `SYMBOLFETCHER_SMB_PASSWORD` and
+//! `SYMBOLFETCHER_SMB_DOMAIN`; without them the share is accessed as a guest.
+
+use std::fmt;
+
+/// A location on an SMB share.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmbPath {
+    /// The server name or address.
+    pub server: String,
+    /// The name of the share.
+    pub share: String,
+    /// The path below the share, with `/` separators and a leading `/`, or empty for
+    /// the share itself.
+    pub path: String,
+}
+
+impl SmbPath {
+    /// Parses `\\server\share\path`, `//server/share/path` or `smb://server/share/path`,
+    /// returning `None` for anything else, including `\\?\` and `\\.\` device paths.
+    pub fn parse(location: &str) -> Option<Self> {
+        let rest = location
+            .strip_prefix("smb://")
+            .or_else(|| location.strip_prefix(r"\\"))
+            .or_else(|| location.strip_prefix("//"))?;
+        let mut parts = rest.split(['\\', '/']).filter(|part| !part.is_empty());
+        let server = parts
+            .next()
+            .filter(|server| *server != "?" && *server != ".")?;
+        let share = parts.next()?;
+        let path: String = parts.map(|part| format!("/{}", part)).collect();
+        Some(Self {
+            server: server.to_string(),
+            share: share.to_string(),
+            path,
+        })
+    }
+
+    /// Returns the location of `relative`, a `/`-separated path below this one.
+    pub fn join(&self, relative: &str) -> Self {
+        let mut path = self.path.clone();
+        for part in relative.split('/').filter(|part| !part.is_empty()) {
+            path.push('/');
+            path.push_str(part);
+        }
+        Self {
+            path,
+            ..self.clone()
+        }
+    }
+}
+
+/// Formats the location as a UNC path, `\\server\share\path`.
+impl fmt::Display for SmbPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r"\\{}\{}{}",
+            self.server,
+            self.share,
+            self.path.replace('/', r"\")
+        )
+    }
+}
+
+#[cfg(all(not(windows), feature = "smb"))]
+pub use client::{SmbSource, SmbStore, copy_file};
+
+#[cfg(all(not(windows), feature = "smb"))]
+mod client {
+    use std::{
+        fs::File,
+        io::{self, Read},
+        path::{Path, PathBuf},
+    };
+
+    use pavao::{SmbClient, SmbCredentials, SmbError, SmbMode, SmbOpenOptions, SmbOptions};
+
+    use super::SmbPath;
+    use crate::{
+        compress::{StoreCompression, expand_file},
+        download::Download,
+        error::{Error, Result},
+        http::HttpClient,
+        source::{FILE_POINTER, SymbolSource, follow_pointer},
+        store::{INDEX2_FILE, LocalStore, SymbolStore},
+        windows::PdbMeta,
+    };
+
+    const USER_VAR: &str = "SYMBOLFETCHER_SMB_USER";
+    const PASSWORD_VAR: &str = "SYMBOLFETCHER_SMB_PASSWORD";
+    const DOMAIN_VAR: &str = "SYMBOLFETCHER_SMB_DOMAIN";
+
+    impl SmbPath {
+        /// Connects to the share. libsmbclient contexts cannot be shared between
+        /// threads, so every blocking task connects on its own.
+        fn connect(&self) -> Result<SmbClient> {
+            let mut credentials = SmbCredentials::default()
+                .server(format!("smb://{}", self.server))
+                .share(format!("/{}", self.share));
+            if let Ok(user) = std::env::var(USER_VAR) {
+                credentials = credentials.username(user);
+            }
+            if let Ok(password) = std::env::var(PASSWORD_VAR) {
+                credentials = credentials.password(password);
+            }
+            if let Ok(domain) = std::env::var(DOMAIN_VAR) {
+                credentials = credentials.workgroup(domain);
+            }
+            SmbClient::new(
+                credentials,
+                SmbOptions::default().one_share_per_server(true),
+            )
+            .map_err(|e| self.error(e))
+        }
+
+        /// Returns the size of the file, or `None` if it does not exist.
+        fn size(&self, client: &SmbClient) -> Result<Option<u64>> {
+            match client.stat(&self.path) {
+                Ok(stat) => Ok(Some(stat.size)),
+                Err(SmbError::Io(e)) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(self.error(e)),
+            }
+        }
+
+        /// Copies the file to `dest` and returns its size.
+        fn download(&self, client: &SmbClient, dest: &Path) -> Result<u64> {
+            let mut file = client
+                .open_with(&self.path, SmbOpenOptions::default().read(true))
+                .map_err(|e| self.error(e))?;
+            let mut out = File::create(dest).map_err(Error::store(dest))?;
+            io::copy(&mut file, &mut out).map_err(|e| self.error(e))
+        }
+
+        /// Uploads the file at `source` here, creating the directories above it.
+        ///
+        /// The file is written under a `.part` name first and renamed when complete,
+        /// so debuggers reading the share never see half of it.
+        fn upload(&self, client: &SmbClient, source: &Path) -> Result<()> {
+            let mut directory = String::new();
+            let parents: Vec<&str> = self
+                .path
+                .split('/')
+                .filter(|part| !part.is_empty())
+                .collect();
+            for part in parents.iter().take(parents.len().saturating_sub(1)) {
+                directory = format!("{}/{}", directory, part);
+                let location = Self {
+                    path: directory.clone(),
+                    ..self.clone()
+                };
+                if location.size(client)?.is_none() {
+                    client
+                        .mkdir(&directory, SmbMode::from(0o755))
+                        .map_err(|e| location.error(e))?;
+                }
+            }
+            let part = format!("{}.part", self.path);
+            let mut file = client
+                .open_with(
+                    &part,
+                    SmbOpenOptions::default()
+                        .create(true)
+                        .write(true)
+                        .truncate(true),
+                )
+                .map_err(|e| self.error(e))?;
+            io::copy(
+                &mut File::open(source).map_err(Error::store(source))?,
+                &mut file,
+            )
+            .map_err(|e| self.error(e))?;
+            drop(file);
+            client.rename(&part, &self.path).map_err(|e| self.error(e))
+        }
+
+        fn error(&self, error: impl std::fmt::Display) -> Error {
+            Error::RemoteStore {
+                location: self.to_string(),
+                message: error.to_string(),
+            }
+        }
+    }
+
+    /// Returns the folder of `name` and `key` below a store root, in the tiered
+    /// layout if `tiered`.
+    fn key_folder(name: &str, key: &str, tiered: bool) -> String {
+        if tiered {
+            format!(
+                "{}/{}/{}",
+                name.chars().take(2).collect::<String>(),
+                name,
+                key
+            )
+        } else {
+            format!("{}/{}", name, key)
+        }
+    }
+
+    /// Returns whether the store at `root` uses the tiered layout.
+    fn is_tiered(root: &SmbPath, client: &SmbClient) -> Result<bool> {
+        Ok(root.join(INDEX2_FILE).size(client)?.is_some())
+    }
+
+    /// Copies the file at `location` to `dest`, for file pointers to UNC paths.
+    pub async fn copy_file(location: SmbPath, dest: PathBuf) -> Result<u64> {
+        tokio::task::spawn_blocking(move || {
+            let client = location.connect()?;
+            if location.size(&client)?.is_none() {
+                tracing::warn!("File pointer target not found: {}", location);
+                return Err(Error::NotFound);
+            }
+            location.download(&client, &dest)
+        })
+        .await
+        .map_err(|e| Error::Io(io::Error::other(e)))?
+    }
+
+    /// A symbol store on an SMB share, read like a [`LocalSource`](crate::LocalSource).
+    pub struct SmbSource {
+        root: SmbPath,
+        client: HttpClient,
+    }
+
+    /// What [`SmbSource`] found for a PDB.
+    enum Found {
+        File(Download),
+        /// The contents of a `file.ptr`.
+        Pointer(String),
+    }
+
+    impl SmbSource {
+        /// Creates a source for the symbol store at `root`, following file pointers to
+        /// HTTP locations with `client`.
+        pub fn new(root: SmbPath, client: HttpClient) -> Self {
+            Self { root, client }
+        }
+    }
+
+    /// Copies the PDB from the store at `root` to `dest`, expanding a compressed copy
+    /// if only that exists.
+    fn find(root: &SmbPath, pdb: &PdbMeta, dest: &Path) -> Result<Found> {
+        let client = root.connect()?;
+        let folder = root.join(&key_folder(
+            &pdb.name,
+            &pdb.key(),
+            is_tiered(root, &client)?,
+        ));
+        for compression in [
+            StoreCompression::None,
+            StoreCompression::Cab,
+            StoreCompression::Zstd,
+        ] {
+            let file = folder.join(
+                &compression
+                    .compressed_name(&pdb.name)
+                    .unwrap_or_else(|| pdb.name.clone()),
+            );
+            if file.size(&client)?.is_none() {
+                continue;
+            }
+            let size = if compression == StoreCompression::None {
+                file.download(&client, dest)?
+            } else {
+                let compressed = tempfile::NamedTempFile::new()?;
+                file.download(&client, compressed.path())?;
+                expand_file(compressed.path(), compression, dest).map_err(|source| {
+                    Error::Decompress {
+                        name: pdb.name.clone(),
+                        source,
+                    }
+                })?
+            };
+            return Ok(Found::File(Download {
+                url: file.to_string(),
+                size,
+            }));
+        }
+        let pointer = folder.join(FILE_POINTER);
+        if pointer.size(&client)?.is_some() {
+            let mut file = client
+                .open_with(&pointer.path, SmbOpenOptions::default().read(true))
+                .map_err(|e| pointer.error(e))?;
+            let mut text = String::new();
+            file.read_to_string(&mut text)
+                .map_err(|e| pointer.error(e))?;
+            return Ok(Found::Pointer(text));
+        }
+        Err(Error::NotFound)
+    }
+
+    #[async_trait::async_trait]
+    impl SymbolSource for SmbSource {
+        fn describe(&self) -> String {
+            self.root.to_string()
+        }
+
+        /// Copies the PDB, expanding a CAB-compressed `.pd_` or zstd-compressed file if
+        /// only that exists, or following a `file.ptr` left in its place.
+        async fn fetch(&self, pdb: &PdbMeta, dest: &Path) -> Result<Download> {
+            let (root, meta, out) = (self.root.clone(), pdb.clone(), dest.to_path_buf());
+            let found = tokio::task::spawn_blocking(move || find(&root, &meta, &out))
+                .await
+                .map_err(|e| Error::Io(io::Error::other(e)))??;
+            match found {
+                Found::File(download) => Ok(download),
+                Found::Pointer(pointer) => follow_pointer(&self.client, &pointer, dest).await,
+            }
+        }
+    }
+
+    /// A symbol store on an SMB share, using the layout the share already has, or the
+    /// flat one for a new store. Downloads are staged in a local store before they are
+    /// uploaded.
+    pub struct SmbStore {
+        root: SmbPath,
+        tiered: bool,
+        staging: LocalStore,
+    }
+
+    impl SmbStore {
+        /// Connects to the store at `root`, staging downloads in `staging`.
+        pub async fn new(root: SmbPath, staging: LocalStore) -> Result<Self> {
+            let share = root.clone();
+            let tiered = tokio::task::spawn_blocking(move || is_tiered(&share, &share.connect()?))
+                .await
+                .map_err(|e| Error::Io(io::Error::other(e)))??;
+            tracing::info!("Using SMB symbol store: {}", root);
+            Ok(Self {
+                root,
+                tiered,
+                staging,
+            })
+        }
+
+        /// Returns the location of the PDB on the share.
+        fn file(&self, pdb: &PdbMeta) -> SmbPath {
+            self.root
+                .join(&key_folder(&pdb.name, &pdb.key(), self.tiered))
+                .join(&pdb.name)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl SymbolStore for SmbStore {
+        fn location(&self, pdb: &PdbMeta) -> PathBuf {
+            PathBuf::from(self.file(pdb).to_string())
+        }
+
+        async fn stored_size(&self, pdb: &PdbMeta) -> Result<Option<u64>> {
+            let file = self.file(pdb);
+            tokio::task::spawn_blocking(move || file.size(&file.connect()?))
+                .await
+                .map_err(|e| Error::Io(io::Error::other(e)))?
+        }
+
+        async fn prepare(&self, pdb: &PdbMeta) -> Result<PathBuf> {
+            self.staging.prepare(pdb).await
+        }
+
+        /// Uploads the staged download and removes it from the staging store.
+        async fn persist(&self, pdb: &PdbMeta) -> Result<PathBuf> {
+            let part = self.staging.part_path(pdb);
+            let (file, staged) = (self.file(pdb), part.clone());
+            tokio::task::spawn_blocking(move || file.upload(&file.connect()?, &staged))
+                .await
+                .map_err(|e| Error::Io(io::Error::other(e)))??;
+            tokio::fs::remove_file(&part)
+                .await
+                .map_err(Error::store(&part))?;
+            Ok(self.location(pdb))
+        }
+    }
+}
@@ -0,0 +1,386 @@
+//! Sources PDBs are fetched from.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+use crate::{
+    compress::{StoreCompression, expand_file},
+    download::{Download, compressed_name, expand_compressed, fetch_url},
+    error::{Error, Result},
+    health::SourceHealth,
+    http::HttpClient,
+    pe::{DebugType, expand_embedded_pdb},
+    smb::SmbPath,
+    store::LocalStore,
+    windows::{PdbMeta, SymbolKind},
+};
+
+/// Something that can resolve a PDB key to the PDB file, such as a symbol server.
+///
+/// Implement this to fetch PDBs from internal artifact stores or authenticated APIs,
+/// and register it with [`DownloadManager::with_sources`](crate::DownloadManager::with_sources).
+#[async_trait::async_trait]
+pub trait SymbolSource: Send + Sync {
+    /// Returns a short description of the source for logs, e.g. its URL.
+    fn describe(&self) -> String;
+
+    /// Returns the URL the PDB can be downloaded from, for sources reachable by URL.
+    fn url(&self, _pdb: &PdbMeta) -> Option<String> {
+        None
+    }
+
+    /// Writes the PDB to the file at `dest`.
+    ///
+    /// Returns [`Error::NotFound`] if the source does not have the PDB, so the next
+    /// source is tried.
+    async fn fetch(&self, pdb: &PdbMeta, dest: &Path) -> Result<Download>;
+}
+
+/// A symbol server reached over HTTP, such as the Microsoft public symbol server.
+pub struct HttpSource {
+    client: HttpClient,
+    server: String,
+}
+
+impl HttpSource {
+    /// Creates a source for the symbol server at `server`, sending requests with `client`.
+    pub fn new(client: HttpClient, server: &str) -> Self {
+        Self {
+            client,
+            server: server.trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SymbolSource for HttpSource {
+    fn describe(&self) -> String {
+        self.server.clone()
+    }
+
+    fn url(&self, pdb: &PdbMeta) -> Option<String> {
+        Some(pdb.url(&self.server))
+    }
+
+    /// Downloads the PDB, falling back to the CAB-compressed `.pd_` variant and then to
+    /// a `file.ptr` when the server does not have the uncompressed file.
+    ///
+    /// Pointers are followed, also when the server returns one in place of the PDB.
+    async fn fetch(&self, pdb: &PdbMeta, dest: &Path) -> Result<Download> {
+        let url = pdb.url(&self.server);
+        let download = match fetch_url(&self.client, &url, dest).await {
+            Ok(size) => Download { url, size },
+            Err(Error::NotFound) => match self.fetch_compressed(pdb, dest).await {
+                Err(Error::NotFound) => return self.fetch_pointer(pdb, dest).await,
+                result => result?,
+            },
+            Err(e) => return Err(e),
+        };
+        match read_pointer(dest).await {
+            Some(pointer) => {
+                tracing::info!("{} is a file pointer, following it", download.url);
+                follow_pointer(&self.client, &pointer, dest).await
+            }
+            None => Ok(download),
+        }
+    }
+}
+
+impl HttpSource {
+    /// Downloads and expands the CAB-compressed variant of the PDB.
+    async fn fetch_compressed(&self, pdb: &PdbMeta, dest: &Path) -> Result<Download> {
+        let compressed = compressed_name(&pdb.name);
+        let url = pdb.url_for(&self.server, &compressed);
+        let cab_path = dest.with_extension("cab");
+        let expanded = match fetch_url(&self.client, &url, &cab_path).await {
+            Ok(_) => expand_compressed(&compressed, &cab_path, dest).await,
+            Err(e) => Err(e),
+        };
+        let _ = tokio::fs::remove_file(&cab_path).await;
+        Ok(Download {
+            url,
+            size: expanded?,
+        })
+    }
+
+    /// Downloads the `file.ptr` stored in place of the PDB and follows it.
+    async fn fetch_pointer(&self, pdb: &PdbMeta, dest: &Path) -> Result<Download> {
+        let url = pdb.url_for(&self.server, FILE_POINTER);
+        let pointer_path = dest.with_extension("ptr");
+        let pointer = match fetch_url(&self.client, &url, &pointer_path).await {
+            Ok(_) => tokio::fs::read_to_string(&pointer_path)
+                .await
+                .map_err(Error::store(&pointer_path)),
+            Err(e) => Err(e),
+        };
+        let _ = tokio::fs::remove_file(&pointer_path).await;
+        follow_pointer(&self.client, &pointer?, dest).await
+    }
+}
+
+/// The binaries themselves, for .NET assemblies that embed their portable PDB in a
+/// debug directory entry, so no server has to be asked.
+pub struct EmbeddedSource;
+
+#[async_trait::async_trait]
+impl SymbolSource for EmbeddedSource {
+    fn describe(&self) -> String {
+        "embedded portable PDBs".to_string()
+    }
+
+    /// Extracts the PDB from the binary it was read from, if it is embedded there.
+    async fn fetch(&self, pdb: &PdbMeta, dest: &Path) -> Result<Download> {
+        if pdb.kind != SymbolKind::PortablePdb {
+            return Err(Error::NotFound);
+        }
+        let Some(entry) = pdb
+            .debug_entries
+            .iter()
+            .find(|entry| entry.kind == DebugType::EmbeddedPortablePdb)
+        else {
+            return Err(Error::NotFound);
+        };
+        // Binaries scanned from ISO and WIM files are gone by now.
+        let Ok(image) = tokio::fs::read(&pdb.path).await else {
+            tracing::debug!("Binary {} no longer readable", pdb.path.display());
+            return Err(Error::NotFound);
+        };
+        let data = entry.data(&image).ok_or(Error::NotFound)?;
+        let expanded = expand_embedded_pdb(data).map_err(|source| Error::Decompress {
+            name: pdb.name.clone(),
+            source,
+        })?;
+        tokio::fs::write(dest, &expanded)
+            .await
+            .map_err(Error::store(dest))?;
+        tracing::info!(
+            "Extracted embedded PDB {} from {}",
+            pdb.name,
+            pdb.path.display()
+        );
+        Ok(Download {
+            url: pdb.path.display().to_string(),
+            size: expanded.len() as u64,
+        })
+    }
+}
+
+/// A symbol store directory (or network share) laid out like a symbol server.
+pub struct LocalSource {
+    store: LocalStore,
+    client: HttpClient,
+}
+
+impl LocalSource {
+    /// Creates a source for the symbol store at `root`, following file pointers to
+    /// HTTP locations with `client`.
+    pub fn new(root: PathBuf, client: HttpClient) -> Self {
+        Self {
+            store: LocalStore::new(root),
+            client,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SymbolSource for LocalSource {
+    fn describe(&self) -> String {
+        self.store.get_root().display().to_string()
+    }
+
+    /// Copies the PDB, expanding a CAB-compressed `.pd_` or zstd-compressed file if
+    /// only that exists, or following a `file.ptr` left in its place.
+    async fn fetch(&self, pdb: &PdbMeta, dest: &Path) -> Result<Download> {
+        if let Some((path, compression)) = self.store.find_file(&pdb.name, &pdb.key(), &pdb.name) {
+            let size = if compression == StoreCompression::None {
+                tokio::fs::copy(&path, dest)
+                    .await
+                    .map_err(Error::store(&path))?
+            } else {
+                let (source, out) = (path.clone(), dest.to_path_buf());
+                tokio::task::spawn_blocking(move || expand_file(&source, compression, &out))
+                    .await
+                    .map_err(std::io::Error::other)
+                    .and_then(|result| result)
+                    .map_err(|source| Error::Decompress {
+                        name: pdb.name.clone(),
+                        source,
+                    })?
+            };
+            return Ok(Download {
+                url: path.display().to_string(),
+                size,
+            });
+        }
+        let pointer_path = self.store.pdb_folder(pdb).join(FILE_POINTER);
+        if pointer_path.is_file() {
+            let pointer = tokio::fs::read_to_string(&pointer_path)
+                .await
+                .map_err(Error::store(&pointer_path))?;
+            return follow_pointer(&self.client, &pointer, dest).await;
+        }
+        Err(Error::NotFound)
+    }
+}
+
+/// The file `symstore add /p` stores in place of a file, pointing to where it really is.
+pub(crate) const FILE_POINTER: &str = "file.ptr";
+
+/// Pointers are a single short line; anything larger is not one.
+const MAX_POINTER_LEN: u64 = 4096;
+
+/// Returns the contents of the file at `path` if it is a symbol server file pointer,
+/// `PATH:<location>` or `MSG:<message>`, rather than the requested file.
+async fn read_pointer(path: &Path) -> Option<String> {
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+    if metadata.len() > MAX_POINTER_LEN {
+        return None;
+    }
+    let text = tokio::fs::read_to_string(path).await.ok()?;
+    let trimmed = text.trim_start();
+    (trimmed.starts_with("PATH:") || trimmed.starts_with("MSG:")).then_some(text)
+}
+
+/// Fetches the file a `file.ptr` points to into `dest`: a URL, or a local or UNC path.
+///
+/// Pointers carrying a message instead of a location are logged and treated as the
+/// file not being available.
+pub(crate) async fn follow_pointer(
+    client: &HttpClient,
+    pointer: &str,
+    dest: &Path,
+) -> Result<Download> {
+    let pointer = pointer.trim();
+    let Some(target) = pointer.strip_prefix("PATH:").map(str::trim) else {
+        match pointer.strip_prefix("MSG:") {
+            Some(message) => tracing::info!("Symbol server message: {}", message.trim()),
+            None => tracing::warn!("Unrecognized file pointer: {}", pointer),
+        }
+        return Err(Error::NotFound);
+    };
+    tracing::debug!("Following file pointer to {}", target);
+    // The pointer text may have been written to `dest`, and downloads resume.
+    let _ = tokio::fs::remove_file(dest).await;
+    let lower = target.to_ascii_lowercase();
+    let size = if lower.starts_with("http://") || lower.starts_with("https://") {
+        fetch_url(client, target, dest).await?
+    } else if let Some(location) = SmbPath::parse(target) {
+        copy_share_file(location, dest).await?
+    } else {
+        let path = Path::new(target);
+        if !path.is_file() {
+            tracing::warn!("File pointer target not found: {}", target);
+            return Err(Error::NotFound);
+        }
+        tokio::fs::copy(path, dest)
+            .await
+            .map_err(Error::store(path))?
+    };
+    Ok(Download {
+        url: target.to_string(),
+        size,
+    })
+}
+
+/// Copies a file pointer target on an SMB share, which Windows opens natively.
+#[cfg(all(not(windows), feature = "smb"))]
+async fn copy_share_file(location: SmbPath, dest: &Path) -> Result<u64> {
+    crate::smb::copy_file(location, dest.to_path_buf()).await
+}
+
+#[cfg(all(not(windows), not(feature = "smb")))]
+async fn copy_share_file(location: SmbPath, _dest: &Path) -> Result<u64> {
+    Err(Error::FeatureDisabled {
+        location: location.to_string(),
+        feature: "smb",
+    })
+}
+
+#[cfg(windows)]
+async fn copy_share_file(location: SmbPath, dest: &Path) -> Result<u64> {
+    let path = PathBuf::from(location.to_string());
+    if !path.is_file() {
+        tracing::warn!("File pointer target not found: {}", location);
+        return Err(Error::NotFound);
+    }
+    tokio::fs::copy(&path, dest)
+        .await
+        .map_err(Error::store(&path))
+}
+
+/// Returns the source for a server given on the command line or in a symbol path: an
+/// [`HttpSource`] for URLs, an `SmbSource` for UNC paths outside Windows, and a
+/// [`LocalSource`] for anything else.
+///
+/// Outside Windows, UNC paths need the `smb` feature; without it they are read as
+/// local paths, which find nothing.
+pub fn source_for(client: &HttpClient, server: &str) -> Arc<dyn SymbolSource> {
+    if let Some(share) = SmbPath::parse(server) {
+        #[cfg(all(not(windows), feature = "smb"))]
+        return Arc::new(crate::smb::SmbSource::new(share, client.clone()));
+        #[cfg(all(not(windows), not(feature = "smb")))]
+        tracing::warn!(
+            "{}",
+            Error::FeatureDisabled {
+                location: share.to_string(),
+                feature: "smb",
+            }
+        );
+        #[cfg(windows)]
+        return Arc::new(LocalSource::new(
+            PathBuf::from(share.to_string()),
+            client.clone(),
+        ));
+    }
+    let lower = server.to_ascii_lowercase();
+    if lower.starts_with("http://") || lower.starts_with("https://") {
+        Arc::new(HttpSource::new(client.clone(), server))
+    } else {
+        Arc::new(LocalSource::new(PathBuf::from(server), client.clone()))
+    }
+}
+
+/// Fetches the PDB from the first of `sources` that has it.
+///
+/// Sources that fail for other reasons are logged and skipped; if none has the PDB,
+/// the last such failure is returned, or [`Error::NotFound`].
+pub async fn fetch_from(
+    sources: &[Arc<dyn SymbolSource>],
+    pdb: &PdbMeta,
+    dest: &Path,
+) -> Result<Download> {
+    fetch_tracked(sources, &SourceHealth::default(), pdb, dest).await
+}
+
+/// Fetches the PDB like [`fetch_from`], recording every request in `health` and
+/// trying the sources it backs off from last.
+pub async fn fetch_tracked(
+    sources: &[Arc<dyn SymbolSource>],
+    health: &SourceHealth,
+    pdb: &PdbMeta,
+    dest: &Path,
+) -> Result<Download> {
+    let mut failure = None;
+    for source in health.order(sources) {
+        let started = Instant::now();
+        let result = source.fetch(pdb, dest).await;
+        health.record(&source.describe(), &result, started.elapsed());
+        match result {
+            Ok(download) => return Ok(download),
+            Err(Error::NotFound) => continue,
+            Err(e) => {
+                tracing::warn!(
+                    "Source {} failed for {}: {}",
+                    source.describe(),
+                    pdb.name,
+                    e
+                );
+                failure = Some(e);
+            }
+        }
+    }
+    Err(failure.unwrap_or(Error::NotFound))
+}
@@ -0,0 +1,327 @@
+//! SourceLink information of portable PDBs, which maps the source documents of a .NET
+//! build to URLs such as raw GitHub files.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    bytes::{read_u32, read_u64},
+    error::{Error, Result},
+    sources::SourceFile,
+};
+
+/// Signature of ECMA-335 metadata, which portable PDBs consist of.
+const METADATA_SIGNATURE: &[u8; 4] = b"BSJB";
+/// Kind of the SourceLink custom debug information,
+/// `CC110556-A091-4D38-9FEC-25AB9A351A6A` in its in-memory byte order.
+const SOURCE_LINK_KIND: [u8; 16] = [
+    0x56, 0x05, 0x11, 0xCC, 0x91, 0xA0, 0x38, 0x4D, 0x9F, 0xEC, 0x25, 0xAB, 0x9A, 0x35, 0x1A, 0x6A,
+];
+/// Table of the source documents.
+const DOCUMENT: usize = 0x30;
+const METHOD_DEF: usize = 0x06;
+const LOCAL_VARIABLE: usize = 0x33;
+const LOCAL_CONSTANT: usize = 0x34;
+const IMPORT_SCOPE: usize = 0x35;
+/// Table of custom debug information, such as SourceLink.
+const CUSTOM_DEBUG_INFORMATION: usize = 0x37;
+/// Tables a `HasCustomDebugInformation` coded index points into, in tag order.
+const HAS_CUSTOM_DEBUG_INFORMATION: &[usize] = &[
+    0x06, 0x04, 0x01, 0x02, 0x08, 0x09, 0x0A, 0x00, 0x0E, 0x17, 0x14, 0x11, 0x1A, 0x1B, 0x20, 0x23,
+    0x26, 0x27, 0x28, 0x2A, 0x2C, 0x2B, 0x30, 0x32, 0x33, 0x34, 0x35,
+];
+/// Bits of the tag of a `HasCustomDebugInformation` coded index.
+const HAS_CUSTOM_DEBUG_INFORMATION_BITS: u32 = 5;
+
+/// Reads the documents of the portable PDB at `path` and maps them to URLs with its
+/// SourceLink information, see [`map_documents`]. PDBs without SourceLink yield no
+/// files.
+pub fn portable_pdb_sources(path: &Path) -> Result<Vec<SourceFile>> {
+    let data = fs::read(path)?;
+    let invalid = || Error::Pdb {
+        path: path.to_path_buf(),
+        reason: "invalid portable PDB metadata".to_string(),
+    };
+    let metadata = Metadata::parse(&data).ok_or_else(invalid)?;
+    let Some(source_link) = metadata.source_link().ok_or_else(invalid)? else {
+        return Ok(Vec::new());
+    };
+    let documents = metadata.documents().ok_or_else(invalid)?;
+    map_documents(&String::from_utf8_lossy(source_link), &documents)
+}
+
+/// Maps the paths of source documents to URLs with a SourceLink JSON document such as
+/// `{"documents": {"C:\\src\\*": "https://raw.githubusercontent.com/org/repo/sha/*"}}`.
+///
+/// Keys ending in `*` match the paths starting with the rest of the key, and the `*` of
+/// the URL is replaced with the remainder of the path, with forward slashes. Paths are
+/// compared case-insensitively and the longest matching key wins. Documents that no
+/// key matches are left out.
+pub fn map_documents(json: &str, documents: &[String]) -> Result<Vec<SourceFile>> {
+    #[derive(Deserialize)]
+    struct SourceLink {
+        documents: HashMap<String, String>,
+    }
+    let source_link: SourceLink = serde_json::from_str(json)?;
+    Ok(documents
+        .iter()
+        .filter_map(|document| {
+            Some(SourceFile {
+                original: document.clone(),
+                url: map_document(&source_link.documents, document)?,
+            })
+        })
+        .collect())
+}
+
+fn map_document(mappings: &HashMap<String, String>, path: &str) -> Option<String> {
+    mappings
+        .iter()
+        .filter_map(|(key, url)| match key.strip_suffix('*') {
+            Some(prefix) => {
+                let rest = path
+                    .get(..prefix.len())
+                    .filter(|head| head.eq_ignore_ascii_case(prefix))
+                    .map(|_| &path[prefix.len()..])?;
+                Some((prefix.len(), url.replacen('*', &rest.replace('\\', "/"), 1)))
+            }
+            None => path
+                .eq_ignore_ascii_case(key)
+                .then(|| (key.len(), url.clone())),
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, url)| url)
+}
+
+/// The heaps and debug tables of a portable PDB.
+struct Metadata<'a> {
+    blobs: &'a [u8],
+    guids: &'a [u8],
+    tables: &'a [u8],
+    /// `HeapSizes` of the tables stream, which says which heap indexes are 4 bytes.
+    heap_sizes: u8,
+    /// Row counts of all tables: the debug tables of the PDB and the type system tables
+    /// of the assembly it belongs to.
+    rows: [u32; 64],
+    /// Offset of the first row of each table in the tables stream.
+    offsets: [usize; 64],
+}
+
+impl<'a> Metadata<'a> {
+    /// Parses the metadata root, the `#Pdb` stream and the header of the tables
+    /// stream, or returns `None` if they are malformed.
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.get(..4)? != METADATA_SIGNATURE {
+            return None;
+        }
+        let version_len = read_u32(data, 12)? as usize;
+        let mut offset = 16 + version_len;
+        let streams = u16::from_le_bytes(data.get(offset + 2..offset + 4)?.try_into().ok()?);
+        offset += 4;
+        let (mut blobs, mut guids, mut tables, mut pdb) = (None, None, None, None);
+        for _ in 0..streams {
+            let start = read_u32(data, offset)? as usize;
+            let size = read_u32(data, offset + 4)? as usize;
+            let name_start = offset + 8;
+            let name_len = data.get(name_start..)?.iter().position(|&b| b == 0)?;
+            let name = &data[name_start..name_start + name_len];
+            // Names are null-terminated and padded to four bytes.
+            offset = name_start + ((name_len + 4) & !3);
+            let stream = data.get(start..start.checked_add(size)?)?;
+            match name {
+                b"#Blob" => blobs = Some(stream),
+                b"#GUID" => guids = Some(stream),
+                b"#~" | b"#-" => tables = Some(stream),
+                b"#Pdb" => pdb = Some(stream),
+                _ => {}
+            }
+        }
+        let (tables, pdb) = (tables?, pdb?);
+
+        let mut rows = [0; 64];
+        // The #Pdb stream starts with the PDB ID and entry point, then lists the row
+        // counts of the assembly's tables that the debug tables refer to.
+        let referenced = read_u64(pdb, 24)?;
+        let mut offset = 32;
+        for (table, count) in rows.iter_mut().enumerate() {
+            if referenced & (1 << table) != 0 {
+                *count = read_u32(pdb, offset)?;
+                offset += 4;
+            }
+        }
+        let heap_sizes = *tables.get(6)?;
+        let present = read_u64(tables, 8)?;
+        let mut offset = 24;
+        for (table, count) in rows.iter_mut().enumerate() {
+            if present & (1 << table) != 0 {
+                *count = read_u32(tables, offset)?;
+                offset += 4;
+            }
+        }
+        let mut metadata = Self {
+            blobs: blobs.unwrap_or_default(),
+            guids: guids.unwrap_or_default(),
+            tables,
+            heap_sizes,
+            rows,
+            offsets: [0; 64],
+        };
+        for table in 0..64 {
+            metadata.offsets[table] = offset;
+            if present & (1 << table) != 0 {
+                let size = metadata.row_size(table)?;
+                offset = offset.checked_add(size * metadata.rows[table] as usize)?;
+            }
+        }
+        Some(metadata)
+    }
+
+    /// Returns the SourceLink JSON, `None` if the metadata is malformed, or
+    /// `Some(None)` if the PDB has no SourceLink information.
+    fn source_link(&self) -> Option<Option<&'a [u8]>> {
+        let parent = self.coded_index_size(
+            HAS_CUSTOM_DEBUG_INFORMATION,
+            HAS_CUSTOM_DEBUG_INFORMATION_BITS,
+        );
+        let guid = self.heap_index_size(0x02);
+        for row in 0..self.rows[CUSTOM_DEBUG_INFORMATION] as usize {
+            let offset = self.row_offset(CUSTOM_DEBUG_INFORMATION, row)? + parent;
+            let kind = self.guid(self.index_at(offset, guid)?)?;
+            if kind == SOURCE_LINK_KIND {
+                let value = self.index_at(offset + guid, self.heap_index_size(0x04))?;
+                return Some(Some(self.blob(value)?));
+            }
+        }
+        Some(None)
+    }
+
+    /// Returns the paths of all source documents.
+    fn documents(&self) -> Option<Vec<String>> {
+        let blob = self.heap_index_size(0x04);
+        (0..self.rows[DOCUMENT] as usize)
+            .map(|row| {
+                let name = self.index_at(self.row_offset(DOCUMENT, row)?, blob)?;
+                self.document_name(self.blob(name)?)
+            })
+            .collect()
+    }
+
+    /// Decodes a document name blob: a separator character followed by the blob
+    /// indexes of the parts the path is joined from.
+    fn document_name(&self, blob: &[u8]) -> Option<String> {
+        let (&separator, mut rest) = blob.split_first()?;
+        let mut name = Vec::new();
+        let mut first = true;
+        while !rest.is_empty() {
+            let (part, len) = compressed_u32(rest)?;
+            rest = &rest[len..];
+            if !first && separator != 0 {
+                name.push(separator);
+            }
+            first = false;
+            name.extend_from_slice(self.blob(part)?);
+        }
+        Some(String::from_utf8_lossy(&name).into_owned())
+    }
+
+    /// Returns the size of a row of `table`, or `None` for tables that do not belong in
+    /// a portable PDB.
+    fn row_size(&self, table: usize) -> Option<usize> {
+        let string = self.heap_index_size(0x01);
+        let guid = self.heap_index_size(0x02);
+        let blob = self.heap_index_size(0x04);
+        let index = |table| self.table_index_size(table);
+        Some(match table {
+            // Document: name, hash algorithm, hash and language.
+            0x30 => blob + guid + blob + guid,
+            // MethodDebugInformation: document and sequence points.
+            0x31 => index(DOCUMENT) + blob,
+            // LocalScope: method, import scope, variable and constant lists, offset
+            // and length.
+            0x32 => {
+                index(METHOD_DEF)
+                    + index(IMPORT_SCOPE)
+                    + index(LOCAL_VARIABLE)
+                    + index(LOCAL_CONSTANT)
+                    + 8
+            }
+            // LocalVariable: attributes, index and name.
+            0x33 => 4 + string,
+            // LocalConstant: name and signature.
+            0x34 => string + blob,
+            // ImportScope: parent and imports.
+            0x35 => index(IMPORT_SCOPE) + blob,
+            // StateMachineMethod: MoveNext and kickoff methods.
+            0x36 => 2 * index(METHOD_DEF),
+            // CustomDebugInformation: parent, kind and value.
+            0x37 => {
+                self.coded_index_size(
+                    HAS_CUSTOM_DEBUG_INFORMATION,
+                    HAS_CUSTOM_DEBUG_INFORMATION_BITS,
+                ) + guid
+                    + blob
+            }
+            _ => return None,
+        })
+    }
+
+    fn row_offset(&self, table: usize, row: usize) -> Option<usize> {
+        Some(self.offsets[table] + row * self.row_size(table)?)
+    }
+
+    /// Returns the size of indexes into the heap whose `HeapSizes` bit is `flag`.
+    fn heap_index_size(&self, flag: u8) -> usize {
+        if self.heap_sizes & flag != 0 { 4 } else { 2 }
+    }
+
+    fn table_index_size(&self, table: usize) -> usize {
+        if self.rows[table] < 1 << 16 { 2 } else { 4 }
+    }
+
+    fn coded_index_size(&self, tables: &[usize], bits: u32) -> usize {
+        let max = tables
+            .iter()
+            .map(|&table| self.rows[table])
+            .max()
+            .unwrap_or(0);
+        if max < 1 << (16 - bits) { 2 } else { 4 }
+    }
+
+    fn index_at(&self, offset: usize, size: usize) -> Option<u32> {
+        let bytes = self.tables.get(offset..offset + size)?;
+        Some(match size {
+            2 => u16::from_le_bytes([bytes[0], bytes[1]]).into(),
+            _ => u32::from_le_bytes(bytes.try_into().ok()?),
+        })
+    }
+
+    /// Returns the blob at `index` of the blob heap, without its length prefix.
+    fn blob(&self, index: u32) -> Option<&'a [u8]> {
+        let data = self.blobs.get(index as usize..)?;
+        let (len, prefix) = compressed_u32(data)?;
+        data.get(prefix..prefix + len as usize)
+    }
+
+    /// Returns the GUID at the 1-based `index` of the GUID heap.
+    fn guid(&self, index: u32) -> Option<[u8; 16]> {
+        let start = (index as usize).checked_sub(1)? * 16;
+        self.guids.get(start..start + 16)?.try_into().ok()
+    }
+}
+
+/// Decodes a compressed unsigned integer of ECMA-335 and returns it with its length.
+fn compressed_u32(data: &[u8]) -> Option<(u32, usize)> {
+    let first = *data.first()?;
+    if first & 0x80 == 0 {
+        Some((first.into(), 1))
+    } else if first & 0xC0 == 0x80 {
+        Some(((u32::from(first & 0x3F) << 8) | u32::from(*data.get(1)?), 2))
+    } else {
+        let bytes = data.get(..4)?;
+        Some((
+            u32::from_be_bytes([first & 0x1F, bytes[1], bytes[2], bytes[3]]),
+            4,
+        ))
+    }
+}
@@ -0,0 +1,119 @@
+//! A local cache of the source files PDBs point to, so debuggers can show the code
+//! without network access.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    error::{Error, Result},
+    http::HttpClient,
+    package::sanitize,
+};
+
+/// A source file referenced by a PDB.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceFile {
+    /// The path of the file on the build machine, as recorded in the PDB.
+    pub original: String,
+    /// The URL to download the file from.
+    pub url: String,
+}
+
+/// Outcome counts of fetching source files.
+#[derive(Debug, Default)]
+pub struct SourceSummary {
+    pub fetched: usize,
+    pub existing: usize,
+    pub failed: usize,
+}
+
+/// Returns where the file downloaded from `url` is kept in the source cache at `cache`:
+/// below a directory named after the host, at the path of the URL, or `None` for URLs
+/// that do not map to a safe path.
+pub fn cache_path(cache: &Path, url: &str) -> Option<PathBuf> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    let relative = sanitize(url.path())?;
+    Some(cache.join(host).join(relative))
+}
+
+/// Downloads the source files into the cache at `cache`, running up to `jobs` requests
+/// at once. Files already in the cache are not downloaded again.
+pub async fn fetch_sources(
+    client: &HttpClient,
+    files: Vec<SourceFile>,
+    cache: &Path,
+    jobs: usize,
+) -> SourceSummary {
+    let mut summary = SourceSummary::default();
+    let mut seen = HashSet::new();
+    let permits = Arc::new(Semaphore::new(jobs.max(1)));
+    let mut tasks = JoinSet::new();
+    for file in files {
+        if !seen.insert(file.url.clone()) {
+            continue;
+        }
+        let Some(dest) = cache_path(cache, &file.url) else {
+            tracing::warn!(
+                "Skipping source {}: unsupported URL {}",
+                file.original,
+                file.url
+            );
+            summary.failed += 1;
+            continue;
+        };
+        if dest.is_file() {
+            summary.existing += 1;
+            continue;
+        }
+        let client = client.clone();
+        let permits = permits.clone();
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let result = fetch_source(&client, &file.url, &dest).await;
+            if let Err(e) = &result {
+                tracing::warn!("Failed to fetch source {}: {}", file.original, e);
+            }
+            result.is_ok()
+        });
+    }
+    while let Some(result) = tasks.join_next().await {
+        match result {
+            Ok(true) => summary.fetched += 1,
+            Ok(false) => summary.failed += 1,
+            Err(e) => {
+                tracing::error!("Source download task failed: {}", e);
+                summary.failed += 1;
+            }
+        }
+    }
+    summary
+}
+
+/// Downloads a single source file to `dest`, through a `.part` file so interrupted
+/// downloads do not leave truncated sources in the cache.
+async fn fetch_source(client: &HttpClient, url: &str, dest: &Path) -> Result<()> {
+    let response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Err(Error::HttpStatus {
+            url: url.to_string(),
+            status: response.status(),
+        });
+    }
+    let body = response.bytes().await?;
+    client.throttle(body.len() as u64).await;
+    if let Some(parent) = dest.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut part = dest.as_os_str().to_owned();
+    part.push(".part");
+    tokio::fs::write(&part, &body).await?;
+    tokio::fs::rename(&part, dest).await?;
+    tracing::debug!("Fetched {} to {}", url, dest.display());
+    Ok(())
+}
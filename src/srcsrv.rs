@@ -0,0 +1,175 @@
+//! Source server (srcsrv) streams of PDBs, which map the build paths of the source
+//! files to the commands or URLs that retrieve them.
+
+use std::{collections::HashMap, fs::File, path::Path};
+
+use crate::{
+    error::{Error, Result},
+    sources::SourceFile,
+};
+
+/// Name of the PDB stream holding the source server data.
+const STREAM_NAME: &[u8] = b"srcsrv";
+/// Deepest nesting of variables expanded, which stops self-referencing variables.
+const MAX_EXPANSION_DEPTH: usize = 16;
+
+/// Reads the srcsrv stream of the PDB at `path`, or returns `None` if the PDB is not
+/// source indexed or not an MSF PDB, like portable PDBs.
+pub fn read_stream(path: &Path) -> Result<Option<String>> {
+    let invalid = |e: pdb::Error| Error::Pdb {
+        path: path.to_path_buf(),
+        reason: e.to_string(),
+    };
+    let mut pdb = match pdb::PDB::open(File::open(path)?) {
+        Ok(pdb) => pdb,
+        Err(pdb::Error::UnrecognizedFileFormat) => return Ok(None),
+        Err(e) => return Err(invalid(e)),
+    };
+    match pdb.named_stream(STREAM_NAME) {
+        Ok(stream) => Ok(Some(
+            String::from_utf8_lossy(stream.as_slice()).into_owned(),
+        )),
+        Err(pdb::Error::StreamNameNotFound) => Ok(None),
+        Err(e) => Err(invalid(e)),
+    }
+}
+
+/// Parses a srcsrv stream and returns the source files it maps to HTTP URLs.
+///
+/// Files retrieved with a command (`SRCSRVCMD`), such as `tf.exe` or `git`, are
+/// skipped, as are files whose target (`SRCSRVTRG`) does not expand to an `http` or
+/// `https` URL.
+pub fn http_sources(stream: &str) -> Vec<SourceFile> {
+    let mut section = "";
+    let mut variables = HashMap::new();
+    let mut files = Vec::new();
+    for line in stream.lines().map(str::trim_end) {
+        if let Some(header) = line.strip_prefix("SRCSRV:") {
+            section = header.split_whitespace().next().unwrap_or_default();
+            continue;
+        }
+        match section {
+            "ini" | "variables" => {
+                if let Some((name, value)) = line.split_once('=') {
+                    variables.insert(name.trim().to_ascii_uppercase(), value.to_string());
+                }
+            }
+            "source" if !line.is_empty() => {
+                let fields: Vec<&str> = line.split('*').collect();
+                let expansion = Expansion {
+                    variables: &variables,
+                    fields: &fields,
+                };
+                if !expansion.variable("srcsrvcmd", 0).trim().is_empty() {
+                    continue;
+                }
+                let url = expansion.variable("srcsrvtrg", 0);
+                if is_http(&url) {
+                    files.push(SourceFile {
+                        original: fields[0].to_string(),
+                        url,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    files
+}
+
+/// The variables of a srcsrv stream, with `%var1%`, `%var2%`, ... set to the fields of
+/// one source file line.
+struct Expansion<'a> {
+    variables: &'a HashMap<String, String>,
+    fields: &'a [&'a str],
+}
+
+impl Expansion<'_> {
+    /// Replaces the `%name%` variables and `%fnvar%(...)`, `%fnbksl%(...)` and
+    /// `%fnfile%(...)` functions in `text`.
+    fn expand(&self, text: &str, depth: usize) -> String {
+        if depth > MAX_EXPANSION_DEPTH {
+            return String::new();
+        }
+        let mut expanded = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('%') {
+            expanded.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let Some(end) = after.find('%') else {
+                rest = &rest[start..];
+                break;
+            };
+            let name = after[..end].to_ascii_lowercase();
+            rest = &after[end + 1..];
+            match closing_paren(rest) {
+                Some(close) if name.starts_with("fn") => {
+                    let argument = self.expand(&rest[1..close], depth + 1);
+                    rest = &rest[close + 1..];
+                    expanded.push_str(&self.function(&name, &argument, depth));
+                }
+                _ => expanded.push_str(&self.variable(&name, depth)),
+            }
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+
+    /// Returns the expanded value of the variable `name`, given in lowercase, or an
+    /// empty string for unknown variables.
+    fn variable(&self, name: &str, depth: usize) -> String {
+        if let Some(index) = name
+            .strip_prefix("var")
+            .and_then(|n| n.parse::<usize>().ok())
+        {
+            return index
+                .checked_sub(1)
+                .and_then(|index| self.fields.get(index))
+                .map(|field| field.to_string())
+                .unwrap_or_default();
+        }
+        self.variables
+            .get(&name.to_ascii_uppercase())
+            .map(|value| self.expand(value, depth + 1))
+            .unwrap_or_default()
+    }
+
+    fn function(&self, name: &str, argument: &str, depth: usize) -> String {
+        match name {
+            "fnvar" => self.variable(&argument.to_ascii_lowercase(), depth + 1),
+            "fnbksl" => argument.replace('/', "\\"),
+            "fnfile" => argument
+                .rsplit(['\\', '/'])
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Returns the index of the parenthesis closing the one `text` starts with.
+fn closing_paren(text: &str) -> Option<usize> {
+    if !text.starts_with('(') {
+        return None;
+    }
+    let mut depth = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_http(url: &str) -> bool {
+    let url = url.to_ascii_lowercase();
+    url.starts_with("http://") || url.starts_with("https://")
+}
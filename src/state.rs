@@ -0,0 +1,484 @@
+//! The scan and download state database kept in the store root.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use rusqlite::{Connection, OptionalExtension, params};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    download::{DownloadRecord, DownloadStatus, DownloadSummary},
+    error::Result,
+    missing::now,
+    windows::{PdbMeta, SymbolKind},
+};
+
+/// File in the store root holding the state database.
+const STATE_FILE: &str = "symbolfetcher.db";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS files (
+    path TEXT PRIMARY KEY,
+    size INTEGER NOT NULL,
+    mtime INTEGER NOT NULL,
+    sha256 TEXT NOT NULL,
+    pdb_name TEXT,
+    guid TEXT,
+    age INTEGER,
+    scanned_at INTEGER NOT NULL,
+    md5 TEXT
+);
+CREATE TABLE IF NOT EXISTS downloads (
+    pdb_name TEXT NOT NULL,
+    guid TEXT NOT NULL,
+    age INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    url TEXT,
+    size INTEGER,
+    updated_at INTEGER NOT NULL,
+    sha256 TEXT,
+    md5 TEXT,
+    PRIMARY KEY (pdb_name, guid, age)
+);
+CREATE TABLE IF NOT EXISTS queue (
+    pdb_name TEXT NOT NULL,
+    key TEXT NOT NULL,
+    path TEXT NOT NULL,
+    PRIMARY KEY (pdb_name, key)
+);
+CREATE TABLE IF NOT EXISTS pushed (
+    target TEXT NOT NULL,
+    path TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    mtime INTEGER NOT NULL,
+    pushed_at INTEGER NOT NULL,
+    PRIMARY KEY (target, path)
+);
+CREATE TABLE IF NOT EXISTS runs (
+    finished_at INTEGER NOT NULL,
+    downloaded INTEGER NOT NULL,
+    existing INTEGER NOT NULL,
+    missing INTEGER NOT NULL,
+    failed INTEGER NOT NULL,
+    bytes INTEGER NOT NULL
+);
+";
+
+/// Columns added since the first version of the schema, as table, column and type,
+/// which databases created before are missing.
+const ADDED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("files", "md5", "TEXT"),
+    ("downloads", "sha256", "TEXT"),
+    ("downloads", "md5", "TEXT"),
+];
+
+/// What the state database knows about a scanned binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileState {
+    /// File size in bytes.
+    pub size: u64,
+    /// Modification time in seconds since the Unix epoch.
+    pub mtime: u64,
+    /// SHA-256 of the file contents as lowercase hex.
+    pub sha256: String,
+}
+
+/// The outcome counts of one recorded download run.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct RunStats {
+    /// When the run finished, in seconds since the Unix epoch.
+    pub finished_at: u64,
+    pub downloaded: usize,
+    pub existing: usize,
+    pub missing: usize,
+    pub failed: usize,
+    /// Bytes downloaded in the run.
+    pub bytes: u64,
+}
+
+/// A SQLite database recording which binaries were scanned and which PDBs were
+/// fetched, so that later runs can skip work and the store can be queried.
+pub struct StateDb {
+    conn: Connection,
+}
+
+impl StateDb {
+    /// Opens (or creates) the state database of the store at `root`.
+    pub fn open(root: &Path) -> Result<Self> {
+        fs::create_dir_all(root)?;
+        let conn = Connection::open(root.join(STATE_FILE))?;
+        conn.execute_batch(SCHEMA)?;
+        for (table, column, kind) in ADDED_COLUMNS {
+            let exists = conn
+                .prepare(&format!(
+                    "SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1",
+                    table
+                ))?
+                .exists(params![column])?;
+            if !exists {
+                conn.execute_batch(&format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    table, column, kind
+                ))?;
+            }
+        }
+        Ok(Self { conn })
+    }
+
+    /// Returns the recorded state of the binary at `path`, if it was scanned before.
+    pub fn file_state(&self, path: &Path) -> Result<Option<FileState>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT size, mtime, sha256 FROM files WHERE path = ?1",
+                params![path.to_string_lossy()],
+                |row| {
+                    Ok(FileState {
+                        size: row.get(0)?,
+                        mtime: row.get(1)?,
+                        sha256: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    /// Returns the files that are new or changed since they were last recorded.
+    ///
+    /// A file is unchanged if its size and modification time match, or if only the
+    /// modification time differs but the contents hash the same. Files whose PDB failed
+    /// to download in the last run are always returned so the download is retried.
+    pub fn changed_files(&self, files: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+        let total = files.len();
+        let mut statement = self.conn.prepare(
+            "SELECT files.size, files.mtime, files.sha256, downloads.status
+             FROM files LEFT JOIN downloads USING (pdb_name, guid, age)
+             WHERE files.path = ?1",
+        )?;
+        let mut changed = Vec::new();
+        for file in files {
+            let known = statement
+                .query_row(params![file.to_string_lossy()], |row| {
+                    Ok((
+                        FileState {
+                            size: row.get(0)?,
+                            mtime: row.get(1)?,
+                            sha256: row.get(2)?,
+                        },
+                        row.get::<_, Option<String>>(3)?,
+                    ))
+                })
+                .optional()?;
+            let unchanged = match known {
+                Some((_, Some(status))) if status == status_name(DownloadStatus::Failed) => false,
+                Some((known, _)) => is_unchanged(&file, &known),
+                None => false,
+            };
+            if unchanged {
+                tracing::debug!("Unchanged since last scan: {}", file.display());
+            } else {
+                changed.push(file);
+            }
+        }
+        tracing::info!(
+            "{} of {} files are new or changed since the last scan",
+            changed.len(),
+            total
+        );
+        Ok(changed)
+    }
+
+    /// Records the scanned files together with the PDB keys read from them.
+    ///
+    /// Files without a PDB key are recorded too, so incremental scans can skip them.
+    pub fn record_scan<'a>(
+        &mut self,
+        files: &[PathBuf],
+        pdbs: impl IntoIterator<Item = &'a PdbMeta>,
+    ) -> Result<()> {
+        // ARM64X binaries reference two PDBs; the native one is listed first. Image and
+        // DBG keys are derived from the file headers and not recorded.
+        let mut keys: HashMap<&Path, &PdbMeta> = HashMap::new();
+        for pdb in pdbs.into_iter().filter(|pdb| {
+            matches!(
+                pdb.kind,
+                SymbolKind::Pdb | SymbolKind::PortablePdb | SymbolKind::Dsym
+            )
+        }) {
+            keys.entry(pdb.path.as_path()).or_insert(pdb);
+        }
+        let now = now();
+        let tx = self.conn.transaction()?;
+        for file in files {
+            let state = match FileState::of(file) {
+                Ok(state) => state,
+                Err(e) => {
+                    tracing::warn!("Not recording {}: {}", file.display(), e);
+                    continue;
+                }
+            };
+            let pdb = keys.get(file.as_path());
+            tx.execute(
+                "INSERT OR REPLACE INTO files
+                 (path, size, mtime, sha256, pdb_name, guid, age, scanned_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    file.to_string_lossy(),
+                    state.size,
+                    state.mtime,
+                    state.sha256,
+                    pdb.map(|pdb| &pdb.name),
+                    pdb.map(|pdb| &pdb.guid),
+                    pdb.map(|pdb| pdb.age),
+                    now
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Records binaries scanned inside containers such as ISOs and WIMs together with
+    /// the PDB keys read from them.
+    ///
+    /// The binaries were extracted to a scratch directory that is gone by now, so they
+    /// are recorded under their path inside the container, e.g.
+    /// `install.wim.d/Windows/System32/ntdll.dll`, without a size, modification time
+    /// or hash. Incremental scans do not apply to them.
+    pub fn record_container_scan<'a>(
+        &mut self,
+        pdbs: impl IntoIterator<Item = &'a PdbMeta>,
+    ) -> Result<()> {
+        // As in `record_scan`, the native PDB of ARM64X binaries comes first and is kept.
+        let mut keys: HashMap<&Path, &PdbMeta> = HashMap::new();
+        for pdb in pdbs.into_iter().filter(|pdb| {
+            matches!(
+                pdb.kind,
+                SymbolKind::Pdb | SymbolKind::PortablePdb | SymbolKind::Dsym
+            )
+        }) {
+            keys.entry(pdb.path.as_path()).or_insert(pdb);
+        }
+        let now = now();
+        let tx = self.conn.transaction()?;
+        for pdb in keys.into_values() {
+            tx.execute(
+                "INSERT OR REPLACE INTO files
+                 (path, size, mtime, sha256, pdb_name, guid, age, scanned_at)
+                 VALUES (?1, 0, 0, '', ?2, ?3, ?4, ?5)",
+                params![pdb.path.to_string_lossy(), pdb.name, pdb.guid, pdb.age, now],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Records the outcome of a download run, per PDB and as a whole.
+    ///
+    /// Hashes of the PDBs are kept from earlier runs if the records have none, and the
+    /// MD5 hashes of the binaries are added to their scan records.
+    pub fn record_downloads(&mut self, records: &[DownloadRecord]) -> Result<()> {
+        let now = now();
+        let summary = DownloadSummary::from_records(records);
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO runs (finished_at, downloaded, existing, missing, failed, bytes)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                now,
+                summary.downloaded,
+                summary.existing,
+                summary.missing,
+                summary.failed,
+                summary.bytes
+            ],
+        )?;
+        for record in records {
+            let hashes = record.pdb_hashes.as_ref();
+            tx.execute(
+                "INSERT INTO downloads
+                 (pdb_name, guid, age, status, url, size, updated_at, sha256, md5)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                 ON CONFLICT (pdb_name, guid, age) DO UPDATE SET
+                 status = excluded.status, url = excluded.url, size = excluded.size,
+                 updated_at = excluded.updated_at,
+                 sha256 = COALESCE(excluded.sha256, sha256),
+                 md5 = COALESCE(excluded.md5, md5)",
+                params![
+                    record.pdb.name,
+                    record.pdb.guid,
+                    record.pdb.age,
+                    status_name(record.status),
+                    record.url,
+                    record.size,
+                    now,
+                    hashes.map(|hashes| &hashes.sha256),
+                    hashes.and_then(|hashes| hashes.md5.as_ref())
+                ],
+            )?;
+            if let Some(md5) = record
+                .pdb
+                .binary_hashes
+                .as_ref()
+                .and_then(|hashes| hashes.md5.as_ref())
+            {
+                tx.execute(
+                    "UPDATE files SET md5 = ?1 WHERE path = ?2",
+                    params![md5, record.pdb.path.to_string_lossy()],
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Returns the most recent download runs, newest first.
+    pub fn runs(&self, limit: usize) -> Result<Vec<RunStats>> {
+        let mut statement = self.conn.prepare(
+            "SELECT finished_at, downloaded, existing, missing, failed, bytes FROM runs
+             ORDER BY finished_at DESC LIMIT ?1",
+        )?;
+        let runs = statement
+            .query_map(params![limit], |row| {
+                Ok(RunStats {
+                    finished_at: row.get(0)?,
+                    downloaded: row.get(1)?,
+                    existing: row.get(2)?,
+                    missing: row.get(3)?,
+                    failed: row.get(4)?,
+                    bytes: row.get(5)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(runs)
+    }
+
+    /// Replaces the queue of PDBs left over from an interrupted run with `pdbs`.
+    pub fn save_queue(&mut self, pdbs: &[PdbMeta]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM queue", [])?;
+        for pdb in pdbs {
+            tx.execute(
+                "INSERT OR IGNORE INTO queue (pdb_name, key, path) VALUES (?1, ?2, ?3)",
+                params![pdb.name, pdb.key(), pdb.path.to_string_lossy()],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Returns the PDBs left over from an interrupted run, see [`StateDb::save_queue`].
+    ///
+    /// Only the keys are kept, so the architecture and debug entries of the binaries
+    /// are not known.
+    pub fn queue(&self) -> Result<Vec<PdbMeta>> {
+        let mut statement = self.conn.prepare("SELECT pdb_name, key, path FROM queue")?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|(name, key, path)| PdbMeta::from_key(&name, &key, Path::new(&path)))
+            .collect())
+    }
+
+    /// Empties the queue once its PDBs have been processed.
+    pub fn clear_queue(&mut self) -> Result<()> {
+        self.conn.execute("DELETE FROM queue", [])?;
+        Ok(())
+    }
+
+    /// Returns the files pushed to `target` before, by their path below it, with the
+    /// size and modification time they had then.
+    pub fn pushed_files(&self, target: &str) -> Result<HashMap<String, (u64, u64)>> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT path, size, mtime FROM pushed WHERE target = ?1")?;
+        let files = statement
+            .query_map(params![target], |row| {
+                Ok((row.get(0)?, (row.get(1)?, row.get(2)?)))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(files)
+    }
+
+    /// Records that the file at `path` below `target` was pushed with the given size
+    /// and modification time.
+    pub fn record_push(&mut self, target: &str, path: &str, size: u64, mtime: u64) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO pushed (target, path, size, mtime, pushed_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![target, path, size, mtime, now()],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the PDB keys (name, GUID and age) whose last download failed.
+    pub fn failed_downloads(&self) -> Result<Vec<(String, String, u32)>> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT pdb_name, guid, age FROM downloads WHERE status = ?1")?;
+        let keys = statement
+            .query_map(params![status_name(DownloadStatus::Failed)], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(keys)
+    }
+}
+
+impl FileState {
+    /// Reads the size, modification time and hash of the file at `path`.
+    pub fn of(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(path)?, &mut hasher)?;
+        Ok(Self {
+            size: metadata.len(),
+            mtime,
+            sha256: hex::encode(hasher.finalize()),
+        })
+    }
+}
+
+/// Compares a file against its recorded state, hashing it only if the size matches
+/// but the modification time does not.
+fn is_unchanged(path: &Path, known: &FileState) -> bool {
+    let Ok(metadata) = fs::metadata(path) else {
+        return false;
+    };
+    if metadata.len() != known.size {
+        return false;
+    }
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_secs());
+    mtime == Some(known.mtime)
+        || FileState::of(path).is_ok_and(|state| state.sha256 == known.sha256)
+}
+
+fn status_name(status: DownloadStatus) -> &'static str {
+    match status {
+        DownloadStatus::Downloaded => "downloaded",
+        DownloadStatus::Existing => "existing",
+        DownloadStatus::Missing => "missing",
+        DownloadStatus::Failed => "failed",
+    }
+}
@@ -0,0 +1,103 @@
+//! Statistics about the contents of a local symbol store.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use crate::{
+    error::Result,
+    store::{LocalStore, StoredFile},
+};
+
+/// Length of a PDB GUID in a symbol server key; the age follows it.
+const GUID_LEN: usize = 32;
+
+/// What a store holds, see [`store_stats`].
+#[derive(Debug, Default, Serialize)]
+pub struct StoreStats {
+    /// Number of stored files.
+    pub files: usize,
+    /// Total size of the stored files in bytes.
+    pub total_size: u64,
+    /// Files and bytes per name, largest first.
+    pub modules: Vec<ModuleStats>,
+    /// GUIDs stored under more than one name or age.
+    pub duplicate_guids: Vec<DuplicateGuid>,
+    /// The largest stored files, largest first.
+    pub largest: Vec<FileStats>,
+}
+
+/// The stored files of one name, e.g. all versions of `ntdll.pdb`.
+#[derive(Debug, Serialize)]
+pub struct ModuleStats {
+    pub name: String,
+    pub files: usize,
+    pub size: u64,
+}
+
+/// A PDB GUID found in several folders of the store.
+#[derive(Debug, Serialize)]
+pub struct DuplicateGuid {
+    pub guid: String,
+    /// The `<name>/<key>` folders holding the GUID.
+    pub locations: Vec<String>,
+}
+
+/// A single stored file.
+#[derive(Debug, Serialize)]
+pub struct FileStats {
+    pub name: String,
+    pub key: String,
+    pub size: u64,
+}
+
+/// Collects statistics about the files in the store, listing the `top` largest files
+/// and modules.
+pub fn store_stats(store: &LocalStore, top: usize) -> Result<StoreStats> {
+    Ok(stats_of(store.stored_files()?, top))
+}
+
+fn stats_of(mut files: Vec<StoredFile>, top: usize) -> StoreStats {
+    let mut modules: HashMap<&str, ModuleStats> = HashMap::new();
+    let mut guids: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for file in &files {
+        let module = modules.entry(&file.name).or_insert_with(|| ModuleStats {
+            name: file.name.clone(),
+            files: 0,
+            size: 0,
+        });
+        module.files += 1;
+        module.size += file.size;
+        // Only PDB keys start with a GUID; image keys are much shorter.
+        if file.key.len() > GUID_LEN && file.key.is_char_boundary(GUID_LEN) {
+            guids
+                .entry(file.key[..GUID_LEN].to_ascii_uppercase())
+                .or_default()
+                .push(format!("{}/{}", file.name, file.key));
+        }
+    }
+    let mut modules: Vec<ModuleStats> = modules.into_values().collect();
+    modules.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    modules.truncate(top);
+
+    files.sort_by(|a, b| b.size.cmp(&a.size));
+    StoreStats {
+        files: files.len(),
+        total_size: files.iter().map(|file| file.size).sum(),
+        modules,
+        duplicate_guids: guids
+            .into_iter()
+            .filter(|(_, locations)| locations.len() > 1)
+            .map(|(guid, locations)| DuplicateGuid { guid, locations })
+            .collect(),
+        largest: files
+            .into_iter()
+            .take(top)
+            .map(|file| FileStats {
+                name: file.name,
+                key: file.key,
+                size: file.size,
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,634 @@
+//! Symbol stores and the local symstore-compatible store.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    compress::{StoreCompression, compress_file, find_compressed},
+    error::{Error, Result},
+    lock::FileLock,
+    msf,
+    windows::{PdbMeta, SymbolKind},
+};
+
+const ADMIN_DIR: &str = "000Admin";
+const PINGME_FILE: &str = "pingme.txt";
+pub(crate) const INDEX2_FILE: &str = "index2.txt";
+/// Directory in `000Admin` holding the files locked while a PDB is downloaded, see
+/// [`SymbolStore::lock`].
+const LOCKS_DIR: &str = "locks";
+/// Extension of the lock files in [`LOCKS_DIR`].
+const LOCK_EXTENSION: &str = "lock";
+/// File in `000Admin` locked while a transaction is written.
+const TRANSACTION_LOCK_FILE: &str = "symbolfetcher.lock";
+/// Extension of signature files, as `minisign` names them.
+pub const SIGNATURE_EXTENSION: &str = "minisig";
+
+/// Where a [`DownloadManager`](crate::DownloadManager) keeps PDBs.
+///
+/// Downloads are written to a local staging file returned by [`SymbolStore::prepare`],
+/// which survives interrupted runs so they can be resumed, checked, and then handed to
+/// [`SymbolStore::persist`].
+#[async_trait::async_trait]
+pub trait SymbolStore: Send + Sync {
+    /// Returns where the PDB lives (or would live) in the store.
+    fn location(&self, pdb: &PdbMeta) -> PathBuf;
+
+    /// Returns the size of the PDB if the store holds a valid copy of it.
+    async fn stored_size(&self, pdb: &PdbMeta) -> Result<Option<u64>>;
+
+    /// Returns the size of the PDB if the store holds a copy of it, without checking
+    /// the copy, e.g. for estimates. Defaults to [`SymbolStore::stored_size`], for
+    /// stores where that check is cheap and leaves the store alone.
+    async fn existing_size(&self, pdb: &PdbMeta) -> Result<Option<u64>> {
+        self.stored_size(pdb).await
+    }
+
+    /// Returns the local staging file to download the PDB to.
+    async fn prepare(&self, pdb: &PdbMeta) -> Result<PathBuf>;
+
+    /// Moves the completed staging file of the PDB into the store and returns its
+    /// location.
+    async fn persist(&self, pdb: &PdbMeta) -> Result<PathBuf>;
+
+    /// Locks the PDB against other runs sharing the store until the returned lock is
+    /// dropped, waiting while another run holds it. Stores that cannot be shared this
+    /// way return `None`.
+    async fn lock(&self, _pdb: &PdbMeta) -> Result<Option<FileLock>> {
+        Ok(None)
+    }
+
+    /// Records the files persisted since the last commit, for stores that keep a
+    /// history.
+    fn commit_transaction(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// A file found in a [`LocalStore`] by [`LocalStore::stored_files`].
+#[derive(Debug, Clone)]
+pub struct StoredFile {
+    /// Full path of the file.
+    pub path: PathBuf,
+    /// The name the file is stored under, e.g. `ntdll.pdb`.
+    pub name: String,
+    /// The symbol server key, e.g. `1EB9FACB04C73C5DE79D35E14B2FDC231`.
+    pub key: String,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// When the file was last read, or last modified if the filesystem does not
+    /// record access times.
+    pub accessed: SystemTime,
+}
+
+/// The directory layouts `symstore.exe` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StoreLayout {
+    /// Files live at `<root>/<name>/<key>/<name>`.
+    #[default]
+    Flat,
+    /// Files live at `<root>/<na>/<name>/<key>/<name>`, marked by an `index2.txt`.
+    Tiered,
+}
+
+impl std::str::FromStr for StoreLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "flat" => Ok(Self::Flat),
+            "tiered" | "two-tier" => Ok(Self::Tiered),
+            _ => Err(format!(
+                "unknown store layout {:?}, expected flat or tiered",
+                s
+            )),
+        }
+    }
+}
+
+/// A local symbol store compatible with the layout written by `symstore.exe`.
+///
+/// Files live at `<root>/<name>/<GUID><age>/<name>`, or at
+/// `<root>/<na>/<name>/<GUID><age>/<name>` when the store contains an `index2.txt`.
+/// Every run that adds files is recorded as a transaction in `000Admin`.
+#[derive(Debug, Clone)]
+pub struct LocalStore {
+    root: PathBuf,
+    tiered: bool,
+    compression: StoreCompression,
+    pending: Arc<Mutex<Vec<String>>>,
+}
+
+impl LocalStore {
+    /// Opens the store at `root`, detecting a two-tier layout from `index2.txt`.
+    pub fn new(root: PathBuf) -> Self {
+        let tiered = root.join(INDEX2_FILE).exists();
+        tracing::info!(
+            "Using symbol store at: {} ({} layout)",
+            root.display(),
+            if tiered { "two-tier" } else { "flat" }
+        );
+        Self {
+            root,
+            tiered,
+            compression: StoreCompression::None,
+            pending: Arc::default(),
+        }
+    }
+
+    /// Opens the store at `root` like [`LocalStore::new`], setting it up with `layout`
+    /// if it does not hold any files yet.
+    ///
+    /// Existing stores keep their layout, since moving all files is up to `symstore`.
+    pub fn with_layout(root: PathBuf, layout: StoreLayout) -> Result<Self> {
+        let store = Self::new(root);
+        let tiered = layout == StoreLayout::Tiered;
+        if store.tiered == tiered {
+            return Ok(store);
+        }
+        if store.tiered || !store.is_empty() {
+            tracing::warn!(
+                "Keeping the {} layout of the existing store at {}",
+                if store.tiered { "two-tier" } else { "flat" },
+                store.root.display()
+            );
+            return Ok(store);
+        }
+        fs::create_dir_all(&store.root).map_err(Error::store(&store.root))?;
+        let index2 = store.root.join(INDEX2_FILE);
+        fs::write(&index2, "").map_err(Error::store(&index2))?;
+        tracing::info!("Created two-tier symbol store at: {}", store.root.display());
+        Ok(Self { tiered, ..store })
+    }
+
+    /// Compresses files added to the store from now on. Compressed files are expanded
+    /// again when they are read, served or verified.
+    pub fn with_compression(mut self, compression: StoreCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Returns whether the store holds no files yet: it has no folders, only possibly
+    /// the state files kept next to the store.
+    fn is_empty(&self) -> bool {
+        fs::read_dir(&self.root).map_or(true, |entries| {
+            !entries
+                .flatten()
+                .any(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        })
+    }
+
+    /// Returns the root directory of the store.
+    pub fn get_root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Lists the symstore admin files in `000Admin`: the transactions, `history.txt`,
+    /// `server.txt` and `lastid.txt`, without signatures and lock files.
+    pub fn admin_files(&self) -> Result<Vec<PathBuf>> {
+        let admin = self.root.join(ADMIN_DIR);
+        if !admin.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut files: Vec<PathBuf> = fs::read_dir(&admin)
+            .map_err(Error::store(&admin))?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.is_file()
+                    && path
+                        .file_name()
+                        .is_some_and(|name| name != TRANSACTION_LOCK_FILE)
+                    && path
+                        .extension()
+                        .is_none_or(|ext| ext != SIGNATURE_EXTENSION)
+            })
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    /// Returns whether the store uses the two-tier layout.
+    pub fn is_tiered(&self) -> bool {
+        self.tiered
+    }
+
+    /// Lists all files in the store, skipping `000Admin` and partial downloads.
+    pub fn stored_files(&self) -> Result<Vec<StoredFile>> {
+        let name_parents = if self.tiered {
+            subdirectories(&self.root)?
+        } else {
+            vec![self.root.clone()]
+        };
+        let mut files = Vec::new();
+        for parent in name_parents {
+            for name_dir in subdirectories(&parent)? {
+                for key_dir in subdirectories(&name_dir)? {
+                    let entries = fs::read_dir(&key_dir).map_err(Error::store(&key_dir))?;
+                    for entry in entries.flatten() {
+                        let path = entry.path();
+                        let Ok(metadata) = entry.metadata() else {
+                            continue;
+                        };
+                        if !metadata.is_file() || path.extension().is_some_and(|ext| ext == "part")
+                        {
+                            continue;
+                        }
+                        files.push(StoredFile {
+                            name: file_name_of(&name_dir),
+                            key: file_name_of(&key_dir),
+                            size: metadata.len(),
+                            accessed: metadata
+                                .accessed()
+                                .or_else(|_| metadata.modified())
+                                .unwrap_or(SystemTime::UNIX_EPOCH),
+                            path,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// Deletes a stored file, its lock file and the folders left empty by it.
+    ///
+    /// A run waiting for the lock of the file while it is removed locks a new lock
+    /// file instead, so pruning a store shared with a running fetch may let a second
+    /// run download the same file at the same time; one of them wins the rename.
+    pub fn remove(&self, file: &StoredFile) -> Result<()> {
+        fs::remove_file(&file.path).map_err(Error::store(&file.path))?;
+        // Downloads lock the file under the name of its folder, merges under the name
+        // of the stored file, which differs for compressed files.
+        for locked in [file.name.clone(), file_name_of(&file.path)] {
+            fs::remove_file(self.lock_path(&file.name, &file.key, &locked)).ok();
+        }
+        // Removing a folder fails while it still holds other files.
+        let mut folder = file.path.parent();
+        while let Some(dir) = folder
+            && dir != self.root
+            && fs::remove_dir(dir).is_ok()
+        {
+            folder = dir.parent();
+        }
+        Ok(())
+    }
+
+    /// Returns the directory holding the given PDB.
+    pub fn pdb_folder(&self, pdb: &PdbMeta) -> PathBuf {
+        self.key_folder(&pdb.name, &pdb.key())
+    }
+
+    /// Returns the directory for the file `name` with the symbol server key `key`,
+    /// e.g. `ntdll.pdb` and `1EB9FACB04C73C5DE79D35E14B2FDC231`.
+    pub fn key_folder(&self, name: &str, key: &str) -> PathBuf {
+        let mut folder = self.root.clone();
+        if self.tiered {
+            folder.push(name.chars().take(2).collect::<String>());
+        }
+        folder.join(name).join(key)
+    }
+
+    /// Returns the full path of the given PDB inside the store.
+    pub fn pdb_path(&self, pdb: &PdbMeta) -> PathBuf {
+        self.pdb_folder(pdb).join(&pdb.name)
+    }
+
+    /// Returns whether the given PDB is already present in the store.
+    pub fn contains(&self, pdb: &PdbMeta) -> bool {
+        self.pdb_path(pdb).exists()
+    }
+
+    /// Returns the path of the partial download of the given PDB.
+    pub fn part_path(&self, pdb: &PdbMeta) -> PathBuf {
+        self.pdb_folder(pdb).join(format!("{}.part", pdb.name))
+    }
+
+    /// Checks that a stored PDB is complete and matches the requested GUID and age,
+    /// see [`verify_file`].
+    pub fn verify(&self, pdb: &PdbMeta) -> std::result::Result<(), String> {
+        verify_file(pdb, &self.pdb_path(pdb))
+    }
+
+    /// Creates the folder for `file` with the key `key` and returns the path of its
+    /// `.part` file, like [`SymbolStore::prepare`] but for any file in the store.
+    pub async fn prepare_file(&self, name: &str, key: &str, file: &str) -> Result<PathBuf> {
+        let folder = self.key_folder(name, key);
+        tokio::fs::create_dir_all(&folder)
+            .await
+            .map_err(Error::store(&folder))?;
+        Ok(folder.join(format!("{}.part", file)))
+    }
+
+    /// Locks `file` with the key `key` against other runs sharing the store, like
+    /// [`SymbolStore::lock`] but for any file in the store.
+    pub async fn lock_file(&self, name: &str, key: &str, file: &str) -> Result<FileLock> {
+        let path = self.lock_path(name, key, file);
+        if let Some(folder) = path.parent() {
+            tokio::fs::create_dir_all(folder)
+                .await
+                .map_err(Error::store(folder))?;
+        }
+        FileLock::acquire(path).await
+    }
+
+    /// Returns the lock file of `file` with the key `key` in [`LOCKS_DIR`], named by a
+    /// hash of the three so that the served layout holds no lock files. The hash is
+    /// taken case-insensitively, like the folders of the store on Windows.
+    fn lock_path(&self, name: &str, key: &str, file: &str) -> PathBuf {
+        let id = format!("{}/{}/{}", name, key, file).to_lowercase();
+        let hash = hex::encode(Sha256::digest(id.as_bytes()));
+        self.root.join(ADMIN_DIR).join(LOCKS_DIR).join(format!(
+            "{}.{}",
+            &hash[..32],
+            LOCK_EXTENSION
+        ))
+    }
+
+    /// Renames the completed `.part` file of `file` into place and adds it to the
+    /// pending transaction, recording `source` as where it came from.
+    pub async fn persist_file(
+        &self,
+        name: &str,
+        key: &str,
+        file: &str,
+        source: &Path,
+    ) -> Result<PathBuf> {
+        let folder = self.key_folder(name, key);
+        let path = folder.join(file);
+        persist_part(&folder.join(format!("{}.part", file)), &path).await?;
+        let path = self.compress(path).await?;
+        self.record_pending(name, key, source);
+        Ok(path)
+    }
+
+    /// Copies the file at `from` into the store as `file` with the key `key`, as it is,
+    /// through a `.part` file, and adds it to the pending transaction.
+    pub async fn add_file(
+        &self,
+        name: &str,
+        key: &str,
+        file: &str,
+        from: &Path,
+    ) -> Result<PathBuf> {
+        let part = self.prepare_file(name, key, file).await?;
+        tokio::fs::copy(from, &part)
+            .await
+            .map_err(Error::store(&part))?;
+        let path = self.key_folder(name, key).join(file);
+        persist_part(&part, &path).await?;
+        self.record_pending(name, key, from);
+        Ok(path)
+    }
+
+    /// Adds a file to the pending transaction. Like symstore, the entry names the file
+    /// the stored one was added from, e.g. the scanned binary, not its place in the
+    /// store.
+    fn record_pending(&self, name: &str, key: &str, source: &Path) {
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(format!("\"{}\\{}\",\"{}\"", name, key, source.display()));
+    }
+
+    /// Records the files added since the last commit as a transaction like
+    /// [`SymbolStore::commit_transaction`], with `comment` in the comment column of
+    /// `server.txt` and `history.txt`, e.g. where the files came from.
+    pub fn commit_transaction_with_comment(&self, comment: &str) -> Result<()> {
+        let entries =
+            std::mem::take(&mut *self.pending.lock().unwrap_or_else(PoisonError::into_inner));
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let id = self
+            .write_transaction(&entries, comment)
+            .map_err(Error::store(self.root.join(ADMIN_DIR)))?;
+        tracing::info!("Recorded transaction {} with {} files", id, entries.len());
+        Ok(())
+    }
+
+    /// Finds `file` with the key `key` in the store, as it is or compressed, and
+    /// returns its path and compression.
+    pub fn find_file(
+        &self,
+        name: &str,
+        key: &str,
+        file: &str,
+    ) -> Option<(PathBuf, StoreCompression)> {
+        let folder = self.key_folder(name, key);
+        let path = folder.join(file);
+        if path.is_file() {
+            return Some((path, StoreCompression::None));
+        }
+        find_compressed(&folder, file)
+    }
+
+    /// Replaces the stored file at `path` with its compressed variant if the store
+    /// compresses files, and returns the path of the stored file.
+    async fn compress(&self, path: PathBuf) -> Result<PathBuf> {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let Some(compressed_name) = self.compression.compressed_name(&file_name) else {
+            return Ok(path);
+        };
+        let compressed = path.with_file_name(&compressed_name);
+        let part = path.with_file_name(format!("{}.part", compressed_name));
+        let compression = self.compression;
+        let (source, dest) = (path.clone(), part.clone());
+        let size = tokio::task::spawn_blocking(move || compress_file(&source, &dest, compression))
+            .await
+            .map_err(std::io::Error::other)
+            .and_then(|result| result)
+            .map_err(Error::store(&compressed))?;
+        persist_part(&part, &compressed).await?;
+        tokio::fs::remove_file(&path)
+            .await
+            .map_err(Error::store(&path))?;
+        tracing::debug!("Compressed {} to {} bytes", compressed.display(), size);
+        Ok(compressed)
+    }
+
+    /// Writes the admin files for a new transaction and returns its id.
+    fn write_transaction(&self, entries: &[String], comment: &str) -> std::io::Result<String> {
+        let admin = self.root.join(ADMIN_DIR);
+        fs::create_dir_all(&admin)?;
+        // Concurrent runs would otherwise hand out the same transaction ID.
+        let _lock = FileLock::acquire_blocking(&admin.join(TRANSACTION_LOCK_FILE))?;
+        let pingme = self.root.join(PINGME_FILE);
+        if !pingme.exists() {
+            fs::write(pingme, "")?;
+        }
+
+        let last_id = fs::read_to_string(admin.join("lastid.txt"))
+            .ok()
+            .and_then(|id| id.trim().parse::<u32>().ok())
+            .unwrap_or(0);
+        let id = format!("{:010}", last_id + 1);
+        fs::write(admin.join(&id), entries.join("\n") + "\n")?;
+
+        let now = chrono::Local::now();
+        let record = format!(
+            "{},add,file,{},\"symbolfetcher\",\"{}\",\"{}\",\n",
+            id,
+            now.format("%m/%d/%Y,%H:%M:%S"),
+            env!("CARGO_PKG_VERSION"),
+            comment.replace('"', "'")
+        );
+        for file in ["server.txt", "history.txt"] {
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(admin.join(file))?
+                .write_all(record.as_bytes())?;
+        }
+        fs::write(admin.join("lastid.txt"), format!("{}\n", id))?;
+        Ok(id)
+    }
+}
+
+#[async_trait::async_trait]
+impl SymbolStore for LocalStore {
+    fn location(&self, pdb: &PdbMeta) -> PathBuf {
+        self.pdb_path(pdb)
+    }
+
+    /// Returns the size of the stored PDB, removing it if it is corrupt so that it is
+    /// downloaded again.
+    async fn stored_size(&self, pdb: &PdbMeta) -> Result<Option<u64>> {
+        let path = self.pdb_path(pdb);
+        let Ok(metadata) = tokio::fs::metadata(&path).await else {
+            // Compressed files were verified before they were compressed.
+            return self.existing_size(pdb).await;
+        };
+        match self.verify(pdb) {
+            Ok(()) => Ok(Some(metadata.len())),
+            Err(reason) => {
+                tracing::warn!("Re-downloading corrupt PDB {}: {}", path.display(), reason);
+                tokio::fs::remove_file(&path)
+                    .await
+                    .map_err(Error::store(&path))?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Returns the size of the PDB or its compressed copy, without reading either.
+    async fn existing_size(&self, pdb: &PdbMeta) -> Result<Option<u64>> {
+        if let Ok(metadata) = tokio::fs::metadata(self.pdb_path(pdb)).await {
+            return Ok(Some(metadata.len()));
+        }
+        Ok(find_compressed(&self.pdb_folder(pdb), &pdb.name)
+            .and_then(|(path, _)| fs::metadata(path).ok())
+            .map(|metadata| metadata.len()))
+    }
+
+    /// Creates the folder of the PDB and returns the path of its `.part` file.
+    async fn prepare(&self, pdb: &PdbMeta) -> Result<PathBuf> {
+        let pdb_folder = self.pdb_folder(pdb);
+        tokio::fs::create_dir_all(&pdb_folder)
+            .await
+            .map_err(Error::store(&pdb_folder))?;
+        Ok(self.part_path(pdb))
+    }
+
+    /// Locks the lock file of the PDB in `000Admin/locks`, which stays behind until
+    /// the PDB is removed so that runs waiting for the lock keep locking the same file.
+    async fn lock(&self, pdb: &PdbMeta) -> Result<Option<FileLock>> {
+        self.lock_file(&pdb.name, &pdb.key(), &pdb.name)
+            .await
+            .map(Some)
+    }
+
+    /// Atomically renames a completed `.part` file to its place in the store,
+    /// compressing it if the store compresses files.
+    ///
+    /// The file is added to the pending transaction, see
+    /// [`SymbolStore::commit_transaction`].
+    async fn persist(&self, pdb: &PdbMeta) -> Result<PathBuf> {
+        let pdb_path = self.pdb_path(pdb);
+        persist_part(&self.part_path(pdb), &pdb_path).await?;
+        let pdb_path = self.compress(pdb_path).await?;
+        self.record_pending(&pdb.name, &pdb.key(), &pdb.path);
+        Ok(pdb_path)
+    }
+
+    /// Records all files written since the last commit as a symstore `add` transaction.
+    ///
+    /// Creates `pingme.txt` and the `000Admin` directory on first use, appends the
+    /// transaction to `server.txt` and `history.txt` and updates `lastid.txt`.
+    fn commit_transaction(&self) -> Result<()> {
+        self.commit_transaction_with_comment("")
+    }
+}
+
+/// Checks that the PDB file at `path` is complete and matches the GUID and age of
+/// `pdb`, see [`msf::check_file`] and [`msf::read_key`].
+///
+/// Only `.pdb` files are checked, portable PDBs only for their metadata signature;
+/// other files are assumed to be valid.
+pub fn verify_file(pdb: &PdbMeta, path: &Path) -> std::result::Result<(), String> {
+    if pdb.kind == SymbolKind::PortablePdb {
+        return check_portable_pdb(path);
+    }
+    if !Path::new(&pdb.name)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pdb"))
+    {
+        return Ok(());
+    }
+    msf::check_file(path)?;
+    match msf::read_key(path)? {
+        Some((guid, age)) if guid != pdb.guid || age != pdb.age => Err(format!(
+            "GUID/age mismatch: expected {}{}, found {}{}",
+            pdb.guid, pdb.age, guid, age
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that the file starts with the `BSJB` signature of ECMA-335 metadata, which
+/// portable PDBs consist of.
+fn check_portable_pdb(path: &Path) -> std::result::Result<(), String> {
+    let mut signature = [0; 4];
+    std::fs::File::open(path)
+        .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut signature))
+        .map_err(|e| format!("failed to read portable PDB: {}", e))?;
+    if &signature != b"BSJB" {
+        return Err("not a portable PDB, metadata signature missing".to_string());
+    }
+    Ok(())
+}
+
+/// Returns the subdirectories of `dir` except `000Admin`.
+fn subdirectories(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(dir).map_err(Error::store(dir))?;
+    Ok(entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .filter(|entry| entry.file_name() != ADMIN_DIR)
+        .map(|entry| entry.path())
+        .collect())
+}
+
+/// Returns the last component of `path` as a string.
+fn file_name_of(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Renames a finished `.part` file to its final path.
+async fn persist_part(part: &Path, path: &Path) -> Result<()> {
+    tokio::fs::rename(part, path)
+        .await
+        .map_err(Error::store(path))
+}
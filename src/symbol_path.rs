@@ -0,0 +1,186 @@
+//! Parsing of WinDbg `_NT_SYMBOL_PATH` strings.
+
+use std::path::PathBuf;
+
+use crate::download::DEFAULT_SYMBOL_SERVER;
+
+/// Store and server configuration derived from an `_NT_SYMBOL_PATH` style string.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SymbolPath {
+    /// The first local downstream store found, used as the download target.
+    pub cache: Option<PathBuf>,
+    /// Upstream HTTP symbol servers, in the order they should be queried.
+    pub servers: Vec<String>,
+}
+
+impl SymbolPath {
+    /// Parses a symbol path such as `srv*C:\symbols*https://msdl.microsoft.com/download/symbols`.
+    ///
+    /// Elements are separated by `;`. `srv*`/`symsrv*` elements list local stores followed
+    /// by an optional server, `cache*` elements name a local cache, and anything else is
+    /// treated as a plain local directory. Elements of an unknown `type*` form are
+    /// ignored.
+    pub fn parse(path: &str) -> Self {
+        let mut result = Self::default();
+        for element in path.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let mut parts = element.split('*');
+            let kind = parts.next().unwrap_or_default();
+            if kind.eq_ignore_ascii_case("srv") || kind.eq_ignore_ascii_case("symsrv") {
+                let mut parts: Vec<&str> = parts.collect();
+                if kind.eq_ignore_ascii_case("symsrv") && !parts.is_empty() {
+                    // The first field names the symbol server DLL, e.g. symsrv.dll.
+                    parts.remove(0);
+                }
+                result.add_srv(&parts);
+            } else if kind.eq_ignore_ascii_case("cache") {
+                for dir in parts.filter(|p| !p.is_empty()) {
+                    result.add_cache(dir);
+                }
+            } else if element.contains('*') {
+                // Windows paths cannot contain `*`, so this is a misspelt element type.
+                tracing::warn!("Ignoring unknown symbol path element {:?}", element);
+            } else {
+                result.add_location(element);
+            }
+        }
+        tracing::debug!("Parsed symbol path {:?} into {:?}", path, result);
+        result
+    }
+
+    fn add_srv(&mut self, parts: &[&str]) {
+        let parts: Vec<&str> = parts.iter().copied().filter(|p| !p.is_empty()).collect();
+        if !parts.iter().any(|p| is_url(p)) {
+            // `srv*` without an explicit server implies the Microsoft symbol server.
+            self.add_server(DEFAULT_SYMBOL_SERVER);
+        }
+        for part in parts {
+            self.add_location(part);
+        }
+    }
+
+    fn add_location(&mut self, location: &str) {
+        if is_url(location) {
+            self.add_server(location);
+        } else {
+            self.add_cache(location);
+        }
+    }
+
+    fn add_cache(&mut self, dir: &str) {
+        if self.cache.is_none() {
+            self.cache = Some(PathBuf::from(dir));
+        }
+    }
+
+    fn add_server(&mut self, server: &str) {
+        let server = server.trim_end_matches('/');
+        if !self.servers.iter().any(|s| s == server) {
+            self.servers.push(server.to_string());
+        }
+    }
+}
+
+fn is_url(location: &str) -> bool {
+    let lower = location.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: &str = "https://symbols.example.com";
+
+    fn symbol_path(cache: Option<&str>, servers: &[&str]) -> SymbolPath {
+        SymbolPath {
+            cache: cache.map(PathBuf::from),
+            servers: servers.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn parses_symbol_path_forms() {
+        let cases: &[(&str, Option<&str>, &[&str])] = &[
+            (
+                "srv*C:\\symbols*https://symbols.example.com",
+                Some("C:\\symbols"),
+                &[SERVER],
+            ),
+            (
+                "SRV*C:\\symbols*https://symbols.example.com/",
+                Some("C:\\symbols"),
+                &[SERVER],
+            ),
+            (
+                "srv*C:\\symbols",
+                Some("C:\\symbols"),
+                &[DEFAULT_SYMBOL_SERVER],
+            ),
+            ("srv*", None, &[DEFAULT_SYMBOL_SERVER]),
+            (
+                "symsrv*symsrv.dll*C:\\symbols*https://symbols.example.com",
+                Some("C:\\symbols"),
+                &[SERVER],
+            ),
+            ("symsrv*symsrv.dll", None, &[DEFAULT_SYMBOL_SERVER]),
+            (
+                "cache*C:\\cache;srv*https://symbols.example.com",
+                Some("C:\\cache"),
+                &[SERVER],
+            ),
+            ("cache*C:\\cache", Some("C:\\cache"), &[]),
+            // Only the first of several downstream stores is used as the cache.
+            (
+                "srv*C:\\first*\\\\share\\second*https://symbols.example.com",
+                Some("C:\\first"),
+                &[SERVER],
+            ),
+            (
+                "srv*C:\\first*https://symbols.example.com;srv*C:\\second*https://other.example.com",
+                Some("C:\\first"),
+                &[SERVER, "https://other.example.com"],
+            ),
+            ("C:\\symbols", Some("C:\\symbols"), &[]),
+            ("https://symbols.example.com", None, &[SERVER]),
+            // Duplicate servers are queried once.
+            (
+                "srv*https://symbols.example.com;https://symbols.example.com/",
+                None,
+                &[SERVER],
+            ),
+        ];
+        for (path, cache, servers) in cases {
+            assert_eq!(
+                SymbolPath::parse(path),
+                symbol_path(*cache, servers),
+                "{path}"
+            );
+        }
+    }
+
+    #[test]
+    fn tolerates_malformed_symbol_paths() {
+        let cases: &[(&str, Option<&str>, &[&str])] = &[
+            ("", None, &[]),
+            (" ; ;; ", None, &[]),
+            ("cache*", None, &[]),
+            ("cache**", None, &[]),
+            ("srv**https://symbols.example.com", None, &[SERVER]),
+            ("*", None, &[]),
+            ("*C:\\symbols", None, &[]),
+            ("svr*C:\\symbols*https://symbols.example.com", None, &[]),
+            (
+                " srv*C:\\symbols*https://symbols.example.com ;",
+                Some("C:\\symbols"),
+                &[SERVER],
+            ),
+        ];
+        for (path, cache, servers) in cases {
+            assert_eq!(
+                SymbolPath::parse(path),
+                symbol_path(*cache, servers),
+                "{path}"
+            );
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Manifests in the format `symchk /om` writes and `symchk /im` reads.
+//!
+//! Each line names a file and its symbol server key, followed by a flag that is
+//! always 1, e.g. `ntdll.pdb,1EB9FACB04C73C5DE79D35E14B2FDC231,1`. This lets the
+//! binaries be scanned on an offline machine and the symbols be downloaded elsewhere.
+
+use std::{fmt::Write as _, fs, path::Path};
+
+use crate::{error::Result, windows::PdbMeta};
+
+/// Writes the keys in the manifest format of `symchk /om`.
+pub fn write_manifest(path: &Path, pdbs: &[PdbMeta]) -> Result<()> {
+    let mut manifest = String::new();
+    for pdb in pdbs {
+        let _ = writeln!(manifest, "{},{},1", pdb.name, pdb.key());
+    }
+    fs::write(path, manifest)?;
+    tracing::info!(
+        "Wrote symchk manifest with {} entries to {}",
+        pdbs.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Reads the keys from a manifest written by `symchk /om`, skipping lines that are
+/// not valid.
+///
+/// Lines of the form `binary,key,name` naming the PDB last are accepted as well.
+pub fn read_manifest(path: &Path) -> Result<Vec<PdbMeta>> {
+    let manifest = fs::read_to_string(path)?;
+    let mut pdbs = Vec::new();
+    for (number, line) in manifest.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match parse_line(line, path) {
+            Some(pdb) => pdbs.push(pdb),
+            None => tracing::warn!(
+                "Skipping invalid line {} of {}: {}",
+                number + 1,
+                path.display(),
+                line
+            ),
+        }
+    }
+    Ok(pdbs)
+}
+
+fn parse_line(line: &str, path: &Path) -> Option<PdbMeta> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    match fields.as_slice() {
+        [name, key] => PdbMeta::from_key(name, key, path),
+        [name, key, flag] if flag.chars().all(|c| c.is_ascii_digit()) => {
+            PdbMeta::from_key(name, key, path)
+        }
+        [_, key, name] => PdbMeta::from_key(name, key, path),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::windows::SymbolKind;
+
+    const GUID: &str = "1EB9FACB04C73C5DE79D35E14B2FDC23";
+
+    fn parse(line: &str) -> Option<PdbMeta> {
+        parse_line(line, Path::new("manifest.txt"))
+    }
+
+    #[test]
+    fn parses_manifest_lines() {
+        let cases = [
+            (
+                "ntdll.pdb,1EB9FACB04C73C5DE79D35E14B2FDC231,1",
+                "ntdll.pdb",
+                1,
+            ),
+            (
+                "ntdll.pdb, 1EB9FACB04C73C5DE79D35E14B2FDC231A ,1",
+                "ntdll.pdb",
+                0x1a,
+            ),
+            (
+                "ntdll.pdb,1EB9FACB04C73C5DE79D35E14B2FDC2310",
+                "ntdll.pdb",
+                0x10,
+            ),
+            (
+                "ntdll.dll,1EB9FACB04C73C5DE79D35E14B2FDC231F,ntdll.pdb",
+                "ntdll.pdb",
+                0x1f,
+            ),
+        ];
+        for (line, name, age) in cases {
+            let pdb = parse(line).unwrap_or_else(|| panic!("{line}"));
+            assert_eq!(
+                (pdb.name.as_str(), pdb.guid.as_str(), pdb.age, pdb.kind),
+                (name, GUID, age, SymbolKind::Pdb),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for line in [
+            "ntdll.pdb",
+            "ntdll.pdb,",
+            "ntdll.pdb,not-a-key,1",
+            "ntdll.pdb,1EB9FACB04C73C5DE79D35E14B2FDC231,1,extra",
+            "..\\ntdll.pdb,1EB9FACB04C73C5DE79D35E14B2FDC231,1",
+            ",1EB9FACB04C73C5DE79D35E14B2FDC231,1",
+        ] {
+            assert!(parse(line).is_none(), "{line}");
+        }
+    }
+
+    #[test]
+    fn manifests_round_trip() {
+        let pdbs: Vec<PdbMeta> = [
+            "ntdll.pdb,1EB9FACB04C73C5DE79D35E14B2FDC231,1",
+            "kernel32.pdb,1EB9FACB04C73C5DE79D35E14B2FDC231C,1",
+        ]
+        .into_iter()
+        .map(|line| parse(line).unwrap())
+        .collect();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("manifest.txt");
+        write_manifest(&path, &pdbs).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "ntdll.pdb,1EB9FACB04C73C5DE79D35E14B2FDC231,1\n\
+             kernel32.pdb,1EB9FACB04C73C5DE79D35E14B2FDC231C,1\n"
+        );
+        fs::write(
+            &path,
+            "ntdll.pdb,1EB9FACB04C73C5DE79D35E14B2FDC231,1\n\ngarbage\n\
+             kernel32.pdb,1EB9FACB04C73C5DE79D35E14B2FDC231C,1\n",
+        )
+        .unwrap();
+        let keys: Vec<String> = read_manifest(&path)
+            .unwrap()
+            .iter()
+            .map(PdbMeta::key)
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "1EB9FACB04C73C5DE79D35E14B2FDC231",
+                "1EB9FACB04C73C5DE79D35E14B2FDC231C"
+            ]
+        );
+    }
+}
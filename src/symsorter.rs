@@ -0,0 +1,104 @@
+//! Sentry's unified symbol server layout, as `symsorter` writes it, for uploading
+//! symbols to a self-hosted Sentry.
+//!
+//! Files are stored as `ab/cdef.../<type>`, split after the first two characters of
+//! the lowercase debug ID, next to a `meta` JSON file with the name, architecture and
+//! format of the object.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    compress::ExpandedFile,
+    error::Result,
+    windows::{Architecture, PdbMeta},
+};
+
+/// The `meta` file next to the files of a debug ID.
+#[derive(Debug, Serialize)]
+struct Meta<'a> {
+    name: &'a str,
+    arch: &'a str,
+    file_format: &'a str,
+}
+
+/// Returns the debug ID of a PDB in the form the layout uses: the lowercase GUID
+/// followed by the age in hex, without separators.
+pub fn debug_id(pdb: &PdbMeta) -> String {
+    format!("{}{:x}", pdb.guid.to_lowercase(), pdb.age)
+}
+
+/// Returns the directory of the debug ID of `pdb` below `root`.
+pub fn object_dir(root: &Path, pdb: &PdbMeta) -> PathBuf {
+    let id = debug_id(pdb);
+    root.join(&id[..2]).join(&id[2..])
+}
+
+/// Copies the PDB at `store_path`, expanding it if the store keeps it compressed, into
+/// the layout as `debuginfo`. Returns the path of the copy; existing copies are kept.
+pub fn write_pdb(root: &Path, pdb: &PdbMeta, store_path: &Path) -> Result<PathBuf> {
+    let dir = object_dir(root, pdb);
+    let dest = dir.join("debuginfo");
+    if !dest.is_file() {
+        let file = ExpandedFile::open(store_path)?;
+        copy_file(file.path(), &dest)?;
+    }
+    write_meta(&dir, &pdb.name, pdb.arch, "pdb")?;
+    Ok(dest)
+}
+
+/// Copies the binary that references `pdb` into the layout as `executable`, under the
+/// debug ID of its PDB as Sentry looks it up. Returns the path of the copy, or `None`
+/// if the binary is gone, e.g. because it was extracted from an image.
+pub fn write_executable(root: &Path, pdb: &PdbMeta) -> Result<Option<PathBuf>> {
+    if !pdb.path.is_file() {
+        return Ok(None);
+    }
+    let dir = object_dir(root, pdb);
+    let dest = dir.join("executable");
+    if !dest.is_file() {
+        copy_file(&pdb.path, &dest)?;
+        let name = pdb
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // The PDB's meta file describes the debug ID if both are present.
+        if !dir.join("meta").is_file() {
+            write_meta(&dir, &name, pdb.arch, "pe")?;
+        }
+    }
+    Ok(Some(dest))
+}
+
+fn write_meta(dir: &Path, name: &str, arch: Architecture, file_format: &str) -> Result<()> {
+    let arch = match arch {
+        Architecture::X86 => "x86",
+        Architecture::X64 => "x86_64",
+        Architecture::Arm => "arm",
+        Architecture::Arm64 => "arm64",
+        Architecture::Unknown => "unknown",
+    };
+    let meta = Meta {
+        name,
+        arch,
+        file_format,
+    };
+    fs::write(dir.join("meta"), serde_json::to_vec(&meta)?)?;
+    Ok(())
+}
+
+/// Copies `source` to `dest` through a `.part` file, creating the directories.
+fn copy_file(source: &Path, dest: &Path) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let part = dest.with_extension("part");
+    fs::copy(source, &part)?;
+    fs::rename(&part, dest)?;
+    Ok(())
+}
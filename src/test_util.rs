@@ -0,0 +1,20 @@
+//! Helpers shared by the fixture tests of the image and binary parsers.
+
+use std::io::Write;
+
+use tempfile::NamedTempFile;
+
+/// Copies `bytes` into `data` at `offset`.
+pub fn put(data: &mut [u8], offset: impl TryInto<usize>, bytes: &[u8]) {
+    let Ok(offset) = offset.try_into() else {
+        panic!("fixture offset does not fit in usize");
+    };
+    data[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+/// Writes `data` to a new temporary file.
+pub fn write(data: &[u8]) -> NamedTempFile {
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(data).unwrap();
+    file
+}
@@ -0,0 +1,150 @@
+//! Packaging part of a local store into a zstd-compressed tar archive, to carry it
+//! across an air gap, and importing such archives into another store.
+
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io,
+    path::Path,
+    time::SystemTime,
+};
+
+use globset::GlobSet;
+
+use crate::{
+    error::Result,
+    merge::{self, MergeSummary},
+    package::sanitize,
+    store::{LocalStore, StoredFile},
+    windows::glob_set,
+};
+
+/// Which stored files [`export`] packages. A file is exported if it meets all of the
+/// criteria that are set.
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    /// Export only files whose name and key are in the set, see
+    /// [`crate::prune::manifest_references`].
+    pub referenced: Option<HashSet<(String, String)>>,
+    /// Export only files whose name, e.g. `ntdll.pdb`, matches one of the globs.
+    pub names: Option<GlobSet>,
+    /// Export only files added to the store at or after this time.
+    pub since: Option<SystemTime>,
+    /// Export only files added to the store before this time.
+    pub until: Option<SystemTime>,
+}
+
+impl ExportFilter {
+    /// Sets [`ExportFilter::names`] from case-insensitive glob patterns, e.g.
+    /// `ntdll.*`; no patterns export files of any name.
+    pub fn with_names(mut self, patterns: &[String]) -> Result<Self> {
+        self.names = glob_set(patterns)?;
+        Ok(self)
+    }
+
+    /// Returns whether `file` is exported, judging its age by its modification time.
+    fn accepts(&self, file: &StoredFile) -> bool {
+        if self
+            .referenced
+            .as_ref()
+            .is_some_and(|referenced| !referenced.contains(&(file.name.clone(), file.key.clone())))
+            || self
+                .names
+                .as_ref()
+                .is_some_and(|names| !names.is_match(&file.name))
+        {
+            return false;
+        }
+        if self.since.is_none() && self.until.is_none() {
+            return true;
+        }
+        let Ok(modified) = fs::metadata(&file.path).and_then(|metadata| metadata.modified()) else {
+            return false;
+        };
+        self.since.is_none_or(|since| modified >= since)
+            && self.until.is_none_or(|until| modified < until)
+    }
+}
+
+/// Outcome counts of [`export`].
+#[derive(Debug, Default)]
+pub struct ExportSummary {
+    /// Files written to the archive.
+    pub files: usize,
+    /// Total size of the files before compression.
+    pub bytes: u64,
+}
+
+/// Writes the files of `store` that `filter` accepts to a zstd-compressed tar archive
+/// at `output`.
+///
+/// Files are stored as `<name>/<key>/<file>` whatever the layout of the store, and
+/// compressed files as they are, so the archive can be imported into any store with
+/// [`import`].
+pub fn export(store: &LocalStore, filter: &ExportFilter, output: &Path) -> Result<ExportSummary> {
+    let mut summary = ExportSummary::default();
+    let mut archive = tar::Builder::new(zstd::Encoder::new(File::create(output)?, 0)?);
+    for file in store.stored_files()? {
+        if !filter.accepts(&file) {
+            continue;
+        }
+        let Some(file_name) = file.path.file_name() else {
+            continue;
+        };
+        let entry = Path::new(&file.name).join(&file.key).join(file_name);
+        archive.append_path_with_name(&file.path, &entry)?;
+        tracing::debug!("Exported {}", file.path.display());
+        summary.files += 1;
+        summary.bytes += file.size;
+    }
+    archive.into_inner()?.finish()?;
+    Ok(summary)
+}
+
+/// Imports an archive written by [`export`] into `dest`.
+///
+/// The files are unpacked into a scratch directory and merged like
+/// [`merge::merge`] does, so PDBs are checked against their keys, files `dest` already
+/// has are kept, and the import is recorded in its symstore history.
+pub async fn import(archive: &Path, dest: &LocalStore, dry_run: bool) -> Result<MergeSummary> {
+    let scratch = tempfile::tempdir()?;
+    unpack(archive, scratch.path())?;
+    let source = LocalStore::new(scratch.path().to_path_buf());
+    merge::merge_with_comment(
+        &source,
+        dest,
+        dry_run,
+        &format!("imported from {}", archive.display()),
+    )
+    .await
+}
+
+/// Unpacks the files of the archive that sit at `<name>/<key>/<file>` into `dest`,
+/// skipping everything else.
+fn unpack(archive: &Path, dest: &Path) -> Result<()> {
+    let file = File::open(archive)?;
+    // Plain tar archives are accepted too, e.g. ones repacked by hand.
+    let reader: Box<dyn io::Read> = if archive.extension().is_some_and(|ext| ext == "tar") {
+        Box::new(file)
+    } else {
+        Box::new(zstd::Decoder::new(file)?)
+    };
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let Some(relative) = sanitize(&name).filter(|path| path.components().count() == 3) else {
+            tracing::warn!("Skipping {}: not a stored file", name);
+            continue;
+        };
+        let target = dest.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut File::create(&target)?)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,190 @@
+//! Checking a symbol store against binaries, like `symchk /r`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    compress::{StoreCompression, expand_file, find_compressed},
+    error::Result,
+    msf,
+    store::{LocalStore, verify_file},
+    windows::{PdbMeta, SymbolKind},
+};
+
+/// The result of checking the store for the PDB of one binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifyStatus {
+    /// The matching PDB is stored and readable.
+    Ok,
+    /// The store has no PDB of that name.
+    Missing,
+    /// The store only has PDBs of that name with a different GUID or age.
+    Mismatched,
+    /// The stored PDB is truncated or not a PDB at all.
+    Corrupt,
+}
+
+/// The outcome of checking one PDB key.
+#[derive(Debug)]
+pub struct VerifyRecord {
+    /// The PDB key read from the binary.
+    pub pdb: PdbMeta,
+    /// Whether the store has a usable copy.
+    pub status: VerifyStatus,
+    /// Where the PDB is expected in the store.
+    pub store_path: PathBuf,
+    /// What is wrong with the stored copy, if anything.
+    pub reason: Option<String>,
+}
+
+/// A machine-readable record of one checked binary, see [`write_report`].
+#[derive(Debug, Serialize)]
+struct ReportEntry<'a> {
+    path: &'a Path,
+    pdb_name: &'a str,
+    key: String,
+    kind: SymbolKind,
+    status: VerifyStatus,
+    store_path: &'a Path,
+    reason: Option<&'a str>,
+}
+
+/// Outcome counts of a verify run.
+#[derive(Debug, Default)]
+pub struct VerifySummary {
+    pub ok: usize,
+    pub missing: usize,
+    pub mismatched: usize,
+    pub corrupt: usize,
+}
+
+impl VerifySummary {
+    /// Counts the statuses of `records`.
+    pub fn from_records(records: &[VerifyRecord]) -> Self {
+        let mut summary = Self::default();
+        for record in records {
+            match record.status {
+                VerifyStatus::Ok => summary.ok += 1,
+                VerifyStatus::Missing => summary.missing += 1,
+                VerifyStatus::Mismatched => summary.mismatched += 1,
+                VerifyStatus::Corrupt => summary.corrupt += 1,
+            }
+        }
+        summary
+    }
+
+    /// Returns whether every PDB was found intact.
+    pub fn is_ok(&self) -> bool {
+        self.missing == 0 && self.mismatched == 0 && self.corrupt == 0
+    }
+}
+
+/// Checks that the store holds a complete copy of every PDB with the right GUID and
+/// age, see [`verify_pdb`].
+pub fn verify_store(store: &LocalStore, pdbs: Vec<PdbMeta>) -> Vec<VerifyRecord> {
+    pdbs.into_iter().map(|pdb| verify_pdb(store, pdb)).collect()
+}
+
+/// Checks the stored copy of a PDB.
+///
+/// MSF 7.0 PDBs are opened to compare the GUID and age in their streams with the key,
+/// so a PDB stored under the wrong key counts as mismatched. If the key is not stored
+/// at all but other keys of the same name are, the PDB counts as mismatched too, as
+/// `symchk` reports it. Compressed copies are expanded to a temporary file first.
+pub fn verify_pdb(store: &LocalStore, pdb: PdbMeta) -> VerifyRecord {
+    let found = store.find_file(&pdb.name, &pdb.key(), &pdb.name);
+    let store_path = found
+        .as_ref()
+        .map_or_else(|| store.pdb_path(&pdb), |(path, _)| path.clone());
+    let (status, reason) = match found {
+        None => {
+            let others = other_keys(store, &pdb);
+            if others.is_empty() {
+                (VerifyStatus::Missing, None)
+            } else {
+                let reason = format!("only other versions stored: {}", others.join(", "));
+                (VerifyStatus::Mismatched, Some(reason))
+            }
+        }
+        Some((path, StoreCompression::None)) => check_file(&pdb, &path),
+        Some((path, compression)) => {
+            let expanded = tempfile::NamedTempFile::new()
+                .and_then(|temp| expand_file(&path, compression, temp.path()).map(|_| temp));
+            match expanded {
+                Ok(temp) => check_file(&pdb, temp.path()),
+                Err(e) => (
+                    VerifyStatus::Corrupt,
+                    Some(format!("failed to expand: {}", e)),
+                ),
+            }
+        }
+    };
+    VerifyRecord {
+        pdb,
+        status,
+        store_path,
+        reason,
+    }
+}
+
+/// Checks the PDB file at `path` against the key of `pdb`.
+fn check_file(pdb: &PdbMeta, path: &Path) -> (VerifyStatus, Option<String>) {
+    match verify_file(pdb, path) {
+        Ok(()) => (VerifyStatus::Ok, None),
+        Err(reason) => match msf::read_key(path) {
+            Ok(Some((guid, age))) if guid != pdb.guid || age != pdb.age => {
+                (VerifyStatus::Mismatched, Some(reason))
+            }
+            _ => (VerifyStatus::Corrupt, Some(reason)),
+        },
+    }
+}
+
+/// Returns the keys stored for the name of `pdb` other than its own.
+fn other_keys(store: &LocalStore, pdb: &PdbMeta) -> Vec<String> {
+    let key = pdb.key();
+    let folder = store.key_folder(&pdb.name, &key);
+    let Some(Ok(entries)) = folder.parent().map(fs::read_dir) else {
+        return Vec::new();
+    };
+    let mut keys: Vec<String> = entries
+        .flatten()
+        .filter(|entry| {
+            let folder = entry.path();
+            folder.join(&pdb.name).is_file() || find_compressed(&folder, &pdb.name).is_some()
+        })
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|other| *other != key)
+        .collect();
+    keys.sort();
+    keys
+}
+
+/// Writes the verify records as a pretty-printed JSON array.
+pub fn write_report(path: &Path, records: &[VerifyRecord]) -> Result<()> {
+    let entries: Vec<ReportEntry> = records
+        .iter()
+        .map(|record| ReportEntry {
+            path: &record.pdb.path,
+            pdb_name: &record.pdb.name,
+            key: record.pdb.key(),
+            kind: record.pdb.kind,
+            status: record.status,
+            store_path: &record.store_path,
+            reason: record.reason.as_deref(),
+        })
+        .collect();
+    let json = serde_json::to_string_pretty(&entries)?;
+    fs::write(path, json)?;
+    tracing::info!(
+        "Wrote verify report with {} entries to {}",
+        entries.len(),
+        path.display()
+    );
+    Ok(())
+}
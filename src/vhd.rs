@@ -0,0 +1,475 @@
+//! Reading the virtual disks of Hyper-V and Azure, VHD and VHDX, as raw disks.
+
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::{
+    bytes::{read_be_u32, read_be_u64, read_u16, read_u32, read_u64},
+    error::{Error, Result},
+    windows::encode_guid,
+};
+
+/// Signature at the start of VHDX files.
+const VHDX_SIGNATURE: &[u8; 8] = b"vhdxfile";
+/// Cookie of the footer at the end of VHD files, also copied to the start of dynamic
+/// disks.
+const VHD_COOKIE: &[u8; 8] = b"conectix";
+/// Size of the VHD footer.
+const VHD_FOOTER_LEN: u64 = 512;
+/// VHD disk types.
+const VHD_TYPE_FIXED: u32 = 2;
+const VHD_TYPE_DYNAMIC: u32 = 3;
+/// Marks an unallocated block in a VHD block allocation table.
+const VHD_UNALLOCATED: u32 = u32::MAX;
+/// Offsets of the two VHDX region tables; the second is a backup of the first.
+const VHDX_REGION_TABLES: [u64; 2] = [192 * 1024, 256 * 1024];
+/// Region of the VHDX block allocation table.
+const VHDX_BAT_REGION: &str = "2DC27766F62342009D64115E9BFD4A08";
+/// Region of the VHDX metadata.
+const VHDX_METADATA_REGION: &str = "8B7CA20647904B9AB8FE575F050F886E";
+/// Metadata items of VHDX files.
+const VHDX_FILE_PARAMETERS: &str = "CAA16737FA364D43B3B633F0AA44E76B";
+const VHDX_VIRTUAL_DISK_SIZE: &str = "2FA54224CD1B4876B2115DBED83BF4B8";
+const VHDX_LOGICAL_SECTOR_SIZE: &str = "8141BF1DA96F4709BA47F233A8FAAB5F";
+/// `HasParent` flag of the VHDX file parameters.
+const VHDX_HAS_PARENT: u32 = 2;
+/// BAT entry state of fully present VHDX payload blocks; other states read as zeros.
+const VHDX_BLOCK_PRESENT: u64 = 6;
+/// Upper bound for the size of a block allocation table, in entries.
+const MAX_BAT_ENTRIES: u64 = 64 * 1024 * 1024;
+/// Upper bound for the size of the VHDX metadata region, which Hyper-V makes 1 MiB.
+const MAX_METADATA_LEN: u32 = 16 * 1024 * 1024;
+
+/// A VHD or VHDX file read as the raw disk it holds.
+///
+/// Fixed and dynamic disks are supported; differencing disks, which need their parent,
+/// are not. Unallocated blocks read as zeros.
+pub struct VirtualDisk {
+    file: File,
+    size: u64,
+    position: u64,
+    layout: Layout,
+}
+
+/// Where the blocks of a virtual disk are stored in the file.
+enum Layout {
+    /// The disk is stored as is at the start of the file.
+    Fixed,
+    /// Blocks of `block_size` bytes at the file offsets in `blocks`, 0 if unallocated.
+    Blocks { block_size: u64, blocks: Vec<u64> },
+}
+
+/// Returns whether the file is a VHD or VHDX file, by its signatures.
+pub fn is_virtual_disk(path: &Path) -> bool {
+    let Ok(mut file) = File::open(path) else {
+        return false;
+    };
+    let mut signature = [0; 8];
+    if file.read_exact(&mut signature).is_ok()
+        && (signature == *VHDX_SIGNATURE || signature == *VHD_COOKIE)
+    {
+        return true;
+    }
+    file.seek(SeekFrom::End(-(VHD_FOOTER_LEN as i64)))
+        .and_then(|_| file.read_exact(&mut signature))
+        .is_ok_and(|()| signature == *VHD_COOKIE)
+}
+
+impl VirtualDisk {
+    /// Opens the VHD or VHDX file at `path`.
+    pub fn open(path: &Path) -> Result<Self> {
+        let invalid = |reason: String| Error::VirtualDisk {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let mut file = File::open(path)?;
+        let mut signature = [0; 8];
+        file.read_exact(&mut signature)?;
+        let (size, layout) = if signature == *VHDX_SIGNATURE {
+            open_vhdx(&mut file).map_err(invalid)?
+        } else {
+            open_vhd(&mut file).map_err(invalid)?
+        };
+        tracing::debug!("Virtual disk {} holds {} bytes", path.display(), size);
+        Ok(Self {
+            file,
+            size,
+            position: 0,
+            layout,
+        })
+    }
+}
+
+impl Read for VirtualDisk {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.size.saturating_sub(self.position);
+        let mut len = (buf.len() as u64).min(remaining) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+        let offset = match &self.layout {
+            Layout::Fixed => Some(self.position),
+            Layout::Blocks { block_size, blocks } => {
+                let in_block = self.position % block_size;
+                len = len.min((block_size - in_block) as usize);
+                match blocks.get((self.position / block_size) as usize) {
+                    Some(&start) if start != 0 => Some(start + in_block),
+                    _ => None,
+                }
+            }
+        };
+        let read = match offset {
+            Some(offset) => {
+                self.file.seek(SeekFrom::Start(offset))?;
+                self.file.read(&mut buf[..len])?
+            }
+            None => {
+                buf[..len].fill(0);
+                len
+            }
+        };
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for VirtualDisk {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start"))?;
+        Ok(self.position)
+    }
+}
+
+/// Reads the footer and, for dynamic disks, the block allocation table of a VHD file.
+fn open_vhd(file: &mut File) -> std::result::Result<(u64, Layout), String> {
+    let len = file.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+    let footer = read_at(
+        file,
+        len.saturating_sub(VHD_FOOTER_LEN),
+        VHD_FOOTER_LEN as usize,
+    )?;
+    if footer[..8] != *VHD_COOKIE {
+        return Err("missing VHD footer".to_string());
+    }
+    let (Some(header_offset), Some(size), Some(disk_type)) = (
+        read_be_u64(&footer, 0x10),
+        read_be_u64(&footer, 0x30),
+        read_be_u32(&footer, 0x3c),
+    ) else {
+        return Err("truncated VHD footer".to_string());
+    };
+    match disk_type {
+        VHD_TYPE_FIXED => Ok((size, Layout::Fixed)),
+        VHD_TYPE_DYNAMIC => {
+            let header = read_at(file, header_offset, 1024)?;
+            if &header[..8] != b"cxsparse" {
+                return Err("missing dynamic disk header".to_string());
+            }
+            let (Some(table), Some(entries), Some(block_size)) = (
+                read_be_u64(&header, 0x10),
+                read_be_u32(&header, 0x1c),
+                read_be_u32(&header, 0x20),
+            ) else {
+                return Err("truncated dynamic disk header".to_string());
+            };
+            let (entries, block_size) = (u64::from(entries), u64::from(block_size));
+            if block_size == 0 || entries > MAX_BAT_ENTRIES {
+                return Err("invalid dynamic disk header".to_string());
+            }
+            // Each block starts with a bitmap of its sectors, padded to a sector.
+            let bitmap_len = (block_size / 512).div_ceil(8).div_ceil(512) * 512;
+            let blocks = read_at(file, table, entries as usize * 4)?
+                .chunks_exact(4)
+                .filter_map(|entry| read_be_u32(entry, 0))
+                .map(|sector| match sector {
+                    VHD_UNALLOCATED => 0,
+                    sector => u64::from(sector) * 512 + bitmap_len,
+                })
+                .collect();
+            Ok((size, Layout::Blocks { block_size, blocks }))
+        }
+        4 => Err("differencing disks are not supported".to_string()),
+        other => Err(format!("unknown disk type {}", other)),
+    }
+}
+
+/// Reads the region table, metadata and block allocation table of a VHDX file.
+///
+/// The log is not replayed, so disks that were not closed cleanly may read stale
+/// blocks.
+fn open_vhdx(file: &mut File) -> std::result::Result<(u64, Layout), String> {
+    let mut regions = None;
+    for offset in VHDX_REGION_TABLES {
+        let table = read_at(file, offset, 64 * 1024)?;
+        if &table[..4] == b"regi" {
+            regions = Some(table);
+            break;
+        }
+    }
+    let regions = regions.ok_or("missing region table")?;
+    let (mut bat, mut metadata) = (None, None);
+    let count = read_u32(&regions, 8).ok_or("truncated region table")?;
+    for index in 0..(count as usize).min(2047) {
+        let entry = regions
+            .get(16 + index * 32..48 + index * 32)
+            .ok_or("region table entry outside of the table")?;
+        let (Some(guid), Some(offset), Some(len)) =
+            (guid_at(entry), read_u64(entry, 16), read_u32(entry, 24))
+        else {
+            return Err("truncated region table entry".to_string());
+        };
+        let region = (offset, len);
+        match guid.as_str() {
+            VHDX_BAT_REGION => bat = Some(region),
+            VHDX_METADATA_REGION => metadata = Some(region),
+            _ => {}
+        }
+    }
+    let (bat, metadata) = bat.zip(metadata).ok_or("missing BAT or metadata region")?;
+
+    if metadata.1 < 32 || metadata.1 > MAX_METADATA_LEN {
+        return Err("invalid metadata region".to_string());
+    }
+    let items = read_at(file, metadata.0, metadata.1 as usize)?;
+    if &items[..8] != b"metadata" {
+        return Err("missing metadata signature".to_string());
+    }
+    let (mut block_size, mut size, mut sector_size, mut flags) = (0, 0, 0, 0);
+    let count = read_u16(&items, 10).ok_or("truncated metadata table")?;
+    for index in 0..usize::from(count) {
+        let entry = items
+            .get(32 + index * 32..64 + index * 32)
+            .ok_or("metadata table outside of the region")?;
+        let (Some(guid), Some(offset)) = (guid_at(entry), read_u32(entry, 16)) else {
+            return Err("truncated metadata table entry".to_string());
+        };
+        let offset = offset as usize;
+        let outside = "metadata item outside of the region";
+        match guid.as_str() {
+            VHDX_FILE_PARAMETERS => {
+                block_size = u64::from(read_u32(&items, offset).ok_or(outside)?);
+                flags = read_u32(&items, offset + 4).ok_or(outside)?;
+            }
+            VHDX_VIRTUAL_DISK_SIZE => size = read_u64(&items, offset).ok_or(outside)?,
+            VHDX_LOGICAL_SECTOR_SIZE => {
+                sector_size = u64::from(read_u32(&items, offset).ok_or(outside)?);
+            }
+            _ => {}
+        }
+    }
+    if flags & VHDX_HAS_PARENT != 0 {
+        return Err("differencing disks are not supported".to_string());
+    }
+    if block_size == 0 || sector_size == 0 || size == 0 {
+        return Err("missing file parameters".to_string());
+    }
+    // A sector bitmap entry follows every `chunk_ratio` payload block entries.
+    let chunk_ratio = ((1u64 << 23) * sector_size / block_size).max(1);
+    let payload_blocks = size.div_ceil(block_size).min(MAX_BAT_ENTRIES + 1);
+    let entries = payload_blocks + payload_blocks.saturating_sub(1) / chunk_ratio;
+    if entries > MAX_BAT_ENTRIES || entries * 8 > u64::from(bat.1) {
+        return Err("invalid block allocation table".to_string());
+    }
+    let table = read_at(file, bat.0, entries as usize * 8)?;
+    let blocks = (0..payload_blocks)
+        .map(|block| {
+            let entry = read_u64(&table, ((block + block / chunk_ratio) * 8) as usize)
+                .ok_or("truncated block allocation table")?;
+            Ok(if entry & 0x7 == VHDX_BLOCK_PRESENT {
+                (entry >> 20) << 20
+            } else {
+                0
+            })
+        })
+        .collect::<std::result::Result<_, String>>()?;
+    Ok((size, Layout::Blocks { block_size, blocks }))
+}
+
+fn read_at(file: &mut File, offset: u64, len: usize) -> std::result::Result<Vec<u8>, String> {
+    let mut data = vec![0; len];
+    file.seek(SeekFrom::Start(offset))
+        .and_then(|_| file.read_exact(&mut data))
+        .map_err(|e| format!("failed to read {} bytes at offset {}: {}", len, offset, e))?;
+    Ok(data)
+}
+
+/// Reads the GUID at the start of a region or metadata table entry.
+fn guid_at(entry: &[u8]) -> Option<String> {
+    Some(encode_guid(entry.get(..16)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{put, write};
+
+    const MIB: usize = 1024 * 1024;
+
+    fn read_all(path: &Path) -> Vec<u8> {
+        let mut data = Vec::new();
+        VirtualDisk::open(path)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        data
+    }
+
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|index| (index % 251) as u8).collect()
+    }
+
+    /// Returns the bytes of a GUID in the form [`encode_guid`] reads them.
+    fn guid(text: &str) -> [u8; 16] {
+        let mut bytes: [u8; 16] = hex::decode(text).unwrap().try_into().unwrap();
+        bytes[..4].reverse();
+        bytes[4..6].reverse();
+        bytes[6..8].reverse();
+        bytes
+    }
+
+    fn vhd_footer(disk_type: u32, size: u64, data_offset: u64) -> Vec<u8> {
+        let mut footer = vec![0; VHD_FOOTER_LEN as usize];
+        put(&mut footer, 0, VHD_COOKIE);
+        put(&mut footer, 0x10, &data_offset.to_be_bytes());
+        put(&mut footer, 0x30, &size.to_be_bytes());
+        put(&mut footer, 0x3c, &disk_type.to_be_bytes());
+        footer
+    }
+
+    /// Builds a dynamic VHD of two 4 KiB blocks, of which only the first is allocated.
+    fn dynamic_vhd(block_size: u32) -> Vec<u8> {
+        let footer = vhd_footer(VHD_TYPE_DYNAMIC, 8192, 512);
+        let mut data = vec![0; 2560];
+        put(&mut data, 0, &footer);
+        put(&mut data, 512, b"cxsparse");
+        put(&mut data, 512 + 0x10, &1536u64.to_be_bytes());
+        put(&mut data, 512 + 0x1c, &2u32.to_be_bytes());
+        put(&mut data, 512 + 0x20, &block_size.to_be_bytes());
+        put(&mut data, 1536, &4u32.to_be_bytes());
+        put(&mut data, 1540, &VHD_UNALLOCATED.to_be_bytes());
+        data.extend(pattern(4096));
+        data.extend(footer);
+        data
+    }
+
+    /// Builds a VHDX of two 1 MiB blocks, of which only the first is present, with the
+    /// metadata at 1 MiB, the BAT at 2 MiB and the first block at 3 MiB.
+    fn vhdx() -> Vec<u8> {
+        let mut data = vec![0; 3 * MIB];
+        put(&mut data, 0, VHDX_SIGNATURE);
+        let regions = VHDX_REGION_TABLES[0] as usize;
+        put(&mut data, regions, b"regi");
+        put(&mut data, regions + 8, &2u32.to_le_bytes());
+        for (index, (region, offset)) in [(VHDX_METADATA_REGION, MIB), (VHDX_BAT_REGION, 2 * MIB)]
+            .into_iter()
+            .enumerate()
+        {
+            let entry = regions + 16 + index * 32;
+            put(&mut data, entry, &guid(region));
+            put(&mut data, entry + 16, &(offset as u64).to_le_bytes());
+            put(&mut data, entry + 24, &(MIB as u32).to_le_bytes());
+        }
+        put(&mut data, MIB, b"metadata");
+        put(&mut data, MIB + 10, &3u16.to_le_bytes());
+        let items = [
+            (VHDX_FILE_PARAMETERS, 0x1000, (MIB as u64).to_le_bytes()),
+            (
+                VHDX_VIRTUAL_DISK_SIZE,
+                0x1008,
+                (2 * MIB as u64).to_le_bytes(),
+            ),
+            (VHDX_LOGICAL_SECTOR_SIZE, 0x1010, 512u64.to_le_bytes()),
+        ];
+        for (index, (item, offset, value)) in items.into_iter().enumerate() {
+            let entry = MIB + 32 + index * 32;
+            put(&mut data, entry, &guid(item));
+            put(&mut data, entry + 16, &(offset as u32).to_le_bytes());
+            put(&mut data, entry + 20, &8u32.to_le_bytes());
+            put(&mut data, MIB + offset, &value);
+        }
+        put(
+            &mut data,
+            2 * MIB,
+            &((3 * MIB as u64) | VHDX_BLOCK_PRESENT).to_le_bytes(),
+        );
+        data.extend(pattern(MIB));
+        data
+    }
+
+    #[test]
+    fn reads_fixed_vhds() {
+        let mut data = pattern(1024);
+        data.extend(vhd_footer(VHD_TYPE_FIXED, 1024, u64::MAX));
+        let file = write(&data);
+        assert!(is_virtual_disk(file.path()));
+        assert_eq!(read_all(file.path()), pattern(1024));
+    }
+
+    #[test]
+    fn reads_dynamic_vhds() {
+        let file = write(&dynamic_vhd(4096));
+        assert!(is_virtual_disk(file.path()));
+        let data = read_all(file.path());
+        assert_eq!(data.len(), 8192);
+        assert_eq!(data[..4096], pattern(4096));
+        assert!(data[4096..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn reads_vhdxs() {
+        let file = write(&vhdx());
+        assert!(is_virtual_disk(file.path()));
+        let mut disk = VirtualDisk::open(file.path()).unwrap();
+        let mut block = vec![0; 4096];
+        disk.read_exact(&mut block).unwrap();
+        assert_eq!(block, pattern(4096));
+        disk.seek(SeekFrom::Start(MIB as u64 + 100)).unwrap();
+        disk.read_exact(&mut block).unwrap();
+        assert!(block.iter().all(|&b| b == 0));
+        assert_eq!(disk.seek(SeekFrom::End(0)).unwrap(), 2 * MIB as u64);
+        assert_eq!(disk.read(&mut block).unwrap(), 0);
+    }
+
+    #[test]
+    fn rejects_truncated_virtual_disks() {
+        assert!(
+            VirtualDisk::open(write(&vhd_footer(VHD_TYPE_FIXED, 1024, 0)[..100]).path()).is_err()
+        );
+        let data = dynamic_vhd(4096);
+        assert!(VirtualDisk::open(write(&data[..1600]).path()).is_err());
+        let data = vhdx();
+        assert!(VirtualDisk::open(write(&data[..200 * 1024]).path()).is_err());
+        assert!(VirtualDisk::open(write(&data[..MIB + 100]).path()).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupt_virtual_disks() {
+        assert!(VirtualDisk::open(write(&dynamic_vhd(0)).path()).is_err());
+        assert!(VirtualDisk::open(write(&vhd_footer(4, 1024, 0)).path()).is_err());
+        let mut data = vhdx();
+        put(&mut data, MIB + 32 + 16, &u32::MAX.to_le_bytes());
+        assert!(VirtualDisk::open(write(&data).path()).is_err());
+        let mut data = vhdx();
+        put(&mut data, MIB + 0x1004, &VHDX_HAS_PARENT.to_le_bytes());
+        assert!(VirtualDisk::open(write(&data).path()).is_err());
+        let mut data = vhdx();
+        put(&mut data, MIB + 10, &u16::MAX.to_le_bytes());
+        assert!(VirtualDisk::open(write(&data).path()).is_err());
+        let mut data = vhdx();
+        let bat_entry = VHDX_REGION_TABLES[0] as usize + 16 + 32;
+        put(&mut data, bat_entry + 24, &8u32.to_le_bytes());
+        assert!(VirtualDisk::open(write(&data).path()).is_err());
+        let mut data = vhdx();
+        put(&mut data, VHDX_REGION_TABLES[0] as usize, b"xxxx");
+        assert!(VirtualDisk::open(write(&data).path()).is_err());
+    }
+}
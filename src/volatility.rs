@@ -0,0 +1,141 @@
+//! Preparing the kernel symbols memory forensics tools such as Volatility 3 need.
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    compress::ExpandedFile,
+    error::Result,
+    pe::RsdsRecord,
+    windows::{Architecture, PdbMeta, SymbolKind, pdb_file_name},
+};
+
+/// PDBs of the kernel and of win32k, which memory forensics tools need to make sense
+/// of a memory image.
+pub const KERNEL_PDBS: &[&str] = &[
+    "ntkrnlmp.pdb",
+    "ntkrnlpa.pdb",
+    "ntkrpamp.pdb",
+    "ntoskrnl.pdb",
+    "win32k.pdb",
+    "win32kbase.pdb",
+    "win32kfull.pdb",
+];
+
+/// Python module of Volatility 3 that converts PDBs into its symbol tables.
+const PDBCONV_MODULE: &str = "volatility3.framework.symbols.windows.pdbconv";
+/// Size of the blocks a memory image is searched in.
+const CHUNK_LEN: usize = 16 * 1024 * 1024;
+/// Bytes of an RSDS record searched for a kernel PDB name: the signature, GUID, age
+/// and the longest name in [`KERNEL_PDBS`] with its NUL.
+const RECORD_LEN: usize = 24 + 16;
+
+/// Returns whether `name` is one of the [`KERNEL_PDBS`].
+pub fn is_kernel_pdb(name: &str) -> bool {
+    KERNEL_PDBS
+        .iter()
+        .any(|kernel| kernel.eq_ignore_ascii_case(name))
+}
+
+/// Searches a raw memory image or crash dump for the RSDS CodeView records of the
+/// [`KERNEL_PDBS`], the way Volatility's `pdbscan` finds the kernel.
+///
+/// Every distinct key found is returned, so images of machines that were updated
+/// without a reboot may yield several.
+pub fn scan_memory_image(path: &Path) -> Result<Vec<PdbMeta>> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; CHUNK_LEN + RECORD_LEN];
+    let mut kept = 0;
+    let mut seen = HashSet::new();
+    let mut pdbs = Vec::new();
+    loop {
+        let read = read_full(&mut file, &mut buffer[kept..])?;
+        let len = kept + read;
+        for start in 0..len.saturating_sub(3) {
+            if &buffer[start..start + 4] != b"RSDS" {
+                continue;
+            }
+            let Ok(record) = RsdsRecord::parse(&buffer[start..len.min(start + RECORD_LEN)]) else {
+                continue;
+            };
+            let name = String::from_utf8_lossy(&record.name);
+            if !is_kernel_pdb(pdb_file_name(&name)) || !seen.insert(record.clone()) {
+                continue;
+            }
+            match PdbMeta::from_record(
+                path,
+                &record,
+                SymbolKind::Pdb,
+                Architecture::Unknown,
+                Vec::new(),
+            ) {
+                Ok(pdb) => {
+                    tracing::info!("Found {} {} in {}", pdb.name, pdb.key(), path.display());
+                    pdbs.push(pdb);
+                }
+                Err(e) => tracing::debug!("Skipping RSDS record: {}", e),
+            }
+        }
+        if read == 0 {
+            break;
+        }
+        // Keep the tail so that records spanning two blocks are found; records seen
+        // twice are skipped.
+        kept = len.min(RECORD_LEN - 1);
+        buffer.copy_within(len - kept..len, 0);
+    }
+    Ok(pdbs)
+}
+
+/// Returns where Volatility 3 looks for the symbol table of `pdb` in its `symbols`
+/// directory: `windows/<name>/<GUID>-<age>.json.xz`.
+pub fn symbol_table_path(symbols: &Path, pdb: &PdbMeta) -> PathBuf {
+    symbols
+        .join("windows")
+        .join(&pdb.name)
+        .join(format!("{}-{}.json.xz", pdb.guid, pdb.age))
+}
+
+/// Converts the PDB at `store_path` in a local store into a Volatility 3 symbol table
+/// at [`symbol_table_path`], using Volatility's own `pdbconv`.
+///
+/// Compressed copies in the store are expanded first. Requires Volatility 3 to be
+/// installed for the Python found as `python3` (`python` on Windows).
+pub fn write_symbol_table(symbols: &Path, pdb: &PdbMeta, store_path: &Path) -> io::Result<PathBuf> {
+    let dest = symbol_table_path(symbols, pdb);
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let source = ExpandedFile::open(store_path)?;
+    let python = if cfg!(windows) { "python" } else { "python3" };
+    let output = Command::new(python)
+        .args(["-m", PDBCONV_MODULE, "-f"])
+        .arg(source.path())
+        .arg("-o")
+        .arg(&dest)
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "pdbconv failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(dest)
+}
+
+/// Fills `buffer` from `file` as far as possible, returning the number of bytes read.
+fn read_full(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match file.read(&mut buffer[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
@@ -1,24 +1,326 @@
+//! Extracting files from Windows imaging (WIM) files via wimlib.
+
+use std::{
+    ffi::{CStr, CString},
+    mem::MaybeUninit,
+    os::raw::{c_char, c_int, c_void},
+    path::{Path, PathBuf},
+    ptr,
+    sync::Once,
+};
 use tracing::info;
-use wimlib::sys::wimlib_open_wim;
+use wimlib::sys::{
+    WIMLIB_EXTRACT_FLAG_NO_ACLS, WIMLIB_EXTRACT_FLAG_NO_PRESERVE_DIR_STRUCTURE,
+    WIMLIB_EXTRACT_FLAG_NORPFIX, WIMLIB_FILE_ATTRIBUTE_DIRECTORY,
+    WIMLIB_INIT_FLAG_DEFAULT_CASE_INSENSITIVE, WIMLIB_ITERATE_DIR_TREE_FLAG_RECURSIVE, WIMStruct,
+    wimlib_dir_entry, wimlib_extract_paths, wimlib_free, wimlib_get_error_string,
+    wimlib_get_image_property, wimlib_get_wim_info, wimlib_global_init, wimlib_iterate_dir_tree,
+    wimlib_open_wim, wimlib_wim_info,
+};
+
+use crate::{
+    error::{Error, Result},
+    windows::{FileFilter, PdbMeta, Windows, in_scan_depth},
+};
+
+/// The WIM format version used by files with solid resources (ESD).
+const SOLID_WIM_VERSION: u32 = 0xE00;
+
+/// Directories extracted from an image before scanning.
+const SYSTEM_DIRECTORIES: &[&str] = &["/Windows/System32", "/Windows/SysWOW64"];
+
+/// An opened WIM or ESD file.
+///
+/// ESD files are WIM files using solid LZMS compression and are read transparently.
+///
+/// Paths passed to the extraction methods are matched case-insensitively like on
+/// Windows and use `/` as separator.
+pub struct Wim {
+    raw: *mut WIMStruct,
+}
+
+/// An image inside a WIM file, e.g. one Windows edition.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WimImage {
+    /// The 1-based image index.
+    pub index: u32,
+    /// The image name, e.g. `Windows 11 Pro`.
+    pub name: Option<String>,
+    /// The image description.
+    pub description: Option<String>,
+    /// The Windows build number, e.g. `22631`.
+    pub build: Option<String>,
+}
+
+/// Selects an image inside a WIM file.
+#[derive(Debug, Clone)]
+pub enum ImageSelector {
+    /// The 1-based image index.
+    Index(u32),
+    /// The image name, compared case-insensitively.
+    Name(String),
+}
+
+// SAFETY: the handle is owned exclusively by this struct and wimlib does not tie it
+// to the thread that opened it.
+unsafe impl Send for Wim {}
 
-use crate::iso::Iso;
+static INIT: Once = Once::new();
 
-pub struct Wim {}
 impl Wim {
-    pub fn new(iso: &Iso, image: u8) -> Result<Self, std::io::Error> {
-        // Placeholder for WIM file opening logic
-        // For now, we just return an empty Wim struct
-        info!("Opening install.wim image {} from ISO", image);
-        let wim = iso
-            .0
-            .open("sources/install.wim")
-            .expect("Failed to open install.wim from ISO")
-            .unwrap();
-        let open_flags = wimlib::sys::WIMLIB_OPEN_FLAG_CHECK_INTEGRITY as i32;
-        let mut wim_ret = std::ptr::null_mut();
-
-        let wimlib =
-            unsafe { wimlib_open_wim(wim.identifier().as_ptr() as *const i8, open_flags, wim_ret) };
-        Ok(Self {})
+    /// Opens the WIM or ESD file at `path`.
+    pub fn open(path: &Path) -> Result<Self> {
+        INIT.call_once(|| {
+            // SAFETY: called once before any other wimlib function.
+            let code =
+                unsafe { wimlib_global_init(WIMLIB_INIT_FLAG_DEFAULT_CASE_INSENSITIVE as i32) };
+            if let Err(e) = check(code) {
+                tracing::warn!("Failed to initialize wimlib: {}", e);
+            }
+        });
+        info!("Opening WIM file: {}", path.display());
+        let path = c_path(path)?;
+        let mut raw = ptr::null_mut();
+        // SAFETY: `path` is a valid NUL-terminated string and `raw` a valid out-pointer.
+        check(unsafe { wimlib_open_wim(path.as_ptr(), 0, &mut raw) })?;
+        let wim = Self { raw };
+        if wim.is_solid()? {
+            info!("WIM file uses solid compression (ESD)");
+        }
+        Ok(wim)
+    }
+
+    /// Returns whether the WIM file contains solid-compressed resources, as ESD files do.
+    pub fn is_solid(&self) -> Result<bool> {
+        Ok(self.info()?.wim_version == SOLID_WIM_VERSION)
+    }
+
+    /// Extracts the PE files in the system directories of an image into a temporary
+    /// directory and scans them for PDB references.
+    ///
+    /// Only files whose names `filter` accepts and that lie within `max_depth` are
+    /// extracted, which with the default filter keeps scratch space to a fraction of
+    /// the full image. The temporary directory is removed afterwards, and the paths of
+    /// the returned PDBs are relative to the image root.
+    pub fn fetch_pdbs(
+        &self,
+        image: &ImageSelector,
+        max_depth: Option<usize>,
+        filter: &FileFilter,
+    ) -> Result<Vec<PdbMeta>> {
+        let scratch = tempfile::tempdir()?;
+        let root = scratch.path();
+        let windows = self.extract_system_directories(image, root, max_depth, filter)?;
+        let mut pdbs = Windows::new(windows)
+            .with_max_depth(max_depth)
+            .with_filter(filter.clone())
+            .fetch_all_pdbs()?;
+        for pdb in &mut pdbs {
+            if let Ok(relative) = pdb.path.strip_prefix(root) {
+                pdb.path = relative.to_path_buf();
+            }
+        }
+        Ok(pdbs)
+    }
+
+    /// Extracts the files in the system directories of an image that `filter` accepts
+    /// into `root`, and returns the path of the `Windows` directory below it.
+    ///
+    /// Only the files a scan with `max_depth` reaches are extracted, see
+    /// [`Windows::with_max_depth`].
+    pub fn extract_system_directories(
+        &self,
+        image: &ImageSelector,
+        root: &Path,
+        max_depth: Option<usize>,
+        filter: &FileFilter,
+    ) -> Result<PathBuf> {
+        let image = self.select_image(image)?;
+        info!("Extracting WIM image {}", image);
+        let mut files = Vec::new();
+        for dir in SYSTEM_DIRECTORIES {
+            match self.list_files(image, dir) {
+                Ok(listed) => files.extend(listed.into_iter().filter(|file| {
+                    // Listed paths start with `dir`, though not necessarily in its case.
+                    let path = Path::new(file);
+                    let relative: PathBuf = path
+                        .components()
+                        .skip(Path::new(dir).components().count())
+                        .collect();
+                    in_scan_depth(&relative, max_depth) && filter.accepts_name(path)
+                })),
+                Err(e) => tracing::warn!("Skipping {} in WIM image {}: {}", dir, image, e),
+            }
+        }
+        let files: Vec<&str> = files.iter().map(String::as_str).collect();
+        self.extract_paths(image, &files, root)?;
+        Ok(root.join("Windows"))
+    }
+
+    /// Returns the number of images in the WIM file.
+    pub fn image_count(&self) -> Result<u32> {
+        Ok(self.info()?.image_count)
+    }
+
+    fn info(&self) -> Result<wimlib_wim_info> {
+        let mut info = MaybeUninit::<wimlib_wim_info>::zeroed();
+        // SAFETY: `self.raw` is a valid WIM handle and `info` is writable.
+        check(unsafe { wimlib_get_wim_info(self.raw, info.as_mut_ptr()) })?;
+        // SAFETY: initialized by the successful call above.
+        Ok(unsafe { info.assume_init() })
+    }
+
+    /// Lists the images in the WIM file.
+    pub fn images(&self) -> Result<Vec<WimImage>> {
+        (1..=self.image_count()?)
+            .map(|index| {
+                Ok(WimImage {
+                    index,
+                    name: self.image_property(index, "NAME")?,
+                    description: self.image_property(index, "DESCRIPTION")?,
+                    build: self.image_property(index, "WINDOWS/VERSION/BUILD")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Resolves an [`ImageSelector`] to a 1-based image index.
+    pub fn select_image(&self, selector: &ImageSelector) -> Result<u32> {
+        match selector {
+            ImageSelector::Index(index) => Ok(*index),
+            ImageSelector::Name(name) => self
+                .images()?
+                .into_iter()
+                .find(|image| {
+                    image
+                        .name
+                        .as_deref()
+                        .is_some_and(|n| n.eq_ignore_ascii_case(name))
+                })
+                .map(|image| image.index)
+                .ok_or_else(|| Error::ImageNotFound(name.clone())),
+        }
+    }
+
+    fn image_property(&self, image: u32, property: &str) -> Result<Option<String>> {
+        let property = CString::new(property).expect("property names contain no NUL bytes");
+        // SAFETY: `self.raw` is a valid WIM handle and `image` is in range.
+        let value = unsafe { wimlib_get_image_property(self.raw, image as i32, property.as_ptr()) };
+        if value.is_null() {
+            return Ok(None);
+        }
+        // SAFETY: wimlib returns a NUL-terminated string owned by the WIM handle.
+        let value = unsafe { CStr::from_ptr(value) };
+        Ok(Some(value.to_string_lossy().into_owned()))
+    }
+
+    /// Extracts the given paths of the 1-based `image` below `target`, keeping their
+    /// directory structure. The paths are taken literally and must exist in the image.
+    pub fn extract_paths(&self, image: u32, paths: &[&str], target: &Path) -> Result<()> {
+        info!("Extracting {:?} from WIM image {}", paths, image);
+        self.extract(image, paths, target, 0)
+    }
+
+    /// Lists the full paths of all files below `dir` in the 1-based `image`.
+    pub fn list_files(&self, image: u32, dir: &str) -> Result<Vec<String>> {
+        unsafe extern "C" fn collect(entry: *const wimlib_dir_entry, ctx: *mut c_void) -> c_int {
+            // SAFETY: wimlib passes a valid entry and the `files` vector given below.
+            let (entry, files) = unsafe { (&*entry, &mut *(ctx as *mut Vec<String>)) };
+            if entry.attributes & WIMLIB_FILE_ATTRIBUTE_DIRECTORY == 0 {
+                // SAFETY: `full_path` is a NUL-terminated string valid during the callback.
+                let path = unsafe { CStr::from_ptr(entry.full_path) };
+                files.push(path.to_string_lossy().into_owned());
+            }
+            0
+        }
+
+        let dir = CString::new(dir).map_err(|_| Error::MissingInImage(dir.to_string()))?;
+        let mut files: Vec<String> = Vec::new();
+        // SAFETY: `self.raw` is a valid WIM handle, `dir` outlives the call and the
+        // callback only accesses `files` through the context pointer.
+        check(unsafe {
+            wimlib_iterate_dir_tree(
+                self.raw,
+                image as i32,
+                dir.as_ptr(),
+                WIMLIB_ITERATE_DIR_TREE_FLAG_RECURSIVE as i32,
+                Some(collect),
+                &mut files as *mut Vec<String> as *mut c_void,
+            )
+        })?;
+        Ok(files)
+    }
+
+    /// Reads a single file of the 1-based `image` into memory.
+    pub fn read_file(&self, image: u32, path: &str) -> Result<Vec<u8>> {
+        let scratch = tempfile::tempdir()?;
+        self.extract(
+            image,
+            &[path],
+            scratch.path(),
+            WIMLIB_EXTRACT_FLAG_NO_PRESERVE_DIR_STRUCTURE as i32,
+        )?;
+        let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+        std::fs::read(scratch.path().join(name))
+            .map_err(|_| Error::MissingInImage(path.to_string()))
+    }
+
+    fn extract(&self, image: u32, paths: &[&str], target: &Path, extra_flags: i32) -> Result<()> {
+        let image_count = self.image_count()?;
+        if image == 0 || image > image_count {
+            return Err(Error::InvalidImage {
+                image,
+                count: image_count,
+            });
+        }
+        std::fs::create_dir_all(target)?;
+        let target = c_path(target)?;
+        let paths = paths
+            .iter()
+            .map(|path| {
+                CString::new(*path).map_err(|e| {
+                    Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let path_ptrs: Vec<*const c_char> = paths.iter().map(|path| path.as_ptr()).collect();
+        let flags =
+            (WIMLIB_EXTRACT_FLAG_NO_ACLS | WIMLIB_EXTRACT_FLAG_NORPFIX) as i32 | extra_flags;
+        // SAFETY: `self.raw` is a valid WIM handle and all strings outlive the call.
+        check(unsafe {
+            wimlib_extract_paths(
+                self.raw,
+                image as i32,
+                target.as_ptr(),
+                path_ptrs.as_ptr(),
+                path_ptrs.len(),
+                flags,
+            )
+        })
+    }
+}
+
+impl Drop for Wim {
+    fn drop(&mut self) {
+        // SAFETY: `self.raw` came from `wimlib_open_wim` and is freed exactly once.
+        unsafe { wimlib_free(self.raw) };
+    }
+}
+
+fn c_path(path: &Path) -> Result<CString> {
+    CString::new(path.to_string_lossy().as_bytes())
+        .map_err(|e| Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)))
+}
+
+/// Converts a wimlib return code into a [`Result`].
+fn check(code: i32) -> Result<()> {
+    if code == 0 {
+        return Ok(());
     }
+    // SAFETY: wimlib returns a pointer to a static NUL-terminated string for any code.
+    let message = unsafe { CStr::from_ptr(wimlib_get_error_string(code as _)) };
+    Err(Error::Wim {
+        code,
+        message: message.to_string_lossy().into_owned(),
+    })
 }
@@ -1,170 +1,1057 @@
-use exe::{Buffer, Castable, DebugDirectory, VecPE};
+//! Scanning a Windows installation for binaries and their PDB keys.
+
+use exe::{Buffer, NTHeaders, PE};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use rayon::prelude::*;
 use std::{
+    collections::VecDeque,
     fs,
     path::{Path, PathBuf},
-    thread,
-    time::Duration,
+    sync::Arc,
+};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    authenticode::{SignerFilter, embedded_signer},
+    error::{Error, Result},
+    hash::{FileHashes, HashOptions},
+    macho::{self, DSYM_KEY_PREFIX, is_macho},
+    pe::{
+        DebugEntry, DebugType, MiscRecord, PeImage, RsdsRecord, arm64x_debug_entries,
+        debug_entries, is_pe_file,
+    },
 };
 
+/// A Windows installation rooted at a directory containing `System32`.
 pub struct Windows {
     path: PathBuf,
+    max_depth: Option<usize>,
+    filter: FileFilter,
 }
 
-#[derive(Debug)]
+/// Decides which files found in directories are scanned.
+///
+/// By default files with a known PE extension (dll, exe, sys, drv, cpl, mui, ocx) are
+/// scanned.
+#[derive(Debug, Clone)]
+pub struct FileFilter {
+    all_files: bool,
+    extensions: Vec<String>,
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+    images: bool,
+    hashes: Option<HashOptions>,
+    signers: Option<Arc<SignerFilter>>,
+}
+
+/// The symbol server key of a PDB referenced by a binary, or of another file a symbol
+/// server stores, such as the binary itself.
+#[derive(Debug, Clone)]
 pub struct PdbMeta {
+    /// The binary the PDB key was read from.
+    pub path: PathBuf,
+    /// The PDB file name, e.g. `ntdll.pdb`.
     pub name: String,
+    /// The PDB signature GUID as 32 uppercase hex digits; for images and DBG files,
+    /// the `TimeDateStamp` of the file header as 8 uppercase hex digits.
     pub guid: String,
+    /// The PDB age; for images and DBG files, the `SizeOfImage` of the optional
+    /// header.
     pub age: u32,
+    /// The kind of file the key refers to.
+    pub kind: SymbolKind,
+    /// The architecture of the binary.
+    pub arch: Architecture,
+    /// All entries of the binary's debug directory, e.g. CodeView, POGO and REPRO.
+    pub debug_entries: Vec<DebugEntry>,
+    /// The signer of the Authenticode signature embedded in the binary, e.g.
+    /// `CN=Microsoft Windows, O=Microsoft Corporation`.
+    pub signer: Option<String>,
+    /// Hashes of the binary, taken while it was scanned, see
+    /// [`FileFilter::with_hashes`].
+    pub binary_hashes: Option<FileHashes>,
 }
 
-#[repr(C, packed)]
-struct DDRaw {
-    magic: [u8; 4],
-    guid: [u8; 16],
-    age: u32,
-    name: [u8; 255],
+/// The kinds of files stored on symbol servers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymbolKind {
+    /// A PDB, keyed by its GUID and age.
+    Pdb,
+    /// A portable PDB of a .NET assembly, keyed by its GUID in the lowercase SSQP style.
+    PortablePdb,
+    /// An executable image, keyed by its timestamp and image size.
+    Image,
+    /// A `.dbg` file split off an older binary, keyed like the binary.
+    Dbg,
+    /// The DWARF file of the dSYM bundle of a Mach-O binary, keyed by the binary's
+    /// UUID in the SSQP style.
+    Dsym,
 }
 
-unsafe impl Castable for DDRaw {}
+/// The machine type a binary was built for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Architecture {
+    X86,
+    X64,
+    Arm,
+    Arm64,
+    Unknown,
+}
 
 const MIN_PDB_NAME_LEN: usize = 4;
+/// Length of a PDB GUID in a symbol server key.
+const GUID_HEX_LEN: usize = 32;
+/// Length of an image timestamp in a symbol server key.
+const TIMESTAMP_HEX_LEN: usize = 8;
 const ALLOWED_EXTENSIONS: &[&str] = &["dll", "exe", "sys", "drv", "cpl", "mui", "ocx"];
+/// System directory subdirectories scanned recursively when no maximum depth is given.
+const DEFAULT_SUBDIRECTORIES: &[&str] = &["drivers", "DriverStore/FileRepository", "wbem"];
+/// Number of files the lazy scans, such as [`scan_walk`], read in parallel at a time.
+pub const SCAN_BATCH_LEN: usize = 64;
+/// Number of scanned keys [`scan_stream`] buffers ahead of its consumer.
+pub const SCAN_STREAM_CAPACITY: usize = 256;
 
 impl Windows {
+    /// Creates a scanner for the Windows installation at `path`.
     pub fn new(path: PathBuf) -> Self {
         tracing::info!("Creating Windows instance with path: {}", path.display());
-        Self { path }
+        Self {
+            path,
+            max_depth: None,
+            filter: FileFilter::default(),
+        }
     }
 
+    /// Selects the files to scan with `filter` instead of by their extension.
+    pub fn with_filter(mut self, filter: FileFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Scans all of System32 recursively, descending at most `max_depth` directories.
+    ///
+    /// Without a maximum depth the top level of System32 is scanned together with the
+    /// `drivers`, `DriverStore\FileRepository` and `wbem` subdirectories.
+    pub fn with_max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Returns the root of the Windows installation.
     pub fn get_path(&self) -> &Path {
         &self.path
     }
 
+    /// Fetches PDB metadata from files in System32 and, if present, SysWOW64.
+    ///
+    /// With [`FileFilter::with_images`], the image keys of the files are included.
+    pub fn fetch_all_pdbs(&self) -> Result<Vec<PdbMeta>> {
+        self.pdbs().collect()
+    }
+
+    /// Lazily scans the files in System32 and, if present, SysWOW64, yielding their
+    /// PDB keys as the directories are walked, so downloads can start before the scan
+    /// finishes. See [`Windows::fetch_all_pdbs`] for the keys yielded.
+    ///
+    /// Files that cannot be parsed or carry no PDB reference are logged and skipped;
+    /// directories that cannot be read are yielded as errors.
+    pub fn pdbs(&self) -> impl Iterator<Item = Result<PdbMeta>> + Send + use<> {
+        scan_walk(self.walk_files(), self.filter.clone())
+    }
+
+    /// Scans like [`Windows::pdbs`] on a blocking thread and returns the keys as an
+    /// async stream. Must be called from within a Tokio runtime.
+    pub fn pdb_stream(&self) -> ReceiverStream<Result<PdbMeta>> {
+        scan_stream(self.pdbs())
+    }
+
+    /// Lists the files in System32 and, if present, SysWOW64 that would be scanned by
+    /// [`Windows::fetch_all_pdbs`].
+    pub fn list_files(&self) -> Result<Vec<PathBuf>> {
+        self.walk_files().collect()
+    }
+
+    /// Lazily walks the files in System32 and, if present, SysWOW64 that would be
+    /// scanned by [`Windows::fetch_all_pdbs`].
+    pub fn walk_files(&self) -> FileWalker {
+        let mut walker = FileWalker::new(self.filter.clone());
+        self.push_system_dir(&mut walker, "System32");
+        if self.path.join("SysWOW64").is_dir() {
+            self.push_system_dir(&mut walker, "SysWOW64");
+        } else {
+            tracing::info!("No SysWOW64 directory in: {}", self.path.display());
+        }
+        walker
+    }
+
     /// Fetches PDB metadata from files in the System32 directory.
-    pub fn fetch_system32_pdbs(&self) -> Result<Vec<PdbMeta>, std::io::Error> {
-        tracing::info!("Fetching system32 PDBs from: {}", self.path.display());
-        let files = self.get_files_in_system32()?;
-        let pdbs = files
-            .into_iter()
-            .filter_map(|file| match self.get_hash_and_pdb_name(&file) {
-                Some(pdb) => Some(pdb),
+    ///
+    /// Files that cannot be parsed or carry no PDB reference are logged and skipped.
+    pub fn fetch_system32_pdbs(&self) -> Result<Vec<PdbMeta>> {
+        self.fetch_pdbs_in("System32")
+    }
+
+    /// Fetches PDB metadata from the 32-bit binaries in the SysWOW64 directory.
+    pub fn fetch_syswow64_pdbs(&self) -> Result<Vec<PdbMeta>> {
+        self.fetch_pdbs_in("SysWOW64")
+    }
+
+    fn fetch_pdbs_in(&self, system_dir: &str) -> Result<Vec<PdbMeta>> {
+        tracing::info!("Fetching {} PDBs from: {}", system_dir, self.path.display());
+        let mut walker = FileWalker::new(self.filter.clone());
+        self.push_system_dir(&mut walker, system_dir);
+        Ok(scan_files(walker.collect::<Result<_>>()?))
+    }
+
+    fn push_system_dir(&self, walker: &mut FileWalker, system_dir: &str) {
+        let system_path = self.path.join(system_dir);
+        tracing::info!("Listing files in {}: {}", system_dir, system_path.display());
+
+        match self.max_depth {
+            Some(max_depth) => walker.push_dir(system_path, Some(max_depth)),
+            None => {
+                walker.push_dir(system_path.clone(), Some(0));
+                for subdirectory in DEFAULT_SUBDIRECTORIES {
+                    let path = system_path.join(subdirectory);
+                    if path.is_dir() {
+                        walker.push_dir(path, None);
+                    } else {
+                        tracing::debug!("Skipping missing directory: {}", path.display());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns whether the file at `relative`, a path below System32 or SysWOW64, is in a
+/// directory [`Windows`] scans with `max_depth`, see [`Windows::with_max_depth`].
+///
+/// Used to pick the files to extract from images before they are walked. Directory
+/// names are compared case-insensitively.
+pub(crate) fn in_scan_depth(relative: &Path, max_depth: Option<usize>) -> bool {
+    let depth = relative.components().count().saturating_sub(1);
+    match max_depth {
+        Some(max_depth) => depth <= max_depth,
+        None => {
+            depth == 0
+                || DEFAULT_SUBDIRECTORIES.iter().any(|subdirectory| {
+                    let subdirectory = Path::new(subdirectory);
+                    let len = subdirectory.components().count();
+                    depth >= len
+                        && relative
+                            .components()
+                            .zip(subdirectory.components())
+                            .all(|(a, b)| a.as_os_str().eq_ignore_ascii_case(b.as_os_str()))
+                })
+        }
+    }
+}
+
+/// A file or directory tree a [`FileWalker`] still has to walk.
+#[derive(Debug)]
+enum Root {
+    /// A file given explicitly, yielded whatever the filter says.
+    File(PathBuf),
+    /// A directory, with the number of levels to descend below it, if limited.
+    Dir(PathBuf, Option<usize>),
+}
+
+/// Lazily walks directory trees, yielding the files accepted by a [`FileFilter`].
+///
+/// Directories are read one at a time as the iterator advances, so scanning can start
+/// before the walk finishes and memory does not grow with the size of the tree.
+/// Only a root directory that cannot be read is an error; unreadable directories and
+/// entries below it are logged and skipped.
+/// Created by [`Windows::walk_files`] and [`walk_paths`].
+#[derive(Debug)]
+pub struct FileWalker {
+    filter: FileFilter,
+    roots: VecDeque<Root>,
+    /// The open directories of the current root, innermost last.
+    stack: Vec<(fs::ReadDir, Option<usize>)>,
+}
+
+impl FileWalker {
+    fn new(filter: FileFilter) -> Self {
+        Self {
+            filter,
+            roots: VecDeque::new(),
+            stack: Vec::new(),
+        }
+    }
+
+    fn push_dir(&mut self, path: PathBuf, depth: Option<usize>) {
+        self.roots.push_back(Root::Dir(path, depth));
+    }
+
+    fn push_file(&mut self, path: PathBuf) {
+        self.roots.push_back(Root::File(path));
+    }
+}
+
+impl Iterator for FileWalker {
+    type Item = Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some((entries, depth)) = self.stack.last_mut() else {
+                match self.roots.pop_front()? {
+                    Root::File(path) => return Some(Ok(path)),
+                    Root::Dir(path, depth) => match fs::read_dir(&path) {
+                        Ok(entries) => self.stack.push((entries, depth)),
+                        Err(e) => return Some(Err(e.into())),
+                    },
+                }
+                continue;
+            };
+            let depth = *depth;
+            let entry = match entries.next() {
+                Some(Ok(entry)) => entry,
+                Some(Err(e)) => {
+                    tracing::warn!("Skipping unreadable directory entry: {}", e);
+                    continue;
+                }
                 None => {
-                    tracing::warn!("No PDB found for file: {}", file.display());
-                    None
+                    self.stack.pop();
+                    continue;
                 }
+            };
+            let path = entry.path();
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(e) => {
+                    tracing::warn!("Skipping {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            if file_type.is_dir() {
+                let depth = match depth {
+                    Some(0) => continue,
+                    Some(depth) => Some(depth - 1),
+                    None => None,
+                };
+                match fs::read_dir(&path) {
+                    Ok(entries) => self.stack.push((entries, depth)),
+                    Err(e) => tracing::warn!("Skipping directory {}: {}", path.display(), e),
+                }
+            } else if file_type.is_file() && self.filter.accepts(&path) {
+                tracing::debug!("File accepted: {}", path.display());
+                return Some(Ok(path));
+            }
+        }
+    }
+}
+
+impl Default for FileFilter {
+    fn default() -> Self {
+        Self {
+            all_files: false,
+            extensions: ALLOWED_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+            include: None,
+            exclude: None,
+            images: false,
+            hashes: None,
+            signers: None,
+        }
+    }
+}
+
+impl FileFilter {
+    /// Also returns the image key of every scanned binary, so the binaries themselves
+    /// are fetched along with their PDBs.
+    pub fn with_images(mut self, images: bool) -> Self {
+        self.images = images;
+        self
+    }
+
+    /// Returns whether image keys are returned, see [`FileFilter::with_images`].
+    pub fn images(&self) -> bool {
+        self.images
+    }
+
+    /// Hashes every scanned binary that yields a key, once however many keys it
+    /// yields, and records the hashes in its keys. Binaries are hashed while they are
+    /// scanned, before those extracted from containers are deleted again.
+    pub fn with_hashes(mut self, options: Option<HashOptions>) -> Self {
+        self.hashes = options;
+        self
+    }
+
+    /// Returns how scanned binaries are hashed, see [`FileFilter::with_hashes`].
+    pub fn hashes(&self) -> Option<HashOptions> {
+        self.hashes
+    }
+
+    /// Replaces the extensions of the files to scan, e.g. `["sys", "efi"]`.
+    pub fn with_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// Adds extensions of files to scan to the current ones.
+    pub fn with_extra_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.extensions.extend(extensions);
+        self
+    }
+
+    /// Only scans files whose path matches one of the glob `patterns`, e.g.
+    /// `**/drivers/*`. Patterns are matched case-insensitively against the whole path,
+    /// and `*` also matches path separators.
+    pub fn with_include_globs(mut self, patterns: &[String]) -> Result<Self> {
+        self.include = glob_set(patterns)?;
+        Ok(self)
+    }
+
+    /// Skips files whose path matches one of the glob `patterns`, matched like
+    /// [`FileFilter::with_include_globs`].
+    pub fn with_exclude_globs(mut self, patterns: &[String]) -> Result<Self> {
+        self.exclude = glob_set(patterns)?;
+        Ok(self)
+    }
+
+    /// Scans every file that is a PE image, detected by its MZ and PE headers, instead
+    /// of only files with a known extension.
+    pub fn with_all_files(mut self, all_files: bool) -> Self {
+        self.all_files = all_files;
+        self
+    }
+
+    /// Only scans binaries whose Authenticode signer is accepted by `signers`, e.g.
+    /// those signed by Microsoft.
+    pub fn with_signers(mut self, signers: SignerFilter) -> Self {
+        self.signers = Some(Arc::new(signers));
+        self
+    }
+
+    /// Returns whether the file at `path` should be scanned.
+    pub fn accepts(&self, path: &Path) -> bool {
+        self.accepts_name(path)
+            && (!self.all_files || is_pe_file(path) || is_macho(path))
+            && self
+                .signers
+                .as_ref()
+                .is_none_or(|signers| signers.accepts(path))
+    }
+
+    /// Returns whether a file named `path` may be scanned, judging only by its name.
+    ///
+    /// Used to pick the files to extract from images before their contents can be read.
+    pub fn accepts_name(&self, path: &Path) -> bool {
+        (self.all_files || self.has_extension(path))
+            && self
+                .include
+                .as_ref()
+                .is_none_or(|include| include.is_match(path))
+            && !self
+                .exclude
+                .as_ref()
+                .is_some_and(|exclude| exclude.is_match(path))
+    }
+
+    /// Returns whether the file has one of the extensions to scan.
+    fn has_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                self.extensions
+                    .iter()
+                    .any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(ext))
             })
-            .collect();
-        Ok(pdbs)
-    }
-
-    fn get_files_in_system32(&self) -> Result<Vec<PathBuf>, std::io::Error> {
-        let system32_path = self.path.join("System32");
-        tracing::info!("Listing files in System32: {}", system32_path.display());
-
-        fs::read_dir(system32_path)?
-            .filter_map(|entry_result| match entry_result {
-                Ok(entry) => {
-                    let path = entry.path();
-                    if path
-                        .extension()
-                        .and_then(|ext| ext.to_str())
-                        .map(Self::is_allowed_extension)
-                        .unwrap_or(false)
-                    {
-                        tracing::debug!("File accepted: {}", path.display());
-                        Some(Ok(path))
+    }
+}
+
+/// Compiles case-insensitive glob patterns, or returns `None` if there are none.
+pub(crate) fn glob_set(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(GlobBuilder::new(pattern).case_insensitive(true).build()?);
+    }
+    Ok(Some(builder.build()?))
+}
+
+impl PdbMeta {
+    /// Reads the PDB key from the RSDS CodeView entry of a PE file's debug directory.
+    ///
+    /// For ARM64X images this is the PDB of the native ARM64 view, see
+    /// [`PdbMeta::all_from_file`].
+    pub fn from_file(file: &Path) -> Result<Self> {
+        let mut pdbs = Self::all_from_file(file)?;
+        Ok(pdbs.swap_remove(0))
+    }
+
+    /// Reads the keys of all distinct PDBs referenced by RSDS CodeView entries of a PE
+    /// file, including those of the x64 view of ARM64X images, and of the `.dbg` files
+    /// named by MISC entries.
+    ///
+    /// Mach-O binaries yield the keys of the dSYMs of their architectures instead, see
+    /// [`macho::dsym_keys`].
+    ///
+    /// Returns at least one key or an error.
+    pub fn all_from_file(file: &Path) -> Result<Vec<Self>> {
+        if is_macho(file) {
+            return macho::dsym_keys(file);
+        }
+        let pe_image = PeImage::open(file)?;
+        let image = pe_image.image();
+        let pe_error = |source| Error::Pe {
+            path: file.to_path_buf(),
+            source,
+        };
+        let mut views = vec![debug_entries(&image).map_err(pe_error)?];
+        if let Some(entries) = arm64x_debug_entries(&image) {
+            tracing::debug!("Found x64 view of ARM64X image: {}", file.display());
+            views.push(entries);
+        }
+        let arch = Architecture::of(&image);
+        let signer = embedded_signer(pe_image.as_slice());
+
+        let mut pdbs: Vec<Self> = Vec::new();
+        let mut failure = None;
+        for entries in views {
+            let records: Vec<(RsdsRecord, SymbolKind)> = entries
+                .iter()
+                .filter(|entry| entry.kind == DebugType::CodeView)
+                .filter_map(|entry| {
+                    let parsed = entry
+                        .data(image.as_slice())
+                        .ok_or("CodeView record outside of the file")
+                        .and_then(RsdsRecord::parse);
+                    let kind = if entry.is_portable_pdb() {
+                        SymbolKind::PortablePdb
                     } else {
-                        None
-                    }
+                        SymbolKind::Pdb
+                    };
+                    parsed
+                        .map(|record| (record, kind))
+                        .map_err(|reason| failure = Some(reason))
+                        .ok()
+                })
+                .collect();
+            for (record, kind) in records {
+                let mut pdb = Self::from_record(file, &record, kind, arch, entries.clone())?;
+                pdb.signer = signer.clone();
+                if !pdbs.iter().any(|other| {
+                    other.name == pdb.name && other.guid == pdb.guid && other.age == pdb.age
+                }) {
+                    pdbs.push(pdb);
                 }
-                Err(e) => Some(Err(e)),
-            })
-            .collect()
+            }
+            let dbgs = entries
+                .iter()
+                .filter(|entry| entry.kind == DebugType::Misc)
+                .filter_map(|entry| MiscRecord::parse(entry.data(image.as_slice())?).ok());
+            for record in dbgs {
+                let (timestamp, size_of_image) = image_key(&image).map_err(pe_error)?;
+                let name = dbg_file_name(&record.name);
+                tracing::debug!("DBG Name: {}, from MISC entry {}", name, record.name);
+                if !pdbs.iter().any(|other| other.name == name) {
+                    pdbs.push(PdbMeta {
+                        path: file.to_path_buf(),
+                        name,
+                        guid: format!("{:08X}", timestamp),
+                        age: size_of_image,
+                        kind: SymbolKind::Dbg,
+                        arch,
+                        debug_entries: entries.clone(),
+                        signer: signer.clone(),
+                        binary_hashes: None,
+                    });
+                }
+            }
+        }
+        match failure {
+            _ if !pdbs.is_empty() => Ok(pdbs),
+            Some(reason) => Err(Error::InvalidCodeView {
+                path: file.to_path_buf(),
+                reason: reason.to_string(),
+            }),
+            None => Err(Error::NoDebugInfo(file.to_path_buf())),
+        }
     }
 
-    fn is_allowed_extension(ext: &str) -> bool {
-        ALLOWED_EXTENSIONS
-            .iter()
-            .any(|allowed| allowed.eq_ignore_ascii_case(ext))
+    /// Reads the image key of a PE file: the `TimeDateStamp` of its file header and its
+    /// `SizeOfImage`, under which symbol servers store the binary itself.
+    pub fn image_from_file(file: &Path) -> Result<Self> {
+        let pe_image = PeImage::open(file)?;
+        let image = pe_image.image();
+        let (timestamp, size_of_image) = image_key(&image).map_err(|source| Error::Pe {
+            path: file.to_path_buf(),
+            source,
+        })?;
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(PdbMeta {
+            path: file.to_path_buf(),
+            name,
+            guid: format!("{:08X}", timestamp),
+            age: size_of_image,
+            kind: SymbolKind::Image,
+            arch: Architecture::of(&image),
+            debug_entries: Vec::new(),
+            signer: embedded_signer(pe_image.as_slice()),
+            binary_hashes: None,
+        })
     }
 
-    fn get_hash_and_pdb_name(&self, file: &Path) -> Option<PdbMeta> {
-        let image = VecPE::from_file(exe::PEType::Disk, file).ok()?;
-        let dir = DebugDirectory::parse(&image).ok()?;
-        let dd = image
-            .get_ref::<DDRaw>(dir.pointer_to_raw_data.into())
-            .ok()?;
+    /// Builds the key of a file from its name and symbol server key, e.g. `ntdll.pdb`
+    /// and `1EB9FACB04C73C5DE79D35E14B2FDC231`, as found in manifests and key lists.
+    ///
+    /// The kind is derived from the shape of the key and the extension of the name.
+    /// `source` is recorded as the path the key was read from. Returns `None` for keys
+    /// that are not valid hex, and for names that are not a plain file name (with a
+    /// directory part, or `.` and `..`), which would escape the store directory.
+    pub fn from_key(name: &str, key: &str, source: &Path) -> Option<Self> {
+        if name.is_empty() || name == "." || name == ".." || pdb_file_name(name) != name {
+            return None;
+        }
+        if let Some(uuid) = key.strip_prefix(DSYM_KEY_PREFIX)
+            && uuid.len() == GUID_HEX_LEN
+            && uuid.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Some(PdbMeta {
+                path: source.to_path_buf(),
+                name: name.to_string(),
+                guid: uuid.to_ascii_uppercase(),
+                age: 0,
+                kind: SymbolKind::Dsym,
+                arch: Architecture::Unknown,
+                debug_entries: Vec::new(),
+                signer: None,
+                binary_hashes: None,
+            });
+        }
+        if !key.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let lower = name.to_ascii_lowercase();
+        let (guid, age, kind) = if key.len() > GUID_HEX_LEN {
+            let (guid, age) = key.split_at(GUID_HEX_LEN);
+            // Ages of Windows PDBs are hex but never reach u32::MAX, so this is only
+            // ever a portable PDB.
+            if age.eq_ignore_ascii_case("ffffffff") {
+                (guid.to_ascii_uppercase(), u32::MAX, SymbolKind::PortablePdb)
+            } else {
+                (
+                    guid.to_ascii_uppercase(),
+                    u32::from_str_radix(age, 16).ok()?,
+                    SymbolKind::Pdb,
+                )
+            }
+        } else if key.len() > TIMESTAMP_HEX_LEN {
+            let (timestamp, size) = key.split_at(TIMESTAMP_HEX_LEN);
+            let kind = if lower.ends_with(".dbg") {
+                SymbolKind::Dbg
+            } else {
+                SymbolKind::Image
+            };
+            (
+                timestamp.to_ascii_uppercase(),
+                u32::from_str_radix(size, 16).ok()?,
+                kind,
+            )
+        } else {
+            return None;
+        };
+        Some(PdbMeta {
+            path: source.to_path_buf(),
+            name: name.to_string(),
+            guid,
+            age,
+            kind,
+            arch: Architecture::Unknown,
+            debug_entries: Vec::new(),
+            signer: None,
+            binary_hashes: None,
+        })
+    }
 
-        let debug_name = extract_debug_name(&dd.name)?;
+    /// Returns the symbol server key of the file: the GUID and the age in hex for PDBs, the
+    /// lowercase GUID followed by `ffffffff` for portable PDBs, or the timestamp and
+    /// image size in hex for images and DBG files, or `mach-uuid-sym-` followed by the
+    /// lowercase UUID for dSYMs.
+    pub fn key(&self) -> String {
+        match self.kind {
+            SymbolKind::Pdb => format!("{}{:X}", self.guid, self.age),
+            SymbolKind::PortablePdb => format!("{}ffffffff", self.guid.to_lowercase()),
+            SymbolKind::Image | SymbolKind::Dbg => format!("{}{:x}", self.guid, self.age),
+            SymbolKind::Dsym => format!("{}{}", DSYM_KEY_PREFIX, self.guid.to_lowercase()),
+        }
+    }
+
+    /// Builds the key of the PDB named by an RSDS CodeView record read from `file`.
+    pub(crate) fn from_record(
+        file: &Path,
+        record: &RsdsRecord,
+        kind: SymbolKind,
+        arch: Architecture,
+        debug_entries: Vec<DebugEntry>,
+    ) -> Result<Self> {
+        let invalid = |reason: &str| Error::InvalidCodeView {
+            path: file.to_path_buf(),
+            reason: reason.to_string(),
+        };
+        let debug_path = extract_debug_name(&record.name).unwrap_or_else(|| {
+            let decoded = decode_windows_1252(&record.name);
+            tracing::warn!(
+                "PDB name in {} is not UTF-8, decoded as Windows-1252: {}",
+                file.display(),
+                decoded
+            );
+            decoded
+        });
+        let mut debug_name = pdb_file_name(&debug_path).to_string();
+        if kind == SymbolKind::PortablePdb {
+            // SSQP keys, used by the .NET symbol servers, are lowercase.
+            debug_name = debug_name.to_lowercase();
+        }
+        if debug_name != debug_path {
+            tracing::debug!("Using {} from PDB path {}", debug_name, debug_path);
+        }
         if debug_name.len() < MIN_PDB_NAME_LEN {
-            tracing::warn!("PDB name too short in file: {}", file.display());
-            return None;
+            return Err(invalid("PDB name too short"));
         }
-        let age = dd.age;
+        let guid = encode_guid(&record.guid);
 
         tracing::debug!(
-            "Debug Name: {}, Debug GUID: {}, Debug Age: {}",
+            "Debug Name: {}, Debug GUID: {}, Debug Age: {}, Arch: {:?}",
             debug_name,
-            encode_guid(&dd.guid),
-            age
+            guid,
+            record.age,
+            arch
         );
 
-        Some(PdbMeta {
+        Ok(PdbMeta {
+            path: file.to_path_buf(),
             name: debug_name,
-            guid: encode_guid(&dd.guid),
-            age: dd.age,
+            guid,
+            age: record.age,
+            kind,
+            arch,
+            debug_entries,
+            signer: None,
+            binary_hashes: None,
         })
     }
 }
 
-impl PdbMeta {
-    /// Downloads the PDB file via a retrying http request.
-    pub fn download(&self) -> Option<Vec<u8>> {
-        let url = format!(
-            "https://msdl.microsoft.com/download/symbols/{}/{}{}/{}",
-            self.name, self.guid, self.age, self.name
-        );
-        tracing::info!("Generated download URL: {}", url);
+impl Architecture {
+    /// Maps a COFF file header machine type to an architecture.
+    pub fn from_machine(machine: u16) -> Self {
+        match machine {
+            0x014c => Self::X86,
+            0x8664 => Self::X64,
+            0x01c0 | 0x01c2 | 0x01c4 => Self::Arm,
+            0xaa64 | 0xa641 => Self::Arm64,
+            _ => Self::Unknown,
+        }
+    }
 
-        let mut attempts = 0;
-        let max_attempts = 5;
-        let mut delay = Duration::from_secs(1);
+    fn of<P: PE>(image: &P) -> Self {
+        match image.get_valid_nt_headers() {
+            Ok(NTHeaders::NTHeaders32(headers)) => Self::from_machine(headers.file_header.machine),
+            Ok(NTHeaders::NTHeaders64(headers)) => Self::from_machine(headers.file_header.machine),
+            Err(_) => Self::Unknown,
+        }
+    }
+}
 
-        while attempts < max_attempts {
-            match reqwest::blocking::get(&url) {
-                Ok(response) => {
-                    tracing::info!("Successfully fetched data from URL");
-                    return Some(response.bytes().unwrap_or_default().to_vec());
-                }
-                Err(e) => {
-                    attempts += 1;
-                    tracing::warn!(
-                        "Attempt {} failed to fetch data: {}. Retrying in {:?}...",
-                        attempts,
-                        e,
-                        delay
-                    );
-                    thread::sleep(delay);
-                    delay *= 2; // Exponential backoff
+impl std::str::FromStr for Architecture {
+    type Err = String;
+
+    /// Parses the names used in manifests, plus the common aliases `i386`, `amd64` and
+    /// `aarch64`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "x86" | "i386" => Ok(Self::X86),
+            "x64" | "amd64" | "x86_64" => Ok(Self::X64),
+            "arm" => Ok(Self::Arm),
+            "arm64" | "aarch64" => Ok(Self::Arm64),
+            "unknown" => Ok(Self::Unknown),
+            _ => Err(format!(
+                "unknown architecture {:?}, expected x86, x64, arm or arm64",
+                s
+            )),
+        }
+    }
+}
+
+/// Fetches PDB metadata from explicitly given files and directories.
+///
+/// Files are scanned regardless of their extension; directories are walked recursively,
+/// at most `max_depth` levels deep if given, picking up the files accepted by `filter`.
+pub fn scan_paths(
+    paths: &[PathBuf],
+    max_depth: Option<usize>,
+    filter: &FileFilter,
+) -> Result<Vec<PdbMeta>> {
+    Ok(scan_files(list_paths(paths, max_depth, filter)?))
+}
+
+/// Lists the files that would be scanned by [`scan_paths`].
+pub fn list_paths(
+    paths: &[PathBuf],
+    max_depth: Option<usize>,
+    filter: &FileFilter,
+) -> Result<Vec<PathBuf>> {
+    walk_paths(paths, max_depth, filter).collect()
+}
+
+/// Lazily walks the files that would be scanned by [`scan_paths`].
+pub fn walk_paths(paths: &[PathBuf], max_depth: Option<usize>, filter: &FileFilter) -> FileWalker {
+    let mut walker = FileWalker::new(filter.clone());
+    for path in paths {
+        if path.is_dir() {
+            tracing::info!("Listing files in: {}", path.display());
+            walker.push_dir(path.clone(), max_depth);
+        } else {
+            walker.push_file(path.clone());
+        }
+    }
+    walker
+}
+
+/// Reads the PDB keys of the given files, logging and skipping files without one.
+///
+/// ARM64X binaries can yield two PDBs, one for each view. The files are scanned in
+/// parallel on the rayon thread pool, and the keys returned in the order of the files,
+/// so manifests come out the same on every run.
+pub fn scan_files(files: Vec<PathBuf>) -> Vec<PdbMeta> {
+    files
+        .par_iter()
+        .map(|file| scan_file(file))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Lazily reads the PDB keys of the given files like [`scan_files`], scanning
+/// [`SCAN_BATCH_LEN`] files at a time in parallel as the iterator advances.
+pub fn scan_iter<I>(files: I) -> impl Iterator<Item = PdbMeta>
+where
+    I: IntoIterator<Item = PathBuf>,
+{
+    batches(files.into_iter()).flat_map(scan_files)
+}
+
+/// Reads the PDB keys of the given files like [`scan_files`], adding their image keys
+/// and hashes as `filter` asks for, see [`FileFilter::with_images`] and
+/// [`FileFilter::with_hashes`].
+pub fn scan_with(files: &[PathBuf], filter: &FileFilter) -> Vec<PdbMeta> {
+    files
+        .par_iter()
+        .map(|file| scan_file_with(file, filter))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Lazily reads the PDB keys of the files a walker yields like [`scan_with`]. Errors
+/// of the walk are passed through.
+///
+/// Like [`scan_iter`], batches of files are scanned in parallel and their keys yielded
+/// in the order of the files.
+pub fn scan_walk<I>(files: I, filter: FileFilter) -> impl Iterator<Item = Result<PdbMeta>>
+where
+    I: IntoIterator<Item = Result<PathBuf>>,
+{
+    batches(files.into_iter()).flat_map(move |batch| {
+        batch
+            .into_par_iter()
+            .map(|file| match file {
+                Ok(file) => scan_file_with(&file, &filter)
+                    .into_iter()
+                    .map(Ok)
+                    .collect::<Vec<_>>(),
+                Err(e) => vec![Err(e)],
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flatten()
+    })
+}
+
+/// Splits `items` into batches of up to [`SCAN_BATCH_LEN`] items, taken from `items`
+/// only as the returned iterator advances.
+fn batches<I: Iterator>(mut items: I) -> impl Iterator<Item = Vec<I::Item>> {
+    std::iter::from_fn(move || {
+        let batch: Vec<_> = items.by_ref().take(SCAN_BATCH_LEN).collect();
+        (!batch.is_empty()).then_some(batch)
+    })
+}
+
+/// Runs a scanning iterator, such as [`Windows::pdbs`] or [`scan_walk`], on a blocking
+/// thread and returns its items as an async stream.
+///
+/// The scan stays at most [`SCAN_STREAM_CAPACITY`] items ahead of the consumer, and
+/// stops when the stream is dropped. Must be called from within a Tokio runtime.
+pub fn scan_stream<I>(items: I) -> ReceiverStream<I::Item>
+where
+    I: Iterator + Send + 'static,
+    I::Item: Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(SCAN_STREAM_CAPACITY);
+    tokio::task::spawn_blocking(move || {
+        for item in items {
+            if sender.blocking_send(item).is_err() {
+                break;
+            }
+        }
+    });
+    ReceiverStream::new(receiver)
+}
+
+/// Reads the PDB keys of one file like [`scan_file`], adding its image key and hashes
+/// as `filter` asks for.
+fn scan_file_with(file: &Path, filter: &FileFilter) -> Vec<PdbMeta> {
+    let mut pdbs = scan_file(file);
+    if filter.images() {
+        pdbs.extend(scan_images(&[file.to_path_buf()]));
+    }
+    if let Some(options) = filter.hashes()
+        && !pdbs.is_empty()
+    {
+        match FileHashes::of(file, options) {
+            Ok(hashes) => {
+                for pdb in &mut pdbs {
+                    pdb.binary_hashes = Some(hashes.clone());
                 }
             }
+            Err(e) => tracing::warn!("Failed to hash {}: {}", file.display(), e),
         }
-        tracing::error!(
-            "Failed to fetch data from URL after {} attempts",
-            max_attempts
-        );
-        None
+    }
+    pdbs
+}
+
+/// Reads the PDB keys of one file, logging and skipping files without one.
+fn scan_file(file: &Path) -> Vec<PdbMeta> {
+    match PdbMeta::all_from_file(file) {
+        Ok(pdbs) => {
+            tracing::debug!(
+                event = "file_scanned",
+                path = %file.display(),
+                keys = pdbs.len(),
+                "Scanned {}",
+                file.display()
+            );
+            for pdb in &pdbs {
+                tracing::debug!(
+                    event = "key_computed",
+                    path = %file.display(),
+                    name = %pdb.name,
+                    key = %pdb.key(),
+                    "Found {} {}",
+                    pdb.name,
+                    pdb.key()
+                );
+            }
+            pdbs
+        }
+        Err(e @ Error::NoDebugInfo(_)) => {
+            tracing::debug!(
+                event = "file_scanned",
+                path = %file.display(),
+                keys = 0,
+                "No PDB found: {}",
+                e
+            );
+            Vec::new()
+        }
+        Err(e) => {
+            tracing::warn!("Skipping {}: {}", file.display(), e);
+            Vec::new()
+        }
+    }
+}
+
+/// Returns the `TimeDateStamp` of the file header and the `SizeOfImage` of the optional
+/// header, which key images and DBG files on symbol servers.
+fn image_key<P: PE>(image: &P) -> std::result::Result<(u32, u32), exe::Error> {
+    Ok(match image.get_valid_nt_headers()? {
+        NTHeaders::NTHeaders32(headers) => (
+            headers.file_header.time_date_stamp,
+            headers.optional_header.size_of_image,
+        ),
+        NTHeaders::NTHeaders64(headers) => (
+            headers.file_header.time_date_stamp,
+            headers.optional_header.size_of_image,
+        ),
+    })
+}
+
+/// Returns the `.dbg` file name for the name in a MISC entry, which is either the path
+/// of the DBG file or the name of the image it was split off.
+pub(crate) fn dbg_file_name(name: &str) -> String {
+    let name = pdb_file_name(name);
+    match name.rsplit_once('.') {
+        Some((_, ext)) if ext.eq_ignore_ascii_case("dbg") => name.to_string(),
+        Some((stem, _)) => format!("{}.dbg", stem),
+        None => format!("{}.dbg", name),
     }
 }
 
-/// Extracts a UTF-8 debug name from a null-terminated byte string.
+/// Reads the image keys of the given files, see [`PdbMeta::image_from_file`], logging
+/// and skipping files that are not PE images.
+pub fn scan_images(files: &[PathBuf]) -> Vec<PdbMeta> {
+    files
+        .par_iter()
+        .filter_map(|file| match PdbMeta::image_from_file(file) {
+            Ok(image) => Some(image),
+            Err(e) => {
+                tracing::debug!("No image key for {}: {}", file.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Extracts a UTF-8 debug name from the PDB path of a CodeView record, or `None` if
+/// it is not valid UTF-8.
 fn extract_debug_name(name: &[u8]) -> Option<String> {
-    let name_end = name.iter().position(|&b| b == 0)?;
-    String::from_utf8(name[..name_end].to_vec()).ok()
+    String::from_utf8(name.to_vec()).ok()
+}
+
+/// Decodes a Windows-1252 (Western European ANSI code page) byte string. Bytes that
+/// are undefined in the code page map to the C1 control characters, as on Windows.
+fn decode_windows_1252(bytes: &[u8]) -> String {
+    const HIGH: [char; 32] = [
+        '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}',
+        '\u{2021}', '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}',
+        '\u{8f}', '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}',
+        '\u{2014}', '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}',
+        '\u{178}',
+    ];
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x80..=0x9f => HIGH[usize::from(b - 0x80)],
+            _ => char::from(b),
+        })
+        .collect()
+}
+
+/// Returns the final component of a PDB path such as `d:\builds\x64\release\foo.pdb`,
+/// which is what symbol servers and stores are keyed by. Both `\` and `/` separate
+/// components, whatever the platform.
+pub(crate) fn pdb_file_name(path: &str) -> &str {
+    path.rsplit(['\\', '/']).next().unwrap_or(path)
 }
 
 /// Encodes a GUID (as found in the binary) into the Microsoft symbol server format.
-fn encode_guid(bytes: &[u8; 16]) -> String {
+pub(crate) fn encode_guid(bytes: &[u8; 16]) -> String {
     // Reverse bytes for the first parts per GUID specification.
     hex::encode([
         bytes[3], bytes[2], bytes[1], bytes[0], bytes[5], bytes[4], bytes[7], bytes[6], bytes[8],
@@ -172,3 +1059,137 @@ fn encode_guid(bytes: &[u8; 16]) -> String {
     ])
     .to_uppercase()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(name: &[u8]) -> RsdsRecord {
+        RsdsRecord {
+            guid: [
+                0x78, 0x56, 0x34, 0x12, 0xbc, 0x9a, 0xf0, 0xde, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
+                0x07, 0x08,
+            ],
+            age: 2,
+            name: name.to_vec(),
+        }
+    }
+
+    fn from_record(name: &[u8]) -> Result<PdbMeta> {
+        PdbMeta::from_record(
+            Path::new("foo.dll"),
+            &record(name),
+            SymbolKind::Pdb,
+            Architecture::X64,
+            Vec::new(),
+        )
+    }
+
+    #[test]
+    fn decode_windows_1252_maps_the_high_half() {
+        assert_eq!(decode_windows_1252(b"abc.pdb"), "abc.pdb");
+        assert_eq!(decode_windows_1252(b"caf\xe9.pdb"), "caf\u{e9}.pdb");
+        assert_eq!(
+            decode_windows_1252(b"\x80\x9f\xa0\xff"),
+            "\u{20ac}\u{178}\u{a0}\u{ff}"
+        );
+        assert_eq!(decode_windows_1252(b"\x81\x8d"), "\u{81}\u{8d}");
+        assert_eq!(decode_windows_1252(b""), "");
+    }
+
+    #[test]
+    fn pdb_file_name_strips_both_separators() {
+        assert_eq!(pdb_file_name("d:\\a\\b.pdb"), "b.pdb");
+        assert_eq!(pdb_file_name("a/b.pdb"), "b.pdb");
+        assert_eq!(pdb_file_name("d:\\a/b\\c.pdb"), "c.pdb");
+        assert_eq!(pdb_file_name("b.pdb"), "b.pdb");
+        assert_eq!(pdb_file_name("d:\\a\\"), "");
+    }
+
+    #[test]
+    fn from_record_keys_by_file_name() {
+        let pdb = from_record(b"d:\\a\\b.pdb").unwrap();
+        assert_eq!(pdb.name, "b.pdb");
+        assert_eq!(pdb.guid, "123456789ABCDEF00102030405060708");
+        assert_eq!(pdb.key(), "123456789ABCDEF001020304050607082");
+        assert_eq!(from_record(b"a/b.pdb").unwrap().name, "b.pdb");
+    }
+
+    #[test]
+    fn from_record_keeps_names_over_255_bytes() {
+        let name = format!("d:\\a\\{}.pdb", "x".repeat(300));
+        let pdb = from_record(name.as_bytes()).unwrap();
+        assert_eq!(pdb.name.len(), 304);
+    }
+
+    #[test]
+    fn from_record_decodes_non_utf8_names() {
+        assert_eq!(
+            from_record(b"d:\\a\\caf\xe9.pdb").unwrap().name,
+            "caf\u{e9}.pdb"
+        );
+    }
+
+    #[test]
+    fn from_record_rejects_short_names() {
+        assert!(from_record(b"d:\\a\\").is_err());
+        assert!(from_record(b"").is_err());
+    }
+
+    #[test]
+    fn from_key_rejects_paths() {
+        let key = "123456789ABCDEF001020304050607082";
+        let pdb = PdbMeta::from_key("b.pdb", key, Path::new("keys.txt")).unwrap();
+        assert_eq!((pdb.kind, pdb.age), (SymbolKind::Pdb, 2));
+        assert!(PdbMeta::from_key("d:\\a\\b.pdb", key, Path::new("keys.txt")).is_none());
+        assert!(PdbMeta::from_key("../b.pdb", key, Path::new("keys.txt")).is_none());
+        assert!(PdbMeta::from_key("..", key, Path::new("keys.txt")).is_none());
+    }
+
+    #[test]
+    fn from_key_reads_the_age_as_hex() {
+        for (age, key_age) in [(10, "A"), (16, "10"), (0x1f2, "1F2")] {
+            let key = format!("123456789ABCDEF00102030405060708{}", key_age);
+            let pdb = PdbMeta::from_key("b.pdb", &key, Path::new("keys.txt")).unwrap();
+            assert_eq!((pdb.kind, pdb.age), (SymbolKind::Pdb, age));
+            assert_eq!(pdb.key(), key);
+        }
+        let pdb = PdbMeta::from_key(
+            "b.pdb",
+            "123456789abcdef00102030405060708a",
+            Path::new("keys.txt"),
+        )
+        .unwrap();
+        assert_eq!(pdb.key(), "123456789ABCDEF00102030405060708A");
+        assert!(
+            PdbMeta::from_key(
+                "b.pdb",
+                "123456789ABCDEF00102030405060708123456789",
+                Path::new("keys.txt")
+            )
+            .is_none()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn walk_skips_unreadable_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("locked");
+        let open = dir.path().join("open");
+        fs::create_dir(&locked).unwrap();
+        fs::create_dir(&open).unwrap();
+        fs::write(dir.path().join("a.dll"), b"").unwrap();
+        fs::write(locked.join("b.dll"), b"").unwrap();
+        fs::write(open.join("c.dll"), b"").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        let files = list_paths(&[dir.path().to_path_buf()], None, &FileFilter::default());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        let files = files.unwrap();
+        assert!(files.contains(&dir.path().join("a.dll")));
+        assert!(files.contains(&open.join("c.dll")));
+    }
+}